@@ -30,7 +30,28 @@ fn build_vxlapi() {
         .expect("Couldn't write bindings!");
 }
 
+#[cfg(feature = "pcan")]
+fn link_pcan_basic() {
+    use std::env;
+
+    // The PCAN-Basic library is not vendored. Point PCAN_BASIC_LIB_DIR to the directory containing the import library if it is not on the default search path.
+    println!("cargo:rerun-if-env-changed=PCAN_BASIC_LIB_DIR");
+    if let Ok(dir) = env::var("PCAN_BASIC_LIB_DIR") {
+        println!("cargo:rustc-link-search={}", dir);
+    }
+
+    // Build scripts are compiled for the host, so check the target OS at runtime
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("windows") {
+        println!("cargo:rustc-link-lib=PCANBasic");
+    } else {
+        println!("cargo:rustc-link-lib=pcanbasic");
+    }
+}
+
 fn main() {
     #[cfg(all(target_os = "windows", feature = "vector-xl"))]
     build_vxlapi();
+
+    #[cfg(feature = "pcan")]
+    link_pcan_basic();
 }
@@ -1,5 +1,4 @@
 use automotive::StreamExt;
-use tracing_subscriber;
 
 #[tokio::main]
 async fn main() {
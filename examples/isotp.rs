@@ -1,7 +1,6 @@
 use automotive::can::Identifier;
 use automotive::isotp::{IsoTPAdapter, IsoTPConfig};
 use automotive::StreamExt;
-use tracing_subscriber;
 
 #[tokio::main]
 async fn main() {
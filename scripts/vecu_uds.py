@@ -26,6 +26,8 @@ if __name__ == "__main__":
             EcuResponse([EcuState(session=range(0,255))], responses=UDS() / UDS_TPPR()),
             EcuResponse([EcuState(session=range(0,255))], responses=UDS() / UDS_RDBIPR(dataIdentifier=0x1234) / Raw(b"deadbeef")),
             EcuResponse([EcuState(session=range(0,255))], responses=UDS() /  UDS_NR(negativeResponseCode=0x33, requestServiceId=0x10)),
+            EcuResponse([EcuState(session=range(0,255))], responses=UDS() / UDS_ERPR(resetType=0x01)),
+            EcuResponse([EcuState(session=range(0,255))], responses=UDS() / UDS_ERPR(resetType=0x04, powerDownTime=5)),
         ]
         ecu = EcuAnsweringMachine(supported_responses=resp, main_socket=isotp, basecls=UDS, verbose=False, timeout=args.timeout)
         ecu()
@@ -1,5 +1,79 @@
 //! Convenience functions to get a CAN adapter.
 
+/// Configuration to open a specific adapter using [`get_adapter_with_config`]. Variants are only available if the corresponding adapter is enabled.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AdapterConfig {
+    /// SocketCAN interface name (e.g. `can0`). The bitrate and data bitrate are configured if set, which requires CAP_NET_ADMIN.
+    #[cfg(all(target_os = "linux", feature = "socketcan"))]
+    SocketCan {
+        name: String,
+        bitrate: Option<u32>,
+        dbitrate: Option<u32>,
+    },
+    /// Panda with the given USB serial number, or the first panda found. The bitrate is set on all busses if set.
+    #[cfg(feature = "panda")]
+    Panda {
+        serial: Option<String>,
+        bitrate: Option<u32>,
+    },
+    /// Vector channel based on the global channel index
+    #[cfg(all(target_os = "windows", feature = "vector-xl"))]
+    Vector { channel_idx: usize },
+    /// PCAN channel (e.g. [`crate::pcan::PCAN_USBBUS1`]) in classic CAN mode
+    #[cfg(feature = "pcan")]
+    Pcan { channel: u16, bitrate: u32 },
+    /// SLCAN serial device (e.g. `/dev/ttyACM0`)
+    #[cfg(all(target_os = "linux", feature = "slcan"))]
+    SlCan { path: String, bitrate: u32 },
+    /// CAN over TCP server address (e.g. `127.0.0.1:20000`)
+    #[cfg(feature = "tcp")]
+    Tcp { addr: String },
+}
+
+/// Opens the adapter described by the [`AdapterConfig`]. Unlike [`get_adapter`], this doesn't probe other adapters if opening fails.
+pub fn get_adapter_with_config(
+    config: &AdapterConfig,
+) -> Result<crate::can::AsyncCanAdapter, crate::error::Error> {
+    match *config {
+        #[cfg(all(target_os = "linux", feature = "socketcan"))]
+        AdapterConfig::SocketCan {
+            ref name,
+            bitrate,
+            dbitrate,
+        } => {
+            if let Some(bitrate) = bitrate {
+                crate::socketcan::SocketCan::set_bitrate(name, bitrate, dbitrate)?;
+            }
+            crate::socketcan::SocketCan::new_async(name)
+        }
+        #[cfg(feature = "panda")]
+        AdapterConfig::Panda {
+            ref serial,
+            bitrate,
+        } => {
+            let panda = match serial {
+                Some(serial) => crate::panda::Panda::new_with_serial(serial)?,
+                None => crate::panda::Panda::new()?,
+            };
+            if let Some(bitrate) = bitrate {
+                for bus in 0..crate::panda::PANDA_BUS_CNT {
+                    panda.set_bitrate(bus, bitrate)?;
+                }
+            }
+            Ok(crate::can::AsyncCanAdapter::new(panda))
+        }
+        #[cfg(all(target_os = "windows", feature = "vector-xl"))]
+        AdapterConfig::Vector { channel_idx } => crate::vector::VectorCan::new_async(channel_idx),
+        #[cfg(feature = "pcan")]
+        AdapterConfig::Pcan { channel, bitrate } => crate::pcan::Pcan::new_async(channel, bitrate),
+        #[cfg(all(target_os = "linux", feature = "slcan"))]
+        AdapterConfig::SlCan { ref path, bitrate } => crate::slcan::SlCan::new_async(path, bitrate),
+        #[cfg(feature = "tcp")]
+        AdapterConfig::Tcp { ref addr } => crate::tcp::TcpCan::connect_async(addr),
+    }
+}
+
 /// Convenience function to get the first available adapter on the system. Supports both comma.ai panda, and SocketCAN.
 pub fn get_adapter() -> Result<crate::can::AsyncCanAdapter, crate::error::Error> {
     #[cfg(feature = "panda")]
@@ -11,9 +85,8 @@ pub fn get_adapter() -> Result<crate::can::AsyncCanAdapter, crate::error::Error>
 
     #[cfg(all(target_os = "linux", feature = "socketcan"))]
     {
-        // TODO: iterate over all available SocketCAN adapters to also find things like vcan0
-        for iface in ["can0", "vcan0"] {
-            if let Ok(socket) = crate::socketcan::SocketCan::new_async(iface) {
+        for iface in crate::socketcan::SocketCan::available() {
+            if let Ok(socket) = crate::socketcan::SocketCan::new_async(&iface) {
                 return Ok(socket);
             }
         }
@@ -1,11 +1,12 @@
 //! Async wrapper for Adapters implementing the [`CanAdapter`] trait.
 
 use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::can::CanAdapter;
 use crate::can::Frame;
 use crate::can::Identifier;
-use crate::Stream;
+use crate::{Stream, StreamExt};
 use async_stream::stream;
 use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::{broadcast, mpsc, oneshot};
@@ -14,21 +15,114 @@ use tracing::debug;
 const CAN_TX_BUFFER_SIZE: usize = 128;
 const CAN_RX_BUFFER_SIZE: usize = 1024;
 const DEBUG: bool = false;
+/// Default maximum time the background thread waits for new frames before checking for frames to send.
+pub const DEFAULT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(1);
 
 type BusIdentifier = (u8, Identifier);
-type FrameCallback = (Frame, oneshot::Sender<()>);
+/// Called with the timestamp of the loopback frame once a frame is sent
+type TxCallback = oneshot::Sender<crate::Result<Option<std::time::Duration>>>;
+/// Loopback frame to wait for, with an optional callback. Only the last frame of a batch has a callback.
+type FrameCallback = (Frame, Option<TxCallback>);
+type Filters = Vec<(Identifier, u32)>;
+
+/// Request to the background thread to change the receive filters. Each [`AsyncCanAdapter::recv_hardware_filter`] stream adds its filters under a unique key, and removes them again when dropped.
+enum FilterRequest {
+    Add(u64, Filters, oneshot::Sender<crate::Result<()>>),
+    Remove(u64),
+}
+
+/// Request to the background thread to send frames. The callback is called once the (last) frame is looped back, or with an error if the adapter can't send one of the frames.
+enum TxRequest {
+    Single(Frame, TxCallback),
+    Batch(Vec<Frame>, TxCallback),
+}
+
+/// Returns true if the frame matches any of the (id, mask) filters. Standard and Extended ids never match each other.
+pub(crate) fn filter_matches(filters: &[(Identifier, u32)], frame: &Frame) -> bool {
+    filters.iter().any(|(id, mask)| {
+        id.is_extended() == frame.id.is_extended()
+            && u32::from(*id) & mask == u32::from(frame.id) & mask
+    })
+}
+
+/// Installs the union of all active filters in the adapter, or falls back to filtering in software if the adapter doesn't support filters. Accepts all frames if there are no active filters.
+fn install_filters<T: CanAdapter>(
+    adapter: &mut T,
+    active_filters: &HashMap<u64, Filters>,
+    software_filters: &mut Option<Filters>,
+) -> crate::Result<()> {
+    let filters: Option<Filters> = match active_filters.is_empty() {
+        true => None,
+        false => Some(active_filters.values().flatten().copied().collect()),
+    };
+
+    // Removing the filters installs a filter accepting all frames, as an empty filter list drops all frames
+    let accept_all = || vec![(Identifier::Standard(0), 0), (Identifier::Extended(0), 0)];
+    match adapter.set_hardware_filters(filters.as_deref().unwrap_or(&accept_all())) {
+        Ok(()) => {
+            *software_filters = None;
+            Ok(())
+        }
+        Err(crate::Error::NotSupported) => {
+            *software_filters = filters;
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
 
 fn process<T: CanAdapter>(
     mut adapter: T,
     mut shutdown_receiver: oneshot::Receiver<()>,
     rx_sender: broadcast::Sender<Frame>,
-    mut tx_receiver: mpsc::Receiver<(Frame, oneshot::Sender<()>)>,
+    mut tx_receiver: mpsc::Receiver<TxRequest>,
+    mut filter_receiver: mpsc::UnboundedReceiver<FilterRequest>,
+    poll_interval: std::time::Duration,
 ) {
     let mut buffer: VecDeque<Frame> = VecDeque::new();
     let mut callbacks: HashMap<BusIdentifier, VecDeque<FrameCallback>> = HashMap::new();
+    let mut software_filters: Option<Filters> = None;
+    let mut active_filters: HashMap<u64, Filters> = HashMap::new();
 
     while shutdown_receiver.try_recv().is_err() {
-        let frames: Vec<Frame> = adapter.recv().expect("Failed to Receive CAN Frames");
+        // Install receive filters in the adapter, or fall back to filtering in software
+        while let Ok(request) = filter_receiver.try_recv() {
+            let result = match request {
+                FilterRequest::Add(key, filters, result) => {
+                    active_filters.insert(key, filters);
+                    Some((key, result))
+                }
+                FilterRequest::Remove(key) => {
+                    active_filters.remove(&key);
+                    None
+                }
+            };
+
+            let r = install_filters(&mut adapter, &active_filters, &mut software_filters);
+            match result {
+                Some((key, result)) => {
+                    // Keep the previous filters if the new ones can't be installed
+                    if r.is_err() {
+                        active_filters.remove(&key);
+                    }
+                    result.send(r).ok();
+                }
+                None => {
+                    if let Err(e) = r {
+                        tracing::warn!("Failed to remove receive filters: {}", e);
+                    }
+                }
+            }
+        }
+
+        // Stop processing if the adapter fails, e.g. when it's unplugged. Dropping the callbacks and channels signals the error to the pending senders and receivers.
+        let frames: Vec<Frame> = match adapter.recv() {
+            Ok(frames) => frames,
+            Err(e) => {
+                tracing::error!("Failed to receive CAN frames, stopping adapter: {}", e);
+                return;
+            }
+        };
 
         for frame in frames {
             if DEBUG {
@@ -46,37 +140,81 @@ fn process<T: CanAdapter>(
                     Some((tx_frame, callback)) => {
                         // Ensure the frame we received matches the frame belonging to the callback.
                         // If not, we have a bug in the adapter implementation and frames are sent/received out of order.
-                        assert_eq!(tx_frame, frame);
+                        // The timestamp is only known after the frame is sent, so it is excluded from the comparison.
+                        // The BRS flag is excluded as well, some adapters (e.g. panda) configure it per bus and don't report it.
+                        // The raw DLC is excluded too, adapters don't report a DLC matching the data length, and SocketCAN only reports a DLC above 8 if enabled on the interface.
+                        assert_eq!(
+                            Frame {
+                                timestamp: frame.timestamp,
+                                brs: frame.brs,
+                                dlc: frame.dlc,
+                                ..tx_frame
+                            },
+                            frame
+                        );
 
                         // Callback might be dropped if the sender is not waiting for the response
-                        callback.send(()).ok();
+                        if let Some(callback) = callback {
+                            callback.send(Ok(frame.timestamp)).ok();
+                        }
                     }
                     None => panic!("Received loopback frame with no pending callback"),
                 };
             }
 
+            // Loopback frames always pass, so the filters don't interfere with awaiting sent frames
+            if let Some(filters) = &software_filters {
+                if !frame.loopback && !filter_matches(filters, &frame) {
+                    continue;
+                }
+            }
+
             rx_sender.send(frame).unwrap();
         }
 
         // TODO: use poll_recv_many?
-        while let Ok((frame, callback)) = tx_receiver.try_recv() {
-            let mut loopback_frame = frame.clone();
-            loopback_frame.loopback = true;
-
-            // Insert callback into hashmap
-            callbacks
-                .entry((frame.bus, frame.id))
-                .or_default()
-                .push_back((loopback_frame, callback));
+        while let Ok(request) = tx_receiver.try_recv() {
+            let (frames, callback) = match request {
+                TxRequest::Single(frame, callback) => (vec![frame], callback),
+                TxRequest::Batch(frames, callback) => (frames, callback),
+            };
 
-            if DEBUG {
-                debug! {"TX {:?}", frame};
+            // Reject the whole request if the adapter can't send one of the frames, without stopping the adapter
+            if let Err(e) = frames
+                .iter()
+                .try_for_each(|frame| adapter.check_frame(frame))
+            {
+                debug!("Rejecting frame(s) not supported by the adapter: {}", e);
+                callback.send(Err(e)).ok();
+                continue;
             }
 
-            buffer.push_back(frame);
+            let mut callback = Some(callback);
+            let last = frames.len().saturating_sub(1);
+
+            for (i, frame) in frames.into_iter().enumerate() {
+                let mut loopback_frame = frame.clone();
+                loopback_frame.loopback = true;
+
+                // Insert callback into hashmap
+                let callback = if i == last { callback.take() } else { None };
+                callbacks
+                    .entry((frame.bus, frame.id))
+                    .or_default()
+                    .push_back((loopback_frame, callback));
+
+                if DEBUG {
+                    debug! {"TX {:?}", frame};
+                }
+
+                buffer.push_back(frame);
+            }
         }
         if !buffer.is_empty() {
-            adapter.send(&mut buffer).unwrap();
+            if let Err(e) = adapter.send(&mut buffer) {
+                tracing::error!("Failed to send CAN frames, stopping adapter: {}", e);
+                return;
+            }
 
             if !buffer.is_empty() {
                 debug!(
@@ -85,7 +223,51 @@ fn process<T: CanAdapter>(
                 );
             }
         }
-        std::thread::sleep(std::time::Duration::from_millis(1));
+
+        // Wait for new frames, unless there are already frames waiting to be sent
+        if tx_receiver.is_empty() {
+            if let Err(e) = adapter.poll(poll_interval) {
+                tracing::error!("Failed to poll adapter, stopping adapter: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// Removes the receive filters of a [`AsyncCanAdapter::recv_hardware_filter`] stream when the stream is dropped
+struct FilterGuard {
+    filter_sender: mpsc::UnboundedSender<FilterRequest>,
+    key: u64,
+}
+
+impl Drop for FilterGuard {
+    fn drop(&mut self) {
+        self.filter_sender
+            .send(FilterRequest::Remove(self.key))
+            .ok();
+    }
+}
+
+/// Owned handle to send frames on an [`AsyncCanAdapter`], which can be moved into a background task
+#[derive(Clone)]
+pub(crate) struct CanSender {
+    send_sender: mpsc::Sender<TxRequest>,
+}
+
+impl CanSender {
+    /// See [`AsyncCanAdapter::send`]. Returns the timestamp of the loopback frame, if provided by the adapter.
+    pub(crate) async fn send(&self, frame: &Frame) -> crate::Result<Option<std::time::Duration>> {
+        // Create oneshot channel to signal the completion of the send operation
+        let (callback_sender, callback_receiver) = oneshot::channel();
+        self.send_sender
+            .send(TxRequest::Single(frame.clone(), callback_sender))
+            .await
+            .map_err(|_| crate::Error::Disconnected)?;
+
+        // Callback is dropped without being called if the background thread stops
+        callback_receiver
+            .await
+            .map_err(|_| crate::Error::Disconnected)?
     }
 }
 
@@ -93,40 +275,93 @@ fn process<T: CanAdapter>(
 pub struct AsyncCanAdapter {
     processing_handle: Option<std::thread::JoinHandle<()>>,
     recv_receiver: broadcast::Receiver<Frame>,
-    send_sender: mpsc::Sender<(Frame, oneshot::Sender<()>)>,
+    sender: CanSender,
+    filter_sender: mpsc::UnboundedSender<FilterRequest>,
+    next_filter_key: AtomicU64,
     shutdown: Option<oneshot::Sender<()>>,
 }
 
 impl AsyncCanAdapter {
     pub fn new<T: CanAdapter + Send + Sync + 'static>(adapter: T) -> Self {
+        Self::new_with_poll_interval(adapter, DEFAULT_POLL_INTERVAL)
+    }
+
+    /// Creates a new [`AsyncCanAdapter`], where the background thread waits at most `poll_interval` for new frames before checking for frames to send. A shorter interval reduces the latency of sending frames at the cost of CPU usage. Adapters implementing [`CanAdapter::poll`] wake up as soon as a frame is received.
+    pub fn new_with_poll_interval<T: CanAdapter + Send + Sync + 'static>(
+        adapter: T,
+        poll_interval: std::time::Duration,
+    ) -> Self {
         let (shutdown_sender, shutdown_receiver) = oneshot::channel();
         let (send_sender, send_receiver) = mpsc::channel(CAN_TX_BUFFER_SIZE);
         let (recv_sender, recv_receiver) = broadcast::channel(CAN_RX_BUFFER_SIZE);
+        let (filter_sender, filter_receiver) = mpsc::unbounded_channel();
 
         let mut ret = AsyncCanAdapter {
             shutdown: Some(shutdown_sender),
             processing_handle: None,
             recv_receiver,
-            send_sender,
+            sender: CanSender { send_sender },
+            filter_sender,
+            next_filter_key: AtomicU64::new(0),
         };
 
         ret.processing_handle = Some(std::thread::spawn(move || {
-            process(adapter, shutdown_receiver, recv_sender, send_receiver);
+            process(
+                adapter,
+                shutdown_receiver,
+                recv_sender,
+                send_receiver,
+                filter_receiver,
+                poll_interval,
+            );
         }));
 
         ret
     }
 
-    /// Send a single frame. The Future will resolve once the frame has been handed over to the adapter for sending. This does not mean the message is sent out on the CAN bus yet, as this could be pending arbitration.
-    pub async fn send(&self, frame: &Frame) {
-        // Create oneshot channel to signal the completion of the send operation
+    /// Returns false if the background thread has stopped, e.g. because the adapter was disconnected. No frames can be sent or received after this happens.
+    pub fn is_healthy(&self) -> bool {
+        self.processing_handle
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+    }
+
+    /// Send a single frame. The Future will resolve once the frame has been handed over to the adapter for sending. This does not mean the message is sent out on the CAN bus yet, as this could be pending arbitration. Returns [`crate::Error::Disconnected`] if the background thread has stopped, or the error from [`CanAdapter::check_frame`] if the adapter can't send the frame.
+    pub async fn send(&self, frame: &Frame) -> crate::Result<()> {
+        self.sender.send(frame).await?;
+        Ok(())
+    }
+
+    /// Same as [`AsyncCanAdapter::send`], but returns the timestamp of the loopback frame, if provided by the adapter.
+    pub(crate) async fn send_timestamped(
+        &self,
+        frame: &Frame,
+    ) -> crate::Result<Option<std::time::Duration>> {
+        self.sender.send(frame).await
+    }
+
+    /// Owned handle to send frames, which stays usable as long as this adapter is alive
+    pub(crate) fn sender(&self) -> CanSender {
+        self.sender.clone()
+    }
+
+    /// Send multiple frames in order. The Future will resolve once the last frame has been handed over to the adapter for sending. This is more efficient than awaiting [`AsyncCanAdapter::send`] for every frame, as all frames are handed to the background thread at once. If the adapter can't send one of the frames, none of the frames are sent.
+    pub async fn send_batch(&self, frames: &[Frame]) -> crate::Result<()> {
+        if frames.is_empty() {
+            return Ok(());
+        }
+
         let (callback_sender, callback_receiver) = oneshot::channel();
-        self.send_sender
-            .send((frame.clone(), callback_sender))
+        self.sender
+            .send_sender
+            .send(TxRequest::Batch(frames.to_vec(), callback_sender))
             .await
-            .unwrap();
+            .map_err(|_| crate::Error::Disconnected)?;
 
-        callback_receiver.await.unwrap();
+        callback_receiver
+            .await
+            .map_err(|_| crate::Error::Disconnected)??;
+        Ok(())
     }
 
     /// Receive all frames.
@@ -134,6 +369,26 @@ impl AsyncCanAdapter {
         self.recv_filter(|_| true)
     }
 
+    /// Receive all frames until no frame has been received for the duration of `timeout`, after which the stream ends. Useful to drain the bus until it goes quiet.
+    pub fn recv_timeout(&self, timeout: std::time::Duration) -> impl Stream<Item = Frame> {
+        Box::pin(
+            self.recv()
+                .timeout(timeout)
+                .map_while(|frame| frame.ok())
+                .fuse(),
+        )
+    }
+
+    /// Receive all frames on a single bus. The bus numbering depends on the adapter: the panda numbers its busses 0 to 2, Vector adapters number the opened channels starting at 0, and single bus adapters like SocketCAN, PCAN, SLCAN and TCP always use bus 0.
+    pub fn recv_bus(&self, bus: u8) -> impl Stream<Item = Frame> {
+        self.recv_filter(move |frame| frame.bus == bus)
+    }
+
+    /// Subscribe to all received frames. Frames are buffered in the returned receiver until read, allowing them to be inspected without blocking using `try_recv`.
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<Frame> {
+        self.recv_receiver.resubscribe()
+    }
+
     /// Receive frames that match a filter. Useful in combination with stream adapters.
     pub fn recv_filter(&self, filter: impl Fn(&Frame) -> bool) -> impl Stream<Item = Frame> {
         let mut rx = self.recv_receiver.resubscribe();
@@ -145,7 +400,10 @@ impl AsyncCanAdapter {
                             yield frame
                         }
                     },
-                    Err(RecvError::Closed) => panic!("Adapter thread has exited"),
+                    Err(RecvError::Closed) => {
+                        tracing::error!("Adapter thread has exited");
+                        break;
+                    },
                     Err(RecvError::Lagged(n)) => {
                         tracing::warn!("Receive too slow, dropping {} frame(s).", n)
                     },
@@ -153,14 +411,374 @@ impl AsyncCanAdapter {
             }
         })
     }
+
+    /// Install a receive filter for the whole adapter, and receive all frames passing the filter. Each filter is an (id, mask) pair, and a frame passes if `frame_id & mask == id & mask` for any of the filters. Standard and Extended ids never match each other.
+    ///
+    /// The filter is pushed down to the adapter if supported (e.g. SocketCAN), so unwanted frames are dropped before they reach userspace. Other adapters fall back to filtering in the background thread, before frames are broadcast to the streams. Loopback frames of sent frames always pass the filter.
+    ///
+    /// Note the filter affects every subscriber of this adapter, not just the returned stream: while the stream is alive, all other streams (e.g. the one used by an [`IsoTPAdapter`](crate::isotp::IsoTPAdapter)) only receive frames passing the filter. When multiple filtered streams are alive at the same time, the union of their filters is installed, while each returned stream only yields the frames passing its own filters. Dropping a stream removes its filters, and all frames are received again once the last filtered stream is dropped.
+    pub async fn recv_hardware_filter(
+        &self,
+        filters: &[(Identifier, u32)],
+    ) -> crate::Result<impl Stream<Item = Frame>> {
+        let key = self.next_filter_key.fetch_add(1, Ordering::Relaxed);
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.filter_sender
+            .send(FilterRequest::Add(key, filters.to_vec(), result_sender))
+            .map_err(|_| crate::Error::Disconnected)?;
+
+        result_receiver
+            .await
+            .map_err(|_| crate::Error::Disconnected)??;
+
+        // Moved into the stream, so the filters are removed once the stream is dropped
+        let guard = FilterGuard {
+            filter_sender: self.filter_sender.clone(),
+            key,
+        };
+        // Other streams can widen the installed filters, so filter again for this stream
+        let filters = filters.to_vec();
+        let stream =
+            self.recv_filter(move |frame| frame.loopback || filter_matches(&filters, frame));
+
+        Ok(Box::pin(stream! {
+            let _guard = guard;
+            tokio::pin!(stream);
+            while let Some(frame) = stream.next().await {
+                yield frame;
+            }
+        }))
+    }
+
+    /// Software gateway that forwards all frames received on `src_bus` to `dst_bus`. The `transform` closure is called for every frame, and can be used to modify the frame before it is forwarded, or to drop it by returning `None`. Frames sent out by the adapter itself (loopback) are ignored, to prevent forwarded frames from being fed back into the gateway. The future runs until the adapter stops, or returns an error if forwarding a frame fails.
+    ///
+    /// Frames are forwarded one at a time, waiting for the adapter to accept each frame before reading the next one. If frames arrive faster than they can be forwarded, e.g. on a busy bus, the receive buffer overflows and frames are dropped with a warning.
+    pub async fn gateway(
+        &self,
+        src_bus: u8,
+        dst_bus: u8,
+        transform: impl Fn(&Frame) -> Option<Frame>,
+    ) -> crate::Result<()> {
+        let stream = self.recv_filter(|frame| frame.bus == src_bus && !frame.loopback);
+        tokio::pin!(stream);
+
+        while let Some(frame) = stream.next().await {
+            if let Some(mut frame) = transform(&frame) {
+                frame.bus = dst_bus;
+                frame.loopback = false;
+                self.send(&frame).await?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Drop for AsyncCanAdapter {
     fn drop(&mut self) {
         if let Some(handle) = self.processing_handle.take() {
             // Send shutdown signal to background tread
-            self.shutdown.take().unwrap().send(()).unwrap();
+            // Background thread might have already exited because the adapter failed
+            self.shutdown.take().unwrap().send(()).ok();
             handle.join().unwrap();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Adapter that fails like an unplugged device
+    struct DisconnectedAdapter;
+
+    impl CanAdapter for DisconnectedAdapter {
+        fn send(&mut self, _frames: &mut VecDeque<Frame>) -> crate::Result<()> {
+            Err(crate::Error::Disconnected)
+        }
+
+        fn recv(&mut self) -> crate::Result<Vec<Frame>> {
+            Err(crate::Error::Disconnected)
+        }
+    }
+
+    #[tokio::test]
+    async fn disconnect() {
+        let adapter = AsyncCanAdapter::new(DisconnectedAdapter);
+        let stream = adapter.recv();
+        tokio::pin!(stream);
+
+        // Stream ends instead of panicking once the background thread stops
+        assert_eq!(stream.next().await, None);
+
+        let frame = Frame::new(0, 0x123.into(), &[0xaa]).unwrap();
+        assert_eq!(adapter.send(&frame).await, Err(crate::Error::Disconnected));
+
+        // Thread might still be cleaning up after closing the channels
+        let stopped = async {
+            while adapter.is_healthy() {
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(1), stopped)
+            .await
+            .unwrap();
+    }
+
+    /// Adapter that loops back all frames, but can't send remote frames
+    #[derive(Default)]
+    struct NoRemoteAdapter {
+        queue: VecDeque<Frame>,
+    }
+
+    impl CanAdapter for NoRemoteAdapter {
+        fn send(&mut self, frames: &mut VecDeque<Frame>) -> crate::Result<()> {
+            for mut frame in frames.drain(..) {
+                frame.loopback = true;
+                self.queue.push_back(frame);
+            }
+            Ok(())
+        }
+
+        fn recv(&mut self) -> crate::Result<Vec<Frame>> {
+            Ok(self.queue.drain(..).collect())
+        }
+
+        fn check_frame(&self, frame: &Frame) -> crate::Result<()> {
+            match frame.rtr {
+                true => Err(crate::Error::NotSupported),
+                false => Ok(()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn unsupported_frame() {
+        let adapter = AsyncCanAdapter::new(NoRemoteAdapter::default());
+
+        let frame = Frame::new(0, 0x123.into(), &[0xaa]).unwrap();
+        let remote = Frame::builder(0x123.into()).rtr(true).build().unwrap();
+
+        // Only the unsupported frame fails, the adapter keeps running
+        assert_eq!(adapter.send(&remote).await, Err(crate::Error::NotSupported));
+        assert_eq!(
+            adapter.send_batch(&[frame.clone(), remote]).await,
+            Err(crate::Error::NotSupported)
+        );
+        assert_eq!(adapter.send(&frame).await, Ok(()));
+        assert!(adapter.is_healthy());
+    }
+
+    #[tokio::test]
+    async fn software_filter() {
+        let (a, b) = crate::can::VirtualCanAdapter::pair_async();
+
+        let filters = [
+            (Identifier::Standard(0x7e8), 0x7f8),
+            (Identifier::Extended(0x18daf100), 0x1fffff00),
+        ];
+        let stream = b.recv_hardware_filter(&filters).await.unwrap();
+        let stream = stream.timeout(std::time::Duration::from_millis(100));
+        tokio::pin!(stream);
+
+        for id in [0x7e0, 0x7e8, 0x7ef, 0x123, 0x18daf110, 0x18da10f1] {
+            a.send(&Frame::new(0, id.into(), &[0xaa]).unwrap())
+                .await
+                .unwrap();
+        }
+
+        let received: Vec<Identifier> = stream
+            .map_while(|frame| frame.ok())
+            .map(|frame| frame.id)
+            .collect()
+            .await;
+        assert_eq!(
+            received,
+            vec![
+                Identifier::Standard(0x7e8),
+                Identifier::Standard(0x7ef),
+                Identifier::Extended(0x18daf110),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn restore_filter() {
+        let (a, b) = crate::can::VirtualCanAdapter::pair_async();
+        let frame = Frame::new(0, 0x123.into(), &[0xaa]).unwrap();
+
+        let stream = b
+            .recv_hardware_filter(&[(Identifier::Standard(0x7e8), 0x7ff)])
+            .await
+            .unwrap();
+
+        // Sent frames are looped back, even if the filter doesn't pass them
+        b.send(&frame).await.unwrap();
+
+        // Dropping the stream removes the filter again
+        drop(stream);
+        let stream = b.recv_filter(|frame| !frame.loopback);
+        tokio::pin!(stream);
+        a.send(&frame).await.unwrap();
+        assert_eq!(stream.next().await.unwrap(), frame);
+    }
+
+    #[tokio::test]
+    async fn overlapping_filters() {
+        let (a, b) = crate::can::VirtualCanAdapter::pair_async();
+        let frame = |id: u32| Frame::new(0, id.into(), &[0xaa]).unwrap();
+
+        let mut first = b
+            .recv_hardware_filter(&[(Identifier::Standard(0x7e8), 0x7ff)])
+            .await
+            .unwrap();
+        let mut second = b
+            .recv_hardware_filter(&[(Identifier::Standard(0x123), 0x7ff)])
+            .await
+            .unwrap();
+
+        let stream = b.recv_filter(|frame| !frame.loopback);
+        tokio::pin!(stream);
+
+        // Union of both filters is installed
+        for id in [0x456, 0x7e8, 0x123, 0x7e8] {
+            a.send(&frame(id)).await.unwrap();
+        }
+        assert_eq!(stream.next().await.unwrap().id, Identifier::Standard(0x7e8));
+        assert_eq!(stream.next().await.unwrap().id, Identifier::Standard(0x123));
+        assert_eq!(stream.next().await.unwrap().id, Identifier::Standard(0x7e8));
+
+        // Each filtered stream only yields the frames passing its own filter
+        assert_eq!(first.next().await.unwrap().id, Identifier::Standard(0x7e8));
+        assert_eq!(first.next().await.unwrap().id, Identifier::Standard(0x7e8));
+        assert_eq!(second.next().await.unwrap().id, Identifier::Standard(0x123));
+
+        // Dropping the first stream keeps the filter of the second stream
+        drop(first);
+        for id in [0x7e8, 0x123] {
+            a.send(&frame(id)).await.unwrap();
+        }
+        assert_eq!(stream.next().await.unwrap().id, Identifier::Standard(0x123));
+
+        // All frames pass once the last filtered stream is dropped
+        drop(second);
+        a.send(&frame(0x456)).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().id, Identifier::Standard(0x456));
+    }
+
+    #[tokio::test]
+    async fn gateway() {
+        let (a, b) = crate::can::VirtualCanAdapter::pair_async();
+        let stream = a.recv_filter(|frame| !frame.loopback);
+        tokio::pin!(stream);
+
+        let transform =
+            |frame: &Frame| (frame.id != Identifier::Standard(0x456)).then(|| frame.clone());
+        let forward = async {
+            for id in [0x123, 0x456, 0x7e8] {
+                a.send(&Frame::new(0, id.into(), &[0xaa]).unwrap())
+                    .await
+                    .unwrap();
+            }
+
+            // Re-sent on the destination bus, 0x456 is dropped by the transform
+            let frame = stream.next().await.unwrap();
+            assert_eq!((frame.bus, frame.id), (1, Identifier::Standard(0x123)));
+            let frame = stream.next().await.unwrap();
+            assert_eq!((frame.bus, frame.id), (1, Identifier::Standard(0x7e8)));
+        };
+
+        // Biased, so the gateway subscribes before the first frame is sent
+        tokio::select! {
+            biased;
+            result = b.gateway(0, 1, transform) => panic!("Gateway stopped: {:?}", result),
+            _ = forward => {}
+        }
+    }
+
+    #[tokio::test]
+    async fn gateway_ignores_loopback() {
+        let (a, b) = crate::can::VirtualCanAdapter::pair_async();
+        let stream = a.recv_filter(|frame| !frame.loopback);
+        tokio::pin!(stream);
+
+        // Forward on the same bus with a new ID, so a looped back frame would be forwarded again as 0x125
+        let transform = |frame: &Frame| {
+            let id = u32::from(frame.id) + 1;
+            Some(Frame::new(frame.bus, id.into(), &frame.data).unwrap())
+        };
+        let forward = async {
+            a.send(&Frame::new(0, 0x123.into(), &[0xaa]).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(stream.next().await.unwrap().id, Identifier::Standard(0x124));
+
+            a.send(&Frame::new(0, 0x200.into(), &[0xaa]).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(stream.next().await.unwrap().id, Identifier::Standard(0x201));
+        };
+
+        // Biased, so the gateway subscribes before the first frame is sent
+        tokio::select! {
+            biased;
+            result = b.gateway(0, 0, transform) => panic!("Gateway stopped: {:?}", result),
+            _ = forward => {}
+        }
+    }
+
+    #[tokio::test]
+    async fn send_batch() {
+        let (a, b) = crate::can::VirtualCanAdapter::pair_async();
+        let stream = b.recv();
+        tokio::pin!(stream);
+
+        let frames: Vec<Frame> = (0..100u8)
+            .map(|i| Frame::new(0, 0x123.into(), &[i]).unwrap())
+            .collect();
+        a.send_batch(&frames).await.unwrap();
+
+        for frame in frames {
+            assert_eq!(stream.next().await.unwrap(), frame);
+        }
+    }
+
+    #[tokio::test]
+    async fn recv_bus() {
+        let (a, b) = crate::can::VirtualCanAdapter::pair_async();
+        let stream = b.recv_bus(1);
+        tokio::pin!(stream);
+
+        for bus in [0, 1, 2] {
+            let frame = Frame::new(bus, 0x123.into(), &[bus]).unwrap();
+            a.send(&frame).await.unwrap();
+        }
+
+        let frame = stream.next().await.unwrap();
+        assert_eq!(frame.bus, 1);
+        assert_eq!(frame.data, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn recv_timeout() {
+        let (a, b) = crate::can::VirtualCanAdapter::pair_async();
+        let timeout = std::time::Duration::from_millis(50);
+        let stream = b.recv_timeout(timeout);
+        tokio::pin!(stream);
+
+        // Frame received before the deadline
+        let frame = Frame::new(0, 0x123.into(), &[0xaa]).unwrap();
+        tokio::time::sleep(timeout / 2).await;
+        a.send(&frame).await.unwrap();
+        assert_eq!(stream.next().await.unwrap(), frame);
+
+        // Stream ends once the bus is quiet for the timeout
+        let start = tokio::time::Instant::now();
+        assert_eq!(stream.next().await, None);
+        assert!(start.elapsed() >= timeout / 2);
+
+        // And stays ended, even when new frames arrive
+        a.send(&frame).await.unwrap();
+        assert_eq!(stream.next().await, None);
+    }
+}
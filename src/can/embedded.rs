@@ -0,0 +1,197 @@
+//! Implementations of the [embedded-can](https://crates.io/crates/embedded-can) traits, enabled using the `embedded-can` feature.
+use crate::can::{CanAdapter, Frame, Identifier, DLC_TO_LEN};
+use embedded_can::{ExtendedId, Id, StandardId};
+use std::collections::VecDeque;
+
+const DEFAULT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(1);
+
+impl From<Id> for Identifier {
+    fn from(id: Id) -> Identifier {
+        match id {
+            Id::Standard(id) => Identifier::Standard(id.as_raw() as u32),
+            Id::Extended(id) => Identifier::Extended(id.as_raw()),
+        }
+    }
+}
+
+/// Bits outside of the 11 or 29 bit range are masked off
+impl From<Identifier> for Id {
+    fn from(id: Identifier) -> Id {
+        match id {
+            Identifier::Standard(id) => Id::Standard(StandardId::new((id & 0x7ff) as u16).unwrap()),
+            Identifier::Extended(id) => Id::Extended(ExtendedId::new(id & 0x1fff_ffff).unwrap()),
+        }
+    }
+}
+
+/// Frames created through this trait are sent on bus 0
+impl embedded_can::Frame for Frame {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        Frame::new(0, id.into().into(), data).ok()
+    }
+
+    fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
+        let mut frame = Frame::builder(id.into().into()).rtr(true).build().ok()?;
+        if dlc > 0 {
+            frame.dlc = Some(u8::try_from(dlc).ok().filter(|&dlc| dlc <= 15)?);
+        }
+        Some(frame)
+    }
+
+    fn is_extended(&self) -> bool {
+        self.id.is_extended()
+    }
+
+    fn is_remote_frame(&self) -> bool {
+        self.rtr
+    }
+
+    fn id(&self) -> Id {
+        self.id.into()
+    }
+
+    fn dlc(&self) -> usize {
+        match self.dlc {
+            Some(dlc) => dlc as usize,
+            None => DLC_TO_LEN
+                .iter()
+                .position(|&len| len >= self.data.len())
+                .unwrap_or(DLC_TO_LEN.len() - 1),
+        }
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl embedded_can::Error for crate::Error {
+    fn kind(&self) -> embedded_can::ErrorKind {
+        embedded_can::ErrorKind::Other
+    }
+}
+
+/// Wraps a blocking [`CanAdapter`] to implement the [`embedded_can::nb::Can`] and [`embedded_can::blocking::Can`] traits. Frames looped back by the adapter after transmitting are dropped.
+pub struct BlockingCan<T: CanAdapter> {
+    adapter: T,
+    rx_queue: VecDeque<Frame>,
+    poll_interval: std::time::Duration,
+}
+
+impl<T: CanAdapter> BlockingCan<T> {
+    pub fn new(adapter: T) -> Self {
+        Self {
+            adapter,
+            rx_queue: VecDeque::new(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    /// Consumes the wrapper, returning the underlying adapter
+    pub fn into_inner(self) -> T {
+        self.adapter
+    }
+}
+
+impl<T: CanAdapter> embedded_can::nb::Can for BlockingCan<T> {
+    type Frame = Frame;
+    type Error = crate::Error;
+
+    /// Returns [`nb::Error::WouldBlock`] if the adapter has no space to queue the frame
+    fn transmit(&mut self, frame: &Frame) -> nb::Result<Option<Frame>, crate::Error> {
+        let mut queue = VecDeque::from([frame.clone()]);
+        self.adapter.send(&mut queue)?;
+
+        match queue.is_empty() {
+            true => Ok(None),
+            false => Err(nb::Error::WouldBlock),
+        }
+    }
+
+    /// Returns [`nb::Error::WouldBlock`] if no frame is available
+    fn receive(&mut self) -> nb::Result<Frame, crate::Error> {
+        if self.rx_queue.is_empty() {
+            let frames = self.adapter.recv()?;
+            self.rx_queue
+                .extend(frames.into_iter().filter(|frame| !frame.loopback));
+        }
+
+        self.rx_queue.pop_front().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+impl<T: CanAdapter> embedded_can::blocking::Can for BlockingCan<T> {
+    type Frame = Frame;
+    type Error = crate::Error;
+
+    fn transmit(&mut self, frame: &Frame) -> crate::Result<()> {
+        loop {
+            match embedded_can::nb::Can::transmit(self, frame) {
+                Ok(_) => return Ok(()),
+                Err(nb::Error::WouldBlock) => std::thread::sleep(self.poll_interval),
+                Err(nb::Error::Other(e)) => return Err(e),
+            }
+        }
+    }
+
+    fn receive(&mut self) -> crate::Result<Frame> {
+        loop {
+            match embedded_can::nb::Can::receive(self) {
+                Ok(frame) => return Ok(frame),
+                Err(nb::Error::WouldBlock) => self.adapter.poll(self.poll_interval)?,
+                Err(nb::Error::Other(e)) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::can::VirtualCanAdapter;
+    use embedded_can::blocking::Can as _;
+    use embedded_can::Frame as _;
+
+    #[test]
+    fn frame() {
+        let id = StandardId::new(0x123).unwrap();
+        let frame = <Frame as embedded_can::Frame>::new(id, &[0xaa, 0xbb]).unwrap();
+        assert_eq!(frame.id, Identifier::Standard(0x123));
+        assert_eq!(embedded_can::Frame::dlc(&frame), 2);
+        assert!(!frame.is_extended());
+
+        let id = ExtendedId::new(0x18db33f1).unwrap();
+        let frame = Frame::new_remote(id, 4).unwrap();
+        assert!(frame.is_remote_frame());
+        assert_eq!(embedded_can::Frame::id(&frame), Id::Extended(id));
+        assert_eq!(embedded_can::Frame::dlc(&frame), 4);
+
+        let frame = Frame::builder(Identifier::Standard(0x1))
+            .data(&[0; 12])
+            .build()
+            .unwrap();
+        assert_eq!(embedded_can::Frame::dlc(&frame), 9);
+    }
+
+    #[test]
+    fn blocking_can() {
+        let (a, b) = VirtualCanAdapter::pair();
+        let (mut a, mut b) = (BlockingCan::new(a), BlockingCan::new(b));
+
+        assert!(matches!(
+            embedded_can::nb::Can::receive(&mut b),
+            Err(nb::Error::WouldBlock)
+        ));
+
+        let frame = Frame::new(0, Identifier::Standard(0x123), &[0x01, 0x02]).unwrap();
+        a.transmit(&frame).unwrap();
+
+        assert_eq!(b.receive().unwrap(), frame);
+
+        // The loopback of the transmitted frame is dropped
+        assert!(matches!(
+            embedded_can::nb::Can::receive(&mut a),
+            Err(nb::Error::WouldBlock)
+        ));
+    }
+}
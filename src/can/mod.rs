@@ -2,12 +2,17 @@
 
 pub mod adapter;
 pub mod async_can;
+pub mod virtual_can;
+
+#[cfg(feature = "embedded-can")]
+pub mod embedded;
 
 use std::collections::VecDeque;
 use std::fmt;
 
-pub use adapter::get_adapter;
+pub use adapter::{get_adapter, get_adapter_with_config, AdapterConfig};
 pub use async_can::AsyncCanAdapter;
+pub use virtual_can::VirtualCanAdapter;
 
 pub static DLC_TO_LEN: &[usize] = &[0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
 
@@ -67,25 +72,98 @@ pub struct Frame {
     pub bus: u8,
     /// Arbitration ID
     pub id: Identifier,
-    /// Frame Data
+    /// Frame Data. Serialized as a hex string.
+    #[cfg_attr(feature = "serde", serde(with = "hex_data"))]
     pub data: Vec<u8>,
     /// Wheter the frame was sent out by the adapter
     pub loopback: bool,
-    /// CAN-FD Frame
+    /// CAN-FD Frame. Independent of the data length, so a frame with up to 8 bytes can also be sent as a CAN-FD frame.
     pub fd: bool,
-    // TODO: Add timestamp, rtr, dlc
+    /// Bit Rate Switch. Transmit the data phase of a CAN-FD frame at the data bitrate. Only valid for CAN-FD frames. Disabled by default, use [`FrameBuilder::brs`] or set it after creating the frame to opt in.
+    pub brs: bool,
+    /// Receive timestamp relative to opening the adapter, also set on the loopback frames of transmitted frames. The clock depends on the adapter: SocketCAN uses the kernel software receive timestamp, converted to a monotonic clock (an adjustment of the system clock only affects frames received while it happens), and Vector uses the hardware timestamp. `None` for frames that are not sent yet, and for adapters that don't support timestamping.
+    pub timestamp: Option<std::time::Duration>,
+    /// Remote Transmission Request. Remote frames don't carry any data, only classic CAN supports them.
+    pub rtr: bool,
+    /// Raw DLC override for classic CAN, e.g. to send a DLC of 9-15 with 8 data bytes, or to request a length in a remote frame. `None` derives the DLC from the data length.
+    pub dlc: Option<u8>,
 }
 impl Unpin for Frame {}
 
 impl Frame {
+    /// Create a new frame. The `fd` flag is set if the data doesn't fit in a classic CAN frame. To send a short payload as a CAN-FD frame, use [`Frame::builder`] or set `fd` to `true` after creating the frame. Bit rate switching is never enabled, see [`FrameBuilder::brs`].
     pub fn new(bus: u8, id: Identifier, data: &[u8]) -> Result<Frame, crate::error::Error> {
+        Frame::builder(id).bus(bus).data(data).build()
+    }
+
+    /// Create a [`FrameBuilder`] to construct a frame with explicit flags.
+    pub fn builder(id: Identifier) -> FrameBuilder {
+        FrameBuilder::new(id)
+    }
+}
+
+/// Builder for a [`Frame`]. Defaults to bus 0, no data, and a classic CAN frame unless the data doesn't fit.
+#[derive(Debug, Clone)]
+pub struct FrameBuilder {
+    bus: u8,
+    id: Identifier,
+    data: Vec<u8>,
+    fd: Option<bool>,
+    brs: bool,
+    rtr: bool,
+}
+
+impl FrameBuilder {
+    fn new(id: Identifier) -> Self {
+        Self {
+            bus: 0,
+            id,
+            data: vec![],
+            fd: None,
+            brs: false,
+            rtr: false,
+        }
+    }
+
+    /// The bus index for adapters supporting multiple CAN busses
+    pub fn bus(mut self, bus: u8) -> Self {
+        self.bus = bus;
+        self
+    }
+
+    /// Frame data. Needs to be a valid CAN-FD length (0-8, 12, 16, 20, 24, 32, 48 or 64 bytes)
+    pub fn data(mut self, data: &[u8]) -> Self {
+        self.data = data.to_vec();
+        self
+    }
+
+    /// Send as CAN-FD frame. If not set, the frame is only sent as CAN-FD if the data doesn't fit in a classic CAN frame.
+    pub fn fd(mut self, fd: bool) -> Self {
+        self.fd = Some(fd);
+        self
+    }
+
+    /// Bit Rate Switch. Requires a CAN-FD frame, so make sure to also set [`FrameBuilder::fd`] for short payloads.
+    pub fn brs(mut self, brs: bool) -> Self {
+        self.brs = brs;
+        self
+    }
+
+    /// Remote Transmission Request. Remote frames can't carry data, and are not supported on CAN-FD.
+    pub fn rtr(mut self, rtr: bool) -> Self {
+        self.rtr = rtr;
+        self
+    }
+
+    /// Validates the frame. Returns [`crate::error::Error::MalformedFrame`] if the data length, ID or flags are invalid.
+    pub fn build(self) -> Result<Frame, crate::error::Error> {
         // Check if the data length is valid
-        if !DLC_TO_LEN.contains(&data.len()) {
+        if !DLC_TO_LEN.contains(&self.data.len()) {
             return Err(crate::error::Error::MalformedFrame);
         }
 
         // Check if the ID makes sense
-        match id {
+        match self.id {
             Identifier::Standard(id) if id > 0x7ff => {
                 return Err(crate::error::Error::MalformedFrame)
             }
@@ -95,12 +173,24 @@ impl Frame {
             _ => {}
         };
 
+        let fd = self.fd.unwrap_or(self.data.len() > 8);
+
+        // Check if the flags match the payload
+        if (!fd && (self.data.len() > 8 || self.brs)) || (self.rtr && (fd || !self.data.is_empty()))
+        {
+            return Err(crate::error::Error::MalformedFrame);
+        }
+
         Ok(Frame {
-            bus,
-            id,
-            data: data.to_vec(),
+            bus: self.bus,
+            id: self.id,
+            data: self.data,
             loopback: false,
-            fd: data.len() > 8,
+            fd,
+            brs: self.brs,
+            timestamp: None,
+            rtr: self.rtr,
+            dlc: None,
         })
     }
 }
@@ -113,6 +203,10 @@ impl fmt::Display for Frame {
             .field("data", &hex::encode(&self.data))
             .field("loopback", &self.loopback)
             .field("fd", &self.fd)
+            .field("brs", &self.brs)
+            .field("timestamp", &self.timestamp)
+            .field("rtr", &self.rtr)
+            .field("dlc", &self.dlc)
             .finish()
     }
 }
@@ -127,6 +221,37 @@ impl fmt::Debug for Frame {
 pub trait CanAdapter {
     fn send(&mut self, frames: &mut VecDeque<crate::can::Frame>) -> crate::Result<()>;
     fn recv(&mut self) -> crate::Result<Vec<Frame>>;
+
+    /// Wait up to `timeout` for frames to become available. The default implementation sleeps for the full timeout, adapters that can wait for incoming frames should override this to reduce receive latency.
+    fn poll(&mut self, timeout: std::time::Duration) -> crate::Result<()> {
+        std::thread::sleep(timeout);
+        Ok(())
+    }
+
+    /// Check if the adapter can send the frame, e.g. adapters without support for remote frames return [`crate::Error::NotSupported`]. [`AsyncCanAdapter`] calls this before handing frames to [`CanAdapter::send`], and returns the error to the sender of the frame.
+    fn check_frame(&self, _frame: &Frame) -> crate::Result<()> {
+        Ok(())
+    }
+
+    /// Install (id, mask) receive filters in the adapter hardware or driver. Adapters that don't support this return [`crate::Error::NotSupported`], in which case [`AsyncCanAdapter`] filters in software.
+    fn set_hardware_filters(&mut self, _filters: &[(Identifier, u32)]) -> crate::Result<()> {
+        Err(crate::Error::NotSupported)
+    }
+}
+
+/// Serializes frame data as a hex string, matching the [`fmt::Display`] implementation of [`Frame`]
+#[cfg(feature = "serde")]
+mod hex_data {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let data = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        hex::decode(data.as_ref()).map_err(serde::de::Error::custom)
+    }
 }
 
 #[cfg(test)]
@@ -135,19 +260,83 @@ mod tests {
 
     #[test]
     fn id_compare() {
-        assert_eq!(
-            true,
-            Identifier::Standard(0x123) < Identifier::Standard(0x124)
-        );
-        assert_eq!(
-            true,
-            Identifier::Standard(0x7ff) > Identifier::Standard(0x100)
-        );
+        assert!(Identifier::Standard(0x123) < Identifier::Standard(0x124));
+        assert!(Identifier::Standard(0x7ff) > Identifier::Standard(0x100));
 
         // Extended IDs always have lower priority than standard IDs
-        assert_eq!(
-            true,
-            Identifier::Extended(0x1) > Identifier::Standard(0x100)
-        );
+        assert!(Identifier::Extended(0x1) > Identifier::Standard(0x100));
+    }
+
+    #[test]
+    fn frame_builder() {
+        let frame = Frame::builder(0x123.into())
+            .bus(1)
+            .data(&[0xaa; 8])
+            .fd(true)
+            .build()
+            .unwrap();
+        assert_eq!(frame.bus, 1);
+        assert!(frame.fd);
+
+        // FD flag is inferred from the length if not set
+        let frame = Frame::builder(0x123.into()).data(&[0xaa; 12]).build();
+        assert!(frame.unwrap().fd);
+
+        let frame = Frame::builder(0x123.into())
+            .data(&[0xaa; 12])
+            .fd(false)
+            .build();
+        assert_eq!(frame, Err(crate::error::Error::MalformedFrame));
+
+        let frame = Frame::builder(0x123.into()).data(&[0xaa; 9]).build();
+        assert_eq!(frame, Err(crate::error::Error::MalformedFrame));
+
+        // Bit rate switch requires a CAN-FD frame
+        let frame = Frame::builder(0x123.into())
+            .data(&[0xaa; 8])
+            .fd(true)
+            .brs(true)
+            .build();
+        assert!(frame.unwrap().brs);
+
+        let frame = Frame::builder(0x123.into())
+            .data(&[0xaa; 8])
+            .brs(true)
+            .build();
+        assert_eq!(frame, Err(crate::error::Error::MalformedFrame));
+
+        // Bit rate switching is opt-in
+        let frame = Frame::new(0, 0x123.into(), &[0xaa; 12]).unwrap();
+        assert!(!frame.brs);
+        let frame = Frame::builder(0x123.into()).fd(true).brs(true).build();
+        assert!(frame.unwrap().brs);
+
+        // Remote frames can't carry data
+        let frame = Frame::builder(0x123.into()).rtr(true).build();
+        assert!(frame.unwrap().rtr);
+
+        let frame = Frame::builder(0x123.into()).data(&[0xaa]).rtr(true).build();
+        assert_eq!(frame, Err(crate::error::Error::MalformedFrame));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let frame = Frame::new(
+            1,
+            Identifier::Extended(0x18db33f1),
+            &[0xde, 0xad, 0xbe, 0xef],
+        )
+        .unwrap();
+
+        let json = serde_json::to_value(&frame).unwrap();
+        assert_eq!(json["data"], "deadbeef");
+        assert_eq!(json["id"], serde_json::json!({ "Extended": 0x18db33f1 }));
+
+        let decoded: Frame = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded, frame);
+
+        let json = r#"{"bus":0,"id":{"Standard":291},"data":"0g","loopback":false,"fd":false,"brs":false,"timestamp":null,"rtr":false,"dlc":null}"#;
+        assert!(serde_json::from_str::<Frame>(json).is_err());
     }
 }
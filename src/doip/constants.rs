@@ -0,0 +1,37 @@
+use strum_macros::FromRepr;
+
+/// Protocol version as defined in ISO 13400-2:2012
+pub static PROTOCOL_VERSION: u8 = 0x02;
+pub static HEADER_SIZE: usize = 8;
+/// Upper limit on the payload length of a received message, protects against allocating huge buffers on a corrupt header.
+pub static MAX_PAYLOAD_LENGTH: usize = 0x10_0000;
+pub static TCP_DATA_PORT: u16 = 13400;
+/// UDP port used for vehicle identification requests and announcements
+pub static UDP_DISCOVERY_PORT: u16 = 13400;
+
+/// Default activation type for routing activation
+pub static ACTIVATION_TYPE_DEFAULT: u8 = 0x00;
+/// Routing activation response code for a successful activation
+pub static ROUTING_ACTIVATION_SUCCESS: u8 = 0x10;
+
+#[derive(Debug, PartialEq, Copy, Clone, FromRepr)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u16)]
+pub enum PayloadType {
+    GenericNegativeAcknowledge = 0x0000,
+    VehicleIdentificationRequest = 0x0001,
+    VehicleIdentificationRequestWithEID = 0x0002,
+    VehicleIdentificationRequestWithVIN = 0x0003,
+    VehicleAnnouncement = 0x0004,
+    RoutingActivationRequest = 0x0005,
+    RoutingActivationResponse = 0x0006,
+    AliveCheckRequest = 0x0007,
+    AliveCheckResponse = 0x0008,
+    EntityStatusRequest = 0x4001,
+    EntityStatusResponse = 0x4002,
+    DiagnosticPowerModeRequest = 0x4003,
+    DiagnosticPowerModeResponse = 0x4004,
+    DiagnosticMessage = 0x8001,
+    DiagnosticMessagePositiveAcknowledge = 0x8002,
+    DiagnosticMessageNegativeAcknowledge = 0x8003,
+}
@@ -0,0 +1,17 @@
+//! Error types for the DoIP protocol.
+
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum Error {
+    #[error("Invalid Header")]
+    InvalidHeader,
+    #[error("Invalid Payload Length")]
+    InvalidPayloadLength,
+    #[error("Generic Negative Acknowledge: {0}")]
+    GenericNegativeAcknowledge(u8),
+    #[error("Diagnostic Message Negative Acknowledge: {0}")]
+    DiagnosticMessageNegativeAcknowledge(u8),
+    #[error("Routing Activation Failed: {0}")]
+    RoutingActivationFailed(u8),
+}
@@ -0,0 +1,618 @@
+//! Diagnostics over Internet Protocol (DoIP) transport, implements ISO 13400-2
+//! ## Example:
+//! ```rust
+//! async fn doip_example() {
+//!    let vehicles = automotive::doip::DoIPAdapter::discover("255.255.255.255:13400", std::time::Duration::from_secs(1)).await.unwrap();
+//!    println!("Found vehicles: {:?}", vehicles);
+//!
+//!    let config = automotive::doip::DoIPConfig::new(0x0e00, 0x1001);
+//!    let doip = automotive::doip::DoIPAdapter::connect("192.168.0.10:13400", config).await.unwrap();
+//!    let uds = automotive::uds::UDSClient::new(&doip);
+//!
+//!    uds.tester_present().await.unwrap();
+//! }
+//! ```
+
+mod constants;
+mod error;
+
+pub use constants::{PayloadType, PROTOCOL_VERSION, TCP_DATA_PORT, UDP_DISCOVERY_PORT};
+pub use error::Error;
+
+use crate::transport::DiagnosticTransport;
+use crate::{Result, Stream};
+use async_stream::stream;
+use constants::{
+    ACTIVATION_TYPE_DEFAULT, HEADER_SIZE, MAX_PAYLOAD_LENGTH, ROUTING_ACTIVATION_SUCCESS,
+};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, Mutex};
+use tracing::debug;
+
+const DEFAULT_TIMEOUT_MS: u64 = 2000;
+const DOIP_RX_BUFFER_SIZE: usize = 64;
+
+/// Configuration passed to the DoIPAdapter.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DoIPConfig {
+    /// Logical address of the tester (e.g. 0x0e00)
+    pub source_address: u16,
+    /// Logical address of the ECU
+    pub target_address: u16,
+    /// Activation type used for routing activation
+    pub activation_type: u8,
+    /// Max timeout for receiving a message
+    pub timeout: std::time::Duration,
+}
+
+impl DoIPConfig {
+    pub fn new(source_address: u16, target_address: u16) -> Self {
+        Self {
+            source_address,
+            target_address,
+            activation_type: ACTIVATION_TYPE_DEFAULT,
+            timeout: std::time::Duration::from_millis(DEFAULT_TIMEOUT_MS),
+        }
+    }
+}
+
+/// A single DoIP message, consisting of the payload type and payload
+#[derive(Debug, Clone, PartialEq)]
+struct Message {
+    payload_type: u16,
+    payload: Vec<u8>,
+}
+
+impl Message {
+    fn new(payload_type: PayloadType, payload: Vec<u8>) -> Self {
+        Self {
+            payload_type: payload_type as u16,
+            payload,
+        }
+    }
+
+    fn is(&self, payload_type: PayloadType) -> bool {
+        self.payload_type == payload_type as u16
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_SIZE + self.payload.len());
+        buf.push(PROTOCOL_VERSION);
+        buf.push(!PROTOCOL_VERSION);
+        buf.extend(self.payload_type.to_be_bytes());
+        buf.extend((self.payload.len() as u32).to_be_bytes());
+        buf.extend(&self.payload);
+        buf
+    }
+
+    /// Validates the header, and returns the payload type and payload length
+    fn decode_header(header: &[u8]) -> Result<(u16, usize)> {
+        // Inverse protocol version is used to validate the header
+        if header.len() < HEADER_SIZE || header[0] != !header[1] {
+            return Err(Error::InvalidHeader.into());
+        }
+
+        let payload_type = u16::from_be_bytes([header[2], header[3]]);
+        let len = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        if len > MAX_PAYLOAD_LENGTH {
+            return Err(Error::InvalidPayloadLength.into());
+        }
+
+        Ok((payload_type, len))
+    }
+
+    /// Decodes a message from a single UDP datagram
+    fn decode(buf: &[u8]) -> Result<Message> {
+        let (payload_type, len) = Message::decode_header(buf)?;
+        let payload = buf
+            .get(HEADER_SIZE..HEADER_SIZE + len)
+            .ok_or(Error::InvalidPayloadLength)?;
+
+        Ok(Message {
+            payload_type,
+            payload: payload.to_vec(),
+        })
+    }
+
+    async fn read(reader: &mut OwnedReadHalf) -> Result<Message> {
+        let mut header = [0u8; HEADER_SIZE];
+        reader
+            .read_exact(&mut header)
+            .await
+            .map_err(|_| crate::Error::Disconnected)?;
+
+        let (payload_type, len) = Message::decode_header(&header)?;
+
+        let mut payload = vec![0; len];
+        reader
+            .read_exact(&mut payload)
+            .await
+            .map_err(|_| crate::Error::Disconnected)?;
+
+        Ok(Message {
+            payload_type,
+            payload,
+        })
+    }
+}
+
+/// Vehicle announcement, sent by a DoIP entity in response to a vehicle identification request
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VehicleAnnouncement {
+    /// Address of the DoIP entity, connect to it on [`TCP_DATA_PORT`]
+    pub addr: std::net::SocketAddr,
+    pub vin: String,
+    /// Logical address of the DoIP entity
+    pub logical_address: u16,
+    /// Entity Identification, usually the MAC address
+    pub eid: [u8; 6],
+    /// Group Identification
+    pub gid: [u8; 6],
+    /// Further action required, e.g. 0x10 if central security is needed before routing activation
+    pub further_action: u8,
+}
+
+impl VehicleAnnouncement {
+    fn decode(addr: std::net::SocketAddr, payload: &[u8]) -> Result<Self> {
+        // VIN (17), logical address (2), EID (6), GID (6), further action (1), optional sync status (1)
+        if payload.len() < 32 {
+            return Err(Error::InvalidPayloadLength.into());
+        }
+
+        Ok(Self {
+            addr,
+            vin: String::from_utf8_lossy(&payload[0..17]).into_owned(),
+            logical_address: u16::from_be_bytes([payload[17], payload[18]]),
+            eid: payload[19..25].try_into().unwrap(),
+            gid: payload[25..31].try_into().unwrap(),
+            further_action: payload[31],
+        })
+    }
+}
+
+/// Background task that receives all messages from the DoIP entity and answers alive check requests
+async fn process(
+    mut reader: OwnedReadHalf,
+    writer: Arc<Mutex<OwnedWriteHalf>>,
+    rx_sender: broadcast::Sender<Message>,
+    source_address: u16,
+) {
+    loop {
+        let msg = match Message::read(&mut reader).await {
+            Ok(msg) => msg,
+            Err(e) => {
+                debug!("DoIP connection closed: {}", e);
+                return;
+            }
+        };
+
+        debug!("RX {:04x} {}", msg.payload_type, hex::encode(&msg.payload));
+
+        if msg.is(PayloadType::AliveCheckRequest) {
+            let response = Message::new(
+                PayloadType::AliveCheckResponse,
+                source_address.to_be_bytes().to_vec(),
+            );
+            if writer
+                .lock()
+                .await
+                .write_all(&response.encode())
+                .await
+                .is_err()
+            {
+                return;
+            }
+            continue;
+        }
+
+        // Error only means there are no receivers
+        rx_sender.send(msg).ok();
+    }
+}
+
+/// DoIP Adapter. Connects to a DoIP entity over TCP and exchanges diagnostic messages with a single ECU.
+pub struct DoIPAdapter {
+    config: DoIPConfig,
+    writer: Arc<Mutex<OwnedWriteHalf>>,
+    rx_receiver: broadcast::Receiver<Message>,
+    processing_handle: tokio::task::JoinHandle<()>,
+}
+
+impl DoIPAdapter {
+    /// Connects to the DoIP entity at `addr` (port 13400) and performs routing activation.
+    pub async fn connect(addr: impl ToSocketAddrs, config: DoIPConfig) -> Result<Self> {
+        let stream = TcpStream::connect(addr)
+            .await
+            .map_err(|_| crate::Error::NotFound)?;
+        stream.set_nodelay(true).ok();
+
+        let (reader, writer) = stream.into_split();
+        let writer = Arc::new(Mutex::new(writer));
+        let (rx_sender, rx_receiver) = broadcast::channel(DOIP_RX_BUFFER_SIZE);
+
+        let processing_handle = tokio::spawn(process(
+            reader,
+            writer.clone(),
+            rx_sender,
+            config.source_address,
+        ));
+
+        let adapter = Self {
+            config,
+            writer,
+            rx_receiver,
+            processing_handle,
+        };
+        adapter.routing_activation().await?;
+
+        Ok(adapter)
+    }
+
+    /// Sends a vehicle identification request over UDP to `addr`, and collects the vehicle announcements received until `timeout`. Use the broadcast address (e.g. `255.255.255.255:13400`) to find all DoIP entities on the network.
+    pub async fn discover(
+        addr: impl ToSocketAddrs,
+        timeout: std::time::Duration,
+    ) -> Result<Vec<VehicleAnnouncement>> {
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .await
+            .map_err(|_| crate::Error::NotFound)?;
+        socket.set_broadcast(true).ok();
+
+        let request = Message::new(PayloadType::VehicleIdentificationRequest, vec![]);
+        socket
+            .send_to(&request.encode(), addr)
+            .await
+            .map_err(|_| crate::Error::NotFound)?;
+
+        let mut vehicles = vec![];
+        let mut buf = [0u8; 512];
+        let deadline = tokio::time::Instant::now() + timeout;
+
+        while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await
+        {
+            let (len, addr) = received.map_err(|_| crate::Error::Disconnected)?;
+
+            match Message::decode(&buf[..len]) {
+                Ok(msg) if msg.is(PayloadType::VehicleAnnouncement) => {
+                    match VehicleAnnouncement::decode(addr, &msg.payload) {
+                        Ok(vehicle) => vehicles.push(vehicle),
+                        Err(e) => {
+                            debug!("Ignoring invalid vehicle announcement from {}: {}", addr, e)
+                        }
+                    }
+                }
+                Ok(msg) => debug!(
+                    "Ignoring DoIP message {:04x} from {}",
+                    msg.payload_type, addr
+                ),
+                Err(e) => debug!("Ignoring invalid DoIP message from {}: {}", addr, e),
+            }
+        }
+
+        Ok(vehicles)
+    }
+
+    async fn write(&self, msg: &Message) -> Result<()> {
+        debug!("TX {:04x} {}", msg.payload_type, hex::encode(&msg.payload));
+
+        self.writer
+            .lock()
+            .await
+            .write_all(&msg.encode())
+            .await
+            .map_err(|_| crate::Error::Disconnected)
+    }
+
+    /// Waits for the first message accepted by `filter`. Generic negative acknowledges are returned as error.
+    async fn recv_message(
+        &self,
+        rx: &mut broadcast::Receiver<Message>,
+        filter: impl Fn(&Message) -> bool,
+    ) -> Result<Message> {
+        let timeout = self.config.timeout;
+        tokio::time::timeout(timeout, async {
+            loop {
+                let msg = match rx.recv().await {
+                    Ok(msg) => msg,
+                    Err(RecvError::Closed) => return Err(crate::Error::Disconnected),
+                    Err(RecvError::Lagged(_)) => continue,
+                };
+
+                if msg.is(PayloadType::GenericNegativeAcknowledge) {
+                    let code = msg.payload.first().copied().unwrap_or_default();
+                    return Err(Error::GenericNegativeAcknowledge(code).into());
+                }
+
+                if filter(&msg) {
+                    return Ok(msg);
+                }
+            }
+        })
+        .await
+        .map_err(|_| crate::Error::Timeout)?
+    }
+
+    /// Request routing activation. This is done automatically when connecting, and is required before diagnostic messages are accepted.
+    pub async fn routing_activation(&self) -> Result<()> {
+        let mut payload = self.config.source_address.to_be_bytes().to_vec();
+        payload.push(self.config.activation_type);
+        payload.extend([0x00; 4]); // Reserved by ISO 13400
+
+        let mut rx = self.rx_receiver.resubscribe();
+        self.write(&Message::new(
+            PayloadType::RoutingActivationRequest,
+            payload,
+        ))
+        .await?;
+
+        let resp = self
+            .recv_message(&mut rx, |msg| {
+                msg.is(PayloadType::RoutingActivationResponse)
+            })
+            .await?;
+
+        // Tester address (2), entity address (2), response code (1), reserved (4)
+        if resp.payload.len() < 9 {
+            return Err(Error::InvalidPayloadLength.into());
+        }
+
+        match resp.payload[4] {
+            code if code == ROUTING_ACTIVATION_SUCCESS => Ok(()),
+            code => Err(Error::RoutingActivationFailed(code).into()),
+        }
+    }
+
+    /// Send a diagnostic message to the ECU. Resolves once the DoIP entity acknowledged the message.
+    pub async fn send(&self, data: &[u8]) -> Result<()> {
+        let mut payload = self.config.source_address.to_be_bytes().to_vec();
+        payload.extend(self.config.target_address.to_be_bytes());
+        payload.extend(data);
+
+        let mut rx = self.rx_receiver.resubscribe();
+        self.write(&Message::new(PayloadType::DiagnosticMessage, payload))
+            .await?;
+
+        let ack = self
+            .recv_message(&mut rx, |msg| {
+                (msg.is(PayloadType::DiagnosticMessagePositiveAcknowledge)
+                    || msg.is(PayloadType::DiagnosticMessageNegativeAcknowledge))
+                    && self.is_from_target(&msg.payload)
+            })
+            .await?;
+
+        // Source address (2), target address (2), ack code (1)
+        if ack.payload.len() < 5 {
+            return Err(Error::InvalidPayloadLength.into());
+        }
+
+        if ack.is(PayloadType::DiagnosticMessageNegativeAcknowledge) {
+            return Err(Error::DiagnosticMessageNegativeAcknowledge(ack.payload[4]).into());
+        }
+
+        Ok(())
+    }
+
+    /// Checks the source and target address of a diagnostic message (acknowledge) payload
+    fn is_from_target(&self, payload: &[u8]) -> bool {
+        payload.len() >= 4
+            && payload[0..2] == self.config.target_address.to_be_bytes()
+            && payload[2..4] == self.config.source_address.to_be_bytes()
+    }
+
+    /// Stream of diagnostic messages from the ECU. Returns Timeout if no message is received within the timeout. Returns Disconnected once the connection is closed, after which the stream ends.
+    pub fn recv(&self) -> impl Stream<Item = Result<Vec<u8>>> + Unpin + Send + '_ {
+        let mut rx = self.rx_receiver.resubscribe();
+        let timeout = self.config.timeout;
+
+        Box::pin(stream! {
+            loop {
+                match tokio::time::timeout(timeout, rx.recv()).await {
+                    Ok(Ok(msg)) => {
+                        if msg.is(PayloadType::DiagnosticMessage) && self.is_from_target(&msg.payload) {
+                            yield Ok(msg.payload[4..].to_vec());
+                        }
+                    }
+                    Ok(Err(RecvError::Closed)) => {
+                        // Connection closed, end the stream
+                        yield Err(crate::Error::Disconnected);
+                        break;
+                    }
+                    Ok(Err(RecvError::Lagged(n))) => {
+                        tracing::warn!("Receive too slow, dropping {} message(s).", n)
+                    }
+                    Err(_) => yield Err(crate::Error::Timeout),
+                }
+            }
+        })
+    }
+}
+
+impl DiagnosticTransport for DoIPAdapter {
+    async fn send(&self, data: &[u8]) -> Result<()> {
+        DoIPAdapter::send(self, data).await
+    }
+
+    fn recv(&self) -> impl Stream<Item = Result<Vec<u8>>> + Unpin + Send + '_ {
+        DoIPAdapter::recv(self)
+    }
+}
+
+impl Drop for DoIPAdapter {
+    fn drop(&mut self) {
+        self.processing_handle.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StreamExt;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    const TESTER: u16 = 0x0e00;
+    const ECU: u16 = 0x1001;
+
+    /// Simulated DoIP entity. Activates routing and answers every diagnostic message with a positive UDS response echoing the sub function. The UDS requests are forwarded to `requests`.
+    async fn entity(listener: TcpListener, requests: mpsc::UnboundedSender<Vec<u8>>) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (mut reader, mut writer) = stream.into_split();
+
+        loop {
+            let Ok(msg) = Message::read(&mut reader).await else {
+                return;
+            };
+
+            let mut responses = vec![];
+            if msg.is(PayloadType::RoutingActivationRequest) {
+                let mut payload = TESTER.to_be_bytes().to_vec();
+                payload.extend(ECU.to_be_bytes());
+                payload.push(ROUTING_ACTIVATION_SUCCESS);
+                payload.extend([0x00; 4]);
+                responses.push(Message::new(
+                    PayloadType::RoutingActivationResponse,
+                    payload,
+                ));
+            } else if msg.is(PayloadType::DiagnosticMessage) {
+                let mut addresses = ECU.to_be_bytes().to_vec();
+                addresses.extend(TESTER.to_be_bytes());
+
+                let mut ack = addresses.clone();
+                ack.push(0x00);
+                responses.push(Message::new(
+                    PayloadType::DiagnosticMessagePositiveAcknowledge,
+                    ack,
+                ));
+
+                // Unrelated alive check, which should be handled by the adapter
+                responses.push(Message::new(PayloadType::AliveCheckRequest, vec![]));
+
+                requests.send(msg.payload[4..].to_vec()).ok();
+
+                let mut response = addresses;
+                response.extend([
+                    msg.payload[4] | 0x40,
+                    msg.payload.get(5).copied().unwrap_or(0),
+                ]);
+                responses.push(Message::new(PayloadType::DiagnosticMessage, response));
+            }
+
+            for response in responses {
+                writer.write_all(&response.encode()).await.unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn encode_header() {
+        let msg = Message::new(
+            PayloadType::DiagnosticMessage,
+            vec![0x0e, 0x00, 0x10, 0x01, 0x3e],
+        );
+        assert_eq!(
+            msg.encode(),
+            vec![0x02, 0xfd, 0x80, 0x01, 0x00, 0x00, 0x00, 0x05, 0x0e, 0x00, 0x10, 0x01, 0x3e]
+        );
+    }
+
+    #[tokio::test]
+    async fn discover() {
+        let entity = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = entity.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            let (len, tester) = entity.recv_from(&mut buf).await.unwrap();
+            let request = Message::decode(&buf[..len]).unwrap();
+            assert!(request.is(PayloadType::VehicleIdentificationRequest));
+
+            let mut payload = b"WVWZZZ1JZXW000001".to_vec();
+            payload.extend(ECU.to_be_bytes());
+            payload.extend([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+            payload.extend([0x00; 6]);
+            payload.push(0x00);
+
+            // A truncated announcement doesn't abort the discovery
+            let truncated = Message::new(PayloadType::VehicleAnnouncement, payload[..10].to_vec());
+            entity.send_to(&truncated.encode(), tester).await.unwrap();
+
+            let announcement = Message::new(PayloadType::VehicleAnnouncement, payload);
+            entity
+                .send_to(&announcement.encode(), tester)
+                .await
+                .unwrap();
+        });
+
+        let vehicles = DoIPAdapter::discover(addr, std::time::Duration::from_millis(200))
+            .await
+            .unwrap();
+        assert_eq!(vehicles.len(), 1);
+        assert_eq!(vehicles[0].addr, addr);
+        assert_eq!(vehicles[0].vin, "WVWZZZ1JZXW000001");
+        assert_eq!(vehicles[0].logical_address, ECU);
+        assert_eq!(vehicles[0].eid, [0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+    }
+
+    #[tokio::test]
+    async fn diagnostic_message_exchange() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (requests, _) = mpsc::unbounded_channel();
+        tokio::spawn(entity(listener, requests));
+
+        let doip = DoIPAdapter::connect(addr, DoIPConfig::new(TESTER, ECU))
+            .await
+            .unwrap();
+
+        let mut stream = doip.recv();
+        doip.send(&[0x3e, 0x00]).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), vec![0x7e, 0x00]);
+
+        // UDS runs unchanged on top of DoIP
+        let uds = crate::uds::UDSClient::new(&doip);
+        uds.tester_present().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn connection_closed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (requests, _) = mpsc::unbounded_channel();
+        let entity = tokio::spawn(entity(listener, requests));
+
+        let doip = DoIPAdapter::connect(addr, DoIPConfig::new(TESTER, ECU))
+            .await
+            .unwrap();
+        let mut stream = doip.recv();
+
+        // Closing the connection is reported once, after which the stream ends
+        entity.abort();
+        assert_eq!(stream.next().await, Some(Err(crate::Error::Disconnected)));
+        assert_eq!(stream.next().await, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn session_drop() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (requests, mut received) = mpsc::unbounded_channel();
+        tokio::spawn(entity(listener, requests));
+
+        let doip = DoIPAdapter::connect(addr, DoIPConfig::new(TESTER, ECU))
+            .await
+            .unwrap();
+        let uds = crate::uds::UDSClient::new(&doip);
+
+        // The process task of the adapter runs on this runtime, so it has to keep running while the guard is dropped
+        let session = uds.session(0x03).await.unwrap();
+        assert_eq!(received.recv().await.unwrap(), vec![0x10, 0x03]);
+        drop(session);
+        assert_eq!(received.try_recv().unwrap(), vec![0x10, 0x01]);
+    }
+}
@@ -15,12 +15,24 @@ pub enum Error {
     #[error("Disconnected")]
     Disconnected,
 
+    #[error(transparent)]
+    DoIPError(#[from] crate::doip::Error),
     #[error(transparent)]
     IsoTPError(#[from] crate::isotp::Error),
     #[error(transparent)]
+    J1939Error(#[from] crate::j1939::Error),
+    #[error(transparent)]
+    KWP2000Error(#[from] crate::kwp2000::Error),
+    #[error(transparent)]
     LibUsbError(#[from] rusb::Error),
     #[error(transparent)]
+    LoggingError(#[from] crate::logging::Error),
+    #[error(transparent)]
+    OBDError(#[from] crate::obd::Error),
+    #[error(transparent)]
     UDSError(#[from] crate::uds::Error),
+    #[error(transparent)]
+    XCPError(#[from] crate::xcp::Error),
 
     #[cfg(all(target_os = "windows", feature = "vector-xl"))]
     #[error(transparent)]
@@ -29,6 +41,24 @@ pub enum Error {
     #[cfg(feature = "panda")]
     #[error(transparent)]
     PandaError(#[from] crate::panda::Error),
+
+    #[cfg(feature = "pcan")]
+    #[error(transparent)]
+    PcanError(#[from] crate::pcan::Error),
+}
+
+impl Error {
+    /// Returns true for any kind of timeout. This includes the more specific ISO-TP timeouts, which distinguish between no response at all and a transfer that stalled halfway.
+    pub fn is_timeout(&self) -> bool {
+        matches!(
+            self,
+            Error::Timeout
+                | Error::IsoTPError(
+                    crate::isotp::Error::FirstFrameTimeout
+                        | crate::isotp::Error::ConsecutiveTimeout
+                )
+        )
+    }
 }
 
 impl From<tokio_stream::Elapsed> for Error {
@@ -18,4 +18,8 @@ pub enum Error {
     MalformedFrame,
     #[error("Too many WAIT Flow Control, N_WFTmax exeeded")]
     TooManyFCWait,
+    #[error("Timeout, no response received")]
+    FirstFrameTimeout,
+    #[error("Timeout, transfer stalled after receiving the first frame")]
+    ConsecutiveTimeout,
 }
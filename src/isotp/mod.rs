@@ -20,12 +20,18 @@ mod types;
 pub use constants::{FlowStatus, FrameType, FLOW_SATUS_MASK, FRAME_TYPE_MASK};
 pub use error::Error;
 
+use crate::can::async_can::CanSender;
 use crate::can::AsyncCanAdapter;
 use crate::can::{Frame, Identifier, DLC_TO_LEN};
+use crate::transport::DiagnosticTransport;
 use crate::Result;
 use crate::{Stream, StreamExt, Timeout};
 use async_stream::stream;
-use tracing::debug;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::TryRecvError};
+use tracing::{debug, debug_span, Instrument};
 
 use self::types::FlowControlConfig;
 
@@ -35,9 +41,19 @@ const DEFAULT_PADDING_BYTE: u8 = 0xAA;
 /// N_WFTmax in ISO 15765-2
 const MAX_WAIT_FC: usize = 10;
 
+/// Interval between Wait flow control frames when the receiver is not ready. Needs to be well below the flow control timeout of the sender (N_Bs).
+const WAIT_FC_INTERVAL_MS: u64 = 50;
+
+/// Counter used to give each ISO-TP transfer a unique id in the debug logs
+static TRANSFER_ID: AtomicUsize = AtomicUsize::new(0);
+
 const CAN_MAX_DLEN: usize = 8;
 const CAN_FD_MAX_DLEN: usize = 64;
 
+/// Target address type in the PDU Format (PF) field of 29 bit normal fixed addressing
+const NORMAL_FIXED_PHYSICAL: u32 = 0xda;
+const NORMAL_FIXED_FUNCTIONAL: u32 = 0xdb;
+
 const ISO_TP_MAX_DLEN: usize = (1 << 12) - 1;
 const ISO_TP_FD_MAX_DLEN: usize = (1 << 32) - 1;
 
@@ -56,29 +72,60 @@ pub struct IsoTPConfig {
     pub timeout: std::time::Duration,
     /// Override for Seperation Time (STmin) for transmitted frames
     pub separation_time_min: Option<std::time::Duration>,
+    /// Lower bound for the Seperation Time (STmin) of transmitted frames, regardless of the flow control or override. Prevents a transfer with an STmin of 0 from starving other senders on a shared bus. Defaults to zero.
+    pub min_separation_floor: std::time::Duration,
+    /// Block Size (BS) advertised in the flow control frames when receiving. The sender waits for a new flow control frame after every `rx_block_size` consecutive frames. Use 0 to receive all consecutive frames without further flow control.
+    pub rx_block_size: u8,
+    /// Separation Time (STmin) advertised in the flow control frames when receiving. Times below 1 ms are sent with a 100 us resolution.
+    pub rx_separation_time_min: std::time::Duration,
+    /// Max number of Wait flow control frames to send before giving up on the transfer (N_WFTmax) while the receiver is not ready, see [`IsoTPAdapter::with_rx_ready`]
+    pub max_wait_frames: usize,
     /// Enable CAN-FD Mode
     pub fd: bool,
     /// Extended address
     pub ext_address: Option<u8>,
     /// Max data length. Will use default of 8 (CAN) or 64 (CAN-FD) if not set
     pub max_dlen: Option<usize>,
+    /// Pad the last consecutive frame to the full transmit data length (TX_DL), even if padding is disabled. Some ECUs reject CAN-FD transfers where not all consecutive frames have the same length.
+    pub force_tx_dl: bool,
+    /// Functional addressing. Only single frames can be sent, since multi frame transfers are not allowed for functional requests. Use [`IsoTPAdapter::recv_any`] to receive the responses.
+    pub functional: bool,
 }
 
 impl IsoTPConfig {
+    /// Create a config for the given transmit ID, and derive the receive ID from it. For standard IDs the receive ID is `tx_id + 8`, for extended IDs the last two bytes (target and source address) are swapped. Use [`IsoTPConfig::new_from_tx_rx`] for address pairs that do not follow this convention.
     pub fn new(bus: u8, id: Identifier) -> Self {
         let tx_id = id;
         let rx_id = match id {
             Identifier::Standard(id) => Identifier::Standard(id + 8),
-            Identifier::Extended(id) => {
-                let bytes = id.to_be_bytes();
-                let id = u32::from_be_bytes([bytes[0], bytes[1], bytes[3], bytes[2]]); // Swap last two bytes
-                Identifier::Extended(id)
-            }
+            Identifier::Extended(id) => Identifier::Extended(swap_addresses(id)),
         };
 
         Self::new_from_tx_rx(bus, tx_id, rx_id)
     }
 
+    /// Create a config for 29 bit normal fixed addressing as defined in ISO 15765-2. Transmits on `0x18DA<target><source>` and receives on `0x18DA<source><target>` when using the default priority of 6.
+    pub fn normal_fixed(bus: u8, priority: u8, source: u8, target: u8) -> Self {
+        let tx_id = normal_fixed_id(priority, NORMAL_FIXED_PHYSICAL, source, target);
+        let rx_id = normal_fixed_id(priority, NORMAL_FIXED_PHYSICAL, target, source);
+        Self::new_from_tx_rx(bus, tx_id, rx_id)
+    }
+
+    /// Create a config for functional requests with 29 bit normal fixed addressing, transmitting on `0x18DB<target><source>` when using the default priority of 6. The ECUs respond using physical addressing on `0x18DA<tester><ecu>`, use [`IsoTPAdapter::recv_any`] to receive the responses.
+    pub fn normal_fixed_functional(bus: u8, priority: u8, source: u8, target: u8) -> Self {
+        let tx_id = normal_fixed_id(priority, NORMAL_FIXED_FUNCTIONAL, source, target);
+        Self::functional(bus, tx_id)
+    }
+
+    /// Create a config for functional (broadcast) requests on the given transmit ID, e.g. 0x7df for OBD-II. Responses from the individual ECUs are received using [`IsoTPAdapter::recv_any`].
+    pub fn functional(bus: u8, tx_id: Identifier) -> Self {
+        Self {
+            functional: true,
+            ..Self::new_from_tx_rx(bus, tx_id, tx_id)
+        }
+    }
+
+    /// Create a config for the given transmit ID, with the receive ID at `tx_id + offset`. Only supported for standard IDs.
     pub fn new_from_offset(bus: u8, id: Identifier, offset: u32) -> Self {
         let tx_id = id;
         let rx_id = match id {
@@ -89,6 +136,7 @@ impl IsoTPConfig {
         Self::new_from_tx_rx(bus, tx_id, rx_id)
     }
 
+    /// Create a config with an explicit transmit and receive ID. The receive ID is not derived from the transmit ID, so this can be used for arbitrary address pairs.
     pub fn new_from_tx_rx(bus: u8, tx_id: Identifier, rx_id: Identifier) -> Self {
         Self {
             bus,
@@ -97,10 +145,41 @@ impl IsoTPConfig {
             padding: Some(DEFAULT_PADDING_BYTE),
             timeout: std::time::Duration::from_millis(DEFAULT_TIMEOUT_MS),
             separation_time_min: None,
+            min_separation_floor: std::time::Duration::ZERO,
+            rx_block_size: 0,
+            rx_separation_time_min: std::time::Duration::ZERO,
+            max_wait_frames: MAX_WAIT_FC,
             fd: false,
             ext_address: None,
             max_dlen: None,
+            force_tx_dl: false,
+            functional: false,
+        }
+    }
+}
+
+/// Builds a 29 bit normal fixed addressing ID from the priority, PDU Format, and source and target address.
+fn normal_fixed_id(priority: u8, pdu_format: u32, source: u8, target: u8) -> Identifier {
+    assert!(priority <= 7, "Priority is a 3 bit value");
+    let id = (priority as u32) << 26 | pdu_format << 16 | (target as u32) << 8 | source as u32;
+    Identifier::Extended(id)
+}
+
+/// Swaps the last two bytes (target and source address) of an extended ID
+fn swap_addresses(id: u32) -> u32 {
+    let bytes = id.to_be_bytes();
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[3], bytes[2]])
+}
+
+/// Formats data as lowercase hex directly into the log message, without allocating an intermediate `String` like `hex::encode`.
+struct Hex<'a>(&'a [u8]);
+
+impl std::fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for b in self.0 {
+            write!(f, "{:02x}", b)?;
         }
+        Ok(())
     }
 }
 
@@ -108,6 +187,11 @@ impl IsoTPConfig {
 pub struct IsoTPAdapter<'a> {
     adapter: &'a AsyncCanAdapter,
     config: IsoTPConfig,
+    rx_ready: Option<Arc<dyn Fn() -> bool + Send + Sync>>,
+    /// Frames received since the last completed transfer, drained by [`IsoTPAdapter::flush`]
+    pending: std::sync::Mutex<broadcast::Receiver<Frame>>,
+    /// Timestamps of the last sent frame and the last frame of a received packet, used for the round trip time
+    timestamps: std::sync::Mutex<(Option<std::time::Duration>, Option<std::time::Duration>)>,
 }
 
 impl<'a> IsoTPAdapter<'a> {
@@ -119,28 +203,48 @@ impl<'a> IsoTPAdapter<'a> {
 
     /// Create a new IsoTPAdapter from a CAN adapter and a configuration.
     pub fn new(adapter: &'a AsyncCanAdapter, config: IsoTPConfig) -> Self {
-        Self { adapter, config }
+        Self {
+            adapter,
+            config,
+            rx_ready: None,
+            pending: std::sync::Mutex::new(adapter.subscribe()),
+            timestamps: std::sync::Mutex::new((None, None)),
+        }
+    }
+
+    /// Sets a callback that is called before sending flow control when receiving. While it returns false, Wait flow control frames are sent to hold off the sender, e.g. when the buffer of a downstream consumer is full. The transfer fails after [`IsoTPConfig::max_wait_frames`] Wait frames.
+    pub fn with_rx_ready(mut self, rx_ready: impl Fn() -> bool + Send + Sync + 'static) -> Self {
+        self.rx_ready = Some(Arc::new(rx_ready));
+        self
+    }
+
+    /// Physical request ID of the ECU responding on `rx_id`, following the same convention as [`IsoTPConfig::new`]. Used to send flow control when receiving responses to functional requests. Fails for standard IDs below 0x008, which have no matching transmit ID.
+    fn physical_tx_id(rx_id: Identifier) -> Result<Identifier> {
+        Ok(match rx_id {
+            Identifier::Standard(id) => {
+                Identifier::Standard(id.checked_sub(8).ok_or(Error::MalformedFrame)?)
+            }
+            Identifier::Extended(id) => Identifier::Extended(swap_addresses(id)),
+        })
     }
 
     fn pad(&self, data: &mut Vec<u8>) {
         // Ensure we leave space for the extended address
-        let offset = self.config.ext_address.is_some() as usize;
-        let len = data.len() + offset;
+        let offset = self.offset();
 
         // Pad to at least 8 bytes if padding is enabled
         if let Some(padding) = self.config.padding {
-            if len < CAN_MAX_DLEN {
-                let padding_len = CAN_MAX_DLEN - len; // Offset for extended address is already accounted for
-                data.extend(std::iter::repeat(padding).take(padding_len));
+            if data.len() + offset < CAN_MAX_DLEN {
+                data.resize(CAN_MAX_DLEN - offset, padding);
             }
         }
 
         // Pad to next valid DLC for CAN-FD
+        let len = data.len() + offset;
         if !DLC_TO_LEN.contains(&len) {
-            let idx = DLC_TO_LEN.iter().position(|&x| x > data.len()).unwrap();
+            let dlc_len = *DLC_TO_LEN.iter().find(|&&x| x >= len).unwrap();
             let padding = self.config.padding.unwrap_or(DEFAULT_PADDING_BYTE);
-            let padding_len = DLC_TO_LEN[idx] - len;
-            data.extend(std::iter::repeat(padding).take(padding_len));
+            data.resize(dlc_len - offset, padding);
         }
     }
 
@@ -162,7 +266,8 @@ impl<'a> IsoTPAdapter<'a> {
     /// Maximum data length for a CAN frame based on the current config
     fn max_can_data_length(&self) -> usize {
         match self.config.max_dlen {
-            Some(dlen) => dlen - self.offset(),
+            Some(dlen) if self.config.fd => dlen - self.offset(),
+            Some(dlen) => std::cmp::min(dlen, CAN_MAX_DLEN) - self.offset(),
             None => {
                 if self.config.fd {
                     self.can_fd_max_dlen()
@@ -182,6 +287,11 @@ impl<'a> IsoTPAdapter<'a> {
         }
     }
 
+    /// Transmit data length (TX_DL) used for first and consecutive frames, including the extended address if configured. Depends on CAN-FD mode and [`IsoTPConfig::max_dlen`].
+    pub fn tx_data_length(&self) -> usize {
+        self.max_can_data_length() + self.offset()
+    }
+
     /// Build a CAN frame from the payload. Inserts extended address and padding if needed.
     fn frame(&self, data: &[u8]) -> Result<Frame> {
         let mut data = data.to_vec();
@@ -202,17 +312,37 @@ impl<'a> IsoTPAdapter<'a> {
             data,
             loopback: false,
             fd: self.config.fd,
+            brs: false,
+            timestamp: None,
+            rtr: false,
+            dlc: None,
         };
 
         Ok(frame)
     }
 
     pub async fn send_single_frame(&self, data: &[u8]) -> Result<()> {
+        let frame = self.single_frame(data)?;
+        self.send_data_frame(&frame).await
+    }
+
+    /// Sends a frame carrying data, and keeps its timestamp for [`IsoTPAdapter::round_trip_time`]
+    async fn send_data_frame(&self, frame: &Frame) -> Result<()> {
+        let timestamp = self.adapter.send_timestamped(frame).await?;
+        self.timestamps.lock().unwrap().0 = timestamp;
+        Ok(())
+    }
+
+    /// Builds a single frame containing `data`
+    pub(crate) fn single_frame(&self, data: &[u8]) -> Result<Frame> {
         let mut buf;
 
         if data.len() < self.can_max_dlen() {
             // Len fits in classic CAN message
             buf = vec![FrameType::Single as u8 | data.len() as u8];
+        } else if !self.config.fd {
+            // Escape sequence is only valid for CAN-FD
+            return Err(crate::isotp::error::Error::DataTooLarge.into());
         } else {
             // Use escape sequence for length, length is in the next byte
             buf = vec![FrameType::Single as u8, data.len() as u8];
@@ -221,11 +351,14 @@ impl<'a> IsoTPAdapter<'a> {
         buf.extend(data);
         self.pad(&mut buf);
 
-        debug!("TX SF, length: {} data {}", data.len(), hex::encode(&buf));
+        debug!("TX SF, length: {} data {}", data.len(), Hex(&buf));
 
-        let frame = self.frame(&buf)?;
-        self.adapter.send(&frame).await;
-        Ok(())
+        self.frame(&buf)
+    }
+
+    /// Owned handle to send frames on the underlying CAN adapter, which can be moved into a background task
+    pub(crate) fn can_sender(&self) -> CanSender {
+        self.adapter.sender()
     }
 
     pub async fn send_first_frame(&self, data: &[u8]) -> Result<usize> {
@@ -243,10 +376,10 @@ impl<'a> IsoTPAdapter<'a> {
         let offset = buf.len();
         buf.extend(&data[..self.max_can_data_length() - buf.len()]);
 
-        debug!("TX FF, length: {} data {}", data.len(), hex::encode(&buf));
+        debug!("TX FF, length: {} data {}", data.len(), Hex(&buf));
 
         let frame = self.frame(&buf)?;
-        self.adapter.send(&frame).await;
+        self.send_data_frame(&frame).await?;
         Ok(offset)
     }
 
@@ -257,26 +390,37 @@ impl<'a> IsoTPAdapter<'a> {
         buf.extend(data);
         self.pad(&mut buf);
 
-        debug!("TX CF, idx: {} data {}", idx, hex::encode(&buf));
-
-        let frame = self.frame(&buf)?;
+        if self.config.force_tx_dl {
+            let padding = self.config.padding.unwrap_or(DEFAULT_PADDING_BYTE);
+            let tx_dl = self.max_can_data_length();
+            if buf.len() < tx_dl {
+                buf.resize(tx_dl, padding);
+            }
+        }
 
-        self.adapter.send(&frame).await;
+        debug!("TX CF, idx: {} data {}", idx, Hex(&buf));
 
-        Ok(())
+        let frame = self.frame(&buf)?;
+        self.send_data_frame(&frame).await
     }
 
+    /// Waits for a flow control frame. `first` indicates the flow control in response to the first frame, a timeout there means the ECU didn't respond at all.
     async fn receive_flow_control(
         &self,
         stream: &mut std::pin::Pin<&mut Timeout<impl Stream<Item = Frame>>>,
+        first: bool,
     ) -> Result<FlowControlConfig> {
-        for _ in 0..MAX_WAIT_FC {
-            let mut frame = stream.next().await.unwrap()?;
+        for i in 0..MAX_WAIT_FC {
+            let mut frame = match stream.next().await.ok_or(crate::Error::Disconnected)? {
+                Ok(frame) => frame,
+                Err(_) if first && i == 0 => return Err(Error::FirstFrameTimeout.into()),
+                Err(_) => return Err(Error::ConsecutiveTimeout.into()),
+            };
 
             // Remove extended address from frame
             frame.data = frame.data.split_off(self.offset());
 
-            debug!("RX FC, data {}", hex::encode(&frame.data));
+            debug!("RX FC, data {}", Hex(&frame.data));
 
             // Check if Flow Control
             if FrameType::from_repr(frame.data[0] & FRAME_TYPE_MASK) != Some(FrameType::FlowControl)
@@ -297,50 +441,51 @@ impl<'a> IsoTPAdapter<'a> {
             // Parse block size and separation time
             let config = types::FlowControlConfig::try_from(&frame)?;
 
-            debug!("RX FC, {:?} data {}", config, hex::encode(&frame.data));
+            debug!("RX FC, {:?} data {}", config, Hex(&frame.data));
             return Ok(config);
         }
 
         Err(crate::isotp::error::Error::TooManyFCWait.into())
     }
 
-    async fn send_multiple(&self, data: &[u8]) -> Result<()> {
+    async fn send_multiple(
+        &self,
+        data: &[u8],
+        st_min: Option<std::time::Duration>,
+        progress: &mut impl FnMut(usize, usize),
+    ) -> Result<()> {
         // Stream for receiving flow control
         let stream = self
             .adapter
-            .recv_filter(|frame| {
-                if frame.id != self.config.rx_id || frame.loopback {
-                    return false;
-                }
-
-                if self.config.ext_address.is_some() {
-                    return frame.data.first() == self.config.ext_address.as_ref();
-                }
-
-                true
-            })
+            .recv_filter(|frame| self.is_rx_frame(frame))
             .timeout(self.config.timeout);
         tokio::pin!(stream);
 
         let offset = self.send_first_frame(data).await?;
-        let mut fc_config = self.receive_flow_control(&mut stream).await?;
+        let tx_dl = self.max_can_data_length();
+        let mut bytes_sent = tx_dl - offset;
+        progress(bytes_sent, data.len());
+
+        let mut fc_config = self.receive_flow_control(&mut stream, true).await?;
 
         // Check for separation time override
-        let st_min = match self.config.separation_time_min {
+        let st_min = match st_min.or(self.config.separation_time_min) {
             Some(st_min) => st_min,
             None => fc_config.separation_time_min,
         };
+        let st_min = st_min.max(self.config.min_separation_floor);
 
-        let tx_dl = self.max_can_data_length();
         let chunks = data[tx_dl - offset..].chunks(tx_dl - 1);
         let mut it = chunks.enumerate().peekable();
         while let Some((idx, chunk)) = it.next() {
             self.send_consecutive_frame(chunk, idx).await?;
+            bytes_sent += chunk.len();
+            progress(bytes_sent, data.len());
 
             // Wait for flow control every `block_size` frames, except for the first frame
             if fc_config.block_size != 0 && idx > 0 && idx % fc_config.block_size as usize == 0 {
                 // Wait for next flow control
-                fc_config = self.receive_flow_control(&mut stream).await?;
+                fc_config = self.receive_flow_control(&mut stream, false).await?;
             } else {
                 // Sleep for separation time between frames
                 let last = it.peek().is_none();
@@ -353,18 +498,57 @@ impl<'a> IsoTPAdapter<'a> {
         Ok(())
     }
 
-    /// Asynchronously send an ISO-TP frame of up to 4095 bytes. Returns Timeout if the ECU is not responding in time with flow control messages.
+    /// Asynchronously send an ISO-TP frame of up to 4095 bytes. Returns [`Error::FirstFrameTimeout`] if the ECU doesn't respond to the first frame with flow control in time, and [`Error::ConsecutiveTimeout`] if a later flow control message is not received in time.
     pub async fn send(&self, data: &[u8]) -> Result<()> {
-        debug!("TX {}", hex::encode(data));
+        self.send_with_progress(data, |_, _| {}).await
+    }
+
+    /// Same as [`IsoTPAdapter::send`], but calls `progress` with the number of bytes sent and the total number of bytes after every frame. Useful for reporting progress of large transfers.
+    pub async fn send_with_progress(
+        &self,
+        data: &[u8],
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<()> {
+        let span = debug_span!("isotp_tx", id = TRANSFER_ID.fetch_add(1, Ordering::Relaxed));
+        let result = self
+            .send_transfer(data, None, &mut progress)
+            .instrument(span)
+            .await;
+        self.mark_handled();
+        result
+    }
+
+    /// Same as [`IsoTPAdapter::send`], but uses `st_min` as separation time between consecutive frames for this transfer only. This ignores both the separation time requested by the ECU in the flow control frames and [`IsoTPConfig::separation_time_min`].
+    pub async fn send_with_stmin(&self, data: &[u8], st_min: std::time::Duration) -> Result<()> {
+        let span = debug_span!("isotp_tx", id = TRANSFER_ID.fetch_add(1, Ordering::Relaxed));
+        let result = self
+            .send_transfer(data, Some(st_min), &mut |_, _| {})
+            .instrument(span)
+            .await;
+        self.mark_handled();
+        result
+    }
+
+    async fn send_transfer(
+        &self,
+        data: &[u8],
+        st_min: Option<std::time::Duration>,
+        progress: &mut impl FnMut(usize, usize),
+    ) -> Result<()> {
+        debug!("TX {}", Hex(data));
 
         // Single frame has 1 byte of overhead for CAN, and 2 bytes for CAN-FD with escape sequence
-        let fits_in_single_frame =
-            data.len() < self.can_max_dlen() || data.len() < self.max_can_data_length() - 1;
+        let fits_in_single_frame = data.len() < self.can_max_dlen()
+            || (self.config.fd && data.len() < self.max_can_data_length() - 1);
 
         if fits_in_single_frame {
             self.send_single_frame(data).await?;
+            progress(data.len(), data.len());
+        } else if self.config.functional {
+            debug!("Multi frame transfers are not allowed with functional addressing");
+            return Err(crate::isotp::error::Error::DataTooLarge.into());
         } else if data.len() <= self.max_isotp_data_length() {
-            self.send_multiple(data).await?;
+            self.send_multiple(data, st_min, progress).await?;
         } else {
             return Err(crate::isotp::error::Error::DataTooLarge.into());
         }
@@ -378,7 +562,9 @@ impl<'a> IsoTPAdapter<'a> {
 
         // CAN-FD Escape sequence
         if len == 0 {
-            len = data[1] as usize;
+            len = *data
+                .get(1)
+                .ok_or(crate::isotp::error::Error::MalformedFrame)? as usize;
             offset = 2;
         }
 
@@ -387,12 +573,23 @@ impl<'a> IsoTPAdapter<'a> {
             return Err(crate::isotp::error::Error::MalformedFrame.into());
         }
 
-        debug!("RX SF, length: {} data {}", len, hex::encode(data));
+        debug!("RX SF, length: {} data {}", len, Hex(data));
 
         Ok(data[offset..len + offset].to_vec())
     }
 
-    async fn recv_first_frame(&self, data: &[u8], buf: &mut Vec<u8>) -> Result<usize> {
+    /// Receives a first frame, and sends flow control on `fc_id`
+    async fn recv_first_frame(
+        &self,
+        data: &[u8],
+        buf: &mut Vec<u8>,
+        fc_id: Identifier,
+    ) -> Result<usize> {
+        // A FF cannot use CAN frame data optmization, and always needs to be full length.
+        if data.len() < self.max_can_data_length() {
+            return Err(crate::isotp::error::Error::MalformedFrame.into());
+        }
+
         let b0 = data[0] as u16;
         let b1 = data[1] as u16;
         let mut len = ((b0 << 8 | b1) & 0xFFF) as usize;
@@ -401,27 +598,55 @@ impl<'a> IsoTPAdapter<'a> {
         // CAN-FD Escape sequence
         if len == 0 {
             offset = 6;
-            len = u32::from_be_bytes([data[2], data[3], data[4], data[5]]) as usize;
+            let escaped: [u8; 4] = data
+                .get(2..6)
+                .and_then(|len| len.try_into().ok())
+                .ok_or(crate::isotp::error::Error::MalformedFrame)?;
+            len = u32::from_be_bytes(escaped) as usize;
         }
-        debug!("RX FF, length: {}, data {}", len, hex::encode(data));
+        debug!("RX FF, length: {}, data {}", len, Hex(data));
 
-        // A FF cannot use CAN frame data optmization, and always needs to be full length.
-        if data.len() < self.max_can_data_length() {
-            return Err(crate::isotp::error::Error::MalformedFrame.into());
+        buf.extend(&data[offset..]);
+
+        self.send_flow_control_when_ready(fc_id).await?;
+
+        Ok(len)
+    }
+
+    /// Sends Wait flow control frames until [`IsoTPAdapter::with_rx_ready`] signals the receiver is ready, followed by Continue To Send. Returns TooManyFCWait if the receiver is not ready after `max_wait_frames` Wait frames.
+    async fn send_flow_control_when_ready(&self, fc_id: Identifier) -> Result<()> {
+        if let Some(rx_ready) = &self.rx_ready {
+            let mut wait_frames = 0;
+            while !rx_ready() {
+                if wait_frames >= self.config.max_wait_frames {
+                    return Err(crate::isotp::error::Error::TooManyFCWait.into());
+                }
+
+                self.send_flow_control(FlowStatus::Wait, fc_id).await?;
+                wait_frames += 1;
+                tokio::time::sleep(std::time::Duration::from_millis(WAIT_FC_INTERVAL_MS)).await;
+            }
         }
 
-        buf.extend(&data[offset..]);
+        self.send_flow_control(FlowStatus::ContinueToSend, fc_id)
+            .await
+    }
 
-        // Send Flow Control
-        let mut flow_control = vec![0x30, 0x00, 0x00];
-        self.pad(&mut flow_control);
+    async fn send_flow_control(&self, flow_status: FlowStatus, fc_id: Identifier) -> Result<()> {
+        let fc_config = FlowControlConfig {
+            block_size: self.config.rx_block_size,
+            separation_time_min: self.config.rx_separation_time_min,
+        };
 
-        debug!("TX FC, data {}", hex::encode(&flow_control));
+        let mut flow_control = fc_config.encode(flow_status);
+        self.pad(&mut flow_control);
 
-        let frame = self.frame(&flow_control)?;
-        self.adapter.send(&frame).await;
+        debug!("TX FC, data {}", Hex(&flow_control));
 
-        Ok(len)
+        let mut frame = self.frame(&flow_control)?;
+        frame.id = fc_id;
+        self.adapter.send(&frame).await?;
+        Ok(())
     }
 
     async fn recv_consecutive_frame(
@@ -430,6 +655,8 @@ impl<'a> IsoTPAdapter<'a> {
         buf: &mut Vec<u8>,
         len: usize,
         idx: u8,
+        frames: usize,
+        fc_id: Identifier,
     ) -> Result<u8> {
         let msg_idx = data[0] & 0xF;
         let remaining_len = len - buf.len();
@@ -451,17 +678,18 @@ impl<'a> IsoTPAdapter<'a> {
         let end_idx = std::cmp::min(remaining_len + 1, data.len());
 
         buf.extend(&data[1..end_idx]);
-        debug!(
-            "RX CF, idx: {}, data {} {}",
-            idx,
-            hex::encode(data),
-            hex::encode(&buf)
-        );
+        debug!("RX CF, idx: {}, data {} {}", idx, Hex(data), Hex(buf));
 
         if msg_idx != idx {
             return Err(crate::isotp::error::Error::OutOfOrder.into());
         }
 
+        // Send a new flow control after every `rx_block_size` consecutive frames, unless the transfer is complete. A block size of 0 means no further flow control.
+        let block_size = self.config.rx_block_size as usize;
+        if buf.len() < len && frames.checked_rem(block_size) == Some(0) {
+            self.send_flow_control_when_ready(fc_id).await?;
+        }
+
         let new_idx = if idx == 0xF { 0 } else { idx + 1 };
         Ok(new_idx)
     }
@@ -474,13 +702,25 @@ impl<'a> IsoTPAdapter<'a> {
         let mut buf = Vec::new();
         let mut len: Option<usize> = None;
         let mut idx: u8 = 1;
+        let mut frames: usize = 0;
 
         while let Some(frame) = stream.next().await {
+            let frame = match frame {
+                Ok(frame) => frame,
+                Err(_) if len.is_none() => return Err(Error::FirstFrameTimeout.into()),
+                Err(_) => return Err(Error::ConsecutiveTimeout.into()),
+            };
+
             // Remove extended address from frame
-            let data = &frame?.data[self.offset()..];
+            let data = &frame.data[self.offset()..];
+            let Some(&pci) = data.first() else {
+                debug!("Ignoring frame without PCI");
+                continue;
+            };
 
-            match FrameType::from_repr(data[0] & FRAME_TYPE_MASK) {
+            match FrameType::from_repr(pci & FRAME_TYPE_MASK) {
                 Some(FrameType::Single) => {
+                    self.timestamps.lock().unwrap().1 = frame.timestamp;
                     return self.recv_single_frame(data).await;
                 }
                 Some(FrameType::First) => {
@@ -488,18 +728,31 @@ impl<'a> IsoTPAdapter<'a> {
                     if len.is_some() {
                         return Err(Error::OutOfOrder.into());
                     }
-                    len = Some(self.recv_first_frame(data, &mut buf).await?);
+                    len = Some(
+                        self.recv_first_frame(data, &mut buf, self.config.tx_id)
+                            .await?,
+                    );
                 }
                 Some(FrameType::Consecutive) => {
                     if let Some(len) = len {
+                        frames += 1;
                         idx = self
-                            .recv_consecutive_frame(data, &mut buf, len, idx)
+                            .recv_consecutive_frame(
+                                data,
+                                &mut buf,
+                                len,
+                                idx,
+                                frames,
+                                self.config.tx_id,
+                            )
                             .await?;
                         if buf.len() >= len {
+                            self.timestamps.lock().unwrap().1 = frame.timestamp;
                             return Ok(buf);
                         }
                     } else {
-                        return Err(Error::OutOfOrder.into());
+                        // Tail of a previous (aborted) transfer, e.g. after a timeout. Ignore it so it doesn't pollute this transfer.
+                        debug!("Ignoring consecutive frame without first frame");
                     }
                 }
                 Some(FrameType::FlowControl) => {} // Ignore flow control frames, these are from a simultaneous transmission
@@ -508,15 +761,93 @@ impl<'a> IsoTPAdapter<'a> {
                 }
             };
         }
-        unreachable!();
+
+        // Stream ended, the adapter has stopped
+        Err(crate::Error::Disconnected)
     }
 
-    /// Stream of ISO-TP packets. Can be used if multiple responses are expected from a single request. Returns Timeout if the timeout is exceeded between individual ISO-TP frames. Note the total time to receive a packet may be longer than the timeout.
+    /// Check if the frame was sent to us on the receive ID, including the extended address if configured
+    fn is_rx_frame(&self, frame: &Frame) -> bool {
+        if frame.id != self.config.rx_id || frame.loopback {
+            return false;
+        }
+
+        if self.config.ext_address.is_some() {
+            return frame.data.first() == self.config.ext_address.as_ref();
+        }
+
+        true
+    }
+
+    /// Time between the last frame of the last sent packet being transmitted, and the last frame of the last received packet arriving, based on the frame timestamps of the CAN adapter. Returns `None` if the adapter doesn't provide timestamps, or the last packet was received before the last packet was sent.
+    pub fn round_trip_time(&self) -> Option<std::time::Duration> {
+        match *self.timestamps.lock().unwrap() {
+            (Some(tx), Some(rx)) => rx.checked_sub(tx),
+            _ => None,
+        }
+    }
+
+    /// Drops stale frames on the receive ID that arrived after the last completed transfer, e.g. the tail of a transfer that was aborted after a timeout, so the next transfer starts clean. Only frames that were already received are dropped, this doesn't wait for new frames. Returns the number of dropped frames.
+    pub fn flush(&self) -> usize {
+        let mut pending = self.pending.lock().unwrap();
+
+        let mut dropped = 0;
+        loop {
+            match pending.try_recv() {
+                Ok(frame) if self.is_rx_frame(&frame) => dropped += 1,
+                Ok(_) | Err(TryRecvError::Lagged(_)) => {}
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+        if dropped > 0 {
+            debug!("Flushed {} stale frame(s)", dropped);
+        }
+        dropped
+    }
+
+    /// Discards the frames received so far, as these were handled by the transfer that just completed.
+    fn mark_handled(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        while !matches!(
+            pending.try_recv(),
+            Err(TryRecvError::Empty | TryRecvError::Closed)
+        ) {}
+    }
+
+    /// Stream of ISO-TP packets. Can be used if multiple responses are expected from a single request. Returns [`Error::FirstFrameTimeout`] if no response is received, and [`Error::ConsecutiveTimeout`] if the timeout is exceeded between individual ISO-TP frames of a multi frame response. Use [`crate::Error::is_timeout`] to check for either. Note the total time to receive a packet may be longer than the timeout. Consecutive frames received before a first frame are ignored, as these are the tail of an earlier aborted transfer; use [`IsoTPAdapter::flush`] to drop such frames before sending a new request.
     pub fn recv(&self) -> impl Stream<Item = Result<Vec<u8>>> + '_ {
         let stream = self
             .adapter
-            .recv_filter(|frame| {
-                if frame.id != self.config.rx_id || frame.loopback {
+            .recv_filter(|frame| self.is_rx_frame(frame))
+            .timeout(self.config.timeout);
+
+        Box::pin(stream! {
+            tokio::pin!(stream);
+
+            loop {
+                let span = debug_span!("isotp_rx", id = TRANSFER_ID.fetch_add(1, Ordering::Relaxed));
+                let result = self.recv_from_stream(&mut stream).instrument(span).await;
+                self.mark_handled();
+
+                // End the stream once the adapter has stopped
+                let disconnected = result == Err(crate::Error::Disconnected);
+                yield result;
+                if disconnected {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Stream of ISO-TP packets from any ECU with a source ID accepted by `filter`, yielding the source ID together with the packet. Used to collect the responses from multiple ECUs to a functional request (see [`IsoTPConfig::functional`]). Flow control for multi frame responses is sent to the physical request ID of the responding ECU, following the same convention as [`IsoTPConfig::new`]. Returns [`Error::FirstFrameTimeout`] if no frame is received within the timeout, which usually means all ECUs have responded.
+    pub fn recv_any<'b>(
+        &'b self,
+        filter: impl Fn(Identifier) -> bool + 'b,
+    ) -> impl Stream<Item = Result<(Identifier, Vec<u8>)>> + 'b {
+        let stream = self
+            .adapter
+            .recv_filter(move |frame| {
+                if frame.loopback || !filter(frame.id) {
                     return false;
                 }
 
@@ -531,9 +862,747 @@ impl<'a> IsoTPAdapter<'a> {
         Box::pin(stream! {
             tokio::pin!(stream);
 
-            loop {
-                yield self.recv_from_stream(&mut stream).await;
+            // Multi frame packets in progress per source ID
+            let mut transfers: HashMap<Identifier, Transfer> = HashMap::new();
+
+            while let Some(frame) = stream.next().await {
+                let frame = match frame {
+                    Ok(frame) => frame,
+                    Err(_) if transfers.is_empty() => {
+                        yield Err(Error::FirstFrameTimeout.into());
+                        continue;
+                    }
+                    Err(_) => {
+                        // Nothing was received within the timeout, so every transfer in progress has stalled
+                        transfers.clear();
+                        yield Err(Error::ConsecutiveTimeout.into());
+                        continue;
+                    }
+                };
+
+                // Traffic from other ECUs keeps the stream alive, so also drop transfers that stalled individually
+                let stalled = transfers.len();
+                transfers.retain(|_, transfer| transfer.last_rx.elapsed() < self.config.timeout);
+                if transfers.len() < stalled {
+                    yield Err(Error::ConsecutiveTimeout.into());
+                }
+
+                let data = &frame.data[self.offset()..];
+                let Some(&pci) = data.first() else {
+                    debug!("Ignoring frame without PCI from {:?}", frame.id);
+                    continue;
+                };
+
+                match FrameType::from_repr(pci & FRAME_TYPE_MASK) {
+                    Some(FrameType::Single) => {
+                        yield self.recv_single_frame(data).await.map(|data| (frame.id, data));
+                    }
+                    Some(FrameType::First) => {
+                        // Flow control is only needed for multi frame responses
+                        let fc_id = match Self::physical_tx_id(frame.id) {
+                            Ok(fc_id) => fc_id,
+                            Err(e) => {
+                                debug!("No transmit ID for responses from {:?}", frame.id);
+                                transfers.remove(&frame.id);
+                                yield Err(e);
+                                continue;
+                            }
+                        };
+
+                        let mut buf = Vec::new();
+                        match self.recv_first_frame(data, &mut buf, fc_id).await {
+                            Ok(len) => {
+                                transfers.insert(frame.id, Transfer { buf, len, idx: 1, frames: 0, fc_id, last_rx: std::time::Instant::now() });
+                            }
+                            Err(e) => {
+                                transfers.remove(&frame.id);
+                                yield Err(e);
+                            }
+                        }
+                    }
+                    Some(FrameType::Consecutive) => {
+                        let Some(transfer) = transfers.get_mut(&frame.id) else {
+                            debug!("Ignoring consecutive frame without first frame");
+                            continue;
+                        };
+
+                        transfer.frames += 1;
+                        transfer.last_rx = std::time::Instant::now();
+                        match self.recv_consecutive_frame(data, &mut transfer.buf, transfer.len, transfer.idx, transfer.frames, transfer.fc_id).await {
+                            Ok(new_idx) => {
+                                transfer.idx = new_idx;
+                                if transfer.buf.len() >= transfer.len {
+                                    let transfer = transfers.remove(&frame.id).unwrap();
+                                    yield Ok((frame.id, transfer.buf));
+                                }
+                            }
+                            Err(e) => {
+                                transfers.remove(&frame.id);
+                                yield Err(e);
+                            }
+                        }
+                    }
+                    Some(FrameType::FlowControl) => {}
+                    _ => yield Err(Error::UnknownFrameType.into()),
+                }
             }
         })
     }
 }
+
+/// Multi frame packet in progress in [`IsoTPAdapter::recv_any`].
+struct Transfer {
+    buf: Vec<u8>,
+    len: usize,
+    /// Next expected consecutive frame index
+    idx: u8,
+    /// Consecutive frames received, used to send flow control after each block
+    frames: usize,
+    /// Physical request ID of the ECU, used to send flow control
+    fc_id: Identifier,
+    last_rx: std::time::Instant,
+}
+
+impl DiagnosticTransport for IsoTPAdapter<'_> {
+    async fn send(&self, data: &[u8]) -> Result<()> {
+        IsoTPAdapter::send(self, data).await
+    }
+
+    fn recv(&self) -> impl Stream<Item = Result<Vec<u8>>> + Unpin + Send + '_ {
+        IsoTPAdapter::recv(self)
+    }
+
+    fn round_trip_time(&self) -> Option<std::time::Duration> {
+        IsoTPAdapter::round_trip_time(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::can::{CanAdapter, VirtualCanAdapter};
+    use std::collections::VecDeque;
+
+    /// Sends `data` and returns the first frame put on the bus. Multi frame transfers will time out waiting for flow control.
+    async fn first_tx_frame(config: IsoTPConfig, data: &[u8]) -> Frame {
+        let adapter = AsyncCanAdapter::new(VirtualCanAdapter::new());
+        let isotp = IsoTPAdapter::new(&adapter, config);
+
+        let stream = adapter.recv_filter(|frame| frame.loopback);
+        tokio::pin!(stream);
+
+        isotp.send(data).await.ok();
+        stream.next().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn classic_single_frame() {
+        let config = IsoTPConfig::new(0, Identifier::Standard(0x7a1));
+
+        // Largest single frame has the length in the low nibble
+        let frame = first_tx_frame(config, &[0x11; 7]).await;
+        assert_eq!(
+            frame.data,
+            vec![0x07, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11]
+        );
+        assert!(!frame.fd);
+    }
+
+    #[tokio::test]
+    async fn classic_no_single_frame_escape() {
+        let mut config = IsoTPConfig::new(0, Identifier::Standard(0x7a1));
+
+        // 8 bytes doesn't fit in a classic single frame and needs to use a first frame
+        let frame = first_tx_frame(config, &[0x11; 8]).await;
+        assert_eq!(
+            frame.data,
+            vec![0x10, 0x08, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11]
+        );
+
+        // A larger max_dlen should not cause an escape sequence in classic mode
+        config.max_dlen = Some(64);
+        let frame = first_tx_frame(config, &[0x11; 8]).await;
+        assert_eq!(
+            frame.data,
+            vec![0x10, 0x08, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11]
+        );
+        assert!(!frame.fd);
+
+        // With extended addressing only 6 bytes fit in a single frame
+        config.ext_address = Some(0xff);
+        let frame = first_tx_frame(config, &[0x11; 7]).await;
+        assert_eq!(
+            frame.data,
+            vec![0xff, 0x10, 0x07, 0x11, 0x11, 0x11, 0x11, 0x11]
+        );
+    }
+
+    /// Adapter that fails like an unplugged device
+    struct DisconnectedAdapter;
+
+    impl CanAdapter for DisconnectedAdapter {
+        fn send(&mut self, _frames: &mut VecDeque<Frame>) -> crate::Result<()> {
+            Err(crate::Error::Disconnected)
+        }
+
+        fn recv(&mut self) -> crate::Result<Vec<Frame>> {
+            Err(crate::Error::Disconnected)
+        }
+    }
+
+    #[tokio::test]
+    async fn recv_disconnect() {
+        let adapter = AsyncCanAdapter::new(DisconnectedAdapter);
+        let isotp = IsoTPAdapter::new(&adapter, IsoTPConfig::new(0, Identifier::Standard(0x7a1)));
+        let mut stream = isotp.recv();
+
+        // Disconnect is reported once, after which the stream ends
+        assert_eq!(stream.next().await, Some(Err(crate::Error::Disconnected)));
+        assert_eq!(stream.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn ignore_stale_consecutive_frame() {
+        // Respond with the tail of an earlier aborted transfer, followed by the actual response
+        let adapter = VirtualCanAdapter::simulated_ecu(|_| {
+            vec![
+                Frame::new(0, Identifier::Standard(0x7a9), &[0x22, 0xaa, 0xaa]).unwrap(),
+                Frame::new(0, Identifier::Standard(0x7a9), &[0x02, 0x7e, 0x00]).unwrap(),
+            ]
+        });
+        let isotp = IsoTPAdapter::from_id(&adapter, 0x7a1);
+
+        let stream = isotp.recv();
+        tokio::pin!(stream);
+
+        isotp.send(&[0x3e, 0x00]).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), vec![0x7e, 0x00]);
+    }
+
+    #[tokio::test]
+    async fn flush_stale_frames() {
+        let (tester, ecu) = VirtualCanAdapter::pair_async();
+        let isotp = IsoTPAdapter::from_id(&tester, 0x7a1);
+
+        let received = tester.recv_filter(|frame| !frame.loopback);
+        tokio::pin!(received);
+
+        // Tail of an earlier aborted transfer, and a frame on another ID
+        let stale = [
+            Frame::new(0, Identifier::Standard(0x7a9), &[0x22, 0xaa, 0xaa]).unwrap(),
+            Frame::new(0, Identifier::Standard(0x7a9), &[0x23, 0xaa, 0xaa]).unwrap(),
+            Frame::new(0, Identifier::Standard(0x7aa), &[0x24, 0xaa, 0xaa]).unwrap(),
+        ];
+        ecu.send_batch(&stale).await.unwrap();
+        for _ in 0..stale.len() {
+            received.next().await.unwrap();
+        }
+
+        assert_eq!(isotp.flush(), 2);
+        assert_eq!(isotp.flush(), 0);
+
+        // Frames handled by a transfer are not stale
+        let stream = isotp.recv();
+        tokio::pin!(stream);
+        let response = Frame::new(0, Identifier::Standard(0x7a9), &[0x02, 0x7e, 0x00]).unwrap();
+        ecu.send(&response).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), vec![0x7e, 0x00]);
+        assert_eq!(isotp.flush(), 0);
+    }
+
+    fn short_timeout_config() -> IsoTPConfig {
+        let mut config = IsoTPConfig::new(0, Identifier::Standard(0x7a1));
+        config.timeout = std::time::Duration::from_millis(20);
+        config
+    }
+
+    #[tokio::test]
+    async fn recv_first_frame_timeout() {
+        let adapter = AsyncCanAdapter::new(VirtualCanAdapter::new());
+        let isotp = IsoTPAdapter::new(&adapter, short_timeout_config());
+
+        let stream = isotp.recv();
+        tokio::pin!(stream);
+        assert_eq!(
+            stream.next().await.unwrap(),
+            Err(Error::FirstFrameTimeout.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn recv_consecutive_timeout() {
+        // Respond to the request with a first frame, but never send the consecutive frames
+        let adapter = VirtualCanAdapter::simulated_ecu(|frame| match frame.data[0] & 0xf0 {
+            0x00 => vec![Frame::new(
+                0,
+                Identifier::Standard(0x7a9),
+                &[0x10, 0x10, 1, 2, 3, 4, 5, 6],
+            )
+            .unwrap()],
+            _ => vec![],
+        });
+        let isotp = IsoTPAdapter::new(&adapter, short_timeout_config());
+
+        let stream = isotp.recv();
+        tokio::pin!(stream);
+        isotp.send(&[0x22, 0xf1, 0x90]).await.unwrap();
+        assert_eq!(
+            stream.next().await.unwrap(),
+            Err(Error::ConsecutiveTimeout.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn send_flow_control_timeout() {
+        // Nobody responds to the first frame
+        let adapter = AsyncCanAdapter::new(VirtualCanAdapter::new());
+        let isotp = IsoTPAdapter::new(&adapter, short_timeout_config());
+        assert_eq!(
+            isotp.send(&[0xaa; 20]).await,
+            Err(Error::FirstFrameTimeout.into())
+        );
+
+        // Flow control with a block size of 1 for the first frame, but not after the first block
+        let adapter = VirtualCanAdapter::simulated_ecu(|frame| match frame.data[0] & 0xf0 {
+            0x10 => vec![Frame::new(0, Identifier::Standard(0x7a9), &[0x30, 0x01, 0x00]).unwrap()],
+            _ => vec![],
+        });
+        let isotp = IsoTPAdapter::new(&adapter, short_timeout_config());
+        assert_eq!(
+            isotp.send(&[0xaa; 20]).await,
+            Err(Error::ConsecutiveTimeout.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn functional_single_frame_only() {
+        let config = IsoTPConfig::functional(0, Identifier::Standard(0x7df));
+        let adapter = AsyncCanAdapter::new(VirtualCanAdapter::new());
+        let isotp = IsoTPAdapter::new(&adapter, config);
+
+        assert_eq!(
+            isotp.send(&[0x11; 8]).await,
+            Err(Error::DataTooLarge.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn functional_recv_any() {
+        // One ECU responds with a single frame, the other with a multi frame response after receiving flow control on its physical ID
+        let adapter = VirtualCanAdapter::simulated_ecu(|frame| match frame.id {
+            Identifier::Standard(0x7df) => vec![
+                Frame::new(0, Identifier::Standard(0x7e8), &[0x02, 0x41, 0x00]).unwrap(),
+                Frame::new(
+                    0,
+                    Identifier::Standard(0x7e9),
+                    &[0x10, 0x08, 1, 2, 3, 4, 5, 6],
+                )
+                .unwrap(),
+            ],
+            Identifier::Standard(0x7e1) => {
+                vec![Frame::new(0, Identifier::Standard(0x7e9), &[0x21, 7, 8]).unwrap()]
+            }
+            _ => vec![],
+        });
+        let config = IsoTPConfig::functional(0, Identifier::Standard(0x7df));
+        let isotp = IsoTPAdapter::new(&adapter, config);
+
+        let stream = isotp.recv_any(|id| matches!(id, Identifier::Standard(0x7e8..=0x7ef)));
+        tokio::pin!(stream);
+
+        isotp.send(&[0x01, 0x00]).await.unwrap();
+        assert_eq!(
+            stream.next().await.unwrap().unwrap(),
+            (Identifier::Standard(0x7e8), vec![0x41, 0x00])
+        );
+        assert_eq!(
+            stream.next().await.unwrap().unwrap(),
+            (Identifier::Standard(0x7e9), vec![1, 2, 3, 4, 5, 6, 7, 8])
+        );
+        assert_eq!(
+            stream.next().await.unwrap(),
+            Err(Error::FirstFrameTimeout.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn recv_any_short_frames() {
+        // Empty frame and a truncated CAN-FD escape sequence, followed by a valid response
+        let adapter = VirtualCanAdapter::simulated_ecu(|_| {
+            vec![
+                Frame::new(0, Identifier::Standard(0x7e8), &[]).unwrap(),
+                Frame::new(0, Identifier::Standard(0x7e9), &[0x00]).unwrap(),
+                Frame::new(0, Identifier::Standard(0x7e8), &[0x02, 0x41, 0x00]).unwrap(),
+            ]
+        });
+        let config = IsoTPConfig::functional(0, Identifier::Standard(0x7df));
+        let isotp = IsoTPAdapter::new(&adapter, config);
+
+        let stream = isotp.recv_any(|id| matches!(id, Identifier::Standard(0x7e8..=0x7ef)));
+        tokio::pin!(stream);
+
+        isotp.send(&[0x01, 0x00]).await.unwrap();
+        assert_eq!(
+            stream.next().await.unwrap(),
+            Err(Error::MalformedFrame.into())
+        );
+        assert_eq!(
+            stream.next().await.unwrap().unwrap(),
+            (Identifier::Standard(0x7e8), vec![0x41, 0x00])
+        );
+    }
+
+    #[tokio::test]
+    async fn recv_any_evict_stalled_transfer() {
+        let (tester, ecu) = VirtualCanAdapter::pair_async();
+        let mut config = IsoTPConfig::functional(0, Identifier::Standard(0x7df));
+        config.timeout = std::time::Duration::from_millis(20);
+        let isotp = IsoTPAdapter::new(&tester, config);
+
+        let stream = isotp.recv_any(|id| matches!(id, Identifier::Standard(0x7e8..=0x7ef)));
+        tokio::pin!(stream);
+
+        // First frame without any consecutive frames
+        let first = Frame::new(
+            0,
+            Identifier::Standard(0x7e8),
+            &[0x10, 0x08, 1, 2, 3, 4, 5, 6],
+        );
+        ecu.send(&first.unwrap()).await.unwrap();
+        assert_eq!(
+            stream.next().await.unwrap(),
+            Err(Error::ConsecutiveTimeout.into())
+        );
+
+        // A late consecutive frame must not complete the evicted transfer
+        let late = Frame::new(0, Identifier::Standard(0x7e8), &[0x21, 7, 8]).unwrap();
+        let response = Frame::new(0, Identifier::Standard(0x7e8), &[0x02, 0x41, 0x00]).unwrap();
+        ecu.send_batch(&[late, response]).await.unwrap();
+        assert_eq!(
+            stream.next().await.unwrap().unwrap(),
+            (Identifier::Standard(0x7e8), vec![0x41, 0x00])
+        );
+    }
+
+    #[tokio::test]
+    async fn recv_any_no_physical_tx_id() {
+        let (tester, ecu) = VirtualCanAdapter::pair_async();
+        let config = IsoTPConfig::functional(0, Identifier::Standard(0x7df));
+        let isotp = IsoTPAdapter::new(&tester, config);
+
+        let stream = isotp.recv_any(|_| true);
+        tokio::pin!(stream);
+
+        // Single frames don't need flow control
+        let frame = Frame::new(0, Identifier::Standard(0x005), &[0x02, 0x41, 0x00]).unwrap();
+        ecu.send(&frame).await.unwrap();
+        assert_eq!(
+            stream.next().await.unwrap().unwrap(),
+            (Identifier::Standard(0x005), vec![0x41, 0x00])
+        );
+
+        let frame = Frame::new(
+            0,
+            Identifier::Standard(0x005),
+            &[0x10, 0x08, 1, 2, 3, 4, 5, 6],
+        )
+        .unwrap();
+        ecu.send(&frame).await.unwrap();
+        assert_eq!(
+            stream.next().await.unwrap(),
+            Err(Error::MalformedFrame.into())
+        );
+    }
+
+    #[test]
+    fn separation_time_encoding() {
+        use std::time::Duration;
+
+        assert_eq!(types::encode_separation_time(Duration::ZERO), 0x00);
+        assert_eq!(
+            types::encode_separation_time(Duration::from_micros(100)),
+            0xf1
+        );
+        assert_eq!(
+            types::encode_separation_time(Duration::from_micros(450)),
+            0xf5
+        );
+        assert_eq!(
+            types::encode_separation_time(Duration::from_micros(900)),
+            0xf9
+        );
+        assert_eq!(
+            types::encode_separation_time(Duration::from_micros(950)),
+            0x01
+        );
+        assert_eq!(
+            types::encode_separation_time(Duration::from_millis(20)),
+            0x14
+        );
+        assert_eq!(types::encode_separation_time(Duration::from_secs(1)), 0x7f);
+    }
+
+    #[tokio::test]
+    async fn rx_block_size() {
+        static FLOW_CONTROL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        // 27 byte response, sent as a first frame and three consecutive frames. Consecutive frames are only sent after receiving the expected flow control.
+        let adapter = VirtualCanAdapter::simulated_ecu(|frame| {
+            let id = Identifier::Standard(0x7a9);
+            if frame.data[0] == 0x02 {
+                return vec![Frame::new(0, id, &[0x10, 27, 0, 1, 2, 3, 4, 5]).unwrap()];
+            }
+
+            if frame.data != [0x30, 0x02, 0xf5, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa] {
+                return vec![];
+            }
+
+            match FLOW_CONTROL_COUNT.fetch_add(1, Ordering::Relaxed) {
+                0 => vec![
+                    Frame::new(0, id, &[0x21, 6, 7, 8, 9, 10, 11, 12]).unwrap(),
+                    Frame::new(0, id, &[0x22, 13, 14, 15, 16, 17, 18, 19]).unwrap(),
+                ],
+                1 => vec![Frame::new(0, id, &[0x23, 20, 21, 22, 23, 24, 25, 26]).unwrap()],
+                _ => vec![],
+            }
+        });
+
+        let mut config = IsoTPConfig::new(0, Identifier::Standard(0x7a1));
+        config.rx_block_size = 2;
+        config.rx_separation_time_min = std::time::Duration::from_micros(500);
+        let isotp = IsoTPAdapter::new(&adapter, config);
+
+        let stream = isotp.recv();
+        tokio::pin!(stream);
+
+        isotp.send(&[0x3e, 0x00]).await.unwrap();
+        assert_eq!(
+            stream.next().await.unwrap().unwrap(),
+            (0..27).collect::<Vec<u8>>()
+        );
+        assert_eq!(FLOW_CONTROL_COUNT.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn tx_min_separation_floor() {
+        // Flow control without separation time
+        let adapter =
+            VirtualCanAdapter::simulated_ecu(|frame| match frame.data[0] & FRAME_TYPE_MASK {
+                0x10 => {
+                    vec![Frame::new(0, Identifier::Standard(0x7a9), &[0x30, 0x00, 0x00]).unwrap()]
+                }
+                _ => vec![],
+            });
+
+        let mut config = IsoTPConfig::new(0, Identifier::Standard(0x7a1));
+        config.min_separation_floor = std::time::Duration::from_millis(20);
+        let isotp = IsoTPAdapter::new(&adapter, config);
+
+        // First frame and three consecutive frames, with a separation time between the consecutive frames
+        let start = tokio::time::Instant::now();
+        isotp.send(&[0xaa; 27]).await.unwrap();
+        assert!(start.elapsed() >= std::time::Duration::from_millis(40));
+    }
+
+    /// First frame and consecutive frames for `data`, with the sequence numbers produced by `seq`
+    fn multi_frame(data: &[u8], seq: impl Fn(usize) -> u8) -> Vec<Frame> {
+        let id = Identifier::Standard(0x7a9);
+        let mut first = vec![0x10 | (data.len() >> 8) as u8, data.len() as u8];
+        first.extend(&data[..6]);
+
+        let mut frames = vec![Frame::new(0, id, &first).unwrap()];
+        for (idx, chunk) in data[6..].chunks(7).enumerate() {
+            let mut cf = vec![FrameType::Consecutive as u8 | seq(idx)];
+            cf.extend(chunk);
+            frames.push(Frame::new(0, id, &cf).unwrap());
+        }
+        frames
+    }
+
+    #[tokio::test]
+    async fn rx_consecutive_index_rollover() {
+        let adapter = AsyncCanAdapter::new(VirtualCanAdapter::new());
+        let isotp = IsoTPAdapter::from_id(&adapter, 0x7a1);
+        let timeout = std::time::Duration::from_millis(100);
+
+        // 42 consecutive frames, the sequence number wraps from 0xF to 0x0 twice. Same numbering as send_consecutive_frame.
+        let data: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let frames = multi_frame(&data, |idx| ((idx + 1) & 0xF) as u8);
+        let stream = tokio_stream::iter(frames).timeout(timeout);
+        tokio::pin!(stream);
+        assert_eq!(isotp.recv_from_stream(&mut stream).await, Ok(data.clone()));
+
+        // Restarting at 1 instead of wrapping to 0
+        let frames = multi_frame(&data, |idx| (idx % 15 + 1) as u8);
+        let stream = tokio_stream::iter(frames).timeout(timeout);
+        tokio::pin!(stream);
+        assert_eq!(
+            isotp.recv_from_stream(&mut stream).await,
+            Err(Error::OutOfOrder.into())
+        );
+    }
+
+    /// Responds to a request with a first frame, and sends the consecutive frame once Continue To Send is received. Wait flow control frames are counted in `wait_count`.
+    fn respond_after_wait(frame: &Frame, wait_count: &AtomicUsize) -> Vec<Frame> {
+        let id = Identifier::Standard(0x7a9);
+        match frame.data[0] {
+            0x02 => vec![Frame::new(0, id, &[0x10, 13, 0, 1, 2, 3, 4, 5]).unwrap()],
+            0x30 => vec![Frame::new(0, id, &[0x21, 6, 7, 8, 9, 10, 11, 12]).unwrap()],
+            0x31 => {
+                wait_count.fetch_add(1, Ordering::Relaxed);
+                vec![]
+            }
+            _ => vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn rx_wait_flow_control() {
+        static WAIT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let adapter =
+            VirtualCanAdapter::simulated_ecu(|frame| respond_after_wait(frame, &WAIT_COUNT));
+
+        // Not ready for the first two flow control attempts
+        let ready_calls = Arc::new(AtomicUsize::new(0));
+        let calls = ready_calls.clone();
+        let mut config = IsoTPConfig::new(0, Identifier::Standard(0x7a1));
+        config.timeout = std::time::Duration::from_millis(200);
+        let isotp = IsoTPAdapter::new(&adapter, config)
+            .with_rx_ready(move || calls.fetch_add(1, Ordering::Relaxed) >= 2);
+
+        let stream = isotp.recv();
+        tokio::pin!(stream);
+
+        isotp.send(&[0x3e, 0x00]).await.unwrap();
+        assert_eq!(
+            stream.next().await.unwrap().unwrap(),
+            (0..13).collect::<Vec<u8>>()
+        );
+        assert_eq!(WAIT_COUNT.load(Ordering::Relaxed), 2);
+        assert_eq!(ready_calls.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn rx_too_many_wait_flow_control() {
+        static WAIT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let adapter =
+            VirtualCanAdapter::simulated_ecu(|frame| respond_after_wait(frame, &WAIT_COUNT));
+
+        let mut config = IsoTPConfig::new(0, Identifier::Standard(0x7a1));
+        config.max_wait_frames = 3;
+        let isotp = IsoTPAdapter::new(&adapter, config).with_rx_ready(|| false);
+
+        let stream = isotp.recv();
+        tokio::pin!(stream);
+
+        isotp.send(&[0x3e, 0x00]).await.unwrap();
+        assert_eq!(
+            stream.next().await.unwrap(),
+            Err(Error::TooManyFCWait.into())
+        );
+        assert_eq!(WAIT_COUNT.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn force_tx_dl() {
+        // Respond to the first frame with flow control
+        let adapter =
+            VirtualCanAdapter::simulated_ecu(|frame| match frame.data[0] & FRAME_TYPE_MASK {
+                0x10 => {
+                    vec![Frame::new(0, Identifier::Standard(0x7a9), &[0x30, 0x00, 0x00]).unwrap()]
+                }
+                _ => vec![],
+            });
+
+        let mut config = IsoTPConfig::new(0, Identifier::Standard(0x7a1));
+        config.fd = true;
+        config.max_dlen = Some(16);
+        config.padding = None;
+        config.force_tx_dl = true;
+        let isotp = IsoTPAdapter::new(&adapter, config);
+        assert_eq!(isotp.tx_data_length(), 16);
+
+        let stream = adapter.recv_filter(|frame| frame.loopback);
+        tokio::pin!(stream);
+
+        // First frame with 14 bytes, and a consecutive frame with the remaining 2 bytes
+        isotp.send(&[0x11; 16]).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().data.len(), 16);
+
+        let last = stream.next().await.unwrap();
+        assert_eq!(last.data[..3], [0x21, 0x11, 0x11]);
+        assert_eq!(last.data.len(), 16);
+    }
+
+    #[tokio::test]
+    async fn pad_ext_address() {
+        let adapter = AsyncCanAdapter::new(VirtualCanAdapter::new());
+
+        let mut config = IsoTPConfig::new(0, Identifier::Standard(0x7a1));
+        config.ext_address = Some(0xff);
+        config.fd = true;
+
+        // Returns the total frame length, including the extended address
+        let padded_len = |config: IsoTPConfig, len: usize| {
+            let isotp = IsoTPAdapter::new(&adapter, config);
+            let mut data = vec![0x11; len];
+            isotp.pad(&mut data);
+            isotp.frame(&data).unwrap().data.len()
+        };
+
+        assert_eq!(padded_len(config, 6), 8);
+        assert_eq!(padded_len(config, 7), 8);
+        assert_eq!(padded_len(config, 8), 12);
+        assert_eq!(padded_len(config, 11), 12);
+        assert_eq!(padded_len(config, 12), 16);
+
+        config.padding = None;
+        assert_eq!(padded_len(config, 6), 7);
+        assert_eq!(padded_len(config, 7), 8);
+        assert_eq!(padded_len(config, 8), 12);
+    }
+
+    #[tokio::test]
+    async fn send_progress() {
+        // Respond to the first frame with flow control
+        let adapter =
+            VirtualCanAdapter::simulated_ecu(|frame| match frame.data[0] & FRAME_TYPE_MASK {
+                0x10 => {
+                    vec![Frame::new(0, Identifier::Standard(0x7a9), &[0x30, 0x00, 0x00]).unwrap()]
+                }
+                _ => vec![],
+            });
+        let isotp = IsoTPAdapter::from_id(&adapter, 0x7a1);
+
+        let mut progress = vec![];
+        isotp
+            .send_with_progress(&[0x11; 20], |sent, total| progress.push((sent, total)))
+            .await
+            .unwrap();
+        assert_eq!(progress, vec![(6, 20), (13, 20), (20, 20)]);
+
+        let mut progress = vec![];
+        isotp
+            .send_with_progress(&[0x11; 2], |sent, total| progress.push((sent, total)))
+            .await
+            .unwrap();
+        assert_eq!(progress, vec![(2, 2)]);
+    }
+
+    #[test]
+    fn normal_fixed_addressing() {
+        let config = IsoTPConfig::normal_fixed(0, 6, 0xf1, 0x10);
+        assert_eq!(config.tx_id, Identifier::Extended(0x18da10f1));
+        assert_eq!(config.rx_id, Identifier::Extended(0x18daf110));
+
+        // Matches the convention used for extended IDs in IsoTPConfig::new
+        let config = IsoTPConfig::new(0, Identifier::Extended(0x18da10f1));
+        assert_eq!(config.rx_id, Identifier::Extended(0x18daf110));
+
+        let config = IsoTPConfig::normal_fixed_functional(0, 6, 0xf1, 0x33);
+        assert_eq!(config.tx_id, Identifier::Extended(0x18db33f1));
+        assert!(config.functional);
+    }
+}
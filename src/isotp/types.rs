@@ -1,4 +1,5 @@
 use crate::can::Frame;
+use crate::isotp::constants::{FlowStatus, FrameType};
 
 #[derive(Debug, Copy, Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -29,3 +30,24 @@ impl TryFrom<&Frame> for FlowControlConfig {
         })
     }
 }
+
+impl FlowControlConfig {
+    /// Encodes the flow control frame payload (without padding or extended address)
+    pub fn encode(&self, flow_status: FlowStatus) -> Vec<u8> {
+        vec![
+            FrameType::FlowControl as u8 | flow_status as u8,
+            self.block_size,
+            encode_separation_time(self.separation_time_min),
+        ]
+    }
+}
+
+/// Encodes a separation time as STmin byte. Values below 1 ms are rounded up to the next 100 us step (0xF1 - 0xF9), larger values are rounded up to whole milliseconds and capped at 127 ms.
+pub fn encode_separation_time(separation_time: std::time::Duration) -> u8 {
+    let micros = separation_time.as_micros();
+    match micros {
+        0 => 0x00,
+        1..=900 => 0xf0 + micros.div_ceil(100) as u8,
+        _ => std::cmp::min(micros.div_ceil(1000), 0x7f) as u8,
+    }
+}
@@ -0,0 +1,39 @@
+//! Constants for the J1939 transport protocol.
+
+/// Destination address used for broadcast messages
+pub static GLOBAL_ADDRESS: u8 = 0xff;
+
+/// Transport Protocol - Connection Management (TP.CM)
+pub static PGN_TP_CM: u32 = 0xec00;
+/// Transport Protocol - Data Transfer (TP.DT)
+pub static PGN_TP_DT: u32 = 0xeb00;
+
+/// PDU Format values below this are PDU1 (destination specific), others are PDU2 (broadcast)
+pub static PDU2_FORMAT_MIN: u8 = 240;
+
+/// Control bytes of the TP.CM messages as defined in SAE J1939-21
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum ControlByte {
+    RequestToSend = 16,
+    ClearToSend = 17,
+    EndOfMessageAcknowledge = 19,
+    BroadcastAnnounceMessage = 32,
+    ConnectionAbort = 255,
+}
+
+/// Connection abort reasons as defined in SAE J1939-21
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum AbortReason {
+    AlreadyInSession = 1,
+    ResourcesNeeded = 2,
+    Timeout = 3,
+    CtsWhileTransferring = 4,
+    MaxRetransmitReached = 5,
+    UnexpectedDataTransfer = 6,
+    BadSequenceNumber = 7,
+    DuplicateSequenceNumber = 8,
+}
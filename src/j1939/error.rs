@@ -0,0 +1,12 @@
+//! Error types for the J1939 transport protocol.
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum Error {
+    #[error("Data Too Large")]
+    DataTooLarge,
+    #[error("Connection Aborted, reason {0}")]
+    ConnectionAborted(u8),
+    #[error("Malformed Frame")]
+    MalformedFrame,
+}
@@ -0,0 +1,681 @@
+//! SAE J1939 Transport Protocol, implements the PGN addressing and the multi packet BAM and RTS/CTS sessions of J1939-21
+//! ## Example
+//! ```rust
+//! async fn j1939_example() {
+//!     use automotive::StreamExt;
+//!
+//!     let adapter = automotive::can::get_adapter().unwrap();
+//!     let j1939 = automotive::j1939::J1939Adapter::new(&adapter, automotive::j1939::J1939Config::new(0, 0xf9));
+//!
+//!     // Request the Vehicle Identification PGN from all ECUs
+//!     let mut stream = j1939.recv_pgn(0xfeec);
+//!     j1939.send_pgn(0xea00, automotive::j1939::GLOBAL_ADDRESS, &[0xec, 0xfe, 0x00]).await.unwrap();
+//!
+//!     let msg = stream.next().await.unwrap().unwrap();
+//!     println!("VIN from 0x{:02x}: {}", msg.source, String::from_utf8_lossy(&msg.data));
+//! }
+//! ```
+
+mod constants;
+mod error;
+
+use crate::can::{AsyncCanAdapter, Frame, Identifier};
+use crate::Result;
+use crate::{Stream, StreamExt};
+pub use constants::*;
+pub use error::Error;
+
+use async_stream::stream;
+use std::collections::HashMap;
+use tracing::{debug, warn};
+
+const DEFAULT_PRIORITY: u8 = 6;
+/// Priority used for the transport protocol messages
+const TP_PRIORITY: u8 = 7;
+
+/// Response timeout (T3/T4) when sending using RTS/CTS
+const DEFAULT_TIMEOUT_MS: u64 = 1250;
+/// Time between the data transfer packets of a broadcast (BAM) message, 50 to 200 ms
+const DEFAULT_BAM_SEPARATION_TIME_MS: u64 = 50;
+/// Max time between two received data transfer packets (T1)
+const T1_MS: u64 = 750;
+/// Max time between sending a CTS and receiving the first data transfer packet (T2)
+const T2_MS: u64 = 1250;
+
+const TP_DT_DATA_LEN: usize = 7;
+const TP_MAX_DLEN: usize = 255 * TP_DT_DATA_LEN;
+const PADDING_BYTE: u8 = 0xff;
+
+/// Decomposition of a 29 bit J1939 identifier
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct J1939Id {
+    pub priority: u8,
+    /// Parameter Group Number. For PDU1 PGNs the destination address is not included.
+    pub pgn: u32,
+    pub source: u8,
+    /// Destination address. Always [`GLOBAL_ADDRESS`] for PDU2 PGNs.
+    pub destination: u8,
+}
+
+/// Returns true if the PGN is destination specific (PDU1)
+fn is_pdu1(pgn: u32) -> bool {
+    (((pgn >> 8) & 0xff) as u8) < PDU2_FORMAT_MIN
+}
+
+impl From<u32> for J1939Id {
+    fn from(id: u32) -> J1939Id {
+        let pgn = (id >> 8) & 0x3ffff;
+        let (pgn, destination) = match is_pdu1(pgn) {
+            true => (pgn & 0x3ff00, (pgn & 0xff) as u8),
+            false => (pgn, GLOBAL_ADDRESS),
+        };
+
+        J1939Id {
+            priority: ((id >> 26) & 0x7) as u8,
+            pgn,
+            source: (id & 0xff) as u8,
+            destination,
+        }
+    }
+}
+
+impl From<J1939Id> for Identifier {
+    fn from(id: J1939Id) -> Identifier {
+        let mut pgn = id.pgn & 0x3ffff;
+        if is_pdu1(pgn) {
+            pgn = (pgn & 0x3ff00) | id.destination as u32;
+        }
+        Identifier::Extended(((id.priority as u32 & 0x7) << 26) | (pgn << 8) | id.source as u32)
+    }
+}
+
+/// A J1939 message, either received in a single frame or reassembled by the transport protocol
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct J1939Message {
+    pub priority: u8,
+    pub pgn: u32,
+    pub source: u8,
+    pub destination: u8,
+    pub data: Vec<u8>,
+}
+
+/// Configuration passed to the J1939Adapter.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct J1939Config {
+    pub bus: u8,
+    /// Own address, used as source address for transmitted messages
+    pub source_address: u8,
+    /// Priority of transmitted single frame messages
+    pub priority: u8,
+    /// Max time to wait for a response of the receiver when sending using RTS/CTS
+    pub timeout: std::time::Duration,
+    /// Time between the data transfer packets of a broadcast (BAM) message
+    pub bam_separation_time: std::time::Duration,
+}
+
+impl J1939Config {
+    pub fn new(bus: u8, source_address: u8) -> Self {
+        Self {
+            bus,
+            source_address,
+            priority: DEFAULT_PRIORITY,
+            timeout: std::time::Duration::from_millis(DEFAULT_TIMEOUT_MS),
+            bam_separation_time: std::time::Duration::from_millis(DEFAULT_BAM_SEPARATION_TIME_MS),
+        }
+    }
+}
+
+/// Multi packet message being received
+struct Session {
+    pgn: u32,
+    destination: u8,
+    size: usize,
+    packets: u8,
+    data: Vec<u8>,
+    /// Next expected sequence number
+    next: u8,
+    /// Last sequence number of the current CTS block, only used for RTS/CTS sessions
+    block_end: u8,
+    max_block_size: u8,
+    rts: bool,
+    /// The session is dropped if the next data transfer packet arrives after this (T1 or T2)
+    deadline: std::time::Instant,
+}
+
+/// Encodes a TP.CM message with the PGN of the transported message in the last three bytes
+fn tp_cm(control: ControlByte, bytes: [u8; 4], pgn: u32) -> Vec<u8> {
+    let mut data = vec![control as u8];
+    data.extend(bytes);
+    data.extend(&pgn.to_le_bytes()[..3]);
+    data
+}
+
+fn tp_cm_pgn(data: &[u8]) -> u32 {
+    u32::from_le_bytes([data[5], data[6], data[7], 0])
+}
+
+/// Wraps a CAN adapter to send and receive PGN addressed messages, handling the J1939 transport protocol for messages longer than 8 bytes.
+pub struct J1939Adapter<'a> {
+    adapter: &'a AsyncCanAdapter,
+    config: J1939Config,
+}
+
+impl<'a> J1939Adapter<'a> {
+    pub fn new(adapter: &'a AsyncCanAdapter, config: J1939Config) -> Self {
+        Self { adapter, config }
+    }
+
+    async fn send_frame(&self, priority: u8, pgn: u32, dst: u8, data: &[u8]) -> Result<()> {
+        let id = J1939Id {
+            priority,
+            pgn,
+            source: self.config.source_address,
+            destination: dst,
+        };
+
+        let frame = Frame::new(self.config.bus, id.into(), data)?;
+        self.adapter.send(&frame).await
+    }
+
+    async fn send_tp_dt(&self, dst: u8, data: &[u8], seq: u8) -> Result<()> {
+        let start = (seq as usize - 1) * TP_DT_DATA_LEN;
+        let end = (start + TP_DT_DATA_LEN).min(data.len());
+
+        let mut packet = vec![seq];
+        packet.extend(&data[start..end]);
+        packet.resize(8, PADDING_BYTE);
+
+        self.send_frame(TP_PRIORITY, PGN_TP_DT, dst, &packet).await
+    }
+
+    /// Send a message to the destination address. Messages of up to 8 bytes are sent in a single frame. Larger messages, up to 1785 bytes, are broadcast using BAM if the destination is [`GLOBAL_ADDRESS`], or sent using an RTS/CTS session otherwise.
+    pub async fn send_pgn(&self, pgn: u32, dst: u8, data: &[u8]) -> Result<()> {
+        if data.len() <= 8 {
+            return self.send_frame(self.config.priority, pgn, dst, data).await;
+        }
+        if data.len() > TP_MAX_DLEN {
+            return Err(Error::DataTooLarge.into());
+        }
+
+        let size = (data.len() as u16).to_le_bytes();
+        let packets = data.len().div_ceil(TP_DT_DATA_LEN) as u8;
+
+        if dst == GLOBAL_ADDRESS {
+            let bam = tp_cm(
+                ControlByte::BroadcastAnnounceMessage,
+                [size[0], size[1], packets, 0xff],
+                pgn,
+            );
+            self.send_frame(TP_PRIORITY, PGN_TP_CM, dst, &bam).await?;
+
+            for seq in 1..=packets {
+                tokio::time::sleep(self.config.bam_separation_time).await;
+                self.send_tp_dt(dst, data, seq).await?;
+            }
+            return Ok(());
+        }
+
+        // Listen for the connection management messages from the receiver
+        let (bus, source) = (self.config.bus, self.config.source_address);
+        let stream = self
+            .adapter
+            .recv_filter(move |frame| {
+                let Identifier::Extended(id) = frame.id else {
+                    return false;
+                };
+                let id = J1939Id::from(id);
+                frame.bus == bus
+                    && !frame.loopback
+                    && id.pgn == PGN_TP_CM
+                    && id.source == dst
+                    && id.destination == source
+                    && frame.data.len() == 8
+                    && tp_cm_pgn(&frame.data) == pgn
+            })
+            .timeout(self.config.timeout);
+        tokio::pin!(stream);
+
+        let rts = tp_cm(
+            ControlByte::RequestToSend,
+            [size[0], size[1], packets, 0xff],
+            pgn,
+        );
+        self.send_frame(TP_PRIORITY, PGN_TP_CM, dst, &rts).await?;
+
+        loop {
+            let frame = stream.next().await.ok_or(crate::Error::Disconnected)??;
+            match frame.data[0] {
+                c if c == ControlByte::ClearToSend as u8 => {
+                    // A CTS for 0 packets holds the connection open
+                    let (count, next) = (frame.data[1], frame.data[2]);
+                    if count > 0 && (next == 0 || next > packets) {
+                        let reason = AbortReason::BadSequenceNumber as u8;
+                        let abort = tp_cm(
+                            ControlByte::ConnectionAbort,
+                            [reason, 0xff, 0xff, 0xff],
+                            pgn,
+                        );
+                        self.send_frame(TP_PRIORITY, PGN_TP_CM, dst, &abort).await?;
+                        return Err(Error::ConnectionAborted(reason).into());
+                    }
+                    for seq in (next..=packets).take(count as usize) {
+                        self.send_tp_dt(dst, data, seq).await?;
+                    }
+                }
+                c if c == ControlByte::EndOfMessageAcknowledge as u8 => return Ok(()),
+                c if c == ControlByte::ConnectionAbort as u8 => {
+                    return Err(Error::ConnectionAborted(frame.data[1]).into());
+                }
+                c => debug!("Ignoring TP.CM with control byte {}", c),
+            }
+        }
+    }
+
+    /// Stream of all messages addressed to this node or broadcast. Multi packet messages are reassembled, and RTS/CTS sessions addressed to this node are acknowledged. Sessions are dropped if a data transfer packet is not received in time (T1 between packets, T2 after a CTS), aborting RTS/CTS sessions with [`AbortReason::Timeout`].
+    pub fn recv(&self) -> impl Stream<Item = Result<J1939Message>> + Unpin + '_ {
+        let (bus, source) = (self.config.bus, self.config.source_address);
+        let stream = self.adapter.recv_filter(move |frame| {
+            let Identifier::Extended(id) = frame.id else {
+                return false;
+            };
+            let id = J1939Id::from(id);
+            frame.bus == bus
+                && !frame.loopback
+                && (id.destination == source || id.destination == GLOBAL_ADDRESS)
+        });
+
+        Box::pin(stream! {
+            tokio::pin!(stream);
+
+            // Multi packet messages in progress per source and destination address. A node can send a BAM and an RTS/CTS session at the same time.
+            let mut sessions: HashMap<(u8, u8), Session> = HashMap::new();
+
+            while let Some(frame) = stream.next().await {
+                let Identifier::Extended(id) = frame.id else { continue };
+                let id = J1939Id::from(id);
+                let key = (id.source, id.destination);
+
+                // Drop stalled sessions, so later data transfer packets don't get attached to them
+                let now = std::time::Instant::now();
+                let expired: Vec<_> = sessions.iter().filter(|(_, session)| now > session.deadline).map(|(&key, _)| key).collect();
+                for (src, dst) in expired {
+                    let session = sessions.remove(&(src, dst)).unwrap();
+                    warn!("TP session from 0x{:02x} timed out", src);
+
+                    if session.rts {
+                        let abort = tp_cm(ControlByte::ConnectionAbort, [AbortReason::Timeout as u8, 0xff, 0xff, 0xff], session.pgn);
+                        if let Err(e) = self.send_frame(TP_PRIORITY, PGN_TP_CM, src, &abort).await {
+                            yield Err(e);
+                        }
+                    }
+                }
+
+                if id.pgn == PGN_TP_CM {
+                    if frame.data.len() != 8 {
+                        yield Err(Error::MalformedFrame.into());
+                        continue;
+                    }
+
+                    let data = &frame.data;
+                    let size = u16::from_le_bytes([data[1], data[2]]) as usize;
+                    let mut session = Session {
+                        pgn: tp_cm_pgn(data),
+                        destination: id.destination,
+                        size,
+                        packets: data[3],
+                        data: vec![],
+                        next: 1,
+                        block_end: 0,
+                        max_block_size: data[4],
+                        rts: false,
+                        deadline: now + std::time::Duration::from_millis(T1_MS),
+                    };
+
+                    match data[0] {
+                        c if c == ControlByte::BroadcastAnnounceMessage as u8 && id.destination == GLOBAL_ADDRESS => {
+                            sessions.insert(key, session);
+                        }
+                        c if c == ControlByte::RequestToSend as u8 && id.destination == source => {
+                            session.rts = true;
+                            session.block_end = session.packets.min(session.max_block_size);
+                            session.deadline = now + std::time::Duration::from_millis(T2_MS);
+
+                            let cts = tp_cm(ControlByte::ClearToSend, [session.block_end, 1, 0xff, 0xff], session.pgn);
+                            if let Err(e) = self.send_frame(TP_PRIORITY, PGN_TP_CM, id.source, &cts).await {
+                                yield Err(e);
+                                continue;
+                            }
+                            sessions.insert(key, session);
+                        }
+                        c if c == ControlByte::ConnectionAbort as u8 => {
+                            sessions.remove(&key);
+                        }
+                        _ => {}
+                    }
+                } else if id.pgn == PGN_TP_DT {
+                    let Some(session) = sessions.get_mut(&key) else {
+                        debug!("Ignoring TP.DT without session from 0x{:02x}", id.source);
+                        continue;
+                    };
+                    if frame.data.is_empty() {
+                        continue;
+                    }
+
+                    let seq = frame.data[0];
+                    if seq != session.next {
+                        warn!("TP.DT from 0x{:02x} out of order, expected {} got {}", id.source, session.next, seq);
+                        let rts = session.rts;
+                        let pgn = session.pgn;
+                        sessions.remove(&key);
+
+                        if rts {
+                            let abort = tp_cm(ControlByte::ConnectionAbort, [AbortReason::BadSequenceNumber as u8, 0xff, 0xff, 0xff], pgn);
+                            if let Err(e) = self.send_frame(TP_PRIORITY, PGN_TP_CM, id.source, &abort).await {
+                                yield Err(e);
+                            }
+                        }
+                        continue;
+                    }
+
+                    session.data.extend(&frame.data[1..]);
+                    session.next = seq.wrapping_add(1);
+                    session.deadline = now + std::time::Duration::from_millis(T1_MS);
+
+                    if session.data.len() >= session.size {
+                        let mut session = sessions.remove(&key).unwrap();
+                        session.data.truncate(session.size);
+
+                        if session.rts {
+                            let size = (session.size as u16).to_le_bytes();
+                            let ack = tp_cm(ControlByte::EndOfMessageAcknowledge, [size[0], size[1], session.packets, 0xff], session.pgn);
+                            if let Err(e) = self.send_frame(TP_PRIORITY, PGN_TP_CM, id.source, &ack).await {
+                                yield Err(e);
+                                continue;
+                            }
+                        }
+
+                        yield Ok(J1939Message {
+                            priority: id.priority,
+                            pgn: session.pgn,
+                            source: id.source,
+                            destination: session.destination,
+                            data: session.data,
+                        });
+                    } else if session.rts && seq == session.block_end {
+                        let count = session.packets.saturating_sub(seq).min(session.max_block_size);
+                        session.block_end = seq.saturating_add(count);
+
+                        session.deadline = now + std::time::Duration::from_millis(T2_MS);
+
+                        let cts = tp_cm(ControlByte::ClearToSend, [count, seq.wrapping_add(1), 0xff, 0xff], session.pgn);
+                        if let Err(e) = self.send_frame(TP_PRIORITY, PGN_TP_CM, id.source, &cts).await {
+                            yield Err(e);
+                        }
+                    }
+                } else {
+                    yield Ok(J1939Message {
+                        priority: id.priority,
+                        pgn: id.pgn,
+                        source: id.source,
+                        destination: id.destination,
+                        data: frame.data,
+                    });
+                }
+            }
+        })
+    }
+
+    /// Stream of messages with the given PGN, see [`J1939Adapter::recv`]
+    pub fn recv_pgn(&self, pgn: u32) -> impl Stream<Item = Result<J1939Message>> + Unpin + '_ {
+        self.recv()
+            .filter(move |msg| msg.as_ref().map_or(true, |msg| msg.pgn == pgn))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::can::virtual_can::VirtualCanAdapter;
+
+    #[test]
+    fn decompose_id() {
+        // Engine speed (EEC1), PDU2 broadcast from the engine
+        let id = J1939Id::from(0x0cf00400);
+        assert_eq!(
+            id,
+            J1939Id {
+                priority: 3,
+                pgn: 0xf004,
+                source: 0x00,
+                destination: GLOBAL_ADDRESS
+            }
+        );
+        assert_eq!(Identifier::from(id), Identifier::Extended(0x0cf00400));
+
+        // Request PGN, PDU1 addressed from the tester to the engine
+        let id = J1939Id::from(0x18ea00f9);
+        assert_eq!(
+            id,
+            J1939Id {
+                priority: 6,
+                pgn: 0xea00,
+                source: 0xf9,
+                destination: 0x00
+            }
+        );
+        assert_eq!(Identifier::from(id), Identifier::Extended(0x18ea00f9));
+    }
+
+    #[tokio::test]
+    async fn single_frame() {
+        let (a, b) = VirtualCanAdapter::pair_async();
+        let tester = J1939Adapter::new(&a, J1939Config::new(0, 0xf9));
+        let ecu = J1939Adapter::new(&b, J1939Config::new(0, 0x00));
+
+        let mut stream = ecu.recv_pgn(0xea00);
+        tester
+            .send_pgn(0xea00, 0x00, &[0xec, 0xfe, 0x00])
+            .await
+            .unwrap();
+
+        // Not addressed to the ECU
+        tester
+            .send_pgn(0xea00, 0x01, &[0xec, 0xfe, 0x00])
+            .await
+            .unwrap();
+        tester
+            .send_pgn(0xea00, GLOBAL_ADDRESS, &[0xda, 0xfe, 0x00])
+            .await
+            .unwrap();
+
+        let msg = stream.next().await.unwrap().unwrap();
+        assert_eq!(msg.source, 0xf9);
+        assert_eq!(msg.destination, 0x00);
+        assert_eq!(msg.data, vec![0xec, 0xfe, 0x00]);
+
+        let msg = stream.next().await.unwrap().unwrap();
+        assert_eq!(msg.destination, GLOBAL_ADDRESS);
+        assert_eq!(msg.data, vec![0xda, 0xfe, 0x00]);
+    }
+
+    #[tokio::test]
+    async fn bam() {
+        let (a, b) = VirtualCanAdapter::pair_async();
+        let mut config = J1939Config::new(0, 0x00);
+        config.bam_separation_time = std::time::Duration::from_millis(1);
+        let ecu = J1939Adapter::new(&a, config);
+        let tester = J1939Adapter::new(&b, J1939Config::new(0, 0xf9));
+
+        let mut stream = tester.recv_pgn(0xfeec);
+        let vin = b"1FUJGLDR5CSBM1234*";
+        ecu.send_pgn(0xfeec, GLOBAL_ADDRESS, vin).await.unwrap();
+
+        let msg = stream.next().await.unwrap().unwrap();
+        assert_eq!(msg.source, 0x00);
+        assert_eq!(msg.destination, GLOBAL_ADDRESS);
+        assert_eq!(msg.data, vin);
+    }
+
+    #[tokio::test]
+    async fn rts_cts() {
+        let (a, b) = VirtualCanAdapter::pair_async();
+        let tester = J1939Adapter::new(&a, J1939Config::new(0, 0xf9));
+        let ecu = J1939Adapter::new(&b, J1939Config::new(0, 0x00));
+
+        let mut stream = ecu.recv_pgn(0xef00);
+        let data: Vec<u8> = (0..100).collect();
+
+        let (sent, received) = tokio::join!(tester.send_pgn(0xef00, 0x00, &data), stream.next());
+        sent.unwrap();
+
+        let msg = received.unwrap().unwrap();
+        assert_eq!(msg.source, 0xf9);
+        assert_eq!(msg.destination, 0x00);
+        assert_eq!(msg.data, data);
+    }
+
+    #[tokio::test]
+    async fn rts_cts_abort() {
+        let (a, b) = VirtualCanAdapter::pair_async();
+        let tester = J1939Adapter::new(&a, J1939Config::new(0, 0xf9));
+        let ecu = J1939Adapter::new(&b, J1939Config::new(0, 0x00));
+
+        // Reply to the RTS with an abort
+        let mut stream = b.recv();
+        let abort = async {
+            let frame = stream.next().await.unwrap();
+            let pgn = tp_cm_pgn(&frame.data);
+            let abort = tp_cm(
+                ControlByte::ConnectionAbort,
+                [AbortReason::ResourcesNeeded as u8, 0xff, 0xff, 0xff],
+                pgn,
+            );
+            ecu.send_frame(TP_PRIORITY, PGN_TP_CM, 0xf9, &abort)
+                .await
+                .unwrap();
+        };
+
+        let (sent, _) = tokio::join!(tester.send_pgn(0xef00, 0x00, &[0; 20]), abort);
+        assert_eq!(
+            sent,
+            Err(Error::ConnectionAborted(AbortReason::ResourcesNeeded as u8).into())
+        );
+    }
+
+    #[tokio::test]
+    async fn rts_cts_invalid_next_packet() {
+        let (a, b) = VirtualCanAdapter::pair_async();
+        let tester = J1939Adapter::new(&a, J1939Config::new(0, 0xf9));
+        let ecu = J1939Adapter::new(&b, J1939Config::new(0, 0x00));
+
+        // Reply to the RTS with a CTS for packet 0, which doesn't exist
+        let mut stream = b.recv_filter(|frame| !frame.loopback);
+        let cts = async {
+            let frame = stream.next().await.unwrap();
+            let pgn = tp_cm_pgn(&frame.data);
+            let cts = tp_cm(ControlByte::ClearToSend, [1, 0, 0xff, 0xff], pgn);
+            ecu.send_frame(TP_PRIORITY, PGN_TP_CM, 0xf9, &cts)
+                .await
+                .unwrap();
+            stream.next().await.unwrap()
+        };
+
+        let (sent, abort) = tokio::join!(tester.send_pgn(0xef00, 0x00, &[0; 20]), cts);
+        let reason = AbortReason::BadSequenceNumber as u8;
+        assert_eq!(sent, Err(Error::ConnectionAborted(reason).into()));
+        assert_eq!(
+            abort.data[..2],
+            [ControlByte::ConnectionAbort as u8, reason]
+        );
+    }
+
+    #[tokio::test]
+    async fn simultaneous_bam_and_rts_cts() {
+        let (a, b) = VirtualCanAdapter::pair_async();
+        let tester = J1939Adapter::new(&a, J1939Config::new(0, 0xf9));
+        let ecu = J1939Adapter::new(&b, J1939Config::new(0, 0x00));
+
+        let mut stream = ecu.recv();
+
+        // Interleave the packets of a BAM and an RTS/CTS session from the same node
+        let bam = tp_cm(
+            ControlByte::BroadcastAnnounceMessage,
+            [10, 0, 2, 0xff],
+            0xfeec,
+        );
+        let rts = tp_cm(ControlByte::RequestToSend, [10, 0, 2, 0xff], 0xef00);
+        let frames = [
+            (PGN_TP_CM, GLOBAL_ADDRESS, bam),
+            (PGN_TP_CM, 0x00, rts),
+            (PGN_TP_DT, GLOBAL_ADDRESS, vec![1, 1, 1, 1, 1, 1, 1, 1]),
+            (PGN_TP_DT, 0x00, vec![1, 2, 2, 2, 2, 2, 2, 2]),
+            (
+                PGN_TP_DT,
+                GLOBAL_ADDRESS,
+                vec![2, 1, 1, 1, 0xff, 0xff, 0xff, 0xff],
+            ),
+            (PGN_TP_DT, 0x00, vec![2, 2, 2, 2, 0xff, 0xff, 0xff, 0xff]),
+        ];
+        for (pgn, dst, data) in frames {
+            tester
+                .send_frame(TP_PRIORITY, pgn, dst, &data)
+                .await
+                .unwrap();
+        }
+
+        let msg = stream.next().await.unwrap().unwrap();
+        assert_eq!(msg.pgn, 0xfeec);
+        assert_eq!(msg.destination, GLOBAL_ADDRESS);
+        assert_eq!(msg.data, vec![1; 10]);
+
+        let msg = stream.next().await.unwrap().unwrap();
+        assert_eq!(msg.pgn, 0xef00);
+        assert_eq!(msg.destination, 0x00);
+        assert_eq!(msg.data, vec![2; 10]);
+    }
+
+    #[tokio::test]
+    async fn bam_timeout() {
+        let (a, b) = VirtualCanAdapter::pair_async();
+        let tester = J1939Adapter::new(&a, J1939Config::new(0, 0xf9));
+        let ecu = J1939Adapter::new(&b, J1939Config::new(0, 0x00));
+
+        let mut stream = ecu.recv();
+
+        let bam = tp_cm(
+            ControlByte::BroadcastAnnounceMessage,
+            [10, 0, 2, 0xff],
+            0xfeec,
+        );
+        tester
+            .send_frame(TP_PRIORITY, PGN_TP_CM, GLOBAL_ADDRESS, &bam)
+            .await
+            .unwrap();
+        tester
+            .send_frame(TP_PRIORITY, PGN_TP_DT, GLOBAL_ADDRESS, &[1; 8])
+            .await
+            .unwrap();
+
+        // Consume the first packet before stalling for longer than T1
+        let next = tokio::time::timeout(std::time::Duration::from_millis(100), stream.next());
+        assert!(next.await.is_err());
+        tokio::time::sleep(std::time::Duration::from_millis(T1_MS)).await;
+
+        // Last packet arrives too late and is ignored
+        tester
+            .send_frame(TP_PRIORITY, PGN_TP_DT, GLOBAL_ADDRESS, &[2; 8])
+            .await
+            .unwrap();
+        tester
+            .send_pgn(0xfeca, GLOBAL_ADDRESS, &[0xaa])
+            .await
+            .unwrap();
+
+        let msg = stream.next().await.unwrap().unwrap();
+        assert_eq!(msg.pgn, 0xfeca);
+        assert_eq!(msg.data, vec![0xaa]);
+    }
+}
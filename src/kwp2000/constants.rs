@@ -0,0 +1,45 @@
+//! Constants for the KWP2000 Client.
+use strum_macros::EnumIter;
+
+pub static POSITIVE_RESPONSE: u8 = 0x40;
+pub static NEGATIVE_RESPONSE: u8 = 0x7f;
+
+/// Service Identifiers (SIDs) as defined in ISO 14230-3
+#[derive(Debug, PartialEq, Copy, Clone, EnumIter)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum ServiceIdentifier {
+    StartDiagnosticSession = 0x10,
+    EcuReset = 0x11,
+    ReadFreezeFrameData = 0x12,
+    ReadDiagnosticTroubleCodes = 0x13,
+    ClearDiagnosticInformation = 0x14,
+    ReadStatusOfDiagnosticTroubleCodes = 0x17,
+    ReadDiagnosticTroubleCodesByStatus = 0x18,
+    ReadEcuIdentification = 0x1a,
+    StopDiagnosticSession = 0x20,
+    ReadDataByLocalIdentifier = 0x21,
+    ReadDataByCommonIdentifier = 0x22,
+    ReadMemoryByAddress = 0x23,
+    SecurityAccess = 0x27,
+    StartRoutineByLocalIdentifier = 0x31,
+    StopRoutineByLocalIdentifier = 0x32,
+    RequestRoutineResultsByLocalIdentifier = 0x33,
+    RequestDownload = 0x34,
+    RequestUpload = 0x35,
+    TransferData = 0x36,
+    RequestTransferExit = 0x37,
+    WriteDataByLocalIdentifier = 0x3b,
+    WriteMemoryByAddress = 0x3d,
+    TesterPresent = 0x3e,
+}
+
+/// Diagnostic Modes used by StartDiagnosticSession as defined in ISO 14230-3
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum DiagnosticMode {
+    StandardSession = 0x81,
+    ProgrammingSession = 0x85,
+    AdjustmentSession = 0x87,
+}
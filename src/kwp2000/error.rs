@@ -0,0 +1,68 @@
+//! Error types for the KWP2000 Client.
+use thiserror::Error;
+
+/// Negative Response Codes returned by ECU as defined in ISO 14230-3
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum NegativeResponseCode {
+    GeneralReject = 0x10,
+    ServiceNotSupported = 0x11,
+    SubFunctionNotSupportedInvalidFormat = 0x12,
+    BusyRepeatRequest = 0x21,
+    ConditionsNotCorrectOrRequestSequenceError = 0x22,
+    RoutineNotComplete = 0x23,
+    RequestOutOfRange = 0x31,
+    SecurityAccessDenied = 0x33,
+    InvalidKey = 0x35,
+    ExceedNumberOfAttempts = 0x36,
+    RequiredTimeDelayNotExpired = 0x37,
+    DownloadNotAccepted = 0x40,
+    UploadNotAccepted = 0x50,
+    TransferSuspended = 0x71,
+    RequestCorrectlyReceivedResponsePending = 0x78,
+    ServiceNotSupportedInActiveDiagnosticSession = 0x80,
+
+    NonStandard(u8),
+}
+
+impl From<u8> for NegativeResponseCode {
+    fn from(val: u8) -> NegativeResponseCode {
+        match val {
+            0x10 => NegativeResponseCode::GeneralReject,
+            0x11 => NegativeResponseCode::ServiceNotSupported,
+            0x12 => NegativeResponseCode::SubFunctionNotSupportedInvalidFormat,
+            0x21 => NegativeResponseCode::BusyRepeatRequest,
+            0x22 => NegativeResponseCode::ConditionsNotCorrectOrRequestSequenceError,
+            0x23 => NegativeResponseCode::RoutineNotComplete,
+            0x31 => NegativeResponseCode::RequestOutOfRange,
+            0x33 => NegativeResponseCode::SecurityAccessDenied,
+            0x35 => NegativeResponseCode::InvalidKey,
+            0x36 => NegativeResponseCode::ExceedNumberOfAttempts,
+            0x37 => NegativeResponseCode::RequiredTimeDelayNotExpired,
+            0x40 => NegativeResponseCode::DownloadNotAccepted,
+            0x50 => NegativeResponseCode::UploadNotAccepted,
+            0x71 => NegativeResponseCode::TransferSuspended,
+            0x78 => NegativeResponseCode::RequestCorrectlyReceivedResponsePending,
+            0x80 => NegativeResponseCode::ServiceNotSupportedInActiveDiagnosticSession,
+            _ => NegativeResponseCode::NonStandard(val),
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum Error {
+    #[error("Invalid Reponse Service ID: {0}")]
+    InvalidServiceId(u8),
+    #[error("Invalid Response Sub Function ID: {0}")]
+    InvalidSubFunction(u8),
+    #[error("Invalid Response Length")]
+    InvalidResponseLength,
+    #[error("Negative Response to service 0x{service:02x}: {code:?}")]
+    NegativeResponse {
+        /// Service identifier of the rejected request
+        service: u8,
+        /// Negative Response Code
+        code: NegativeResponseCode,
+    },
+}
@@ -0,0 +1,262 @@
+//! Keyword Protocol 2000 (KWP2000) Client, implements ISO 14230-3 on top of ISO-TP for older ECUs
+//! ## Example
+//! ```rust
+//! async fn kwp2000_example() {
+//!     let adapter = automotive::can::get_adapter().unwrap();
+//!     let isotp = automotive::isotp::IsoTPAdapter::from_id(&adapter, 0x7a1);
+//!     let kwp = automotive::kwp2000::KWP2000Client::new(&isotp);
+//!
+//!     kwp.start_diagnostic_session(automotive::kwp2000::DiagnosticMode::StandardSession as u8).await.unwrap();
+//!     let response = kwp.read_ecu_identification(0x9b).await.unwrap();
+//!
+//!     println!("ECU Identification: {}", hex::encode(response));
+//! }
+//! ```
+
+mod constants;
+mod error;
+
+use crate::isotp::IsoTPAdapter;
+use crate::transport::DiagnosticTransport;
+use crate::Result;
+use crate::StreamExt;
+pub use constants::*;
+pub use error::{Error, NegativeResponseCode};
+
+use tracing::info;
+
+const DEFAULT_P2_STAR_TIMEOUT_MS: u64 = 5000;
+const DEFAULT_MAX_RESPONSE_PENDING: usize = 10;
+
+/// KWP2000 Client. Wraps an IsoTPAdapter, or any other [`DiagnosticTransport`], to provide a simple interface for making KWP2000 calls.
+pub struct KWP2000Client<'a, T: DiagnosticTransport = IsoTPAdapter<'a>> {
+    adapter: &'a T,
+    p2_star_timeout: std::time::Duration,
+    max_response_pending: usize,
+}
+
+impl<'a, T: DiagnosticTransport> KWP2000Client<'a, T> {
+    pub fn new(adapter: &'a T) -> Self {
+        Self {
+            adapter,
+            p2_star_timeout: std::time::Duration::from_millis(DEFAULT_P2_STAR_TIMEOUT_MS),
+            max_response_pending: DEFAULT_MAX_RESPONSE_PENDING,
+        }
+    }
+
+    /// Maximum time to wait for the next response after the ECU sent a Response Pending (P2* max). Defaults to 5 s.
+    pub fn set_p2_star_timeout(&mut self, timeout: std::time::Duration) {
+        self.p2_star_timeout = timeout;
+    }
+
+    /// Maximum number of consecutive Response Pending messages accepted for a single request, after which [`crate::Error::Timeout`] is returned. Prevents hanging on an ECU that is stuck sending Response Pending. Defaults to 10.
+    pub fn set_max_response_pending(&mut self, count: usize) {
+        self.max_response_pending = count;
+    }
+
+    /// Helper function to make custom KWP2000 requests. This function will verify the ECU responds with the correct service identifier, and echoes the local identifier if `local_identifier` is given. Negative responses are returned as error. Returns the response data following the service identifier and local identifier.
+    pub async fn request(
+        &self,
+        sid: u8,
+        local_identifier: Option<u8>,
+        data: Option<&[u8]>,
+    ) -> Result<Vec<u8>> {
+        let mut request: Vec<u8> = vec![sid];
+
+        if let Some(local_identifier) = local_identifier {
+            request.push(local_identifier);
+        }
+
+        if let Some(data) = data {
+            request.extend(data);
+        }
+
+        let mut stream = self.adapter.recv();
+
+        self.adapter.send(&request).await?;
+
+        let mut response_pending: usize = 0;
+        let mut p2_star_deadline: Option<tokio::time::Instant> = None;
+
+        loop {
+            let response = match stream.next().await.ok_or(crate::Error::Disconnected)? {
+                Ok(response) => response,
+                // After a Response Pending the ECU has up to P2* to send the next response
+                Err(e)
+                    if e.is_timeout()
+                        && p2_star_deadline.is_some_and(|d| tokio::time::Instant::now() < d) =>
+                {
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if response.is_empty() {
+                return Err(Error::InvalidResponseLength.into());
+            }
+
+            // Check for errors
+            let response_sid = response[0];
+            if response_sid == NEGATIVE_RESPONSE {
+                if response.len() < 3 {
+                    return Err(Error::InvalidResponseLength.into());
+                }
+
+                let code: NegativeResponseCode = response[2].into();
+
+                if code == NegativeResponseCode::RequestCorrectlyReceivedResponsePending {
+                    info!("Received Response Pending");
+
+                    response_pending += 1;
+                    if response_pending > self.max_response_pending {
+                        return Err(crate::Error::Timeout);
+                    }
+
+                    p2_star_deadline = Some(tokio::time::Instant::now() + self.p2_star_timeout);
+                    continue;
+                }
+
+                return Err(Error::NegativeResponse {
+                    service: response[1],
+                    code,
+                }
+                .into());
+            }
+
+            // Check service id
+            if response_sid != sid | POSITIVE_RESPONSE {
+                return Err(Error::InvalidServiceId(response_sid).into());
+            }
+
+            // Check local identifier
+            if let Some(local_identifier) = local_identifier {
+                match response.get(1) {
+                    Some(&id) if id == local_identifier => {}
+                    Some(&id) => return Err(Error::InvalidSubFunction(id).into()),
+                    None => return Err(Error::InvalidResponseLength.into()),
+                }
+            }
+
+            let start: usize = if local_identifier.is_some() { 2 } else { 1 };
+            return Ok(response[start..].to_vec());
+        }
+    }
+
+    /// 0x10 - Start Diagnostic Session. Use the [`constants::DiagnosticMode`] enum for the modes defined in the standard.
+    pub async fn start_diagnostic_session(&self, diagnostic_mode: u8) -> Result<()> {
+        self.request(
+            ServiceIdentifier::StartDiagnosticSession as u8,
+            Some(diagnostic_mode),
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// 0x1A - Read ECU Identification. The `identification_option` selects the record to read, the meaning of the options is manufacturer specific.
+    pub async fn read_ecu_identification(&self, identification_option: u8) -> Result<Vec<u8>> {
+        self.request(
+            ServiceIdentifier::ReadEcuIdentification as u8,
+            Some(identification_option),
+            None,
+        )
+        .await
+    }
+
+    /// 0x23 - Read Memory By Address. The `memory_address` is a 3 byte address, and `memory_size` the number of bytes to read. The response does not echo the address.
+    pub async fn read_memory_by_address(
+        &self,
+        memory_address: u32,
+        memory_size: u8,
+    ) -> Result<Vec<u8>> {
+        assert!(memory_address <= 0xFFFFFF);
+
+        let mut data = memory_address.to_be_bytes()[1..].to_vec();
+        data.push(memory_size);
+
+        self.request(
+            ServiceIdentifier::ReadMemoryByAddress as u8,
+            None,
+            Some(&data),
+        )
+        .await
+    }
+
+    /// 0x31 - Start Routine By Local Identifier. The `data` parameter contains the optional routine entry options. Returns the routine entry status reported by the ECU.
+    pub async fn start_routine_by_local_identifier(
+        &self,
+        routine_local_identifier: u8,
+        data: Option<&[u8]>,
+    ) -> Result<Vec<u8>> {
+        self.request(
+            ServiceIdentifier::StartRoutineByLocalIdentifier as u8,
+            Some(routine_local_identifier),
+            data,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::mock::MockTransport;
+
+    #[tokio::test]
+    async fn read_memory_by_address() {
+        let transport = MockTransport::new(|request| match request {
+            [0x23, 0x01, 0x02, 0x03, 0x02] => vec![vec![0x7f, 0x23, 0x78], vec![0x63, 0xaa, 0xbb]],
+            _ => vec![vec![0x7f, request[0], 0x31]],
+        });
+        let kwp = KWP2000Client::new(&transport);
+
+        let data = kwp.read_memory_by_address(0x010203, 2).await.unwrap();
+        assert_eq!(data, vec![0xaa, 0xbb]);
+
+        let request_out_of_range = Error::NegativeResponse {
+            service: 0x23,
+            code: NegativeResponseCode::RequestOutOfRange,
+        };
+        assert_eq!(
+            kwp.read_memory_by_address(0x000000, 2).await,
+            Err(request_out_of_range.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn local_identifier_echo() {
+        let transport = MockTransport::new(|request| match request {
+            [0x1a, 0x9b] => vec![vec![0x5a, 0x9b, 0x01, 0x02]],
+            [0x31, 0x01] => vec![vec![0x71, 0x02]],
+            _ => vec![],
+        });
+        let kwp = KWP2000Client::new(&transport);
+
+        let data = kwp.read_ecu_identification(0x9b).await.unwrap();
+        assert_eq!(data, vec![0x01, 0x02]);
+
+        let resp = kwp.start_routine_by_local_identifier(0x01, None).await;
+        assert_eq!(resp, Err(Error::InvalidSubFunction(0x02).into()));
+    }
+
+    #[tokio::test]
+    async fn response_pending_limit() {
+        // ECU stuck answering Response Pending
+        let transport = MockTransport::new(|request| vec![vec![0x7f, request[0], 0x78]; 1000]);
+        let kwp = KWP2000Client::new(&transport);
+
+        let resp = kwp.read_ecu_identification(0x9b).await;
+        assert_eq!(resp, Err(crate::Error::Timeout));
+    }
+
+    #[tokio::test]
+    async fn response_pending_p2_star_timeout() {
+        let transport = MockTransport::new(|request| vec![vec![0x7f, request[0], 0x78]]);
+        let mut kwp = KWP2000Client::new(&transport);
+        kwp.set_p2_star_timeout(std::time::Duration::from_millis(50));
+
+        let start = tokio::time::Instant::now();
+        let resp = kwp.read_ecu_identification(0x9b).await;
+        assert_eq!(resp, Err(crate::Error::Timeout));
+        assert!(start.elapsed() >= std::time::Duration::from_millis(50));
+    }
+}
@@ -14,7 +14,7 @@
 //!     let mut stream = adapter.recv();
 //!
 //!     let frame = automotive::can::Frame::new(0, 0x541.into(), &[0xff; 8])?;
-//!     adapter.send(&frame).await;
+//!     adapter.send(&frame).await?;
 //!
 //!     while let Some(frame) = stream.next().await {
 //!         let id: u32 = frame.id.into();
@@ -41,13 +41,19 @@
 //!  }
 //! ```
 //!
+//! ## Async Runtime
+//! The async parts of this crate are built on top of [tokio](https://tokio.rs). While the channels used by the [AsyncCanAdapter](https://docs.rs/automotive/latest/automotive/async_can/struct.AsyncCanAdapter.html) work with any executor, the ISO-TP layer (and therefore also the UDS client) uses `tokio::time` for receive timeouts and the separation time between consecutive frames. These futures need to be polled from within a tokio runtime with the time driver enabled (e.g. `#[tokio::main]`), otherwise they will panic. Users of other runtimes such as `async-std` or `smol` can run a tokio runtime on a separate thread and use `Handle::enter` or `Runtime::block_on` to drive the diagnostic calls.
+//!
 //! ## CAN Adapters
 //! The following CAN adapters are supported.
 //!
 //! ### Supported CAN adapters
 //!  - SocketCAN (Linux only)
 //!  - comma.ai panda (all platforms using [rusb](https://crates.io/crates/rusb))
+//!  - SLCAN/Lawicel serial adapters such as the CANable or USBtin (Linux only, using the `slcan` feature)
 //!  - Vector Devices (Windows x64 only)
+//!  - PEAK PCAN Devices (using the PCAN-Basic library)
+//!  - CAN over TCP, compatible with [cannelloni](https://github.com/mguentner/cannelloni) (using the `tcp` feature)
 //!
 //! ### Known limitations / Notes
 //! This library has some unique features that might expose (performance) issues in drivers you wouldn't otherwise notice, so check the list of known limitations below.
@@ -63,6 +69,7 @@
 //!  - comma.ai panda
 //!    - The panda does not retry frames that are not ACKed, and drops them instead. This can cause panics in some internal parts of the library when frames are dropped. [panda#1922](https://github.com/commaai/panda/issues/1922) tracks this issue.
 //!  - Vector Devices are supported through the Vector XL Driver Library, and support can be enabled using the `vector-xl` feature. Make sure to distribute `vxlapi64.dll` alongside your application.
+//!  - PEAK PCAN Devices are supported through the PCAN-Basic library, and support can be enabled using the `pcan` feature. On Linux `libpcanbasic` needs to be installed, on Windows make sure to distribute `PCANBasic.dll` alongside your application. Frames are looped back as soon as they are handed to the driver, so ACKs are emulated.
 //!
 //!
 //! ### Implementing a New Adapter
@@ -73,9 +80,16 @@
 //!  - Once a frame is ACKed it should be put in the receive queue with the `loopback` flag set. The `AsyncCanAdapter` wrapper will take care of matching it against the right transmit frame and resolving the Future. If this is not supported by the underlying hardware, this can be faked by looping back all transmitted frames immediately.
 
 pub mod can;
+pub mod doip;
 mod error;
 pub mod isotp;
+pub mod j1939;
+pub mod kwp2000;
+pub mod logging;
+pub mod obd;
+pub mod transport;
 pub mod uds;
+pub mod xcp;
 
 /// Re-export of relevant stream traits from `tokio_stream`.
 pub use tokio_stream::{Stream, StreamExt, Timeout};
@@ -83,11 +97,20 @@ pub use tokio_stream::{Stream, StreamExt, Timeout};
 pub use error::Error;
 pub type Result<T> = std::result::Result<T, Error>;
 
+#[cfg(all(target_os = "linux", feature = "slcan"))]
+pub mod slcan;
+
 #[cfg(all(target_os = "linux", feature = "socketcan"))]
 pub mod socketcan;
 
+#[cfg(feature = "tcp")]
+pub mod tcp;
+
 #[cfg(all(target_os = "windows", feature = "vector-xl"))]
 pub mod vector;
 
 #[cfg(feature = "panda")]
 pub mod panda;
+
+#[cfg(feature = "pcan")]
+pub mod pcan;
@@ -0,0 +1,296 @@
+//! Reader for Vector ASCII trace files (`.asc`), as written by CANalyzer and CANoe.
+//! ## Example
+//! ```rust
+//! fn asc_example() -> automotive::Result<()> {
+//!     let file = std::fs::File::open("trace.asc").unwrap();
+//!     let reader = automotive::logging::asc::AscReader::new(std::io::BufReader::new(file))?;
+//!
+//!     for frame in reader {
+//!         let frame = frame?;
+//!         println!("{:?} {:?} {}", frame.timestamp, frame.id, hex::encode(frame.data));
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use super::{parse_timestamp, Error};
+use crate::can::{Frame, Identifier};
+use crate::Result;
+use std::io::BufRead;
+use std::iter::Peekable;
+use std::time::Duration;
+
+/// Flags field of a CANFD line, set for frames with the FD Format indicator
+const ASC_FLAG_EDL: u32 = 0x1000;
+
+/// Reads frames from a Vector ASC trace. Channels are numbered from 1 in the trace, channel 1 is returned as bus 0. Frames logged with the `Tx` direction have the `loopback` flag set. Error frames and other events are skipped.
+pub struct AscReader<R: BufRead> {
+    lines: Peekable<std::io::Lines<R>>,
+    line: usize,
+    date: Option<String>,
+    radix: u32,
+    relative: bool,
+    time: Duration,
+}
+
+impl<R: BufRead> AscReader<R> {
+    /// Creates a new reader and parses the header of the trace
+    pub fn new(reader: R) -> Result<Self> {
+        let mut reader = Self {
+            lines: reader.lines().peekable(),
+            line: 0,
+            date: None,
+            radix: 16,
+            relative: false,
+            time: Duration::ZERO,
+        };
+        reader.parse_header()?;
+        Ok(reader)
+    }
+
+    /// Date of the start of the trace, as written in the header (e.g. `Wed Jun 14 10:29:45.123 am 2023`)
+    pub fn date(&self) -> Option<&str> {
+        self.date.as_deref()
+    }
+
+    fn parse_header(&mut self) -> Result<()> {
+        while let Some(line) = self.lines.peek() {
+            let line = match line {
+                Ok(line) => line.trim(),
+                Err(e) => return Err(Error::ReadError(e.kind()).into()),
+            };
+
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("date") => self.date = Some(line["date".len()..].trim().to_owned()),
+                Some("base") => {
+                    self.radix = match tokens.next() {
+                        Some("hex") => 16,
+                        Some("dec") => 10,
+                        _ => return Err(Error::MalformedLine(self.line + 1).into()),
+                    };
+                    self.relative = tokens.nth(1) == Some("relative");
+                }
+                Some("//" | "internal" | "no" | "Begin") | None => {}
+                Some(_) => break,
+            }
+
+            self.lines.next();
+            self.line += 1;
+        }
+        Ok(())
+    }
+
+    /// Parses a single line. Returns `None` for lines that don't contain a frame.
+    fn parse_line(&mut self, line: &str) -> Option<Result<Frame>> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let timestamp = parse_timestamp(tokens.first()?)?;
+
+        // Relative timestamps are relative to the previous event
+        self.time = match self.relative {
+            true => self.time + timestamp,
+            false => timestamp,
+        };
+
+        let frame = match tokens[1..] {
+            ["CANFD", _, "Rx" | "Tx", ..] => self.parse_canfd(&tokens[2..]),
+            [_, _, "Rx" | "Tx", "d" | "r", ..] => self.parse_can(&tokens[1..]),
+            _ => return None,
+        };
+
+        Some(
+            frame
+                .map(|mut frame| {
+                    frame.timestamp = Some(self.time);
+                    frame
+                })
+                .ok_or(Error::MalformedLine(self.line).into()),
+        )
+    }
+
+    /// Classic CAN line: `<channel> <id>[x] <Rx|Tx> <d|r> <dlc> <data>`
+    fn parse_can(&self, tokens: &[&str]) -> Option<Frame> {
+        let (&[channel, id, dir, frame_type], rest) = tokens.split_first_chunk::<4>()?;
+
+        let rtr = frame_type == "r";
+        let dlc = match rest.first() {
+            Some(dlc) => Some(u8::from_str_radix(dlc, 16).ok()?),
+            None if rtr => None,
+            None => return None,
+        };
+
+        let len = if rtr { 0 } else { dlc?.min(8) as usize };
+        let data = self.parse_data(rest.get(1..1 + len)?)?;
+
+        let mut frame = Frame::builder(self.parse_id(id)?)
+            .bus(parse_channel(channel)?)
+            .data(&data)
+            .fd(false)
+            .rtr(rtr)
+            .build()
+            .ok()?;
+        frame.loopback = dir == "Tx";
+        frame.dlc = dlc.filter(|&dlc| rtr || dlc > 8);
+        Some(frame)
+    }
+
+    /// CAN-FD line: `<channel> <Rx|Tx> <id>[x] [symbolic name] <brs> <esi> <dlc> <data length> <data> ... <flags> ...`
+    fn parse_canfd(&self, tokens: &[&str]) -> Option<Frame> {
+        let (&[channel, dir, id], rest) = tokens.split_first_chunk::<3>()?;
+
+        // The symbolic name is optional, the BRS field is always a digit
+        let rest = match rest.first()?.bytes().all(|b| b.is_ascii_digit()) {
+            true => rest,
+            false => &rest[1..],
+        };
+        let (&[brs, _esi, _dlc, len], rest) = rest.split_first_chunk::<4>()?;
+
+        let len: usize = len.parse().ok()?;
+        let data = self.parse_data(rest.get(..len)?)?;
+
+        // Classic frames can also be logged as CANFD lines, the flags are the third field after the data
+        let fd = match rest.get(len + 2) {
+            Some(flags) => u32::from_str_radix(flags, 16).ok()? & ASC_FLAG_EDL != 0,
+            None => true,
+        };
+
+        let mut frame = Frame::builder(self.parse_id(id)?)
+            .bus(parse_channel(channel)?)
+            .data(&data)
+            .fd(fd)
+            .brs(brs == "1")
+            .build()
+            .ok()?;
+        frame.loopback = dir == "Tx";
+        Some(frame)
+    }
+
+    /// Parses an arbitration ID, extended IDs have an `x` suffix
+    fn parse_id(&self, id: &str) -> Option<Identifier> {
+        match id.strip_suffix('x') {
+            Some(id) => Some(Identifier::Extended(
+                u32::from_str_radix(id, self.radix).ok()?,
+            )),
+            None => Some(Identifier::Standard(
+                u32::from_str_radix(id, self.radix).ok()?,
+            )),
+        }
+    }
+
+    fn parse_data(&self, data: &[&str]) -> Option<Vec<u8>> {
+        data.iter()
+            .map(|b| u8::from_str_radix(b, self.radix).ok())
+            .collect()
+    }
+}
+
+fn parse_channel(channel: &str) -> Option<u8> {
+    channel.parse::<u8>().ok()?.checked_sub(1)
+}
+
+impl<R: BufRead> Iterator for AscReader<R> {
+    type Item = Result<Frame>;
+
+    fn next(&mut self) -> Option<Result<Frame>> {
+        loop {
+            self.line += 1;
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(Error::ReadError(e.kind()).into())),
+            };
+
+            if let Some(frame) = self.parse_line(&line) {
+                return Some(frame);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE: &str = "date Wed Jun 14 10:29:45.123 am 2023
+base hex  timestamps absolute
+internal events logged
+// version 9.0.0
+Begin Triggerblock Wed Jun 14 10:29:45.123 am 2023
+   0.000000 Start of measurement
+   0.010000 1  123             Rx   d 8 01 02 03 04 05 06 07 08  Length = 0 BitCount = 0 ID = 291
+   0.020000 2  18DB33F1x       Tx   d 2 AA BB
+   0.025000 1  ErrorFrame
+   0.030000 1  7DF             Rx   r 4
+   0.040000 CANFD   1 Rx        123  EngineData                       1 0 9 12 00 11 22 33 44 55 66 77 88 99 AA BB        0    0     3000 0 0 0 0 0
+   0.050000 CANFD   1 Tx        456                                   0 0 2  2 01 02        0    0     0 0 0 0 0 0
+   0.060000 1  123             Rx   d 8 01 02
+End TriggerBlock
+";
+
+    #[test]
+    fn parse_header() {
+        let reader = AscReader::new(TRACE.as_bytes()).unwrap();
+        assert_eq!(reader.date(), Some("Wed Jun 14 10:29:45.123 am 2023"));
+        assert_eq!(reader.radix, 16);
+        assert!(!reader.relative);
+    }
+
+    #[test]
+    fn parse_frames() {
+        let frames: Vec<Result<Frame>> = AscReader::new(TRACE.as_bytes()).unwrap().collect();
+        assert_eq!(frames.len(), 6);
+
+        let frame = frames[0].as_ref().unwrap();
+        assert_eq!(frame.bus, 0);
+        assert_eq!(frame.id, Identifier::Standard(0x123));
+        assert_eq!(frame.data, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(frame.timestamp, Some(Duration::from_millis(10)));
+        assert!(!frame.loopback);
+
+        let frame = frames[1].as_ref().unwrap();
+        assert_eq!(frame.bus, 1);
+        assert_eq!(frame.id, Identifier::Extended(0x18db33f1));
+        assert_eq!(frame.data, vec![0xaa, 0xbb]);
+        assert!(frame.loopback);
+
+        let frame = frames[2].as_ref().unwrap();
+        assert!(frame.rtr);
+        assert_eq!(frame.dlc, Some(4));
+
+        let frame = frames[3].as_ref().unwrap();
+        assert!(frame.fd && frame.brs);
+        assert_eq!(frame.data.len(), 12);
+        assert_eq!(frame.data[11], 0xbb);
+        assert_eq!(frame.timestamp, Some(Duration::from_millis(40)));
+
+        // Classic frame logged as a CANFD line, without symbolic name
+        let frame = frames[4].as_ref().unwrap();
+        assert!(!frame.fd);
+        assert_eq!(frame.id, Identifier::Standard(0x456));
+        assert_eq!(frame.data, vec![1, 2]);
+
+        // Line with less data bytes than the DLC
+        assert_eq!(frames[5], Err(Error::MalformedLine(13).into()));
+    }
+
+    #[test]
+    fn parse_relative_decimal() {
+        let trace = "base dec  timestamps relative
+   0.010000 1  291             Rx   d 2 1 255
+   0.005000 1  Statistic: D 0 R 0 XD 0 XR 0 E 0 O 0 B 0.00%
+   0.010000 1  1000x           Rx   d 1 16
+";
+        let frames: Vec<Frame> = AscReader::new(trace.as_bytes())
+            .unwrap()
+            .map(|frame| frame.unwrap())
+            .collect();
+        assert_eq!(frames.len(), 2);
+
+        assert_eq!(frames[0].id, Identifier::Standard(0x123));
+        assert_eq!(frames[0].data, vec![0x01, 0xff]);
+        assert_eq!(frames[0].timestamp, Some(Duration::from_millis(10)));
+
+        assert_eq!(frames[1].id, Identifier::Extended(1000));
+        assert_eq!(frames[1].data, vec![0x10]);
+        assert_eq!(frames[1].timestamp, Some(Duration::from_millis(25)));
+    }
+}
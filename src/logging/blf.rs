@@ -0,0 +1,439 @@
+//! Reader for Vector Binary Logging Format files (`.blf`), as recorded by CANalyzer and CANoe.
+//! ## Example
+//! ```rust
+//! fn blf_example() -> automotive::Result<()> {
+//!     let file = std::fs::File::open("trace.blf").unwrap();
+//!     let reader = automotive::logging::blf::BlfReader::new(std::io::BufReader::new(file))?;
+//!
+//!     for frame in reader {
+//!         let frame = frame?;
+//!         println!("{:?} {:?} {}", frame.timestamp, frame.id, hex::encode(frame.data));
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use super::Error;
+use crate::can::{Frame, Identifier};
+use crate::Result;
+use std::io::Read;
+use std::time::Duration;
+
+const FILE_SIGNATURE: &[u8; 4] = b"LOGG";
+const OBJECT_SIGNATURE: &[u8; 4] = b"LOBJ";
+
+/// Size of the common object header, containing the signature, sizes and object type
+const OBJECT_HEADER_BASE_SIZE: usize = 16;
+/// Size of the header of a log container, following the common object header
+const LOG_CONTAINER_HEADER_SIZE: usize = 16;
+
+const COMPRESSION_NONE: u16 = 0;
+const COMPRESSION_ZLIB: u16 = 2;
+
+// Object types
+const CAN_MESSAGE: u32 = 1;
+const LOG_CONTAINER: u32 = 10;
+const CAN_MESSAGE2: u32 = 86;
+const CAN_FD_MESSAGE: u32 = 100;
+const CAN_FD_MESSAGE_64: u32 = 101;
+
+// Object header flags, timestamps are either in units of 10 µs or 1 ns
+const TIME_TEN_MICS: u32 = 0x1;
+#[cfg(test)]
+const TIME_ONE_NANS: u32 = 0x2;
+
+// Message flags
+const DIR_TX: u8 = 0x1;
+const REMOTE_FRAME: u8 = 0x80;
+const EXTENDED_ID: u32 = 0x8000_0000;
+
+// CAN_FD_MESSAGE flags
+const FD_EDL: u8 = 0x1;
+const FD_BRS: u8 = 0x2;
+
+// CAN_FD_MESSAGE_64 flags
+const FD64_REMOTE_FRAME: u32 = 0x10;
+const FD64_EDL: u32 = 0x1000;
+const FD64_BRS: u32 = 0x2000;
+
+fn u16_at(buf: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes(buf[pos..pos + 2].try_into().unwrap())
+}
+
+fn u32_at(buf: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(buf[pos..pos + 4].try_into().unwrap())
+}
+
+fn u64_at(buf: &[u8], pos: usize) -> u64 {
+    u64::from_le_bytes(buf[pos..pos + 8].try_into().unwrap())
+}
+
+/// Reads CAN and CAN-FD frames from a BLF file. Channels are numbered from 1 in the file, channel 1 is returned as bus 0. Transmitted frames have the `loopback` flag set. Other objects, such as error frames, are skipped.
+pub struct BlfReader<R: Read> {
+    reader: R,
+    /// Uncompressed data of the log containers. Objects can span multiple containers.
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> BlfReader<R> {
+    /// Creates a new reader and parses the file header
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut header = [0u8; 8];
+        reader
+            .read_exact(&mut header)
+            .map_err(|e| Error::ReadError(e.kind()))?;
+
+        if &header[..4] != FILE_SIGNATURE {
+            return Err(Error::MalformedObject.into());
+        }
+
+        // The header size depends on the version of the file, skip the remainder
+        let header_size = u32_at(&header, 4) as u64;
+        let skip = header_size
+            .checked_sub(header.len() as u64)
+            .ok_or(Error::MalformedObject)?;
+        std::io::copy(&mut (&mut reader).take(skip), &mut std::io::sink())
+            .map_err(|e| Error::ReadError(e.kind()))?;
+
+        Ok(Self {
+            reader,
+            buf: vec![],
+            pos: 0,
+        })
+    }
+
+    /// Reads the next log container from the file and appends its data to the buffer. Returns false at the end of the file.
+    fn read_container(&mut self) -> Result<bool> {
+        loop {
+            let mut header = [0u8; OBJECT_HEADER_BASE_SIZE];
+            match self.reader.read_exact(&mut header) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(false),
+                Err(e) => return Err(Error::ReadError(e.kind()).into()),
+            }
+
+            if &header[..4] != OBJECT_SIGNATURE {
+                return Err(Error::MalformedObject.into());
+            }
+
+            // Objects in the file are padded to a multiple of 4 bytes
+            let size = u32_at(&header, 8) as usize;
+            let padding = size % 4;
+            let mut data = vec![0; size.saturating_sub(OBJECT_HEADER_BASE_SIZE) + padding];
+            self.reader
+                .read_exact(&mut data)
+                .map_err(|e| Error::ReadError(e.kind()))?;
+
+            if u32_at(&header, 12) != LOG_CONTAINER || data.len() < LOG_CONTAINER_HEADER_SIZE {
+                continue;
+            }
+
+            let container = &data[LOG_CONTAINER_HEADER_SIZE..data.len() - padding];
+            let container = match u16_at(&data, 0) {
+                COMPRESSION_NONE => container.to_vec(),
+                COMPRESSION_ZLIB => miniz_oxide::inflate::decompress_to_vec_zlib(container)
+                    .map_err(|_| Error::MalformedObject)?,
+                method => {
+                    tracing::warn!("Skipping log container with compression method {}", method);
+                    continue;
+                }
+            };
+
+            // Drop the already parsed objects
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+            self.buf.extend(container);
+            return Ok(true);
+        }
+    }
+
+    /// Returns the position and size of the next object in the buffer, or `None` if more data is needed
+    fn next_object(&self) -> Result<Option<(usize, usize)>> {
+        // Objects inside a container are padded, the signature is at most a few bytes further
+        let window = &self.buf[self.pos..self.buf.len().min(self.pos + 8)];
+        let Some(offset) = window.windows(4).position(|w| w == OBJECT_SIGNATURE) else {
+            return match window.len() < 8 {
+                true => Ok(None),
+                false => Err(Error::MalformedObject.into()),
+            };
+        };
+
+        let start = self.pos + offset;
+        if self.buf.len() < start + OBJECT_HEADER_BASE_SIZE {
+            return Ok(None);
+        }
+
+        let size = u32_at(&self.buf, start + 8) as usize;
+        if size < OBJECT_HEADER_BASE_SIZE {
+            return Err(Error::MalformedObject.into());
+        }
+        if self.buf.len() < start + size {
+            return Ok(None);
+        }
+        Ok(Some((start, size)))
+    }
+}
+
+/// Parses a CAN object, returns `Ok(None)` for other object types
+fn parse_object(obj: &[u8]) -> Result<Option<Frame>> {
+    let header_size = u16_at(obj, 4) as usize;
+    let obj_type = u32_at(obj, 12);
+    if !matches!(
+        obj_type,
+        CAN_MESSAGE | CAN_MESSAGE2 | CAN_FD_MESSAGE | CAN_FD_MESSAGE_64
+    ) {
+        return Ok(None);
+    }
+
+    // The flags and timestamp are at the same offset for both object header versions
+    if header_size < 32 || obj.len() < header_size {
+        return Err(Error::MalformedObject.into());
+    }
+    let timestamp = u64_at(obj, 24);
+    let timestamp = match u32_at(obj, 16) {
+        TIME_TEN_MICS => Duration::from_micros(timestamp * 10),
+        _ => Duration::from_nanos(timestamp),
+    };
+
+    let msg = &obj[header_size..];
+    let frame = match obj_type {
+        CAN_MESSAGE | CAN_MESSAGE2 => parse_can_message(msg),
+        CAN_FD_MESSAGE => parse_can_fd_message(msg),
+        _ => parse_can_fd_message_64(msg),
+    };
+
+    let mut frame = frame.ok_or(Error::MalformedObject)?;
+    frame.timestamp = Some(timestamp);
+    Ok(Some(frame))
+}
+
+fn parse_id(id: u32) -> Identifier {
+    match id & EXTENDED_ID != 0 {
+        true => Identifier::Extended(id & !EXTENDED_ID),
+        false => Identifier::Standard(id),
+    }
+}
+
+/// `VBLCANMessage`: channel (u16), flags (u8), dlc (u8), id (u32), data (8 bytes)
+fn parse_can_message(msg: &[u8]) -> Option<Frame> {
+    if msg.len() < 16 {
+        return None;
+    }
+
+    let (flags, dlc) = (msg[2], msg[3]);
+    let rtr = flags & REMOTE_FRAME != 0;
+    let len = if rtr { 0 } else { dlc.min(8) as usize };
+
+    let mut frame = Frame::builder(parse_id(u32_at(msg, 4)))
+        .bus(u16_at(msg, 0).checked_sub(1)? as u8)
+        .data(&msg[8..8 + len])
+        .fd(false)
+        .rtr(rtr)
+        .build()
+        .ok()?;
+    frame.loopback = flags & DIR_TX != 0;
+    frame.dlc = Some(dlc).filter(|&dlc| rtr || dlc > 8);
+    Some(frame)
+}
+
+/// `VBLCANFDMessage`: channel (u16), flags (u8), dlc (u8), id (u32), frame length (u32), bit count (u8), fd flags (u8), valid data bytes (u8), reserved (5 bytes), data (64 bytes)
+fn parse_can_fd_message(msg: &[u8]) -> Option<Frame> {
+    if msg.len() < 84 {
+        return None;
+    }
+
+    let (flags, fd_flags, len) = (msg[2], msg[13], msg[14].min(64) as usize);
+
+    let mut frame = Frame::builder(parse_id(u32_at(msg, 4)))
+        .bus(u16_at(msg, 0).checked_sub(1)? as u8)
+        .data(&msg[20..20 + len])
+        .fd(fd_flags & FD_EDL != 0)
+        .brs(fd_flags & FD_BRS != 0)
+        .rtr(flags & REMOTE_FRAME != 0)
+        .build()
+        .ok()?;
+    frame.loopback = flags & DIR_TX != 0;
+    Some(frame)
+}
+
+/// `VBLCANFDMessage64`: channel (u8), dlc (u8), valid data bytes (u8), tx count (u8), id (u32), frame length (u32), flags (u32), bit timings and offsets (4x u32), bit count (u16), direction (u8), extension offset (u8), crc (u32), data
+fn parse_can_fd_message_64(msg: &[u8]) -> Option<Frame> {
+    const DATA_OFFSET: usize = 40;
+
+    let len = *msg.get(2)? as usize;
+    let data = msg.get(DATA_OFFSET..DATA_OFFSET + len)?;
+    let flags = u32_at(msg, 12);
+
+    let mut frame = Frame::builder(parse_id(u32_at(msg, 4)))
+        .bus(msg[0].checked_sub(1)?)
+        .data(data)
+        .fd(flags & FD64_EDL != 0)
+        .brs(flags & FD64_BRS != 0)
+        .rtr(flags & FD64_REMOTE_FRAME != 0)
+        .build()
+        .ok()?;
+    frame.loopback = msg[34] == DIR_TX;
+    Some(frame)
+}
+
+impl<R: Read> Iterator for BlfReader<R> {
+    type Item = Result<Frame>;
+
+    fn next(&mut self) -> Option<Result<Frame>> {
+        loop {
+            let (start, size) = match self.next_object() {
+                Ok(Some(object)) => object,
+                Ok(None) => match self.read_container() {
+                    Ok(true) => continue,
+                    Ok(false) => return None,
+                    Err(e) => return Some(Err(e)),
+                },
+                Err(e) => {
+                    // Resynchronize at the next log container
+                    self.pos = self.buf.len();
+                    return Some(Err(e));
+                }
+            };
+            self.pos = start + size;
+
+            match parse_object(&self.buf[start..start + size]) {
+                Ok(Some(frame)) => return Some(Ok(frame)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(obj_type: u32, timestamp: u64, payload: &[u8]) -> Vec<u8> {
+        let size = 32 + payload.len();
+
+        let mut obj = OBJECT_SIGNATURE.to_vec();
+        obj.extend(32u16.to_le_bytes());
+        obj.extend(1u16.to_le_bytes());
+        obj.extend((size as u32).to_le_bytes());
+        obj.extend(obj_type.to_le_bytes());
+        obj.extend(TIME_ONE_NANS.to_le_bytes());
+        obj.extend([0; 4]);
+        obj.extend(timestamp.to_le_bytes());
+        obj.extend(payload);
+        obj.resize(size + size % 4, 0);
+        obj
+    }
+
+    fn container(data: &[u8], compressed: bool) -> Vec<u8> {
+        let (method, data) = match compressed {
+            true => (
+                COMPRESSION_ZLIB,
+                miniz_oxide::deflate::compress_to_vec_zlib(data, 6),
+            ),
+            false => (COMPRESSION_NONE, data.to_vec()),
+        };
+
+        let mut payload = method.to_le_bytes().to_vec();
+        payload.extend([0; 6]);
+        payload.extend((data.len() as u32).to_le_bytes());
+        payload.extend([0; 4]);
+        payload.extend(data);
+
+        let size = OBJECT_HEADER_BASE_SIZE + payload.len();
+        let mut obj = OBJECT_SIGNATURE.to_vec();
+        obj.extend(16u16.to_le_bytes());
+        obj.extend(1u16.to_le_bytes());
+        obj.extend((size as u32).to_le_bytes());
+        obj.extend(LOG_CONTAINER.to_le_bytes());
+        obj.extend(payload);
+        obj.resize(size + size % 4, 0);
+        obj
+    }
+
+    fn file(containers: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = FILE_SIGNATURE.to_vec();
+        file.extend(144u32.to_le_bytes());
+        file.resize(144, 0);
+        for container in containers {
+            file.extend(container);
+        }
+        file
+    }
+
+    fn can_message(channel: u16, flags: u8, id: u32, data: &[u8]) -> Vec<u8> {
+        let mut msg = channel.to_le_bytes().to_vec();
+        msg.extend([flags, data.len() as u8]);
+        msg.extend(id.to_le_bytes());
+        msg.extend(data);
+        msg.resize(16, 0);
+        msg
+    }
+
+    fn can_fd_message_64(channel: u8, flags: u32, id: u32, data: &[u8]) -> Vec<u8> {
+        let mut msg = vec![channel, 0, data.len() as u8, 0];
+        msg.extend(id.to_le_bytes());
+        msg.extend(0u32.to_le_bytes());
+        msg.extend(flags.to_le_bytes());
+        msg.resize(34, 0);
+        msg.extend([DIR_TX, 0]);
+        msg.extend(0u32.to_le_bytes());
+        msg.extend(data);
+        msg
+    }
+
+    #[test]
+    fn read_frames() {
+        let mut objects = object(
+            CAN_MESSAGE,
+            1_000_000,
+            &can_message(1, 0, 0x123, &[0xaa, 0xbb, 0xcc]),
+        );
+        objects.extend(object(
+            CAN_MESSAGE2,
+            2_000_000,
+            &can_message(2, DIR_TX, EXTENDED_ID | 0x18db33f1, &[0x01]),
+        ));
+        objects.extend(object(73, 2_500_000, &[0; 20]));
+        objects.extend(object(
+            CAN_FD_MESSAGE_64,
+            3_000_000,
+            &can_fd_message_64(1, FD64_EDL | FD64_BRS, 0x456, &[0x11; 12]),
+        ));
+
+        // The last object continues in the next, compressed, container
+        let (first, second) = objects.split_at(objects.len() - 10);
+        let file = file(&[container(first, false), container(second, true)]);
+
+        let frames: Vec<Frame> = BlfReader::new(file.as_slice())
+            .unwrap()
+            .map(|frame| frame.unwrap())
+            .collect();
+        assert_eq!(frames.len(), 3);
+
+        assert_eq!(frames[0].bus, 0);
+        assert_eq!(frames[0].id, Identifier::Standard(0x123));
+        assert_eq!(frames[0].data, vec![0xaa, 0xbb, 0xcc]);
+        assert_eq!(frames[0].timestamp, Some(Duration::from_millis(1)));
+        assert!(!frames[0].loopback);
+
+        assert_eq!(frames[1].bus, 1);
+        assert_eq!(frames[1].id, Identifier::Extended(0x18db33f1));
+        assert!(frames[1].loopback);
+
+        assert_eq!(frames[2].id, Identifier::Standard(0x456));
+        assert_eq!(frames[2].data, vec![0x11; 12]);
+        assert!(frames[2].fd && frames[2].brs);
+        assert_eq!(frames[2].timestamp, Some(Duration::from_millis(3)));
+    }
+
+    #[test]
+    fn invalid_signature() {
+        let e = BlfReader::new(&b"LOGX\x08\x00\x00\x00"[..]);
+        assert!(matches!(
+            e,
+            Err(crate::Error::LoggingError(Error::MalformedObject))
+        ));
+    }
+}
@@ -0,0 +1,13 @@
+//! Error types for reading log files.
+
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum Error {
+    #[error("Malformed line {0}")]
+    MalformedLine(usize),
+    #[error("Malformed Object")]
+    MalformedObject,
+    #[error("Read Error: {0}")]
+    ReadError(std::io::ErrorKind),
+}
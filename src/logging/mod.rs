@@ -0,0 +1,380 @@
+//! Readers and writers for sharing CAN captures with other tools.
+//! ## Example
+//! ```rust
+//! async fn log_example() -> automotive::Result<()> {
+//!     let adapter = automotive::can::get_adapter()?;
+//!     let file = std::fs::File::create("capture.log").unwrap();
+//!
+//!     let mut writer = automotive::logging::CandumpWriter::new(std::io::BufWriter::new(file));
+//!     writer.write_stream(adapter.recv()).await.unwrap();
+//!     Ok(())
+//! }
+//! ```
+
+pub mod asc;
+#[cfg(feature = "blf")]
+pub mod blf;
+mod error;
+
+pub use error::Error;
+
+use crate::can::{AsyncCanAdapter, Frame, Identifier};
+use crate::{Result, Stream, StreamExt};
+use std::io::{BufRead, Write};
+use std::time::Duration;
+use tracing::warn;
+
+/// CAN-FD flag in the candump format for frames sent with Bit Rate Switch
+const CANFD_BRS: u8 = 0x01;
+
+/// Writes frames in the log file format of `candump -L`, e.g. `(0000000001.000250) can0 123#DEADBEEF`. The bus of the frame is used as interface number, so bus 1 is logged as `can1`. Frames without a timestamp are logged at time 0.
+pub struct CandumpWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> CandumpWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Writes a single frame as one line
+    pub fn write_frame(&mut self, frame: &Frame) -> std::io::Result<()> {
+        let timestamp = frame.timestamp.unwrap_or_default();
+        write!(
+            self.writer,
+            "({:010}.{:06}) can{} ",
+            timestamp.as_secs(),
+            timestamp.subsec_micros(),
+            frame.bus
+        )?;
+
+        match frame.id {
+            Identifier::Standard(id) => write!(self.writer, "{:03X}", id)?,
+            Identifier::Extended(id) => write!(self.writer, "{:08X}", id)?,
+        }
+
+        if frame.fd {
+            let flags = if frame.brs { CANFD_BRS } else { 0 };
+            write!(self.writer, "##{:X}", flags)?;
+        } else {
+            write!(self.writer, "#")?;
+        }
+
+        if frame.rtr {
+            write!(self.writer, "R")?;
+            if let Some(dlc) = frame.dlc {
+                write!(self.writer, "{:X}", dlc)?;
+            }
+        } else {
+            write!(self.writer, "{}", hex::encode_upper(&frame.data))?;
+
+            // Classic CAN frames with 8 bytes can carry a raw DLC of 9-15
+            if let Some(dlc) = frame.dlc.filter(|&dlc| !frame.fd && dlc > 8) {
+                write!(self.writer, "_{:X}", dlc)?;
+            }
+        }
+
+        writeln!(self.writer)
+    }
+
+    /// Writes all frames from the stream until it ends, e.g. from [`AsyncCanAdapter::recv`](crate::can::AsyncCanAdapter::recv)
+    pub async fn write_stream(
+        &mut self,
+        mut stream: impl Stream<Item = Frame> + Unpin,
+    ) -> std::io::Result<()> {
+        while let Some(frame) = stream.next().await {
+            self.write_frame(&frame)?;
+        }
+        self.writer.flush()
+    }
+
+    /// Consumes the writer, returning the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads frames from a `candump -L` log file, e.g. created using [`CandumpWriter`]. The interface number is used as bus, and the timestamps are preserved. Malformed lines are skipped with a warning.
+pub struct CandumpReader<R: BufRead> {
+    lines: std::io::Lines<R>,
+}
+
+impl<R: BufRead> CandumpReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CandumpReader<R> {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => {
+                    warn!("Error reading candump log: {}", e);
+                    return None;
+                }
+            };
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match parse_candump_line(&line) {
+                Some(frame) => return Some(frame),
+                None => warn!("Skipping malformed candump line: {}", line),
+            }
+        }
+    }
+}
+
+/// Parses a timestamp in the `seconds.fraction` format
+fn parse_timestamp(timestamp: &str) -> Option<Duration> {
+    let (secs, frac) = timestamp.split_once('.')?;
+    if frac.is_empty() || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    // Pad or truncate the fraction to microseconds
+    let micros = format!("{:0<6.6}", frac);
+    Some(Duration::from_secs(secs.parse().ok()?) + Duration::from_micros(micros.parse().ok()?))
+}
+
+fn parse_candump_line(line: &str) -> Option<Frame> {
+    let mut parts = line.split_whitespace();
+    let timestamp = parts.next()?.strip_prefix('(')?.strip_suffix(')')?;
+    let interface = parts.next()?;
+    let frame = parts.next()?;
+
+    // The interface number is used as bus, e.g. can1 is bus 1
+    let digits = interface.len()
+        - interface
+            .trim_end_matches(|c: char| c.is_ascii_digit())
+            .len();
+    let bus = interface[interface.len() - digits..].parse().unwrap_or(0);
+
+    let (id, rest) = frame.split_once('#')?;
+    let id = match id.len() {
+        3 => Identifier::Standard(u32::from_str_radix(id, 16).ok()?),
+        8 => Identifier::Extended(u32::from_str_radix(id, 16).ok()?),
+        _ => return None,
+    };
+
+    let mut builder = Frame::builder(id).bus(bus);
+    let mut dlc = None;
+    let data = if let Some(rest) = rest.strip_prefix('#') {
+        let flags = u8::from_str_radix(rest.get(..1)?, 16).ok()?;
+        builder = builder.fd(true).brs(flags & CANFD_BRS != 0);
+        hex::decode(&rest[1..]).ok()?
+    } else if let Some(len) = rest.strip_prefix('R') {
+        builder = builder.fd(false).rtr(true);
+        if !len.is_empty() {
+            dlc = Some(u8::from_str_radix(len, 16).ok()?);
+        }
+        vec![]
+    } else {
+        let (data, len) = match rest.split_once('_') {
+            Some((data, len)) => (data, Some(len)),
+            None => (rest, None),
+        };
+        if let Some(len) = len {
+            dlc = Some(u8::from_str_radix(len, 16).ok()?);
+        }
+        builder = builder.fd(false);
+        hex::decode(data).ok()?
+    };
+
+    let mut frame = builder.data(&data).build().ok()?;
+    frame.dlc = dlc;
+    frame.timestamp = Some(parse_timestamp(timestamp)?);
+    Some(frame)
+}
+
+/// Sends all frames from the reader. If `realtime` is set, the time between frames from the log is preserved, otherwise the frames are sent as fast as possible. Each frame is awaited, see [`AsyncCanAdapter::send`].
+pub async fn replay<R: BufRead>(
+    adapter: &AsyncCanAdapter,
+    reader: CandumpReader<R>,
+    realtime: bool,
+) -> Result<()> {
+    let start = tokio::time::Instant::now();
+    let mut first_timestamp = None;
+
+    for mut frame in reader {
+        let timestamp = frame.timestamp.take().unwrap_or_default();
+        let first_timestamp = *first_timestamp.get_or_insert(timestamp);
+
+        if realtime {
+            tokio::time::sleep_until(start + timestamp.saturating_sub(first_timestamp)).await;
+        }
+
+        adapter.send(&frame).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn write(frame: &Frame) -> String {
+        let mut writer = CandumpWriter::new(vec![]);
+        writer.write_frame(frame).unwrap();
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn candump_classic() {
+        let mut frame =
+            Frame::new(0, Identifier::Standard(0x123), &[0xde, 0xad, 0xbe, 0xef]).unwrap();
+        frame.timestamp = Some(Duration::from_micros(1_000_250));
+        assert_eq!(write(&frame), "(0000000001.000250) can0 123#DEADBEEF\n");
+
+        let frame = Frame::new(1, Identifier::Extended(0x18db33f1), &[]).unwrap();
+        assert_eq!(write(&frame), "(0000000000.000000) can1 18DB33F1#\n");
+
+        let mut frame = Frame::new(0, Identifier::Standard(0x7df), &[0; 8]).unwrap();
+        frame.dlc = Some(0xf);
+        assert_eq!(
+            write(&frame),
+            "(0000000000.000000) can0 7DF#0000000000000000_F\n"
+        );
+    }
+
+    #[test]
+    fn candump_rtr() {
+        let mut frame = Frame::builder(Identifier::Standard(0x123))
+            .rtr(true)
+            .build()
+            .unwrap();
+        assert_eq!(write(&frame), "(0000000000.000000) can0 123#R\n");
+
+        frame.dlc = Some(4);
+        assert_eq!(write(&frame), "(0000000000.000000) can0 123#R4\n");
+    }
+
+    #[test]
+    fn candump_fd() {
+        let frame = Frame::builder(Identifier::Standard(0x123))
+            .data(&[0x11; 12])
+            .brs(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            write(&frame),
+            "(0000000000.000000) can0 123##1111111111111111111111111\n"
+        );
+
+        let frame = Frame::builder(Identifier::Extended(0x1234))
+            .data(&[0x11])
+            .fd(true)
+            .build()
+            .unwrap();
+        assert_eq!(write(&frame), "(0000000000.000000) can0 00001234##011\n");
+    }
+
+    #[tokio::test]
+    async fn candump_stream() {
+        let frames = vec![
+            Frame::new(0, Identifier::Standard(0x1), &[0x01]).unwrap(),
+            Frame::new(2, Identifier::Standard(0x2), &[0x02]).unwrap(),
+        ];
+
+        let mut writer = CandumpWriter::new(vec![]);
+        writer
+            .write_stream(tokio_stream::iter(frames))
+            .await
+            .unwrap();
+
+        let log = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(
+            log,
+            "(0000000000.000000) can0 001#01\n(0000000000.000000) can2 002#02\n"
+        );
+    }
+
+    #[test]
+    fn candump_read() {
+        let log = "(1436509052.249713) vcan0 123#DEADBEEF\n\
+                   (1436509052.250) can1 18DB33F1##1112233\n\
+                   \n\
+                   (1436509052.300000) can0 7DF#R4\n\
+                   (1436509052.350000) can0 7DF#0000000000000000_F\n\
+                   this is not a frame\n\
+                   (1436509052.400000) can0 1234#00\n\
+                   (1436509052.400000) can0 123#0\n";
+        let frames: Vec<Frame> = CandumpReader::new(log.as_bytes()).collect();
+        assert_eq!(frames.len(), 4);
+
+        assert_eq!(frames[0].bus, 0);
+        assert_eq!(frames[0].id, Identifier::Standard(0x123));
+        assert_eq!(frames[0].data, vec![0xde, 0xad, 0xbe, 0xef]);
+        assert!(!frames[0].fd);
+        assert_eq!(
+            frames[0].timestamp,
+            Some(Duration::new(1436509052, 249_713_000))
+        );
+
+        assert_eq!(frames[1].bus, 1);
+        assert_eq!(frames[1].id, Identifier::Extended(0x18db33f1));
+        assert_eq!(frames[1].data, vec![0x11, 0x22, 0x33]);
+        assert!(frames[1].fd && frames[1].brs);
+        assert_eq!(
+            frames[1].timestamp,
+            Some(Duration::new(1436509052, 250_000_000))
+        );
+
+        assert!(frames[2].rtr);
+        assert_eq!(frames[2].dlc, Some(4));
+
+        assert_eq!(frames[3].data, vec![0; 8]);
+        assert_eq!(frames[3].dlc, Some(0xf));
+    }
+
+    #[test]
+    fn candump_round_trip() {
+        let mut frames = vec![
+            Frame::new(0, Identifier::Standard(0x123), &[0xde, 0xad]).unwrap(),
+            Frame::builder(Identifier::Extended(0x1234))
+                .bus(2)
+                .data(&[0x11; 12])
+                .brs(true)
+                .build()
+                .unwrap(),
+        ];
+        for (i, frame) in frames.iter_mut().enumerate() {
+            frame.timestamp = Some(Duration::from_millis(i as u64));
+        }
+
+        let mut writer = CandumpWriter::new(vec![]);
+        for frame in &frames {
+            writer.write_frame(frame).unwrap();
+        }
+
+        let log = writer.into_inner();
+        let read: Vec<Frame> = CandumpReader::new(log.as_slice()).collect();
+        assert_eq!(read, frames);
+    }
+
+    #[tokio::test]
+    async fn candump_replay() {
+        let log = "(0000000010.000000) can0 123#01\n\
+                   (0000000010.100000) can0 123#02\n";
+
+        let adapter = AsyncCanAdapter::new(crate::can::virtual_can::VirtualCanAdapter::new());
+        let mut stream = adapter.recv_filter(|frame| frame.loopback);
+
+        let start = tokio::time::Instant::now();
+        replay(&adapter, CandumpReader::new(log.as_bytes()), true)
+            .await
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        assert_eq!(stream.next().await.unwrap().data, vec![0x01]);
+        assert_eq!(stream.next().await.unwrap().data, vec![0x02]);
+    }
+}
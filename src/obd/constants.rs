@@ -0,0 +1,40 @@
+//! Constants for the OBD-II Client.
+use strum_macros::FromRepr;
+
+pub static POSITIVE_RESPONSE: u8 = 0x40;
+pub static NEGATIVE_RESPONSE: u8 = 0x7f;
+
+/// Functional (broadcast) request identifier for 11 bit OBD-II as defined in ISO 15765-4
+pub static FUNCTIONAL_REQUEST_ID: u32 = 0x7df;
+/// Identifiers used by the ECUs to respond to a functional request as defined in ISO 15765-4
+pub static FUNCTIONAL_RESPONSE_IDS: std::ops::RangeInclusive<u32> = 0x7e8..=0x7ef;
+/// Physical request identifier of the engine ECU. The response is sent on 0x7e8.
+pub static ENGINE_ECU_REQUEST_ID: u32 = 0x7e0;
+
+/// OBD-II Modes (Services) as defined in SAE J1979
+#[derive(Debug, PartialEq, Copy, Clone, FromRepr)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum Mode {
+    CurrentData = 0x01,
+    FreezeFrameData = 0x02,
+    StoredDiagnosticTroubleCodes = 0x03,
+    ClearDiagnosticInformation = 0x04,
+    OnBoardMonitoringTestResults = 0x06,
+    PendingDiagnosticTroubleCodes = 0x07,
+    ControlOnBoardSystem = 0x08,
+    VehicleInformation = 0x09,
+    PermanentDiagnosticTroubleCodes = 0x0a,
+}
+
+/// Mode 0x09 Vehicle Information Types as defined in SAE J1979
+#[derive(Debug, PartialEq, Copy, Clone, FromRepr)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum VehicleInformationType {
+    SupportedInfoTypes = 0x00,
+    VehicleIdentificationNumber = 0x02,
+    CalibrationIdentification = 0x04,
+    CalibrationVerificationNumbers = 0x06,
+    EcuName = 0x0a,
+}
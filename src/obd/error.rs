@@ -0,0 +1,20 @@
+//! Error types for the OBD-II Client.
+use crate::uds::NegativeResponseCode;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum Error {
+    #[error("Invalid Reponse Service ID: {0}")]
+    InvalidServiceId(u8),
+    #[error("Invalid Response PID: {0}")]
+    InvalidPid(u8),
+    #[error("Invalid Response Length")]
+    InvalidResponseLength,
+    #[error("Negative Response to service 0x{service:02x}: {code:?}")]
+    NegativeResponse {
+        /// Service identifier of the rejected request
+        service: u8,
+        /// Negative Response Code
+        code: NegativeResponseCode,
+    },
+}
@@ -0,0 +1,351 @@
+//! On-Board Diagnostics (OBD-II) Client, implements SAE J1979 / ISO 15031-5
+//! ## Example
+//! ```rust
+//! async fn obd_example() {
+//!     let adapter = automotive::can::get_adapter().unwrap();
+//!     let isotp = automotive::isotp::IsoTPAdapter::from_id(&adapter, 0x7e0);
+//!     let obd = automotive::obd::OBDClient::new(&isotp);
+//!
+//!     let pids = obd.supported_pids().await.unwrap();
+//!     println!("Supported PIDs: {:x?}", pids);
+//! }
+//! ```
+//!
+//! To query all emissions related ECUs at once, use a functional adapter on [`FUNCTIONAL_REQUEST_ID`]:
+//! ```rust
+//! use automotive::can::Identifier;
+//! use automotive::isotp::{IsoTPAdapter, IsoTPConfig};
+//! use automotive::obd::{OBDClient, FUNCTIONAL_REQUEST_ID};
+//!
+//! async fn obd_functional_example() {
+//!     let adapter = automotive::can::get_adapter().unwrap();
+//!     let config = IsoTPConfig::functional(0, Identifier::Standard(FUNCTIONAL_REQUEST_ID));
+//!     let isotp = IsoTPAdapter::new(&adapter, config);
+//!     let obd = OBDClient::new(&isotp);
+//!
+//!     for (id, data) in obd.request_functional(0x01, &[0x00]).await.unwrap() {
+//!         println!("{:?}: {:x?}", id, data);
+//!     }
+//! }
+//! ```
+
+mod constants;
+mod error;
+mod types;
+
+use crate::can::Identifier;
+use crate::isotp::IsoTPAdapter;
+use crate::transport::DiagnosticTransport;
+use crate::Result;
+use crate::StreamExt;
+pub use constants::*;
+pub use error::Error;
+pub use types::*;
+
+use std::collections::BTreeSet;
+use tracing::debug;
+
+/// Each "PIDs supported" PID describes the support of the next 32 PIDs
+const SUPPORTED_PIDS_RANGE: u8 = 0x20;
+
+/// OBD-II Client. Wraps an IsoTPAdapter, or any other [`DiagnosticTransport`], to provide a simple interface for making OBD-II requests. Use [`ENGINE_ECU_REQUEST_ID`] to talk to the engine ECU, or [`FUNCTIONAL_REQUEST_ID`] together with [`OBDClient::request_functional`] to talk to all ECUs.
+pub struct OBDClient<'a, T: DiagnosticTransport = IsoTPAdapter<'a>> {
+    adapter: &'a T,
+}
+
+impl<'a, T: DiagnosticTransport> OBDClient<'a, T> {
+    pub fn new(adapter: &'a T) -> Self {
+        Self { adapter }
+    }
+
+    /// Helper function to make custom OBD-II requests. This function will verify the ECU responds with the correct mode, handle negative responses, and will return the response data following the mode byte.
+    pub async fn request(&self, mode: u8, data: &[u8]) -> Result<Vec<u8>> {
+        let mut request: Vec<u8> = vec![mode];
+        request.extend(data);
+
+        let mut stream = self.adapter.recv();
+        self.adapter.send(&request).await?;
+
+        let response = stream.next().await.ok_or(crate::Error::Disconnected)??;
+        parse_response(mode, &response)
+    }
+
+    /// Returns all supported Mode 0x01 PIDs. Queries the "PIDs supported" PIDs (0x00, 0x20, 0x40, ...) for as long as the ECU reports the next range as supported. Vehicles that fail to answer a range they advertised are handled by returning the PIDs found so far.
+    pub async fn supported_pids(&self) -> Result<BTreeSet<u8>> {
+        let mut pids = BTreeSet::new();
+        let mut base: u8 = 0x00;
+
+        loop {
+            let mask = match self.request(Mode::CurrentData as u8, &[base]).await {
+                Ok(resp) => resp,
+                Err(e)
+                    if base != 0x00
+                        && (e.is_timeout()
+                            || matches!(
+                                e,
+                                crate::Error::OBDError(Error::NegativeResponse { .. })
+                            )) =>
+                {
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+
+            if mask.len() < 5 {
+                return Err(Error::InvalidResponseLength.into());
+            }
+            if mask[0] != base {
+                return Err(Error::InvalidPid(mask[0]).into());
+            }
+
+            pids.extend(decode_supported_pids(base, &mask[1..5]));
+
+            // The last bit of each range signals support for the next "PIDs supported" PID
+            match base.checked_add(SUPPORTED_PIDS_RANGE) {
+                Some(next) if pids.contains(&next) => base = next,
+                _ => break,
+            }
+        }
+
+        Ok(pids)
+    }
+
+    /// Requests a PID and verifies the PID is echoed in the response. Returns the data following the PID.
+    async fn request_pid(&self, mode: Mode, pid: u8) -> Result<Vec<u8>> {
+        let resp = self.request(mode as u8, &[pid]).await?;
+
+        match resp.first() {
+            Some(&p) if p == pid => Ok(resp[1..].to_vec()),
+            Some(&p) => Err(Error::InvalidPid(p).into()),
+            None => Err(Error::InvalidResponseLength.into()),
+        }
+    }
+
+    /// Mode 0x01 - Show current data. Use the [`Pid`] enum for common PIDs, and [`Pid::scale`] to convert the returned data into a physical value.
+    pub async fn current_data(&self, pid: u8) -> Result<Vec<u8>> {
+        self.request_pid(Mode::CurrentData, pid).await
+    }
+
+    /// Mode 0x03 - Show stored Diagnostic Trouble Codes. Returns the DTCs formatted as defined in SAE J2012, e.g. `P0420`.
+    pub async fn read_dtcs(&self) -> Result<Vec<String>> {
+        let resp = self
+            .request(Mode::StoredDiagnosticTroubleCodes as u8, &[])
+            .await?;
+
+        // First byte contains the number of DTCs, followed by 2 bytes per DTC
+        if resp.is_empty() || (resp.len() - 1) % 2 != 0 {
+            return Err(Error::InvalidResponseLength.into());
+        }
+
+        Ok(resp[1..]
+            .chunks(2)
+            .map(|dtc| dtc_to_string(u16::from_be_bytes([dtc[0], dtc[1]])))
+            .collect())
+    }
+
+    /// Mode 0x09 - Request vehicle information. Use the [`VehicleInformationType`] enum for the standardized info types. For info types containing data items, the first byte of the returned data is the number of data items (e.g. 1 for the VIN, followed by 17 bytes).
+    pub async fn vehicle_info(&self, info_type: u8) -> Result<Vec<u8>> {
+        self.request_pid(Mode::VehicleInformation, info_type).await
+    }
+}
+
+impl<'a> OBDClient<'a, IsoTPAdapter<'a>> {
+    /// Sends a functional request to all emissions related ECUs and collects their responses until no more ECUs respond. The adapter needs to be created using [`IsoTPConfig::functional`](crate::isotp::IsoTPConfig::functional) on [`FUNCTIONAL_REQUEST_ID`]. Returns the response ID of each ECU together with the data following the mode byte. Negative and invalid responses are skipped, since not all ECUs support every request.
+    pub async fn request_functional(
+        &self,
+        mode: u8,
+        data: &[u8],
+    ) -> Result<Vec<(Identifier, Vec<u8>)>> {
+        let mut request: Vec<u8> = vec![mode];
+        request.extend(data);
+
+        let stream = self.adapter.recv_any(|id| match id {
+            Identifier::Standard(id) => FUNCTIONAL_RESPONSE_IDS.contains(&id),
+            Identifier::Extended(_) => false,
+        });
+        tokio::pin!(stream);
+        self.adapter.send(&request).await?;
+
+        let mut responses = vec![];
+        while let Some(response) = stream.next().await {
+            match response {
+                Ok((id, response)) => match parse_response(mode, &response) {
+                    Ok(data) => responses.push((id, data)),
+                    Err(e) => debug!("Ignoring response from {:?}: {}", id, e),
+                },
+                Err(crate::Error::IsoTPError(crate::isotp::Error::FirstFrameTimeout)) => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(responses)
+    }
+}
+
+/// Verifies the response has the expected mode, and returns the data following the mode byte. Negative responses are returned as [`Error::NegativeResponse`].
+fn parse_response(mode: u8, response: &[u8]) -> Result<Vec<u8>> {
+    if response.is_empty() {
+        return Err(Error::InvalidResponseLength.into());
+    }
+
+    if response[0] == NEGATIVE_RESPONSE {
+        if response.len() < 3 {
+            return Err(Error::InvalidResponseLength.into());
+        }
+
+        return Err(Error::NegativeResponse {
+            service: response[1],
+            code: response[2].into(),
+        }
+        .into());
+    }
+
+    if response[0] != mode | POSITIVE_RESPONSE {
+        return Err(Error::InvalidServiceId(response[0]).into());
+    }
+
+    Ok(response[1..].to_vec())
+}
+
+/// Decodes the 4 byte bitmask returned by a "PIDs supported" PID. The most significant bit corresponds to PID `base + 1`. Bits past PID 0xFF are ignored.
+fn decode_supported_pids(base: u8, mask: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    (0..32u8).filter_map(move |i| {
+        let supported = mask[(i / 8) as usize] & (0x80 >> (i % 8)) != 0;
+        if supported {
+            base.checked_add(i + 1)
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::can::{Frame, VirtualCanAdapter};
+    use crate::isotp::IsoTPConfig;
+    use crate::transport::mock::MockTransport;
+
+    #[test]
+    fn decode_supported_pids_bitmask() {
+        // Example from SAE J1979: 0xBE1FA813 for PIDs 0x01 - 0x20
+        let pids: Vec<u8> = decode_supported_pids(0x00, &[0xbe, 0x1f, 0xa8, 0x13]).collect();
+        assert_eq!(
+            pids,
+            vec![
+                0x01, 0x03, 0x04, 0x05, 0x06, 0x07, 0x0c, 0x0d, 0x0e, 0x0f, 0x10, 0x11, 0x13, 0x15,
+                0x1c, 0x1f, 0x20
+            ]
+        );
+
+        let pids: Vec<u8> = decode_supported_pids(0xe0, &[0x80, 0x00, 0x00, 0x03]).collect();
+        assert_eq!(pids, vec![0xe1, 0xff]);
+    }
+
+    #[test]
+    fn pid_scaling() {
+        assert_eq!(Pid::EngineRpm.scale(&[0x1a, 0xf8]), Some(1726.0));
+        assert_eq!(Pid::VehicleSpeed.scale(&[0x32]), Some(50.0));
+        assert_eq!(Pid::EngineCoolantTemperature.scale(&[0x7b]), Some(83.0));
+        assert_eq!(Pid::EngineRpm.scale(&[0x1a]), None);
+    }
+
+    #[tokio::test]
+    async fn read_dtcs() {
+        let transport = MockTransport::new(|request| match request {
+            [0x03] => vec![vec![0x43, 0x02, 0x04, 0x20, 0xc1, 0x00]],
+            _ => vec![],
+        });
+        let obd = OBDClient::new(&transport);
+
+        let dtcs = obd.read_dtcs().await.unwrap();
+        assert_eq!(dtcs, vec!["P0420", "U0100"]);
+    }
+
+    #[tokio::test]
+    async fn current_data() {
+        let transport = MockTransport::new(|request| match request {
+            [0x01, 0x0d] => vec![vec![0x41, 0x0d, 0x32]],
+            [0x01, 0x0c] => vec![vec![0x41, 0x0d, 0x32]],
+            _ => vec![],
+        });
+        let obd = OBDClient::new(&transport);
+
+        let speed = obd.current_data(Pid::VehicleSpeed as u8).await.unwrap();
+        assert_eq!(speed, vec![0x32]);
+
+        let rpm = obd.current_data(Pid::EngineRpm as u8).await;
+        assert_eq!(rpm, Err(Error::InvalidPid(0x0d).into()));
+    }
+
+    #[tokio::test]
+    async fn supported_pids() {
+        // Every range advertises the next one, until 0x40
+        let transport = MockTransport::new(|request| match request {
+            [0x01, 0x00] => vec![vec![0x41, 0x00, 0x80, 0x00, 0x00, 0x01]],
+            [0x01, 0x20] => vec![vec![0x41, 0x20, 0x00, 0x01, 0x00, 0x01]],
+            [0x01, 0x40] => vec![vec![0x41, 0x40, 0x40, 0x00, 0x00, 0x00]],
+            _ => vec![],
+        });
+        let obd = OBDClient::new(&transport);
+
+        let pids: Vec<u8> = obd.supported_pids().await.unwrap().into_iter().collect();
+        assert_eq!(pids, vec![0x01, 0x20, 0x30, 0x40, 0x42]);
+    }
+
+    #[tokio::test]
+    async fn supported_pids_stop_early() {
+        // Advertises 0x20, but times out
+        let transport = MockTransport::new(|request| match request {
+            [0x01, 0x00] => vec![vec![0x41, 0x00, 0x80, 0x00, 0x00, 0x01]],
+            _ => vec![],
+        });
+        let obd = OBDClient::new(&transport);
+        let pids: Vec<u8> = obd.supported_pids().await.unwrap().into_iter().collect();
+        assert_eq!(pids, vec![0x01, 0x20]);
+
+        // Advertises 0x20, but rejects it
+        let transport = MockTransport::new(|request| match request {
+            [0x01, 0x00] => vec![vec![0x41, 0x00, 0x80, 0x00, 0x00, 0x01]],
+            [0x01, 0x20] => vec![vec![0x7f, 0x01, 0x12]],
+            _ => vec![],
+        });
+        let obd = OBDClient::new(&transport);
+        let pids: Vec<u8> = obd.supported_pids().await.unwrap().into_iter().collect();
+        assert_eq!(pids, vec![0x01, 0x20]);
+
+        // No response at all is an error
+        let transport = MockTransport::new(|_| vec![]);
+        let obd = OBDClient::new(&transport);
+        assert!(obd.supported_pids().await.unwrap_err().is_timeout());
+    }
+
+    #[tokio::test]
+    async fn request_functional() {
+        // One ECU responds positively to the functional request, and a second ECU rejects it
+        let adapter = VirtualCanAdapter::simulated_ecu(|frame| match frame.id {
+            Identifier::Standard(0x7df) => vec![
+                Frame::new(
+                    0,
+                    Identifier::Standard(0x7e8),
+                    &[0x06, 0x41, 0x00, 0xbe, 0x1f, 0xa8, 0x13],
+                )
+                .unwrap(),
+                Frame::new(0, Identifier::Standard(0x7e9), &[0x03, 0x7f, 0x01, 0x12]).unwrap(),
+            ],
+            _ => vec![],
+        });
+        let config = IsoTPConfig::functional(0, Identifier::Standard(FUNCTIONAL_REQUEST_ID));
+        let isotp = IsoTPAdapter::new(&adapter, config);
+        let obd = OBDClient::new(&isotp);
+
+        let responses = obd.request_functional(0x01, &[0x00]).await.unwrap();
+        assert_eq!(
+            responses,
+            vec![(
+                Identifier::Standard(0x7e8),
+                vec![0x00, 0xbe, 0x1f, 0xa8, 0x13]
+            )]
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! Types used in the OBD-II protocol.
+use strum_macros::FromRepr;
+
+/// Common Mode 0x01 Parameter IDs as defined in SAE J1979
+#[derive(Debug, PartialEq, Copy, Clone, FromRepr)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum Pid {
+    SupportedPids01To20 = 0x00,
+    MonitorStatus = 0x01,
+    CalculatedEngineLoad = 0x04,
+    EngineCoolantTemperature = 0x05,
+    IntakeManifoldAbsolutePressure = 0x0b,
+    EngineRpm = 0x0c,
+    VehicleSpeed = 0x0d,
+    IntakeAirTemperature = 0x0f,
+    MafAirFlowRate = 0x10,
+    ThrottlePosition = 0x11,
+    RunTimeSinceEngineStart = 0x1f,
+    FuelTankLevelInput = 0x2f,
+    ControlModuleVoltage = 0x42,
+    AmbientAirTemperature = 0x46,
+}
+
+impl Pid {
+    /// Converts the raw data returned for this PID into a physical value, using the scaling defined in SAE J1979. Units are %, °C, kPa, rpm, km/h, g/s, s and V. Returns `None` if the PID has no scaling (e.g. bit fields) or the data is too short.
+    pub fn scale(&self, data: &[u8]) -> Option<f64> {
+        let a = *data.first()? as f64;
+        let ab = || -> Option<f64> { Some(a * 256.0 + *data.get(1)? as f64) };
+
+        match self {
+            Pid::CalculatedEngineLoad | Pid::ThrottlePosition | Pid::FuelTankLevelInput => {
+                Some(a * 100.0 / 255.0)
+            }
+            Pid::EngineCoolantTemperature
+            | Pid::IntakeAirTemperature
+            | Pid::AmbientAirTemperature => Some(a - 40.0),
+            Pid::IntakeManifoldAbsolutePressure | Pid::VehicleSpeed => Some(a),
+            Pid::EngineRpm => Some(ab()? / 4.0),
+            Pid::MafAirFlowRate => Some(ab()? / 100.0),
+            Pid::RunTimeSinceEngineStart => ab(),
+            Pid::ControlModuleVoltage => Some(ab()? / 1000.0),
+            Pid::SupportedPids01To20 | Pid::MonitorStatus => None,
+        }
+    }
+}
+
+/// Formats a 2 byte OBD-II DTC as defined in SAE J2012, e.g. `P0420`. The top two bits select the system (P, C, B or U), followed by four digits.
+pub fn dtc_to_string(dtc: u16) -> String {
+    let system = match dtc >> 14 {
+        0 => 'P',
+        1 => 'C',
+        2 => 'B',
+        _ => 'U',
+    };
+
+    format!("{}{}{:03X}", system, (dtc >> 12) & 0b11, dtc & 0xfff)
+}
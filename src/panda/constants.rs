@@ -16,6 +16,14 @@ pub enum HwType {
     Quatro = 0x10,
 }
 
+/// Pandas with a CAN-FD capable CAN controller
+pub const FD_PANDAS: &[HwType] = &[
+    HwType::RedPanda,
+    HwType::RedPandaV2,
+    HwType::Tres,
+    HwType::Quatro,
+];
+
 #[repr(u8)]
 pub enum Endpoint {
     CanWrite = 0x3,
@@ -27,11 +35,17 @@ pub enum Endpoint {
     PowerSave = 0xe7,
     CanFDAuto = 0xe8,
     HeartbeatDisabled = 0xf8,
+    CanSpeed = 0xde,
+    CanDataSpeed = 0xf9,
+    Health = 0xd2,
+    CanHealth = 0xc2,
 }
 
 #[repr(u8)]
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum SafetyModel {
     Silent = 0,
+    Elm327 = 3,
     AllOutput = 17,
+    NoOutput = 19,
 }
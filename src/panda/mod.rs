@@ -6,11 +6,13 @@ mod usb_protocol;
 
 pub use error::Error;
 use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 use crate::can::AsyncCanAdapter;
 use crate::can::CanAdapter;
 use crate::can::Frame;
-use crate::panda::constants::{Endpoint, HwType, SafetyModel};
+use crate::panda::constants::{Endpoint, HwType, SafetyModel, FD_PANDAS};
 use crate::Result;
 use tracing::{info, warn};
 
@@ -18,13 +20,138 @@ const VENDOR_ID: u16 = 0xbbaa;
 const PRODUCT_ID: u16 = 0xddcc;
 const EXPECTED_CAN_PACKET_VERSION: u8 = 4;
 const MAX_BULK_SIZE: usize = 16384;
-const PANDA_BUS_CNT: usize = 3;
+pub(crate) const PANDA_BUS_CNT: usize = 3;
+/// Maximum amount of unprocessed data kept between reads. A partial packet is at most 70 bytes, so anything larger means the stream is not making progress.
+const MAX_DAT_SIZE: usize = MAX_BULK_SIZE;
 
 /// Blocking implementation of the panda CAN adapter
 pub struct Panda {
     handle: rusb::DeviceHandle<rusb::GlobalContext>,
     timeout: std::time::Duration,
     dat: Vec<u8>,
+    discarded_bytes: Arc<AtomicUsize>,
+}
+
+/// Size of the part of the health packet that is common to all health versions
+const HEALTH_SIZE: usize = 37;
+
+/// Panda health, as reported by the firmware
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Health {
+    /// Uptime in seconds
+    pub uptime: u32,
+    /// Supply voltage in mV
+    pub voltage: u32,
+    /// Supply current in mA
+    pub current: u32,
+    pub ignition_line: bool,
+    pub ignition_can: bool,
+    pub controls_allowed: bool,
+    /// Harness orientation: 0 = not connected, 1 = normal, 2 = flipped
+    pub car_harness_status: u8,
+    pub safety_model: u8,
+    /// Whether the harness relay is closed, disconnecting the car side of the bus from the camera. The health packet doesn't report the relay state, so this is inferred from the safety model and doesn't reflect a relay that failed to switch.
+    pub relay_closed: bool,
+}
+
+impl TryFrom<&[u8]> for Health {
+    type Error = crate::Error;
+    fn try_from(buf: &[u8]) -> Result<Self> {
+        if buf.len() < HEALTH_SIZE {
+            return Err(crate::Error::MalformedFrame);
+        }
+
+        let u32_at = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        let safety_model = buf[36];
+
+        // The firmware energizes the relay in all safety models, except those that never transmit
+        let relay_closed = ![
+            SafetyModel::Silent,
+            SafetyModel::NoOutput,
+            SafetyModel::Elm327,
+        ]
+        .iter()
+        .any(|&m| m as u8 == safety_model);
+
+        Ok(Health {
+            uptime: u32_at(0),
+            voltage: u32_at(4),
+            current: u32_at(8),
+            ignition_line: buf[32] != 0,
+            ignition_can: buf[33] != 0,
+            controls_allowed: buf[34] != 0,
+            car_harness_status: buf[35],
+            safety_model,
+            relay_closed,
+        })
+    }
+}
+
+/// Size of the part of the CAN health packet that is common to all CAN health versions
+const CAN_HEALTH_SIZE: usize = 48;
+
+/// Health of a single CAN controller, as reported by the panda firmware
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CanHealth {
+    pub bus_off: bool,
+    /// Number of times the controller went bus-off
+    pub bus_off_count: u32,
+    pub error_warning: bool,
+    pub error_passive: bool,
+    /// Last error code (LEC)
+    pub last_error: u8,
+    /// Receive error counter (REC)
+    pub receive_error_count: u8,
+    /// Transmit error counter (TEC)
+    pub transmit_error_count: u8,
+    /// Number of error interrupts
+    pub total_error_count: u32,
+    /// Frames dropped because the transmit queue was full
+    pub total_tx_lost_count: u32,
+    /// Frames dropped because the receive FIFO was full
+    pub total_rx_lost_count: u32,
+    pub total_tx_count: u32,
+    pub total_rx_count: u32,
+    /// Bitrate in bit/s
+    pub bitrate: u32,
+    /// CAN-FD data bitrate in bit/s
+    pub data_bitrate: u32,
+    pub canfd_enabled: bool,
+    pub brs_enabled: bool,
+}
+
+impl TryFrom<&[u8]> for CanHealth {
+    type Error = crate::Error;
+    fn try_from(buf: &[u8]) -> Result<Self> {
+        if buf.len() < CAN_HEALTH_SIZE {
+            return Err(crate::Error::MalformedFrame);
+        }
+
+        let u16_at = |i: usize| u16::from_le_bytes([buf[i], buf[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+
+        Ok(CanHealth {
+            bus_off: buf[0] != 0,
+            bus_off_count: u32_at(1),
+            error_warning: buf[5] != 0,
+            error_passive: buf[6] != 0,
+            last_error: buf[7],
+            receive_error_count: buf[11],
+            transmit_error_count: buf[12],
+            total_error_count: u32_at(13),
+            total_tx_lost_count: u32_at(17),
+            total_rx_lost_count: u32_at(21),
+            total_tx_count: u32_at(25),
+            total_rx_count: u32_at(29),
+            // Speeds are reported in units of 100 bit/s
+            bitrate: u16_at(41) as u32 * 100,
+            data_bitrate: u16_at(43) as u32 * 100,
+            canfd_enabled: buf[45] != 0,
+            brs_enabled: buf[46] != 0,
+        })
+    }
 }
 
 #[allow(dead_code)]
@@ -43,10 +170,45 @@ impl Panda {
         Ok(AsyncCanAdapter::new(panda))
     }
 
+    /// Convenience function to create a new panda adapter with the bitrate (in bit/s) set on all busses, and wrap in an [`AsyncCanAdapter`]
+    pub fn new_async_with_bitrate(bitrate: u32) -> Result<AsyncCanAdapter> {
+        let panda = Panda::new()?;
+        for bus in 0..PANDA_BUS_CNT {
+            panda.set_bitrate(bus, bitrate)?;
+        }
+        Ok(AsyncCanAdapter::new(panda))
+    }
+
     /// Connect to the first available panda. This function will set the safety mode to ALL_OUTPUT and clear all buffers.
     pub fn new() -> Result<Panda> {
-        for device in rusb::devices().unwrap().iter() {
-            let device_desc = device.device_descriptor().unwrap();
+        match Panda::devices()?.into_iter().next() {
+            Some(device) => Panda::open(device),
+            None => Err(crate::Error::NotFound),
+        }
+    }
+
+    /// Connect to the panda with the given USB serial number. See [`Panda::new`]. Pandas that can't be opened to read the serial number (e.g. because they are in use) are skipped.
+    pub fn new_with_serial(serial: &str) -> Result<Panda> {
+        for device in Panda::devices()? {
+            if Panda::try_serial(&device).as_deref() == Some(serial) {
+                return Panda::open(device);
+            }
+        }
+        Err(crate::Error::NotFound)
+    }
+
+    /// Returns the USB serial numbers of all connected pandas. Pandas that can't be opened to read the serial number (e.g. because they are in use) are skipped.
+    pub fn list() -> Result<Vec<String>> {
+        Ok(Panda::devices()?
+            .iter()
+            .filter_map(Panda::try_serial)
+            .collect())
+    }
+
+    fn devices() -> Result<Vec<rusb::Device<rusb::GlobalContext>>> {
+        let mut devices = vec![];
+        for device in rusb::devices()?.iter() {
+            let device_desc = device.device_descriptor()?;
 
             if device_desc.vendor_id() != VENDOR_ID {
                 continue;
@@ -55,38 +217,73 @@ impl Panda {
                 continue;
             }
 
-            let panda = Panda {
-                dat: vec![],
-                handle: device.open()?,
-                timeout: std::time::Duration::from_millis(100),
-            };
+            devices.push(device);
+        }
+        Ok(devices)
+    }
 
-            panda.handle.claim_interface(0)?;
+    fn serial(device: &rusb::Device<rusb::GlobalContext>) -> Result<String> {
+        let device_desc = device.device_descriptor()?;
+        let handle = device.open()?;
+        Ok(handle.read_serial_number_string_ascii(&device_desc)?)
+    }
 
-            // Check panda firmware version
-            let versions = panda.get_packets_versions()?;
-            if versions.can_version != EXPECTED_CAN_PACKET_VERSION {
-                return Err(Error::WrongFirmwareVersion.into());
-            }
+    /// Reads the serial number, logging a warning if that fails
+    fn try_serial(device: &rusb::Device<rusb::GlobalContext>) -> Option<String> {
+        Panda::serial(device)
+            .inspect_err(|e| {
+                warn!(
+                    "Skipping panda at bus {} address {}: {}",
+                    device.bus_number(),
+                    device.address(),
+                    e
+                )
+            })
+            .ok()
+    }
 
-            panda.set_safety_model(SafetyModel::AllOutput)?;
-            panda.set_power_save(false)?;
-            panda.set_heartbeat_disabled()?;
-            panda.can_reset_communications()?;
+    fn open(device: rusb::Device<rusb::GlobalContext>) -> Result<Panda> {
+        let panda = Panda {
+            dat: vec![],
+            discarded_bytes: Arc::new(AtomicUsize::new(0)),
+            handle: device.open()?,
+            timeout: std::time::Duration::from_millis(100),
+        };
 
-            for i in 0..PANDA_BUS_CNT {
-                panda.set_canfd_auto(i, false)?;
-            }
+        panda.handle.claim_interface(0)?;
 
-            // can_reset_communications() doesn't work properly, flush manually
-            panda.flush_rx()?;
+        // Check panda firmware version
+        let versions = panda.get_packets_versions()?;
+        if versions.can_version != EXPECTED_CAN_PACKET_VERSION {
+            return Err(Error::WrongFirmwareVersion.into());
+        }
 
-            let hw_type = panda.get_hw_type()?;
-            info!("Connected to Panda ({:?})", hw_type);
+        panda.set_safety_model(SafetyModel::AllOutput)?;
+        panda.set_power_save(false)?;
+        panda.set_heartbeat_disabled()?;
+        panda.can_reset_communications()?;
 
-            return Ok(panda);
+        for i in 0..PANDA_BUS_CNT {
+            panda.set_canfd_auto(i, false)?;
         }
-        Err(crate::Error::NotFound)
+
+        // can_reset_communications() doesn't work properly, flush manually
+        panda.flush_rx()?;
+
+        let hw_type = panda.get_hw_type()?;
+        info!("Connected to Panda ({:?})", hw_type);
+
+        Ok(panda)
+    }
+
+    /// Total number of received bytes that were discarded because they could not be unpacked. A value that keeps increasing indicates a corrupt USB stream.
+    pub fn discarded_bytes(&self) -> usize {
+        self.discarded_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Shared handle to the counter returned by [`Panda::discarded_bytes`]. Take the handle before wrapping the panda in an [`AsyncCanAdapter`] to keep monitoring the counter.
+    pub fn discarded_bytes_counter(&self) -> Arc<AtomicUsize> {
+        self.discarded_bytes.clone()
     }
 
     fn flush_rx(&self) -> Result<()> {
@@ -110,6 +307,32 @@ impl Panda {
         self.usb_write_control(Endpoint::SafetyModel, safety_model as u16, safety_param)
     }
 
+    /// Close or open the relay in the comma.ai harness. Closing the relay disconnects the car side of the bus from the camera, so frames can be injected without conflicting with the original ECU. This switches the safety model: `AllOutput` to close the relay, and `Silent` to open it, which also disables transmitting.
+    pub fn set_relay(&self, closed: bool) -> Result<()> {
+        let safety_model = if closed {
+            SafetyModel::AllOutput
+        } else {
+            SafetyModel::Silent
+        };
+        self.set_safety_model(safety_model)
+    }
+
+    /// Read the health of the panda, containing supply voltage, current and the relay state inferred from the safety model
+    pub fn health(&self) -> Result<Health> {
+        let buf = self.usb_read_control(Endpoint::Health, 0, HEALTH_SIZE)?;
+        Health::try_from(buf.as_slice())
+    }
+
+    /// Read the health of the CAN controller of a bus, containing error counters, bus-off state and dropped frame counts
+    pub fn get_can_health(&self, bus: usize) -> Result<CanHealth> {
+        if bus >= PANDA_BUS_CNT {
+            return Err(crate::Error::NotSupported);
+        }
+
+        let buf = self.usb_read_control(Endpoint::CanHealth, bus as u16, CAN_HEALTH_SIZE)?;
+        CanHealth::try_from(buf.as_slice())
+    }
+
     fn set_heartbeat_disabled(&self) -> Result<()> {
         self.usb_write_control(Endpoint::HeartbeatDisabled, 0, 0)
     }
@@ -125,14 +348,47 @@ impl Panda {
         self.usb_write_control(Endpoint::CanFDAuto, bus as u16, auto as u16)
     }
 
+    /// Enable or disable CAN-FD on a bus. FD frames are then sent and received with the CAN-FD DLC table by the USB protocol. Returns NotSupported when enabling CAN-FD on a panda without a CAN-FD capable controller. CAN-FD is disabled on all busses when connecting to the panda.
+    pub fn set_canfd_enabled(&self, bus: usize, enabled: bool) -> Result<()> {
+        if enabled && !FD_PANDAS.contains(&self.get_hw_type()?) {
+            return Err(crate::Error::NotSupported);
+        }
+        self.set_canfd_auto(bus, enabled)
+    }
+
+    /// Set the (arbitration) bitrate of a bus in bit/s, e.g. 500000. The panda only supports a fixed set of bitrates, and will ignore unsupported values.
+    pub fn set_bitrate(&self, bus: usize, bitrate: u32) -> Result<()> {
+        self.set_speed(Endpoint::CanSpeed, bus, bitrate)
+    }
+
+    /// Set the CAN-FD data bitrate of a bus in bit/s, e.g. 2000000. Only supported on pandas with CAN-FD support.
+    pub fn set_data_bitrate(&self, bus: usize, bitrate: u32) -> Result<()> {
+        self.set_speed(Endpoint::CanDataSpeed, bus, bitrate)
+    }
+
+    fn set_speed(&self, endpoint: Endpoint, bus: usize, bitrate: u32) -> Result<()> {
+        if bus >= PANDA_BUS_CNT {
+            return Err(crate::Error::NotSupported);
+        }
+
+        // Firmware expects the speed in units of 100 bit/s
+        let speed = bitrate / 100;
+        if speed * 100 != bitrate {
+            return Err(crate::Error::NotSupported);
+        }
+        let speed = u16::try_from(speed).map_err(|_| crate::Error::NotSupported)?;
+
+        self.usb_write_control(endpoint, bus as u16, speed)
+    }
+
     /// Get the hardware type of the panda. Usefull to detect if it supports CAN-FD.
     pub fn get_hw_type(&self) -> Result<HwType> {
-        let hw_type = self.usb_read_control(Endpoint::HwType, 1)?;
+        let hw_type = self.usb_read_control(Endpoint::HwType, 0, 1)?;
         HwType::from_repr(hw_type[0]).ok_or(Error::UnknownHwType.into())
     }
 
     fn get_packets_versions(&self) -> Result<Versions> {
-        let versions = self.usb_read_control(Endpoint::PacketsVersions, 3)?;
+        let versions = self.usb_read_control(Endpoint::PacketsVersions, 0, 3)?;
         Ok({
             Versions {
                 health_version: versions[0],
@@ -146,7 +402,7 @@ impl Panda {
         self.usb_write_control(Endpoint::CanResetCommunications, 0, 0)
     }
 
-    fn usb_read_control(&self, endpoint: Endpoint, n: usize) -> Result<Vec<u8>> {
+    fn usb_read_control(&self, endpoint: Endpoint, value: u16, n: usize) -> Result<Vec<u8>> {
         let mut buf: Vec<u8> = vec![0; n];
 
         let request_type = rusb::request_type(
@@ -156,8 +412,14 @@ impl Panda {
         );
 
         // TOOD: Check if we got the expected amount of data?
-        self.handle
-            .read_control(request_type, endpoint as u8, 0, 0, &mut buf, self.timeout)?;
+        self.handle.read_control(
+            request_type,
+            endpoint as u8,
+            value,
+            0,
+            &mut buf,
+            self.timeout,
+        )?;
         Ok(buf)
     }
 
@@ -196,6 +458,12 @@ impl CanAdapter for Panda {
         Ok(())
     }
 
+    /// Rejects remote frames and raw DLCs that don't match the data length, which can't be represented in the USB protocol
+    fn check_frame(&self, frame: &Frame) -> Result<()> {
+        usb_protocol::check_frame(frame)?;
+        Ok(())
+    }
+
     /// Reads the current buffer of available CAN messages from the panda. This function will return an empty vector if no messages are available. In case of a recoverable error (e.g. unpacking error), the buffer will be cleared and an empty vector will be returned.
     fn recv(&mut self) -> Result<Vec<Frame>> {
         let mut buf: [u8; MAX_BULK_SIZE] = [0; MAX_BULK_SIZE];
@@ -209,12 +477,81 @@ impl CanAdapter for Panda {
 
         // Recover from unpacking errors, can_reset_communications() doesn't work properly
         match frames {
-            Ok(frames) => Ok(frames),
+            Ok(frames) => {
+                if self.dat.len() > MAX_DAT_SIZE {
+                    warn!(
+                        "Receive buffer not making progress, discarding {} bytes",
+                        self.dat.len()
+                    );
+                    self.discarded_bytes
+                        .fetch_add(self.dat.len(), Ordering::Relaxed);
+                    self.dat.clear();
+                }
+                Ok(frames)
+            }
             Err(e) => {
-                warn!("Error unpacking: {:}", e);
+                warn!(
+                    "Error unpacking: {:}, discarding {} bytes",
+                    e,
+                    self.dat.len()
+                );
+                self.discarded_bytes
+                    .fetch_add(self.dat.len(), Ordering::Relaxed);
                 self.dat.clear();
                 Ok(vec![])
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_health() {
+        let mut buf = vec![0u8; HEALTH_SIZE];
+        buf[0..4].copy_from_slice(&120u32.to_le_bytes());
+        buf[4..8].copy_from_slice(&12_300u32.to_le_bytes());
+        buf[8..12].copy_from_slice(&250u32.to_le_bytes());
+        buf[32] = 1;
+        buf[35] = 2;
+        buf[36] = SafetyModel::AllOutput as u8;
+
+        let health = Health::try_from(buf.as_slice()).unwrap();
+        assert_eq!(health.uptime, 120);
+        assert_eq!(health.voltage, 12_300);
+        assert_eq!(health.current, 250);
+        assert!(health.ignition_line);
+        assert!(!health.ignition_can);
+        assert_eq!(health.car_harness_status, 2);
+        assert!(health.relay_closed);
+
+        buf[36] = SafetyModel::Silent as u8;
+        assert!(!Health::try_from(buf.as_slice()).unwrap().relay_closed);
+
+        assert!(Health::try_from(&buf[..10]).is_err());
+    }
+
+    #[test]
+    fn parse_can_health() {
+        let mut buf = vec![0u8; CAN_HEALTH_SIZE];
+        buf[0] = 1;
+        buf[1..5].copy_from_slice(&3u32.to_le_bytes());
+        buf[6] = 1;
+        buf[12] = 255;
+        buf[21..25].copy_from_slice(&42u32.to_le_bytes());
+        buf[41..43].copy_from_slice(&5000u16.to_le_bytes());
+        buf[43..45].copy_from_slice(&20000u16.to_le_bytes());
+
+        let health = CanHealth::try_from(buf.as_slice()).unwrap();
+        assert!(health.bus_off);
+        assert_eq!(health.bus_off_count, 3);
+        assert!(!health.error_warning);
+        assert!(health.error_passive);
+        assert_eq!(health.transmit_error_count, 255);
+        assert_eq!(health.total_rx_lost_count, 42);
+        assert_eq!(health.bitrate, 500_000);
+        assert_eq!(health.data_bitrate, 2_000_000);
+    }
+}
@@ -24,32 +24,54 @@ const CANPACKET_MAX_CHUNK_SIZE: usize = 256;
 //   unsigned char data[CANPACKET_DATA_SIZE_MAX];
 // }
 
+// Note that this version of the packet does not contain a hardware timestamp,
+// so the timestamp of received frames is left as `None`. There is also
+// no flag for remote frames, so these can't be sent or received. The DLC
+// always matches the data length. Bit rate switching is configured per bus
+// in the firmware, so the BRS flag of a frame is ignored.
+
 fn calculate_checksum(dat: &[u8]) -> u8 {
     dat.iter().fold(0, |acc, &x| acc ^ x)
 }
 
+/// Checks if the frame can be sent by the panda, and returns the DLC to use in the header
+pub fn check_frame(frame: &Frame) -> Result<u8, Error> {
+    // Check if the id is valid
+    if let Identifier::Standard(id) = frame.id {
+        if id > 0x7ff {
+            return Err(Error::MalformedFrame);
+        }
+    }
+
+    if frame.rtr {
+        return Err(Error::NotSupported);
+    }
+
+    let dlc = DLC_TO_LEN.iter().position(|&x| x == frame.data.len());
+    let dlc = dlc.ok_or(Error::MalformedFrame)? as u8;
+
+    if frame.dlc.is_some_and(|raw_dlc| raw_dlc != dlc) {
+        return Err(Error::NotSupported);
+    }
+
+    Ok(dlc)
+}
+
 pub fn pack_can_buffer(frames: &[Frame]) -> Result<Vec<Vec<u8>>, Error> {
     let mut ret = vec![];
     ret.push(vec![]);
 
     for frame in frames {
+        let dlc = check_frame(frame)?;
+
         let extended: u32 = match frame.id {
             Identifier::Standard(_) => 0,
             Identifier::Extended(_) => 1,
         };
 
         let id: u32 = frame.id.into();
-
-        // Check if the id is valid
-        if id > 0x7ff && extended == 0 {
-            return Err(Error::MalformedFrame);
-        }
-
         let fd = frame.fd as u8;
 
-        let dlc = DLC_TO_LEN.iter().position(|&x| x == frame.data.len());
-        let dlc = dlc.ok_or(Error::MalformedFrame)? as u8;
-
         let word_4b: u32 = (id << 3) | (extended << 2);
 
         let header: [u8; CANPACKET_HEAD_SIZE - 1] = [
@@ -118,6 +140,10 @@ pub fn unpack_can_buffer(dat: &mut Vec<u8>) -> Result<Vec<Frame>, Error> {
             data: dat[CANPACKET_HEAD_SIZE..(CANPACKET_HEAD_SIZE + data_len)].to_vec(),
             loopback: returned,
             fd,
+            brs: false,
+            timestamp: None,
+            rtr: false,
+            dlc: None,
         });
 
         dat.drain(0..(CANPACKET_HEAD_SIZE + data_len));
@@ -151,6 +177,9 @@ mod tests {
                 0, 0, 0, 199, 116, 151, 129
             ]
         );
+
+        // The packet has no hardware timestamp
+        assert_eq!(frames[0].timestamp, None);
     }
 
     #[test]
@@ -174,6 +203,10 @@ mod tests {
                 data: vec![1, 2, 3, 4, 5, 6, 7, 8],
                 loopback: false,
                 fd: false,
+                brs: false,
+                timestamp: None,
+                rtr: false,
+                dlc: None,
             },
             Frame {
                 bus: 1,
@@ -181,6 +214,10 @@ mod tests {
                 data: vec![1, 2, 3, 4],
                 loopback: false,
                 fd: false,
+                brs: false,
+                timestamp: None,
+                rtr: false,
+                dlc: None,
             },
             Frame {
                 bus: 1,
@@ -188,6 +225,10 @@ mod tests {
                 data: vec![1, 2, 3, 4],
                 loopback: false,
                 fd: true,
+                brs: false,
+                timestamp: None,
+                rtr: false,
+                dlc: None,
             },
         ];
 
@@ -206,6 +247,10 @@ mod tests {
             data: vec![1, 2, 3, 4, 5, 6, 7, 8, 9],
             loopback: false,
             fd: false,
+            brs: false,
+            timestamp: None,
+            rtr: false,
+            dlc: None,
         }];
         let r = pack_can_buffer(&frames);
         assert_eq!(r, Err(Error::MalformedFrame));
@@ -219,8 +264,32 @@ mod tests {
             data: vec![1, 2, 3, 4, 5, 6, 7, 8],
             loopback: false,
             fd: false,
+            brs: false,
+            timestamp: None,
+            rtr: false,
+            dlc: None,
         }];
         let r = pack_can_buffer(&frames);
         assert_eq!(r, Err(Error::MalformedFrame));
     }
+
+    #[test]
+    fn test_rtr_not_supported() {
+        let mut frame = Frame::new(0, Identifier::Standard(0x123), &[]).unwrap();
+        frame.rtr = true;
+
+        let r = pack_can_buffer(&[frame]);
+        assert_eq!(r, Err(Error::NotSupported));
+    }
+
+    #[test]
+    fn test_dlc_override_not_supported() {
+        let mut frame = Frame::new(0, Identifier::Standard(0x123), &[0xaa; 8]).unwrap();
+        frame.dlc = Some(8);
+        assert!(pack_can_buffer(&[frame.clone()]).is_ok());
+
+        frame.dlc = Some(15);
+        let r = pack_can_buffer(&[frame]);
+        assert_eq!(r, Err(Error::NotSupported));
+    }
 }
@@ -0,0 +1,106 @@
+//! Minimal bindings for the PCAN-Basic API (`PCANBasic.h`)
+#![allow(non_snake_case, non_camel_case_types, dead_code)]
+
+use std::os::raw::c_char;
+
+pub type TPCANHandle = u16;
+pub type TPCANStatus = u32;
+pub type TPCANBaudrate = u16;
+pub type TPCANType = u8;
+pub type TPCANMessageType = u8;
+
+// Channels
+pub const PCAN_USBBUS1: TPCANHandle = 0x51;
+
+// Status codes
+pub const PCAN_ERROR_OK: TPCANStatus = 0x00000;
+pub const PCAN_ERROR_XMTFULL: TPCANStatus = 0x00001;
+pub const PCAN_ERROR_QRCVEMPTY: TPCANStatus = 0x00020;
+pub const PCAN_ERROR_QXMTFULL: TPCANStatus = 0x00080;
+
+// Message types
+pub const PCAN_MESSAGE_STANDARD: TPCANMessageType = 0x00;
+pub const PCAN_MESSAGE_RTR: TPCANMessageType = 0x01;
+pub const PCAN_MESSAGE_EXTENDED: TPCANMessageType = 0x02;
+pub const PCAN_MESSAGE_FD: TPCANMessageType = 0x04;
+pub const PCAN_MESSAGE_BRS: TPCANMessageType = 0x08;
+pub const PCAN_MESSAGE_ESI: TPCANMessageType = 0x10;
+pub const PCAN_MESSAGE_ECHO: TPCANMessageType = 0x20;
+pub const PCAN_MESSAGE_ERRFRAME: TPCANMessageType = 0x40;
+pub const PCAN_MESSAGE_STATUS: TPCANMessageType = 0x80;
+
+// BTR0/BTR1 register values for classic CAN bitrates
+pub const PCAN_BAUD_1M: TPCANBaudrate = 0x0014;
+pub const PCAN_BAUD_800K: TPCANBaudrate = 0x0016;
+pub const PCAN_BAUD_500K: TPCANBaudrate = 0x001C;
+pub const PCAN_BAUD_250K: TPCANBaudrate = 0x011C;
+pub const PCAN_BAUD_125K: TPCANBaudrate = 0x031C;
+pub const PCAN_BAUD_100K: TPCANBaudrate = 0x432F;
+pub const PCAN_BAUD_50K: TPCANBaudrate = 0x472F;
+pub const PCAN_BAUD_20K: TPCANBaudrate = 0x532F;
+pub const PCAN_BAUD_10K: TPCANBaudrate = 0x672F;
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TPCANMsg {
+    pub ID: u32,
+    pub MSGTYPE: TPCANMessageType,
+    pub LEN: u8,
+    pub DATA: [u8; 8],
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TPCANTimestamp {
+    pub millis: u32,
+    pub millis_overflow: u16,
+    pub micros: u16,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct TPCANMsgFD {
+    pub ID: u32,
+    pub MSGTYPE: TPCANMessageType,
+    pub DLC: u8,
+    pub DATA: [u8; 64],
+}
+
+impl Default for TPCANMsgFD {
+    fn default() -> Self {
+        Self {
+            ID: 0,
+            MSGTYPE: 0,
+            DLC: 0,
+            DATA: [0; 64],
+        }
+    }
+}
+
+pub type TPCANTimestampFD = u64;
+
+extern "system" {
+    pub fn CAN_Initialize(
+        Channel: TPCANHandle,
+        Btr0Btr1: TPCANBaudrate,
+        HwType: TPCANType,
+        IOPort: u32,
+        Interrupt: u16,
+    ) -> TPCANStatus;
+    pub fn CAN_InitializeFD(Channel: TPCANHandle, BitrateFD: *const c_char) -> TPCANStatus;
+    pub fn CAN_Uninitialize(Channel: TPCANHandle) -> TPCANStatus;
+    pub fn CAN_GetStatus(Channel: TPCANHandle) -> TPCANStatus;
+    pub fn CAN_Read(
+        Channel: TPCANHandle,
+        MessageBuffer: *mut TPCANMsg,
+        TimestampBuffer: *mut TPCANTimestamp,
+    ) -> TPCANStatus;
+    pub fn CAN_ReadFD(
+        Channel: TPCANHandle,
+        MessageBuffer: *mut TPCANMsgFD,
+        TimestampBuffer: *mut TPCANTimestampFD,
+    ) -> TPCANStatus;
+    pub fn CAN_Write(Channel: TPCANHandle, MessageBuffer: *const TPCANMsg) -> TPCANStatus;
+    pub fn CAN_WriteFD(Channel: TPCANHandle, MessageBuffer: *const TPCANMsgFD) -> TPCANStatus;
+    pub fn CAN_GetErrorText(Error: TPCANStatus, Language: u16, Buffer: *mut c_char) -> TPCANStatus;
+}
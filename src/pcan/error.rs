@@ -0,0 +1,8 @@
+//! Error types for the PCAN-Basic adapter.
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum Error {
+    #[error("Driver error: {0}")]
+    DriverError(String),
+}
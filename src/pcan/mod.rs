@@ -0,0 +1,387 @@
+//! PEAK PCAN Adapter support through the PCAN-Basic library. Make sure `libpcanbasic.so` (Linux) or `PCANBasic.dll` (Windows) is installed.
+mod bindings;
+pub mod error;
+
+pub use error::Error;
+
+use std::collections::VecDeque;
+use std::ffi::CString;
+
+use crate::can::{AsyncCanAdapter, CanAdapter, Frame, Identifier, DLC_TO_LEN};
+use crate::pcan::bindings::*;
+use crate::Result;
+use tracing::info;
+
+pub use bindings::PCAN_USBBUS1;
+
+/// CAN-FD bitrate string for 500 kbit/s arbitration and 2 Mbit/s data bitrate, for use with [`Pcan::new_fd`]
+pub const PCAN_BITRATE_500K_2M: &str = "f_clock_mhz=80, nom_brp=2, nom_tseg1=63, nom_tseg2=16, nom_sjw=16, data_brp=2, data_tseg1=15, data_tseg2=4, data_sjw=4";
+
+fn status_to_result(status: TPCANStatus) -> Result<()> {
+    if status == PCAN_ERROR_OK {
+        return Ok(());
+    }
+
+    // Buffer needs to be at least 256 bytes
+    let mut buf = [0 as std::os::raw::c_char; 256];
+    let text = unsafe {
+        CAN_GetErrorText(status, 0x09, buf.as_mut_ptr());
+        std::ffi::CStr::from_ptr(buf.as_ptr())
+    };
+
+    Err(Error::DriverError(format!("0x{:x} {}", status, text.to_string_lossy())).into())
+}
+
+fn btr0btr1(bitrate: u32) -> Option<TPCANBaudrate> {
+    match bitrate {
+        10_000 => Some(PCAN_BAUD_10K),
+        20_000 => Some(PCAN_BAUD_20K),
+        50_000 => Some(PCAN_BAUD_50K),
+        100_000 => Some(PCAN_BAUD_100K),
+        125_000 => Some(PCAN_BAUD_125K),
+        250_000 => Some(PCAN_BAUD_250K),
+        500_000 => Some(PCAN_BAUD_500K),
+        800_000 => Some(PCAN_BAUD_800K),
+        1_000_000 => Some(PCAN_BAUD_1M),
+        _ => None,
+    }
+}
+
+fn id_from_pcan(id: u32, msg_type: TPCANMessageType) -> Identifier {
+    if msg_type & PCAN_MESSAGE_EXTENDED != 0 {
+        Identifier::Extended(id)
+    } else {
+        Identifier::Standard(id)
+    }
+}
+
+fn id_to_pcan(id: Identifier) -> (u32, TPCANMessageType) {
+    match id {
+        Identifier::Standard(id) => (id, PCAN_MESSAGE_STANDARD),
+        Identifier::Extended(id) => (id, PCAN_MESSAGE_EXTENDED),
+    }
+}
+
+/// Error and status messages are reported through the receive queue, but are not CAN frames
+fn is_ignored(msg_type: TPCANMessageType) -> bool {
+    msg_type & (PCAN_MESSAGE_ERRFRAME | PCAN_MESSAGE_STATUS) != 0
+}
+
+impl From<&TPCANMsg> for Frame {
+    fn from(msg: &TPCANMsg) -> Self {
+        let rtr = msg.MSGTYPE & PCAN_MESSAGE_RTR != 0;
+
+        // Remote frames don't carry data, the length only indicates the requested length
+        let len = match rtr {
+            true => 0,
+            false => std::cmp::min(msg.LEN as usize, msg.DATA.len()),
+        };
+        Frame {
+            bus: 0,
+            id: id_from_pcan(msg.ID, msg.MSGTYPE),
+            data: msg.DATA[..len].to_vec(),
+            loopback: false,
+            fd: false,
+            brs: false,
+            timestamp: None,
+            rtr,
+            dlc: (rtr && msg.LEN != 0).then_some(msg.LEN),
+        }
+    }
+}
+
+impl From<&TPCANMsgFD> for Frame {
+    fn from(msg: &TPCANMsgFD) -> Self {
+        let rtr = msg.MSGTYPE & PCAN_MESSAGE_RTR != 0;
+        let len = match rtr {
+            true => 0,
+            false => DLC_TO_LEN[(msg.DLC & 0xf) as usize],
+        };
+        Frame {
+            bus: 0,
+            id: id_from_pcan(msg.ID, msg.MSGTYPE),
+            data: msg.DATA[..len].to_vec(),
+            loopback: false,
+            fd: msg.MSGTYPE & PCAN_MESSAGE_FD != 0,
+            brs: msg.MSGTYPE & PCAN_MESSAGE_BRS != 0,
+            timestamp: None,
+            rtr,
+            dlc: (rtr && msg.DLC != 0).then_some(msg.DLC),
+        }
+    }
+}
+
+impl TryFrom<&Frame> for TPCANMsg {
+    type Error = crate::Error;
+    fn try_from(frame: &Frame) -> Result<Self> {
+        if frame.fd || frame.data.len() > 8 {
+            return Err(crate::Error::MalformedFrame);
+        }
+
+        let (id, mut msg_type) = id_to_pcan(frame.id);
+        let mut len = frame.data.len() as u8;
+
+        // Remote frames don't carry data, the length is the requested DLC
+        if frame.rtr {
+            msg_type |= PCAN_MESSAGE_RTR;
+            len = frame.dlc.unwrap_or(0);
+            if len > 8 {
+                return Err(crate::Error::MalformedFrame);
+            }
+        }
+
+        let mut msg = TPCANMsg {
+            ID: id,
+            MSGTYPE: msg_type,
+            LEN: len,
+            ..Default::default()
+        };
+        msg.DATA[..frame.data.len()].copy_from_slice(&frame.data);
+        Ok(msg)
+    }
+}
+
+impl TryFrom<&Frame> for TPCANMsgFD {
+    type Error = crate::Error;
+    fn try_from(frame: &Frame) -> Result<Self> {
+        let mut dlc = DLC_TO_LEN
+            .iter()
+            .position(|&len| len == frame.data.len())
+            .ok_or(crate::Error::MalformedFrame)?;
+
+        let (id, mut msg_type) = id_to_pcan(frame.id);
+        if frame.fd {
+            msg_type |= PCAN_MESSAGE_FD;
+        }
+
+        // CAN-FD has no remote frames
+        if frame.rtr {
+            if frame.fd {
+                return Err(crate::Error::MalformedFrame);
+            }
+            msg_type |= PCAN_MESSAGE_RTR;
+            dlc = frame.dlc.unwrap_or(0) as usize;
+            if dlc > 8 {
+                return Err(crate::Error::MalformedFrame);
+            }
+        }
+        if frame.brs {
+            msg_type |= PCAN_MESSAGE_BRS;
+        }
+
+        let mut msg = TPCANMsgFD {
+            ID: id,
+            MSGTYPE: msg_type,
+            DLC: dlc as u8,
+            ..Default::default()
+        };
+        msg.DATA[..frame.data.len()].copy_from_slice(&frame.data);
+        Ok(msg)
+    }
+}
+
+/// PEAK PCAN Adapter. ACKs are emulated by looping back frames as soon as they are handed over to the driver.
+pub struct Pcan {
+    channel: TPCANHandle,
+    fd: bool,
+    loopback_queue: VecDeque<Frame>,
+}
+
+impl Pcan {
+    /// Convenience function to create a new adapter and wrap in an [`AsyncCanAdapter`]
+    pub fn new_async(channel: TPCANHandle, bitrate: u32) -> Result<AsyncCanAdapter> {
+        let pcan = Pcan::new(channel, bitrate)?;
+        Ok(AsyncCanAdapter::new(pcan))
+    }
+
+    /// Open a PCAN channel (e.g. [`PCAN_USBBUS1`]) in classic CAN mode. Supports the standard bitrates from 10 kbit/s to 1 Mbit/s.
+    pub fn new(channel: TPCANHandle, bitrate: u32) -> Result<Pcan> {
+        let btr0btr1 = btr0btr1(bitrate).ok_or(crate::Error::NotSupported)?;
+        status_to_result(unsafe { CAN_Initialize(channel, btr0btr1, 0, 0, 0) })?;
+        info!("Connected to PCAN channel 0x{:x}", channel);
+
+        Ok(Pcan {
+            channel,
+            fd: false,
+            loopback_queue: VecDeque::new(),
+        })
+    }
+
+    /// Open a PCAN channel in CAN-FD mode. The bitrate is specified using a PCAN-Basic FD bitrate string, e.g. [`PCAN_BITRATE_500K_2M`].
+    pub fn new_fd(channel: TPCANHandle, bitrate: &str) -> Result<Pcan> {
+        let bitrate = CString::new(bitrate).map_err(|_| crate::Error::NotSupported)?;
+        status_to_result(unsafe { CAN_InitializeFD(channel, bitrate.as_ptr()) })?;
+        info!("Connected to PCAN channel 0x{:x} in CAN-FD mode", channel);
+
+        Ok(Pcan {
+            channel,
+            fd: true,
+            loopback_queue: VecDeque::new(),
+        })
+    }
+
+    fn write(&self, frame: &Frame) -> Result<TPCANStatus> {
+        let status = if self.fd {
+            let msg = TPCANMsgFD::try_from(frame)?;
+            unsafe { CAN_WriteFD(self.channel, &msg) }
+        } else {
+            let msg = TPCANMsg::try_from(frame)?;
+            unsafe { CAN_Write(self.channel, &msg) }
+        };
+        Ok(status)
+    }
+
+    /// Reads a single message. Returns `None` if the receive queue is empty.
+    fn read(&self) -> Result<Option<(TPCANMessageType, Frame)>> {
+        let (status, msg_type, frame) = if self.fd {
+            let mut msg = TPCANMsgFD::default();
+            let mut timestamp: TPCANTimestampFD = 0;
+            let status = unsafe { CAN_ReadFD(self.channel, &mut msg, &mut timestamp) };
+            (status, msg.MSGTYPE, Frame::from(&msg))
+        } else {
+            let mut msg = TPCANMsg::default();
+            let mut timestamp = TPCANTimestamp::default();
+            let status = unsafe { CAN_Read(self.channel, &mut msg, &mut timestamp) };
+            (status, msg.MSGTYPE, Frame::from(&msg))
+        };
+
+        if status & PCAN_ERROR_QRCVEMPTY != 0 {
+            return Ok(None);
+        }
+        status_to_result(status)?;
+        Ok(Some((msg_type, frame)))
+    }
+}
+
+impl Drop for Pcan {
+    fn drop(&mut self) {
+        info!("Closing PCAN channel 0x{:x}", self.channel);
+        unsafe { CAN_Uninitialize(self.channel) };
+    }
+}
+
+impl CanAdapter for Pcan {
+    fn send(&mut self, frames: &mut VecDeque<Frame>) -> Result<()> {
+        while let Some(mut frame) = frames.pop_front() {
+            let status = self.write(&frame)?;
+
+            if status & (PCAN_ERROR_XMTFULL | PCAN_ERROR_QXMTFULL) != 0 {
+                // Transmit queue is full, push it back to the front of the queue for next send call
+                frames.push_front(frame);
+                break;
+            }
+
+            if let Err(e) = status_to_result(status) {
+                frames.push_front(frame);
+                return Err(e);
+            }
+
+            frame.loopback = true;
+            self.loopback_queue.push_back(frame);
+        }
+
+        Ok(())
+    }
+
+    fn check_frame(&self, frame: &Frame) -> Result<()> {
+        if self.fd {
+            TPCANMsgFD::try_from(frame)?;
+        } else {
+            TPCANMsg::try_from(frame)?;
+        }
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Result<Vec<Frame>> {
+        let mut frames = vec![];
+
+        while let Some((msg_type, frame)) = self.read()? {
+            if !is_ignored(msg_type) {
+                frames.push(frame);
+            }
+        }
+
+        // Add fake loopback frames to the receive queue
+        frames.extend(self.loopback_queue.drain(..));
+
+        Ok(frames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classic_round_trip() {
+        let frame = Frame::new(0, Identifier::Extended(0x1234), &[1, 2, 3]).unwrap();
+
+        let msg = TPCANMsg::try_from(&frame).unwrap();
+        assert_eq!(msg.ID, 0x1234);
+        assert_eq!(msg.MSGTYPE, PCAN_MESSAGE_EXTENDED);
+        assert_eq!(msg.LEN, 3);
+        assert_eq!(Frame::from(&msg), frame);
+
+        // CAN-FD frames need a channel opened in CAN-FD mode
+        let fd = Frame::new(0, Identifier::Standard(0x123), &[0; 12]).unwrap();
+        assert!(TPCANMsg::try_from(&fd).is_err());
+    }
+
+    #[test]
+    fn rtr_round_trip() {
+        // Remote frame requesting 4 bytes
+        let mut frame = Frame::new(0, Identifier::Standard(0x123), &[]).unwrap();
+        frame.rtr = true;
+        frame.dlc = Some(4);
+
+        let msg = TPCANMsg::try_from(&frame).unwrap();
+        assert_eq!(msg.MSGTYPE, PCAN_MESSAGE_STANDARD | PCAN_MESSAGE_RTR);
+        assert_eq!(msg.LEN, 4);
+        assert_eq!(Frame::from(&msg), frame);
+
+        let msg = TPCANMsgFD::try_from(&frame).unwrap();
+        assert_eq!(msg.DLC, 4);
+        assert_eq!(Frame::from(&msg), frame);
+    }
+
+    #[test]
+    fn rtr_receive() {
+        let msg = TPCANMsg {
+            ID: 0x123,
+            MSGTYPE: PCAN_MESSAGE_STANDARD | PCAN_MESSAGE_RTR,
+            LEN: 2,
+            DATA: [0xaa; 8],
+        };
+        assert!(!is_ignored(msg.MSGTYPE));
+
+        let frame = Frame::from(&msg);
+        assert!(frame.rtr);
+        assert_eq!(frame.dlc, Some(2));
+        assert!(frame.data.is_empty());
+
+        assert!(is_ignored(PCAN_MESSAGE_STATUS));
+        assert!(is_ignored(PCAN_MESSAGE_ERRFRAME));
+    }
+
+    #[test]
+    fn fd_dlc() {
+        for (dlc, &len) in DLC_TO_LEN.iter().enumerate() {
+            let mut frame = Frame::new(0, Identifier::Standard(0x123), &vec![0xaa; len]).unwrap();
+            frame.fd = true;
+            frame.brs = true;
+
+            let msg = TPCANMsgFD::try_from(&frame).unwrap();
+            assert_eq!(msg.DLC as usize, dlc);
+            assert_eq!(
+                msg.MSGTYPE,
+                PCAN_MESSAGE_STANDARD | PCAN_MESSAGE_FD | PCAN_MESSAGE_BRS
+            );
+            assert_eq!(Frame::from(&msg), frame);
+        }
+
+        // No DLC for 10 bytes
+        let mut frame = Frame::new(0, Identifier::Standard(0x123), &[0; 8]).unwrap();
+        frame.data.extend([0; 2]);
+        assert!(TPCANMsgFD::try_from(&frame).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! Conversion between [`Frame`] and the ASCII representation used by the SLCAN (Lawicel) protocol.
+use crate::can::{Frame, Identifier, DLC_TO_LEN};
+use crate::Result;
+
+/// Encodes a frame as SLCAN command, including the trailing carriage return. Standard frames use `t`, extended frames `T`, and CAN-FD frames `d` or `D` (`b` or `B` with bit rate switch). Remote frames use `r` or `R`, followed by the requested DLC. Returns [`crate::Error::NotSupported`] if [`Frame::dlc`] doesn't match the data length.
+pub fn encode(frame: &Frame) -> Result<String> {
+    if frame.rtr {
+        let dlc = frame.dlc.unwrap_or(0);
+        if frame.fd || dlc > 8 {
+            return Err(crate::Error::MalformedFrame);
+        }
+
+        return Ok(match frame.id {
+            Identifier::Standard(id) => format!("r{:03X}{:X}\r", id, dlc),
+            Identifier::Extended(id) => format!("R{:08X}{:X}\r", id, dlc),
+        });
+    }
+
+    let dlc = DLC_TO_LEN
+        .iter()
+        .position(|&len| len == frame.data.len())
+        .ok_or(crate::Error::MalformedFrame)?;
+
+    // The DLC is always derived from the data length, a DLC of 9-15 for classic frames can't be encoded
+    if frame.dlc.is_some_and(|raw_dlc| raw_dlc as usize != dlc) {
+        return Err(crate::Error::NotSupported);
+    }
+
+    let mut line = match (frame.id, frame.fd, frame.brs) {
+        (Identifier::Standard(id), false, _) => format!("t{:03X}", id),
+        (Identifier::Extended(id), false, _) => format!("T{:08X}", id),
+        (Identifier::Standard(id), true, false) => format!("d{:03X}", id),
+        (Identifier::Extended(id), true, false) => format!("D{:08X}", id),
+        (Identifier::Standard(id), true, true) => format!("b{:03X}", id),
+        (Identifier::Extended(id), true, true) => format!("B{:08X}", id),
+    };
+
+    line.push_str(&format!("{:X}", dlc));
+    line.push_str(&hex::encode_upper(&frame.data));
+    line.push('\r');
+    Ok(line)
+}
+
+/// Decodes a single SLCAN line (without the trailing carriage return). Returns `None` for lines that do not contain a frame, such as command responses. Remote frames (`r`, `R`) are decoded without data, with the requested DLC in [`Frame::dlc`]. A timestamp following the data is ignored.
+pub fn decode(line: &[u8], bus: u8) -> Result<Option<Frame>> {
+    let (id_len, extended, fd, brs) = match line.first() {
+        Some(b't' | b'r') => (3, false, false, false),
+        Some(b'T' | b'R') => (8, true, false, false),
+        Some(b'd') => (3, false, true, false),
+        Some(b'D') => (8, true, true, false),
+        Some(b'b') => (3, false, true, true),
+        Some(b'B') => (8, true, true, true),
+        _ => return Ok(None),
+    };
+    let rtr = matches!(line.first(), Some(b'r' | b'R'));
+
+    let line = std::str::from_utf8(&line[1..]).map_err(|_| crate::Error::MalformedFrame)?;
+    let parse = |s: Option<&str>| {
+        s.and_then(|s| u32::from_str_radix(s, 16).ok())
+            .ok_or(crate::Error::MalformedFrame)
+    };
+
+    let id = parse(line.get(..id_len))?;
+    let dlc = parse(line.get(id_len..id_len + 1))? as usize;
+    let len = *DLC_TO_LEN.get(dlc).ok_or(crate::Error::MalformedFrame)?;
+
+    // Classic CAN frames have a DLC of at most 8
+    if !fd && dlc > 8 {
+        return Err(crate::Error::MalformedFrame);
+    }
+
+    let id = if extended {
+        Identifier::Extended(id)
+    } else {
+        Identifier::Standard(id)
+    };
+
+    // Remote frames don't carry data, the DLC only indicates the requested length
+    if rtr {
+        let mut frame = Frame::builder(id).bus(bus).rtr(true).build()?;
+        frame.dlc = (dlc != 0).then_some(dlc as u8);
+        return Ok(Some(frame));
+    }
+
+    let data = line
+        .get(id_len + 1..id_len + 1 + 2 * len)
+        .and_then(|data| hex::decode(data).ok())
+        .ok_or(crate::Error::MalformedFrame)?;
+
+    let frame = Frame::builder(id)
+        .bus(bus)
+        .data(&data)
+        .fd(fd)
+        .brs(brs)
+        .build()?;
+    Ok(Some(frame))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        let frame = Frame::new(0, Identifier::Standard(0x7a1), &[0x02, 0x3e, 0x00]).unwrap();
+        assert_eq!(encode(&frame).unwrap(), "t7A13023E00\r");
+        assert_eq!(decode(b"t7A13023E00", 0).unwrap(), Some(frame));
+
+        let frame = Frame::new(0, Identifier::Extended(0x18daf110), &[]).unwrap();
+        assert_eq!(encode(&frame).unwrap(), "T18DAF1100\r");
+        assert_eq!(decode(b"T18DAF1100", 0).unwrap(), Some(frame));
+
+        let mut frame = Frame::new(0, Identifier::Standard(0x123), &[0xaa; 12]).unwrap();
+        frame.fd = true;
+        let line = encode(&frame).unwrap();
+        assert_eq!(line, format!("d1239{}\r", "AA".repeat(12)));
+        assert_eq!(
+            decode(line.trim_end().as_bytes(), 0).unwrap(),
+            Some(frame.clone())
+        );
+
+        frame.brs = true;
+        let line = encode(&frame).unwrap();
+        assert_eq!(line, format!("b1239{}\r", "AA".repeat(12)));
+        assert_eq!(decode(line.trim_end().as_bytes(), 0).unwrap(), Some(frame));
+
+        let mut frame = Frame::builder(Identifier::Extended(0x18daf110))
+            .rtr(true)
+            .build()
+            .unwrap();
+        frame.dlc = Some(8);
+        assert_eq!(encode(&frame).unwrap(), "R18DAF1108\r");
+        assert_eq!(decode(b"R18DAF1108", 0).unwrap(), Some(frame.clone()));
+
+        let rtr = Frame::builder(Identifier::Standard(0x123))
+            .rtr(true)
+            .build()
+            .unwrap();
+        assert_eq!(encode(&rtr).unwrap(), "r1230\r");
+        assert_eq!(decode(b"r1230", 0).unwrap(), Some(rtr));
+
+        frame.fd = true;
+        assert!(encode(&frame).is_err());
+    }
+
+    #[test]
+    fn encode_dlc_override() {
+        let mut frame = Frame::new(0, Identifier::Standard(0x123), &[0xaa; 8]).unwrap();
+        frame.dlc = Some(8);
+        assert_eq!(
+            encode(&frame).unwrap(),
+            format!("t1238{}\r", "AA".repeat(8))
+        );
+
+        // Lawicel only supports a DLC of up to 8 for classic frames
+        frame.dlc = Some(15);
+        assert_eq!(encode(&frame), Err(crate::Error::NotSupported));
+    }
+
+    #[test]
+    fn decode_invalid() {
+        // Timestamp after the data is ignored
+        assert!(decode(b"t1232AABB1234", 0).unwrap().is_some());
+
+        // Command responses don't contain frames
+        assert_eq!(decode(b"z", 0).unwrap(), None);
+        assert!(decode(b"r1239", 0).is_err());
+
+        assert!(decode(b"t1232AA", 0).is_err());
+        assert!(decode(b"t123912345678123456789", 0).is_err());
+        assert!(decode(b"tXYZ0", 0).is_err());
+    }
+}
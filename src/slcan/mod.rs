@@ -0,0 +1,169 @@
+//! This module provides a [`CanAdapter`] implementation for serial-line CAN (SLCAN, also known as Lawicel protocol) adapters such as the CANable or USBtin.
+use crate::can::{AsyncCanAdapter, CanAdapter, Frame};
+use crate::Result;
+
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+
+mod frame;
+
+/// Bitrates supported by the `S0` to `S8` commands
+const BITRATES: [u32; 9] = [
+    10_000, 20_000, 50_000, 100_000, 125_000, 250_000, 500_000, 800_000, 1_000_000,
+];
+
+/// Response to a command that could not be executed
+const BELL: u8 = 0x07;
+
+/// SLCAN Adapter
+pub struct SlCan {
+    port: std::fs::File,
+    /// Received bytes that don't form a complete line yet
+    buf: Vec<u8>,
+    /// Queue used for fake loopback frames, SLCAN has no notification when a frame was ACKed.
+    loopback_queue: VecDeque<Frame>,
+}
+
+/// Puts the serial port in raw mode. Reads return immediately, also when no data is available.
+fn set_raw_mode(port: &std::fs::File) -> std::io::Result<()> {
+    let fd = port.as_raw_fd();
+
+    unsafe {
+        let mut termios: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(fd, &mut termios) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        libc::cfmakeraw(&mut termios);
+        termios.c_cc[libc::VMIN] = 0;
+        termios.c_cc[libc::VTIME] = 0;
+
+        if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+impl SlCan {
+    /// Creates a new [`AsyncCanAdapter`] from the path to the serial port (e.g. `/dev/ttyACM0`) and the CAN bitrate
+    pub fn new_async(path: &str, bitrate: u32) -> Result<AsyncCanAdapter> {
+        let slcan = SlCan::new(path, bitrate)?;
+        Ok(AsyncCanAdapter::new(slcan))
+    }
+
+    /// Creates a new blocking [`SlCan`] from the path to the serial port (e.g. `/dev/ttyACM0`) and the CAN bitrate. Only the standard bitrates from 10 kbit/s to 1 Mbit/s are supported.
+    pub fn new(path: &str, bitrate: u32) -> Result<SlCan> {
+        let Some(bitrate_idx) = BITRATES.iter().position(|&b| b == bitrate) else {
+            return Err(crate::Error::NotSupported);
+        };
+
+        let port = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(path)
+            .map_err(|_| crate::Error::NotFound)?;
+
+        set_raw_mode(&port).map_err(|_| crate::Error::NotSupported)?;
+
+        let mut slcan = SlCan {
+            port,
+            buf: vec![],
+            loopback_queue: VecDeque::new(),
+        };
+
+        // Close the channel in case it was left open, the response is ignored
+        slcan.command("C").ok();
+        slcan.command(&format!("S{}", bitrate_idx))?;
+        slcan.command("O")?;
+
+        // Drop any frames or responses received while opening the channel
+        slcan.read_available()?;
+        slcan.buf.clear();
+
+        Ok(slcan)
+    }
+
+    fn command(&mut self, command: &str) -> Result<()> {
+        self.port
+            .write_all(format!("{}\r", command).as_bytes())
+            .map_err(|_| crate::Error::Disconnected)
+    }
+
+    fn read_available(&mut self) -> Result<()> {
+        let mut chunk = [0u8; 1024];
+        loop {
+            match self.port.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => self.buf.extend(&chunk[..n]),
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    tracing::error!("Error reading from serial port: {}", e);
+                    return Err(crate::Error::Disconnected);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SlCan {
+    fn drop(&mut self) {
+        self.command("C").ok();
+    }
+}
+
+impl CanAdapter for SlCan {
+    fn send(&mut self, frames: &mut VecDeque<Frame>) -> Result<()> {
+        while let Some(mut frame) = frames.pop_front() {
+            let line = frame::encode(&frame)?;
+
+            if self.port.write_all(line.as_bytes()).is_err() {
+                // Failed to send frame, push it back to the front of the queue for next send call
+                frames.push_front(frame);
+                return Err(crate::Error::Disconnected);
+            }
+
+            frame.loopback = true;
+            self.loopback_queue.push_back(frame);
+        }
+
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Result<Vec<Frame>> {
+        self.read_available()?;
+
+        let mut frames = vec![];
+
+        // Only parse complete lines, the remainder is kept for the next call
+        while let Some(end) = self.buf.iter().position(|&b| b == b'\r' || b == BELL) {
+            let line: Vec<u8> = self.buf.drain(..=end).collect();
+
+            if line[end] == BELL {
+                tracing::warn!("SLCAN adapter returned an error");
+                continue;
+            }
+
+            match frame::decode(&line[..end], 0) {
+                Ok(Some(frame)) => frames.push(frame),
+                Ok(None) => {}
+                Err(_) => tracing::warn!("Malformed SLCAN line {:?}", bstr::BStr::new(&line)),
+            }
+        }
+
+        // Add fake loopback frames to the receive queue
+        frames.extend(self.loopback_queue.drain(..));
+
+        Ok(frames)
+    }
+
+    fn check_frame(&self, frame: &Frame) -> Result<()> {
+        frame::encode(frame)?;
+        Ok(())
+    }
+}
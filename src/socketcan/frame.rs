@@ -1,6 +1,12 @@
-use libc::{can_frame, canfd_frame, canid_t, CANFD_MAX_DLEN, CAN_EFF_FLAG, CAN_MAX_DLC};
+use libc::{
+    can_frame, canfd_frame, canid_t, CANFD_BRS, CANFD_MAX_DLEN, CAN_EFF_FLAG, CAN_MAX_DLC,
+    CAN_RTR_FLAG,
+};
 
-use crate::can::{Frame, Identifier};
+use crate::can::{Frame, Identifier, DLC_TO_LEN};
+
+/// Offset of the `len8_dlc` field, which is private in the libc bindings
+const LEN8_DLC_OFFSET: usize = 7;
 
 pub fn can_frame_default() -> can_frame {
     unsafe { std::mem::zeroed() }
@@ -17,6 +23,15 @@ fn id_to_canid_t(id: Identifier) -> canid_t {
     }
 }
 
+/// Raw DLC of 9-15 for frames with 8 data bytes. Only used by the kernel if `CAN_CTRLMODE_CC_LEN8_DLC` is enabled on the interface.
+fn len8_dlc(frame: &can_frame) -> u8 {
+    unsafe { *(frame as *const can_frame as *const u8).add(LEN8_DLC_OFFSET) }
+}
+
+fn set_len8_dlc(frame: &mut can_frame, dlc: u8) {
+    unsafe { *(frame as *mut can_frame as *mut u8).add(LEN8_DLC_OFFSET) = dlc }
+}
+
 fn canid_t_to_id(id: canid_t) -> Identifier {
     match id & CAN_EFF_FLAG != 0 {
         true => Identifier::Extended(id & 0x1fffffff),
@@ -24,25 +39,61 @@ fn canid_t_to_id(id: canid_t) -> Identifier {
     }
 }
 
+/// Returns true if the frame can be converted to a [`can_frame`] or [`canfd_frame`] and is looped back unchanged, apart from a raw DLC that matches the data length.
+pub fn is_valid(frame: &Frame) -> bool {
+    let len = frame.data.len();
+    match (frame.fd, frame.rtr) {
+        // CAN-FD has no remote frames, and the DLC is always derived from the data length
+        (true, true) => false,
+        (true, false) => {
+            len <= CANFD_MAX_DLEN
+                && DLC_TO_LEN.contains(&len)
+                && frame
+                    .dlc
+                    .is_none_or(|dlc| DLC_TO_LEN.get(dlc as usize) == Some(&len))
+        }
+        (false, true) => len == 0 && frame.dlc.is_none_or(|dlc| dlc <= CAN_MAX_DLC as u8),
+        // A DLC of 9-15 can only be used with 8 data bytes
+        (false, false) => {
+            len <= CAN_MAX_DLC as usize
+                && frame.dlc.is_none_or(|dlc| {
+                    dlc as usize == len || (len == CAN_MAX_DLC as usize && (8..=15).contains(&dlc))
+                })
+        }
+    }
+}
+
 impl From<can_frame> for Frame {
     fn from(frame: can_frame) -> Self {
-        Self::new(
-            0,
-            canid_t_to_id(frame.can_id),
-            &frame.data[..frame.can_dlc as usize],
-        )
-        .unwrap()
+        let rtr = frame.can_id & CAN_RTR_FLAG != 0;
+
+        // Remote frames don't carry data, the DLC only indicates the requested length
+        let len = match rtr {
+            true => 0,
+            false => frame.can_dlc as usize,
+        };
+
+        let mut ret = Self::new(0, canid_t_to_id(frame.can_id), &frame.data[..len]).unwrap();
+        ret.rtr = rtr;
+        ret.dlc = match rtr {
+            true if frame.can_dlc != 0 => Some(frame.can_dlc),
+            false if frame.can_dlc == CAN_MAX_DLC as u8 && len8_dlc(&frame) > CAN_MAX_DLC as u8 => {
+                Some(len8_dlc(&frame))
+            }
+            _ => None,
+        };
+        ret
     }
 }
 
 impl From<canfd_frame> for Frame {
     fn from(frame: canfd_frame) -> Self {
-        Self::new(
-            0,
-            canid_t_to_id(frame.can_id),
-            &frame.data[..frame.len as usize],
-        )
-        .unwrap()
+        Frame::builder(canid_t_to_id(frame.can_id))
+            .data(&frame.data[..frame.len as usize])
+            .fd(true)
+            .brs(frame.flags & CANFD_BRS as u8 != 0)
+            .build()
+            .unwrap()
     }
 }
 
@@ -53,9 +104,18 @@ impl From<&Frame> for can_frame {
 
         let mut raw_frame = can_frame_default();
         raw_frame.can_id = id_to_canid_t(frame.id);
+        if frame.rtr {
+            raw_frame.can_id |= CAN_RTR_FLAG;
+        }
         raw_frame.can_dlc = frame.data.len() as u8;
         raw_frame.data[..frame.data.len()].copy_from_slice(&frame.data);
 
+        match frame.dlc {
+            Some(dlc) if dlc > CAN_MAX_DLC as u8 => set_len8_dlc(&mut raw_frame, dlc),
+            Some(dlc) => raw_frame.can_dlc = dlc,
+            None => {}
+        }
+
         raw_frame
     }
 }
@@ -63,14 +123,75 @@ impl From<&Frame> for can_frame {
 impl From<&Frame> for canfd_frame {
     fn from(frame: &Frame) -> canfd_frame {
         assert!(frame.fd);
+        assert!(!frame.rtr);
         assert!(frame.data.len() <= CANFD_MAX_DLEN);
 
         let mut raw_frame = canfd_frame_default();
         raw_frame.can_id = id_to_canid_t(frame.id);
         raw_frame.len = frame.data.len() as u8;
-        // TODO: Set flags like BRS
+        if frame.brs {
+            raw_frame.flags |= CANFD_BRS as u8;
+        }
         raw_frame.data[..frame.data.len()].copy_from_slice(&frame.data);
 
         raw_frame
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rtr_round_trip() {
+        let mut frame = Frame::new(0, Identifier::Extended(0x1234), &[]).unwrap();
+        frame.rtr = true;
+
+        let raw = can_frame::from(&frame);
+        assert_eq!(raw.can_id, 0x1234 | CAN_EFF_FLAG | CAN_RTR_FLAG);
+
+        let received = Frame::from(raw);
+        assert!(received.rtr);
+        assert_eq!(received, frame);
+    }
+
+    #[test]
+    fn dlc_override() {
+        let mut frame = Frame::new(0, Identifier::Standard(0x123), &[0xaa; 8]).unwrap();
+        frame.dlc = Some(15);
+
+        let raw = can_frame::from(&frame);
+        assert_eq!(raw.can_dlc, 8);
+        assert_eq!(len8_dlc(&raw), 15);
+        assert_eq!(Frame::from(raw), frame);
+
+        // Remote frame requesting 4 bytes
+        let mut frame = Frame::new(0, Identifier::Standard(0x123), &[]).unwrap();
+        frame.rtr = true;
+        frame.dlc = Some(4);
+
+        let raw = can_frame::from(&frame);
+        assert_eq!(raw.can_dlc, 4);
+        assert_eq!(Frame::from(raw), frame);
+    }
+
+    #[test]
+    fn valid_frames() {
+        let frame = Frame::new(0, Identifier::Standard(0x123), &[0xaa; 8]).unwrap();
+        assert!(is_valid(&frame));
+
+        // Raw DLC equal to the data length
+        let mut dlc = frame.clone();
+        dlc.dlc = Some(8);
+        assert!(is_valid(&dlc));
+
+        // Raw DLC shorter than the data
+        dlc.dlc = Some(4);
+        assert!(!is_valid(&dlc));
+
+        let mut fd_rtr = Frame::new(0, Identifier::Standard(0x123), &[]).unwrap();
+        fd_rtr.fd = true;
+        fd_rtr.rtr = true;
+        assert!(!is_valid(&fd_rtr));
+    }
+}
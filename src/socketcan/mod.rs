@@ -1,22 +1,83 @@
 //! This module provides a [`CanAdapter`] implementation for SocketCAN interfaces
-use crate::can::{AsyncCanAdapter, CanAdapter, Frame};
+use crate::can::async_can::filter_matches;
+use crate::can::{AsyncCanAdapter, CanAdapter, Frame, Identifier};
 use crate::socketcan::socket::CanFdSocket;
 use crate::Result;
 
 use std::collections::VecDeque;
 
 mod frame;
+mod netlink;
 mod socket;
 
 const IFF_ECHO: u64 = 1 << 18; // include/uapi/linux/if.h
 
+/// Kernel receive filter for a [`SocketCan`] adapter. A received frame matches the filter if `received_id & mask == id & mask`. Frames that don't match any filter are dropped in the kernel, and never copied to userspace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketCanFilter {
+    id: Identifier,
+    mask: u32,
+    inverted: bool,
+}
+
+impl SocketCanFilter {
+    /// Filter matching all ids where the bits selected by `mask` are equal to those in `id`. Standard and Extended ids never match each other.
+    pub fn new(id: Identifier, mask: u32) -> Self {
+        Self {
+            id,
+            mask,
+            inverted: false,
+        }
+    }
+
+    /// Filter matching exactly one id (mask 0x7FF for Standard or 0x1FFFFFFF for Extended ids).
+    pub fn exact(id: Identifier) -> Self {
+        let mask = match id {
+            Identifier::Standard(_) => libc::CAN_SFF_MASK,
+            Identifier::Extended(_) => libc::CAN_EFF_MASK,
+        };
+        Self::new(id, mask)
+    }
+
+    /// Filter matching every id except `id`.
+    pub fn exclude(id: Identifier) -> Self {
+        Self {
+            inverted: true,
+            ..Self::exact(id)
+        }
+    }
+}
+
+impl From<&SocketCanFilter> for libc::can_filter {
+    fn from(filter: &SocketCanFilter) -> libc::can_filter {
+        let mut can_id = match filter.id {
+            Identifier::Standard(id) => id & libc::CAN_SFF_MASK,
+            Identifier::Extended(id) => (id & libc::CAN_EFF_MASK) | libc::CAN_EFF_FLAG,
+        };
+
+        if filter.inverted {
+            can_id |= libc::CAN_INV_FILTER;
+        }
+
+        libc::can_filter {
+            can_id,
+            // Always compare the EFF flag so Standard and Extended ids are kept apart
+            can_mask: filter.mask | libc::CAN_EFF_FLAG,
+        }
+    }
+}
+
 /// SocketCAN Adapter
 pub struct SocketCan {
     socket: CanFdSocket,
     /// If the IFF_ECHO flag is set on the interface, it will implement proper ACK logic.
     iff_echo: bool,
-    /// Queue used for fake loopback frames if IFF_ECHO is not set.
+    /// Queue used for fake loopback frames if IFF_ECHO is not set, or if the kernel filter drops the ACK.
     loopback_queue: VecDeque<Frame>,
+    /// Filters installed through [`CanAdapter::set_hardware_filters`]. The kernel also applies these to the ACKs of transmitted frames.
+    hardware_filters: Option<Vec<(Identifier, u32)>>,
+    /// Time the socket was opened, used as reference for the frame timestamps.
+    opened: std::time::Instant,
 }
 
 fn read_iff_echo(if_name: &str) -> Option<bool> {
@@ -32,7 +93,27 @@ fn read_iff_echo(if_name: &str) -> Option<bool> {
     Some(flags & IFF_ECHO != 0)
 }
 
+/// Reads the ARP hardware type of an interface from sysfs
+fn read_if_type(if_name: &str) -> Option<u16> {
+    let if_type = std::fs::read_to_string(format!("/sys/class/net/{}/type", if_name)).ok()?;
+    if_type.trim().parse().ok()
+}
+
 impl SocketCan {
+    /// Returns the sorted names of all CAN interfaces on the system (e.g. `can0`, `vcan0`)
+    pub fn available() -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir("/sys/class/net") else {
+            return vec![];
+        };
+
+        let mut ifaces: Vec<String> = entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| read_if_type(name) == Some(libc::ARPHRD_CAN))
+            .collect();
+        ifaces.sort();
+        ifaces
+    }
+
     /// Creates a new [`AsyncCanAdapter`] from a SocketCAN iface name (e.g. `can0`)
     pub fn new_async(name: &str) -> Result<AsyncCanAdapter> {
         let socket = SocketCan::new(name)?;
@@ -50,6 +131,10 @@ impl SocketCan {
         socket.set_nonblocking(true).unwrap();
         socket.set_loopback(true).unwrap();
 
+        if let Err(e) = socket.set_timestamping(true) {
+            tracing::warn!("Failed to enable timestamping: {}", e);
+        }
+
         // Attempt to increase the buffer receive size to 1MB
         socket.set_recv_buffer_size(1_000_000).ok();
 
@@ -76,8 +161,64 @@ impl SocketCan {
             socket,
             iff_echo,
             loopback_queue: VecDeque::new(),
+            hardware_filters: None,
+            opened: std::time::Instant::now(),
+        })
+    }
+}
+
+impl SocketCan {
+    /// Converts a kernel receive timestamp (CLOCK_REALTIME) to the monotonic time since opening the socket. The age of the frame is measured against the current system time, so adjusting the system clock only affects frames received during the adjustment. Returns `None` if the frame appears to be received before opening the socket.
+    fn relative_timestamp(&self, realtime: std::time::Duration) -> Option<std::time::Duration> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()?;
+
+        // The system clock was set back after receiving the frame, so it was received just now
+        let age = now.checked_sub(realtime).unwrap_or_default();
+
+        let timestamp = self.opened.elapsed().checked_sub(age);
+        if timestamp.is_none() {
+            tracing::debug!("Dropping timestamp of frame received {:?} ago", age);
+        }
+        timestamp
+    }
+
+    /// Configure the bitrate of an interface (e.g. `can0`) in bit/s. The interface is brought down, configured and brought back up, also when the kernel rejects the bitrate. CAN-FD is enabled if a data bitrate is given, and disabled otherwise. This requires the CAP_NET_ADMIN capability, and is not supported on virtual interfaces like `vcan0`.
+    pub fn set_bitrate(name: &str, bitrate: u32, dbitrate: Option<u32>) -> Result<()> {
+        let if_index = socket::if_nametoindex(name).map_err(|_| crate::error::Error::NotFound)?;
+
+        netlink::set_bitrate(if_index, bitrate, dbitrate).map_err(|e| {
+            tracing::error!("Error setting bitrate: {}", e);
+            crate::error::Error::NotSupported
         })
     }
+
+    /// Install kernel receive filters, replacing the default filter that accepts all frames. The kernel combines multiple filters with OR semantics: a frame is received if it matches *any* of the filters. This means a single [`SocketCanFilter::exclude`] drops one id, but multiple exclude filters together still let every frame through. Passing an empty list drops all frames.
+    ///
+    /// Note that the filters also apply to the ACKs of transmitted frames when `IFF_ECHO` is set on the interface. Make sure the ids used for transmitting are accepted by the filters, otherwise awaiting the sent frames will never resolve.
+    pub fn set_filters(&self, filters: &[SocketCanFilter]) -> Result<()> {
+        let filters: Vec<libc::can_filter> = filters.iter().map(|f| f.into()).collect();
+
+        self.socket.set_filters(&filters).map_err(|e| {
+            tracing::error!("Error setting filters: {}", e);
+            crate::error::Error::NotSupported
+        })
+    }
+
+    /// Only receive frames with exactly one of the given ids. Convenience wrapper around [`SocketCan::set_filters`] using [`SocketCanFilter::exact`].
+    pub fn set_filter_ids(&self, ids: &[Identifier]) -> Result<()> {
+        let filters: Vec<SocketCanFilter> =
+            ids.iter().map(|id| SocketCanFilter::exact(*id)).collect();
+        self.set_filters(&filters)
+    }
+
+    /// Returns false if the ACK of a transmitted frame is dropped by the filters installed through [`CanAdapter::set_hardware_filters`]
+    fn ack_passes_filter(&self, frame: &Frame) -> bool {
+        self.hardware_filters
+            .as_ref()
+            .is_none_or(|filters| filter_matches(filters, frame))
+    }
 }
 
 impl CanAdapter for SocketCan {
@@ -87,8 +228,8 @@ impl CanAdapter for SocketCan {
                 // Failed to send frame, push it back to the front of the queue for next send call
                 frames.push_front(frame);
                 break;
-            } else if !self.iff_echo {
-                // If IFF_ECHO is not set, we need to emulate the ACK logic.
+            } else if !self.iff_echo || !self.ack_passes_filter(&frame) {
+                // If IFF_ECHO is not set, or the ACK is dropped by the filter, we need to emulate the ACK logic.
                 let mut frame = frame.clone();
                 frame.loopback = true;
                 self.loopback_queue.push_back(frame);
@@ -103,7 +244,8 @@ impl CanAdapter for SocketCan {
 
         loop {
             match self.socket.read_frame() {
-                Ok(frame) => {
+                Ok(mut frame) => {
+                    frame.timestamp = frame.timestamp.and_then(|t| self.relative_timestamp(t));
                     frames.push(frame);
                 }
                 Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
@@ -121,4 +263,35 @@ impl CanAdapter for SocketCan {
 
         Ok(frames)
     }
+
+    /// Rejects CAN-FD remote frames, and raw DLCs that can't be represented in a [`libc::can_frame`]
+    fn check_frame(&self, frame: &Frame) -> Result<()> {
+        match frame::is_valid(frame) {
+            true => Ok(()),
+            false => Err(crate::error::Error::MalformedFrame),
+        }
+    }
+
+    fn poll(&mut self, timeout: std::time::Duration) -> Result<()> {
+        // Fake loopback frames are already available
+        if !self.loopback_queue.is_empty() {
+            return Ok(());
+        }
+
+        self.socket.poll_read(timeout).map_err(|e| {
+            tracing::error!("Error polling socket: {}", e);
+            crate::error::Error::Disconnected
+        })?;
+        Ok(())
+    }
+
+    fn set_hardware_filters(&mut self, filters: &[(Identifier, u32)]) -> Result<()> {
+        let socket_filters: Vec<SocketCanFilter> = filters
+            .iter()
+            .map(|(id, mask)| SocketCanFilter::new(*id, *mask))
+            .collect();
+        self.set_filters(&socket_filters)?;
+        self.hardware_filters = Some(filters.to_vec());
+        Ok(())
+    }
 }
@@ -0,0 +1,210 @@
+//! Minimal rtnetlink client to configure the bitrate of a SocketCAN interface
+use libc::{
+    AF_NETLINK, IFF_UP, IFLA_INFO_DATA, IFLA_INFO_KIND, IFLA_LINKINFO, NETLINK_ROUTE, NLMSG_ERROR,
+    NLM_F_ACK, NLM_F_REQUEST, RTM_NEWLINK,
+};
+use std::mem::MaybeUninit;
+
+// include/uapi/linux/can/netlink.h
+const IFLA_CAN_BITTIMING: u16 = 1;
+const IFLA_CAN_CTRLMODE: u16 = 5;
+const IFLA_CAN_DATA_BITTIMING: u16 = 9;
+const CAN_CTRLMODE_FD: u32 = 0x20;
+
+const NLMSG_HDR_SIZE: usize = 16;
+const NLA_ALIGNTO: usize = 4;
+
+/// Netlink request with an `ifinfomsg` header, followed by (nested) attributes
+struct LinkRequest {
+    buf: Vec<u8>,
+}
+
+impl LinkRequest {
+    fn new(if_index: u32, flags: u32, change: u32) -> Self {
+        let mut buf = vec![];
+
+        // struct nlmsghdr, length is filled in when finishing the message
+        buf.extend(0u32.to_ne_bytes());
+        buf.extend(RTM_NEWLINK.to_ne_bytes());
+        buf.extend(((NLM_F_REQUEST | NLM_F_ACK) as u16).to_ne_bytes());
+        buf.extend(1u32.to_ne_bytes()); // Sequence number
+        buf.extend(0u32.to_ne_bytes()); // Port ID, 0 is the kernel
+
+        // struct ifinfomsg
+        buf.extend([0u8, 0u8]); // Family (AF_UNSPEC) and padding
+        buf.extend(0u16.to_ne_bytes()); // Device type
+        buf.extend(if_index.to_ne_bytes());
+        buf.extend(flags.to_ne_bytes());
+        buf.extend(change.to_ne_bytes());
+
+        Self { buf }
+    }
+
+    fn align(&mut self) {
+        let len = self.buf.len().next_multiple_of(NLA_ALIGNTO);
+        self.buf.resize(len, 0);
+    }
+
+    fn attr(&mut self, attr_type: u16, data: &[u8]) {
+        self.buf.extend(((4 + data.len()) as u16).to_ne_bytes());
+        self.buf.extend(attr_type.to_ne_bytes());
+        self.buf.extend(data);
+        self.align();
+    }
+
+    fn nested(&mut self, attr_type: u16, f: impl FnOnce(&mut Self)) {
+        let start = self.buf.len();
+        self.attr(attr_type, &[]);
+        f(self);
+
+        let len = (self.buf.len() - start) as u16;
+        self.buf[start..start + 2].copy_from_slice(&len.to_ne_bytes());
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let len = self.buf.len() as u32;
+        self.buf[0..4].copy_from_slice(&len.to_ne_bytes());
+        self.buf
+    }
+}
+
+/// `struct can_bittiming` with only the bitrate set. The kernel calculates the bit timing.
+fn bittiming(bitrate: u32) -> Vec<u8> {
+    let mut ret = bitrate.to_ne_bytes().to_vec();
+    ret.resize(8 * 4, 0);
+    ret
+}
+
+/// Builds the request to set the (data) bitrate. Returns InvalidInput for a bitrate of zero, which the kernel would interpret as "keep the current bit timing".
+fn bitrate_request(if_index: u32, bitrate: u32, dbitrate: Option<u32>) -> std::io::Result<Vec<u8>> {
+    if bitrate == 0 || dbitrate == Some(0) {
+        return Err(std::io::ErrorKind::InvalidInput.into());
+    }
+
+    let mut request = LinkRequest::new(if_index, 0, 0);
+    request.nested(IFLA_LINKINFO, |linkinfo| {
+        linkinfo.attr(IFLA_INFO_KIND, b"can");
+        linkinfo.nested(IFLA_INFO_DATA, |data| {
+            data.attr(IFLA_CAN_BITTIMING, &bittiming(bitrate));
+
+            // struct can_ctrlmode
+            let mut ctrlmode = CAN_CTRLMODE_FD.to_ne_bytes().to_vec();
+            match dbitrate {
+                Some(dbitrate) => {
+                    data.attr(IFLA_CAN_DATA_BITTIMING, &bittiming(dbitrate));
+                    ctrlmode.extend(CAN_CTRLMODE_FD.to_ne_bytes());
+                }
+                None => ctrlmode.extend(0u32.to_ne_bytes()),
+            }
+            data.attr(IFLA_CAN_CTRLMODE, &ctrlmode);
+        });
+    });
+    Ok(request.finish())
+}
+
+/// Sends a request and waits for the acknowledgement
+fn transact(socket: &socket2::Socket, request: &[u8]) -> std::io::Result<()> {
+    socket.send(request)?;
+
+    let mut buf = [MaybeUninit::<u8>::uninit(); 4096];
+    let len = socket.recv(&mut buf)?;
+
+    // SAFETY: recv initialized len bytes
+    let buf = unsafe { std::slice::from_raw_parts(buf.as_ptr() as *const u8, len) };
+    parse_ack(buf)
+}
+
+/// Parses the acknowledgement of a request, returning the error reported by the kernel
+fn parse_ack(buf: &[u8]) -> std::io::Result<()> {
+    if buf.len() < NLMSG_HDR_SIZE + 4 {
+        return Err(std::io::ErrorKind::InvalidData.into());
+    }
+
+    let msg_type = u16::from_ne_bytes([buf[4], buf[5]]);
+    if msg_type != NLMSG_ERROR as u16 {
+        return Err(std::io::ErrorKind::InvalidData.into());
+    }
+
+    // Acknowledgements are error messages with error code 0
+    let error = i32::from_ne_bytes(buf[16..20].try_into().unwrap());
+    match error {
+        0 => Ok(()),
+        e => Err(std::io::Error::from_raw_os_error(-e)),
+    }
+}
+
+/// Brings the interface down, sets the (data) bitrate and brings the interface back up. CAN-FD is enabled if a data bitrate is given. Requires CAP_NET_ADMIN.
+pub fn set_bitrate(if_index: u32, bitrate: u32, dbitrate: Option<u32>) -> std::io::Result<()> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::from(AF_NETLINK),
+        socket2::Type::RAW,
+        Some(socket2::Protocol::from(NETLINK_ROUTE)),
+    )?;
+
+    let request = bitrate_request(if_index, bitrate, dbitrate)?;
+
+    let up = IFF_UP as u32;
+    transact(&socket, &LinkRequest::new(if_index, 0, up).finish())?;
+    let result = transact(&socket, &request);
+
+    // Always bring the interface back up, so a rejected bitrate doesn't leave the interface unusable
+    let up_result = transact(&socket, &LinkRequest::new(if_index, up, up).finish());
+    result.and(up_result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_bitrate_request() {
+        let request = bitrate_request(3, 500_000, None).unwrap();
+
+        // nlmsghdr + ifinfomsg + linkinfo (4) + kind (8) + data (4) + bittiming (36) + ctrlmode (12)
+        assert_eq!(request.len(), 16 + 16 + 4 + 8 + 4 + 36 + 12);
+        assert_eq!(request[0..4], (request.len() as u32).to_ne_bytes());
+        assert_eq!(request[20..24], 3u32.to_ne_bytes());
+
+        // Nested linkinfo attribute spans the rest of the message
+        let linkinfo_len = u16::from_ne_bytes([request[32], request[33]]) as usize;
+        assert_eq!(linkinfo_len, request.len() - 32);
+        assert_eq!(request[40..43], *b"can");
+
+        // Bitrate is the first field of struct can_bittiming
+        assert_eq!(request[52..56], 500_000u32.to_ne_bytes());
+
+        let request = bitrate_request(3, 500_000, Some(2_000_000)).unwrap();
+        assert_eq!(request.len(), 16 + 16 + 4 + 8 + 4 + 36 + 36 + 12);
+    }
+
+    #[test]
+    fn invalid_bitrate_request() {
+        let e = bitrate_request(3, 0, None).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+
+        let e = bitrate_request(3, 500_000, Some(0)).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn decode_ack() {
+        let ack = |error: i32| {
+            let mut buf = 36u32.to_ne_bytes().to_vec();
+            buf.extend((NLMSG_ERROR as u16).to_ne_bytes());
+            buf.resize(NLMSG_HDR_SIZE, 0);
+            buf.extend(error.to_ne_bytes());
+            buf.resize(36, 0);
+            buf
+        };
+
+        assert!(parse_ack(&ack(0)).is_ok());
+
+        // Kernel rejected the request, e.g. a bitrate the controller doesn't support
+        let e = parse_ack(&ack(-libc::EINVAL)).unwrap_err();
+        assert_eq!(e.raw_os_error(), Some(libc::EINVAL));
+
+        // Truncated message
+        let e = parse_ack(&ack(0)[..NLMSG_HDR_SIZE]).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -1,18 +1,46 @@
 //! Low Level SocketCAN code
 //! Code based on socketcan-rs
 use libc::{
-    c_int, c_void, can_frame, canfd_frame, sa_family_t, sockaddr_can, socklen_t, AF_CAN, CANFD_MTU,
-    CAN_MTU, CAN_RAW, CAN_RAW_FD_FRAMES, CAN_RAW_LOOPBACK, CAN_RAW_RECV_OWN_MSGS, SOL_CAN_RAW,
+    c_int, c_void, can_filter, can_frame, canfd_frame, sa_family_t, sockaddr_can, socklen_t,
+    timespec, AF_CAN, CANFD_MTU, CAN_MTU, CAN_RAW, CAN_RAW_FD_FRAMES, CAN_RAW_FILTER,
+    CAN_RAW_LOOPBACK, CAN_RAW_RECV_OWN_MSGS, SCM_TIMESTAMPING, SOF_TIMESTAMPING_RX_SOFTWARE,
+    SOF_TIMESTAMPING_SOFTWARE, SOL_CAN_RAW, SOL_SOCKET, SO_TIMESTAMPING,
 };
 use std::io::Write;
+use std::mem::MaybeUninit;
 use std::os::fd::AsRawFd;
+use std::time::Duration;
 
 use crate::can::Frame;
 use crate::socketcan::frame::{can_frame_default, canfd_frame_default};
 
 pub struct CanFdSocket(socket2::Socket);
 
-fn if_nametoindex(name: &str) -> std::io::Result<libc::c_uint> {
+/// Buffer for the ancillary data returned by recvmsg, aligned for `cmsghdr`
+#[repr(C, align(8))]
+struct ControlBuffer([MaybeUninit<u8>; 128]);
+
+/// Extracts the software receive timestamp from the SCM_TIMESTAMPING control message. The timestamp uses CLOCK_REALTIME.
+fn rx_timestamp(control: &[u8]) -> Option<Duration> {
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_control = control.as_ptr() as *mut c_void;
+    msg.msg_controllen = control.len() as _;
+
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while !cmsg.is_null() {
+        let hdr = unsafe { &*cmsg };
+        if hdr.cmsg_level == SOL_SOCKET && hdr.cmsg_type == SCM_TIMESTAMPING {
+            // Control message contains three timespecs, the first one is the software timestamp
+            let ts = unsafe { std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const timespec) };
+            return Some(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32));
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+    }
+
+    None
+}
+
+pub fn if_nametoindex(name: &str) -> std::io::Result<libc::c_uint> {
     let c_name = std::ffi::CString::new(name).unwrap();
     let if_index = unsafe { libc::if_nametoindex(c_name.as_ptr()) };
 
@@ -75,15 +103,25 @@ impl CanFdSocket {
         let buf = socket2::MaybeUninitSlice::new(frame.spare_capacity_mut());
         let buf_slice = &mut [buf];
 
-        let mut header = socket2::MsgHdrMut::new().with_buffers(buf_slice);
+        let mut control = ControlBuffer([MaybeUninit::uninit(); 128]);
+        let mut header = socket2::MsgHdrMut::new()
+            .with_buffers(buf_slice)
+            .with_control(&mut control.0);
+
+        let received = self.as_raw_socket().recvmsg(&mut header, 0)?;
+        let loopback = header.flags().is_confirm();
+        let control_len = header.control_len();
+
+        // SAFETY: recvmsg initialized control_len bytes of the control buffer
+        let control =
+            unsafe { std::slice::from_raw_parts(control.0.as_ptr() as *const u8, control_len) };
+        let timestamp = rx_timestamp(control);
 
-        match self.as_raw_socket().recvmsg(&mut header, 0)? {
+        match received {
             // If we only get 'can_frame' number of bytes, then the return is,
             // by definition, a can_frame, so we just copy the bytes into the
             // proper type.
             CAN_MTU => {
-                let loopback = header.flags().is_confirm();
-
                 // SAFETY: just received CAN_MTU bytes
                 unsafe {
                     frame.set_len(CAN_MTU);
@@ -94,11 +132,10 @@ impl CanFdSocket {
 
                 let mut frame = Frame::from(ret);
                 frame.loopback = loopback;
+                frame.timestamp = timestamp;
                 Ok(frame)
             }
             CANFD_MTU => {
-                let loopback = header.flags().is_confirm();
-
                 // SAFETY: just received CANFD_MTU bytes
                 unsafe {
                     frame.set_len(CANFD_MTU);
@@ -108,8 +145,8 @@ impl CanFdSocket {
                 as_bytes_mut(&mut ret).copy_from_slice(&frame);
 
                 let mut frame = Frame::from(ret);
-                frame.fd = true;
                 frame.loopback = loopback;
+                frame.timestamp = timestamp;
                 Ok(frame)
             }
             _ => Err(std::io::Error::last_os_error()),
@@ -121,6 +158,40 @@ impl CanFdSocket {
         self.set_socket_option(SOL_CAN_RAW, CAN_RAW_FD_FRAMES, &enable)
     }
 
+    /// Enable software receive timestamps, returned as ancillary data by recvmsg.
+    pub fn set_timestamping(&self, enabled: bool) -> std::io::Result<()> {
+        let flags = match enabled {
+            true => (SOF_TIMESTAMPING_RX_SOFTWARE | SOF_TIMESTAMPING_SOFTWARE) as c_int,
+            false => 0,
+        };
+        self.set_socket_option(SOL_SOCKET, SO_TIMESTAMPING, &flags)
+    }
+
+    /// Wait until the socket is readable, or the timeout expires. Returns true if the socket is readable.
+    pub fn poll_read(&self, timeout: Duration) -> std::io::Result<bool> {
+        let mut fds = libc::pollfd {
+            fd: self.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ts = timespec {
+            tv_sec: timeout.as_secs() as _,
+            tv_nsec: timeout.subsec_nanos() as _,
+        };
+
+        match unsafe { libc::ppoll(&mut fds, 1, &ts, std::ptr::null()) } {
+            -1 => {
+                let e = std::io::Error::last_os_error();
+                match e.kind() {
+                    std::io::ErrorKind::Interrupted => Ok(false),
+                    _ => Err(e),
+                }
+            }
+            0 => Ok(false),
+            _ => Ok(true),
+        }
+    }
+
     pub fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
         self.as_raw_socket().set_nonblocking(nonblocking)
     }
@@ -152,6 +223,27 @@ impl CanFdSocket {
         self.set_socket_option(SOL_CAN_RAW, CAN_RAW_RECV_OWN_MSGS, &recv_own_msgs)
     }
 
+    /// Set the receive filters on the socket.
+    ///
+    /// A frame is received if it matches any of the filters. An empty list
+    /// of filters results in no frames being received.
+    pub fn set_filters(&self, filters: &[can_filter]) -> std::io::Result<()> {
+        let ret = unsafe {
+            libc::setsockopt(
+                self.as_raw_fd(),
+                SOL_CAN_RAW,
+                CAN_RAW_FILTER,
+                filters.as_ptr() as *const c_void,
+                std::mem::size_of_val(filters) as socklen_t,
+            )
+        };
+
+        match ret {
+            0 => Ok(()),
+            _ => Err(std::io::Error::last_os_error()),
+        }
+    }
+
     fn as_raw_socket(&self) -> &socket2::Socket {
         &self.0
     }
@@ -0,0 +1,320 @@
+//! This module provides a [`CanAdapter`] implementation for tunneling CAN over TCP, compatible with the TCP mode of [cannelloni](https://github.com/mguentner/cannelloni). Start the server side with e.g. `cannelloni -I can0 -C s -l 20000`.
+use crate::can::{AsyncCanAdapter, CanAdapter, Frame, Identifier};
+use crate::Result;
+
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+/// Handshake exchanged by both sides after connecting
+const HANDSHAKE: &[u8] = b"CANNELLONIv1";
+const HANDSHAKE_TIMEOUT_MS: u64 = 1000;
+
+// Flags in the CAN ID, matching the SocketCAN definitions
+const CAN_EFF_FLAG: u32 = 0x80000000;
+const CAN_RTR_FLAG: u32 = 0x40000000;
+const CAN_ERR_FLAG: u32 = 0x20000000;
+const CAN_EFF_MASK: u32 = 0x1fffffff;
+
+/// Set in the length byte for CAN-FD frames, followed by an additional flags byte
+const CANFD_FRAME: u8 = 0x80;
+/// Bit Rate Switch flag for CAN-FD frames
+const CANFD_BRS: u8 = 0x01;
+
+/// Returns true if the frame can be encoded. CAN-FD has no remote frames, and remote frames request at most 8 bytes.
+fn is_valid(frame: &Frame) -> bool {
+    !frame.rtr || (!frame.fd && frame.dlc.unwrap_or(0) <= 8)
+}
+
+/// Encodes a frame as CAN ID (big endian), length, flags (CAN-FD only) and data. Remote frames have the requested DLC as length, and no data.
+fn encode(frame: &Frame, buf: &mut Vec<u8>) {
+    let mut can_id = match frame.id {
+        Identifier::Standard(id) => id,
+        Identifier::Extended(id) => id | CAN_EFF_FLAG,
+    };
+
+    if frame.rtr {
+        can_id |= CAN_RTR_FLAG;
+        buf.extend(can_id.to_be_bytes());
+        buf.push(frame.dlc.unwrap_or(0));
+        return;
+    }
+
+    buf.extend(can_id.to_be_bytes());
+    if frame.fd {
+        buf.push(frame.data.len() as u8 | CANFD_FRAME);
+        buf.push(if frame.brs { CANFD_BRS } else { 0 });
+    } else {
+        buf.push(frame.data.len() as u8);
+    }
+    buf.extend(&frame.data);
+}
+
+/// Decodes a single frame from the start of `buf`. Returns the frame (`None` for error and malformed frames) and the number of bytes consumed, or `None` if `buf` doesn't contain a complete frame yet.
+fn decode(buf: &[u8]) -> Option<(Option<Frame>, usize)> {
+    if buf.len() < 5 {
+        return None;
+    }
+
+    let can_id = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+    let fd = buf[4] & CANFD_FRAME != 0;
+    let len = (buf[4] & !CANFD_FRAME) as usize;
+    let offset = if fd { 6 } else { 5 };
+
+    // Remote frames don't carry data
+    let data_len = if can_id & CAN_RTR_FLAG != 0 { 0 } else { len };
+    if buf.len() < offset + data_len {
+        return None;
+    }
+    let consumed = offset + data_len;
+
+    if can_id & CAN_ERR_FLAG != 0 {
+        return Some((None, consumed));
+    }
+
+    let id = if can_id & CAN_EFF_FLAG != 0 {
+        Identifier::Extended(can_id & CAN_EFF_MASK)
+    } else {
+        Identifier::Standard(can_id & CAN_EFF_MASK)
+    };
+
+    // Remote frames have the requested DLC as length
+    if can_id & CAN_RTR_FLAG != 0 {
+        return match Frame::builder(id).rtr(true).build() {
+            Ok(mut frame) if !fd && len <= 8 => {
+                frame.dlc = (len != 0).then_some(len as u8);
+                Some((Some(frame), consumed))
+            }
+            _ => {
+                tracing::warn!("Skipping malformed remote frame");
+                Some((None, consumed))
+            }
+        };
+    }
+
+    let frame = Frame::builder(id)
+        .data(&buf[offset..consumed])
+        .fd(fd)
+        .brs(fd && buf[5] & CANFD_BRS != 0)
+        .build();
+
+    // The length prefix is still valid, so skipping the frame keeps the stream in sync
+    match frame {
+        Ok(frame) => Some((Some(frame), consumed)),
+        Err(e) => {
+            tracing::warn!("Skipping malformed frame: {}", e);
+            Some((None, consumed))
+        }
+    }
+}
+
+/// CAN over TCP Adapter. ACKs are emulated by looping back frames as soon as they are handed over to the TCP socket.
+pub struct TcpCan {
+    stream: TcpStream,
+    /// Received bytes that don't form a complete frame yet
+    rx_buf: Vec<u8>,
+    /// Encoded frames that could not be written to the socket yet
+    tx_buf: Vec<u8>,
+    loopback_queue: VecDeque<Frame>,
+}
+
+impl TcpCan {
+    /// Creates a new [`AsyncCanAdapter`] connected to a cannelloni server (e.g. `192.168.1.10:20000`)
+    pub fn connect_async(addr: &str) -> Result<AsyncCanAdapter> {
+        let tcp = TcpCan::connect(addr)?;
+        Ok(AsyncCanAdapter::new(tcp))
+    }
+
+    /// Creates a new blocking [`TcpCan`] connected to a cannelloni server (e.g. `192.168.1.10:20000`)
+    pub fn connect(addr: &str) -> Result<TcpCan> {
+        let mut stream = TcpStream::connect(addr).map_err(|_| crate::Error::NotFound)?;
+        stream.set_nodelay(true).ok();
+
+        // Both sides send the handshake, and verify the handshake of the other side
+        let timeout = std::time::Duration::from_millis(HANDSHAKE_TIMEOUT_MS);
+        stream
+            .set_read_timeout(Some(timeout))
+            .map_err(|_| crate::Error::Disconnected)?;
+        stream
+            .write_all(HANDSHAKE)
+            .map_err(|_| crate::Error::Disconnected)?;
+
+        let mut handshake = [0u8; HANDSHAKE.len()];
+        stream
+            .read_exact(&mut handshake)
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => {
+                    crate::Error::Timeout
+                }
+                _ => crate::Error::Disconnected,
+            })?;
+        if handshake != HANDSHAKE {
+            return Err(crate::Error::NotSupported);
+        }
+
+        stream
+            .set_nonblocking(true)
+            .map_err(|_| crate::Error::Disconnected)?;
+
+        Ok(TcpCan {
+            stream,
+            rx_buf: vec![],
+            tx_buf: vec![],
+            loopback_queue: VecDeque::new(),
+        })
+    }
+
+    /// Writes as much of the pending transmit buffer as the socket accepts
+    fn flush(&mut self) -> Result<()> {
+        while !self.tx_buf.is_empty() {
+            match self.stream.write(&self.tx_buf) {
+                Ok(0) => return Err(crate::Error::Disconnected),
+                Ok(n) => {
+                    self.tx_buf.drain(..n);
+                }
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    tracing::error!("Error writing to TCP socket: {}", e);
+                    return Err(crate::Error::Disconnected);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CanAdapter for TcpCan {
+    fn send(&mut self, frames: &mut VecDeque<Frame>) -> Result<()> {
+        for mut frame in frames.drain(..) {
+            encode(&frame, &mut self.tx_buf);
+
+            frame.loopback = true;
+            self.loopback_queue.push_back(frame);
+        }
+
+        self.flush()
+    }
+
+    fn recv(&mut self) -> Result<Vec<Frame>> {
+        self.flush()?;
+
+        let mut chunk = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(crate::Error::Disconnected),
+                Ok(n) => self.rx_buf.extend(&chunk[..n]),
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    tracing::error!("Error reading from TCP socket: {}", e);
+                    return Err(crate::Error::Disconnected);
+                }
+            }
+        }
+
+        let mut frames = vec![];
+        let mut offset = 0;
+        while let Some((frame, consumed)) = decode(&self.rx_buf[offset..]) {
+            frames.extend(frame);
+            offset += consumed;
+        }
+        self.rx_buf.drain(..offset);
+
+        // Add fake loopback frames to the receive queue
+        frames.extend(self.loopback_queue.drain(..));
+
+        Ok(frames)
+    }
+
+    fn check_frame(&self, frame: &Frame) -> Result<()> {
+        match is_valid(frame) {
+            true => Ok(()),
+            false => Err(crate::Error::MalformedFrame),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StreamExt;
+    use std::net::TcpListener;
+
+    #[test]
+    fn encode_decode() {
+        let mut frame = Frame::new(0, Identifier::Extended(0x18daf110), &[0xaa; 12]).unwrap();
+        frame.brs = true;
+
+        let mut buf = vec![];
+        encode(&frame, &mut buf);
+        assert_eq!(buf[..6], [0x98, 0xda, 0xf1, 0x10, 0x8c, 0x01]);
+
+        assert_eq!(decode(&buf[..10]), None);
+        assert_eq!(decode(&buf), Some((Some(frame), buf.len())));
+
+        // Remote frame without data
+        let buf = [0x40, 0x00, 0x01, 0x23, 0x08];
+        let mut frame = Frame::builder(Identifier::Standard(0x123))
+            .rtr(true)
+            .build()
+            .unwrap();
+        frame.dlc = Some(8);
+        assert_eq!(decode(&buf), Some((Some(frame.clone()), 5)));
+
+        let mut encoded = vec![];
+        encode(&frame, &mut encoded);
+        assert_eq!(encoded, buf);
+
+        // Error frames are dropped, including their data
+        let mut buf = vec![0x20, 0x00, 0x00, 0x04, 0x08];
+        buf.extend([0x00; 8]);
+        assert_eq!(decode(&buf), Some((None, 13)));
+
+        frame.fd = true;
+        assert!(!is_valid(&frame));
+    }
+
+    #[test]
+    fn decode_malformed() {
+        // 12 bytes without the CAN-FD flag is skipped, and the next frame is still decoded
+        let mut buf = vec![0x00, 0x00, 0x01, 0x23, 0x0c];
+        buf.extend([0xaa; 12]);
+        buf.extend([0x00, 0x00, 0x01, 0x23, 0x01, 0xbb]);
+
+        assert_eq!(decode(&buf), Some((None, 17)));
+        let frame = Frame::new(0, Identifier::Standard(0x123), &[0xbb]).unwrap();
+        assert_eq!(decode(&buf[17..]), Some((Some(frame), 6)));
+    }
+
+    #[tokio::test]
+    async fn cannelloni_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // Server that completes the handshake, and then sends back every received frame with the ID incremented by one
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut handshake = [0u8; HANDSHAKE.len()];
+            stream.read_exact(&mut handshake).unwrap();
+            stream.write_all(HANDSHAKE).unwrap();
+
+            let mut frame = [0u8; 7];
+            stream.read_exact(&mut frame).unwrap();
+            frame[3] += 1;
+            stream.write_all(&frame).unwrap();
+
+            // Keep the connection open until the client disconnects
+            stream.read_exact(&mut [0u8; 1]).ok();
+        });
+
+        let adapter = TcpCan::connect_async(&addr).unwrap();
+        let stream = adapter.recv_filter(|frame| !frame.loopback);
+        tokio::pin!(stream);
+
+        let frame = Frame::new(0, Identifier::Standard(0x123), &[0x01, 0x02]).unwrap();
+        adapter.send(&frame).await.unwrap();
+
+        let received = stream.next().await.unwrap();
+        assert_eq!(received.id, Identifier::Standard(0x124));
+        assert_eq!(received.data, vec![0x01, 0x02]);
+    }
+}
@@ -0,0 +1,57 @@
+//! Abstraction over the transport layer used by the diagnostic clients, such as ISO-TP.
+use crate::{Result, Stream};
+use std::future::Future;
+
+/// Transport that can exchange diagnostic messages (e.g. UDS requests and responses) with an ECU. Implement this trait to use the [`UDSClient`](crate::uds::UDSClient) over a transport other than ISO-TP.
+pub trait DiagnosticTransport {
+    /// Send a single message to the ECU.
+    fn send(&self, data: &[u8]) -> impl Future<Output = Result<()>> + Send;
+
+    /// Stream of messages received from the ECU. Create the stream before sending a request to make sure the response is not missed.
+    fn recv(&self) -> impl Stream<Item = Result<Vec<u8>>> + Unpin + Send + '_;
+
+    /// Time between the last sent message leaving and the last received message arriving, based on the timestamps of the underlying adapter. Returns `None` if the transport doesn't support timestamps.
+    fn round_trip_time(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod mock {
+    use super::*;
+    use tokio::sync::broadcast;
+
+    /// Transport that answers every request with the responses returned by `respond`
+    pub(crate) struct MockTransport {
+        respond: fn(&[u8]) -> Vec<Vec<u8>>,
+        tx: broadcast::Sender<Vec<u8>>,
+    }
+
+    impl MockTransport {
+        pub(crate) fn new(respond: fn(&[u8]) -> Vec<Vec<u8>>) -> Self {
+            let (tx, _) = broadcast::channel(64);
+            Self { respond, tx }
+        }
+    }
+
+    impl DiagnosticTransport for MockTransport {
+        async fn send(&self, data: &[u8]) -> Result<()> {
+            for response in (self.respond)(data) {
+                self.tx.send(response).ok();
+            }
+            Ok(())
+        }
+
+        fn recv(&self) -> impl Stream<Item = Result<Vec<u8>>> + Unpin + Send + '_ {
+            let mut rx = self.tx.subscribe();
+            Box::pin(async_stream::stream! {
+                loop {
+                    match tokio::time::timeout(std::time::Duration::from_millis(10), rx.recv()).await {
+                        Ok(Ok(response)) => yield Ok(response),
+                        _ => yield Err(crate::Error::Timeout),
+                    }
+                }
+            })
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! Codecs to convert typed values to and from the raw data record of a Data Identifier.
+use crate::uds::Error;
+use crate::Result;
+
+/// Conversion between a typed value and the raw data record of a Data Identifier. Used by [`UDSClient::read_data_by_identifier_typed`](crate::uds::UDSClient::read_data_by_identifier_typed) and [`UDSClient::write_data_by_identifier_typed`](crate::uds::UDSClient::write_data_by_identifier_typed). Implement this trait for your own types to describe a known DID schema.
+pub trait DidCodec: Sized {
+    /// Serialize the value into the data record written to the ECU.
+    fn encode(&self) -> Result<Vec<u8>>;
+    /// Deserialize the data record returned by the ECU. Returns InvalidDataLength if the record doesn't have the expected length.
+    fn decode(data: &[u8]) -> Result<Self>;
+}
+
+/// Raw bytes, without any length check.
+impl DidCodec for Vec<u8> {
+    fn encode(&self) -> Result<Vec<u8>> {
+        Ok(self.clone())
+    }
+
+    fn decode(data: &[u8]) -> Result<Self> {
+        Ok(data.to_vec())
+    }
+}
+
+/// Fixed length byte array.
+impl<const N: usize> DidCodec for [u8; N] {
+    fn encode(&self) -> Result<Vec<u8>> {
+        Ok(self.to_vec())
+    }
+
+    fn decode(data: &[u8]) -> Result<Self> {
+        data.try_into()
+            .map_err(|_| Error::InvalidDataLength(data.len()).into())
+    }
+}
+
+/// ASCII string (e.g. VIN or part numbers). Trailing NUL padding is removed when decoding.
+impl DidCodec for String {
+    fn encode(&self) -> Result<Vec<u8>> {
+        if !self.is_ascii() {
+            return Err(Error::NonAsciiData.into());
+        }
+        Ok(self.as_bytes().to_vec())
+    }
+
+    fn decode(data: &[u8]) -> Result<Self> {
+        if !data.is_ascii() {
+            return Err(Error::NonAsciiData.into());
+        }
+
+        let len = data.iter().rposition(|&b| b != 0).map_or(0, |pos| pos + 1);
+        Ok(String::from_utf8_lossy(&data[..len]).into_owned())
+    }
+}
+
+macro_rules! impl_did_codec_be {
+    ($($t:ty),*) => {
+        $(
+            /// Big-endian integer.
+            impl DidCodec for $t {
+                fn encode(&self) -> Result<Vec<u8>> {
+                    Ok(self.to_be_bytes().to_vec())
+                }
+
+                fn decode(data: &[u8]) -> Result<Self> {
+                    Ok(<$t>::from_be_bytes(DidCodec::decode(data)?))
+                }
+            }
+        )*
+    };
+}
+
+impl_did_codec_be!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integers() {
+        assert_eq!(0x1234u16.encode().unwrap(), vec![0x12, 0x34]);
+        assert_eq!(u32::decode(&[0x01, 0x02, 0x03, 0x04]).unwrap(), 0x01020304);
+        assert_eq!(i16::decode(&[0xff, 0xfe]).unwrap(), -2);
+        assert_eq!(
+            u16::decode(&[0x01, 0x02, 0x03]),
+            Err(Error::InvalidDataLength(3).into())
+        );
+    }
+
+    #[test]
+    fn byte_arrays() {
+        assert_eq!([0xaa, 0xbb].encode().unwrap(), vec![0xaa, 0xbb]);
+        assert_eq!(<[u8; 2]>::decode(&[0xaa, 0xbb]).unwrap(), [0xaa, 0xbb]);
+        assert_eq!(
+            <[u8; 4]>::decode(&[0xaa, 0xbb]),
+            Err(Error::InvalidDataLength(2).into())
+        );
+    }
+
+    #[test]
+    fn ascii_strings() {
+        assert_eq!("1234".to_string().encode().unwrap(), b"1234".to_vec());
+        assert_eq!(String::decode(b"VIN\0\0").unwrap(), "VIN");
+        assert_eq!(
+            "caf\u{e9}".to_string().encode(),
+            Err(Error::NonAsciiData.into())
+        );
+        assert_eq!(String::decode(&[0xff]), Err(Error::NonAsciiData.into()));
+    }
+}
@@ -3,6 +3,8 @@ use strum_macros::EnumIter;
 
 pub static POSITIVE_RESPONSE: u8 = 0x40;
 pub static NEGATIVE_RESPONSE: u8 = 0x7f;
+/// suppressPosRspMsgIndicationBit, set in the sub function to indicate no positive response is expected
+pub static SUPPRESS_POSITIVE_RESPONSE: u8 = 0x80;
 pub static ZERO_SUB_FUNCTION: u8 = 0x00;
 
 /// Service Identifiers (SIDs) as defined in ISO 14229
@@ -122,6 +124,74 @@ pub enum SecurityAccessType {
     SendKey = 0x02,
 }
 
+/// Access Timing Parameter Sub-Function ID as defined in ISO 14229
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum AccessTimingParameterType {
+    ReadExtendedTimingParameterSet = 0x01,
+    SetTimingParametersToDefault = 0x02,
+    ReadCurrentlyActiveTimingParameters = 0x03,
+    SetTimingParametersToGivenValues = 0x04,
+}
+
+/// Transmission Mode for Read Data By Periodic Identifier as defined in ISO 14229
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum TransmissionMode {
+    SendAtSlowRate = 0x01,
+    SendAtMediumRate = 0x02,
+    SendAtFastRate = 0x03,
+    StopSending = 0x04,
+}
+
+/// Dynamically Define Data Identifier Sub-Function ID as defined in ISO 14229
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum DynamicallyDefineDataIdentifierType {
+    DefineByIdentifier = 0x01,
+    DefineByMemoryAddress = 0x02,
+    ClearDynamicallyDefinedDataIdentifier = 0x03,
+}
+
+/// Input Output Control Parameter as defined in ISO 14229
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum InputOutputControlParameter {
+    ReturnControlToECU = 0x00,
+    ResetToDefault = 0x01,
+    FreezeCurrentState = 0x02,
+    ShortTermAdjustment = 0x03,
+}
+
+/// Mode Of Operation for Request File Transfer as defined in ISO 14229
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum ModeOfOperation {
+    AddFile = 0x01,
+    DeleteFile = 0x02,
+    ReplaceFile = 0x03,
+    ReadFile = 0x04,
+    ReadDir = 0x05,
+}
+
+/// Response On Event Sub-Function ID as defined in ISO 14229
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum ResponseOnEventType {
+    StopResponseOnEvent = 0x00,
+    OnDTCStatusChange = 0x01,
+    OnTimerInterrupt = 0x02,
+    OnChangeOfDataIdentifier = 0x03,
+    StartResponseOnEvent = 0x05,
+    ClearResponseOnEvent = 0x06,
+}
+
 /// Routine Control Sub-Function ID as defined in ISO 14229
 #[derive(Debug, PartialEq, Copy, Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
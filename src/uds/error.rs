@@ -76,6 +76,19 @@ pub enum Error {
     InvalidBlockSequenceCounter(u8),
     #[error("Invalid Response Length")]
     InvalidResponseLength,
-    #[error("Negative Response: {0:?}")]
-    NegativeResponse(NegativeResponseCode),
+    #[error("Invalid Data Length: {0}")]
+    InvalidDataLength(usize),
+    #[error("Data is not ASCII")]
+    NonAsciiData,
+    #[error("Malformed Request")]
+    MalformedRequest,
+    #[error("Negative Response to service 0x{service:02x}: {code:?}")]
+    NegativeResponse {
+        /// Service identifier of the rejected request
+        service: u8,
+        /// Negative Response Code
+        code: NegativeResponseCode,
+        /// Any additional (manufacturer specific) bytes following the Negative Response Code
+        extra: Vec<u8>,
+    },
 }
@@ -0,0 +1,47 @@
+//! Background Tester Present keepalive.
+use super::{ServiceIdentifier, UDSClient, SUPPRESS_POSITIVE_RESPONSE};
+use crate::isotp::IsoTPAdapter;
+use crate::Result;
+
+/// Handle to a Tester Present keepalive task, created by [`UDSClient::start_tester_present`]. Aborts the task when dropped.
+pub struct TesterPresentHandle {
+    handle: tokio::task::JoinHandle<Result<()>>,
+}
+
+impl TesterPresentHandle {
+    /// Returns true if the keepalive stopped, because sending failed (e.g. the CAN adapter was dropped)
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
+
+impl Drop for TesterPresentHandle {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+impl<'a> UDSClient<'a, IsoTPAdapter<'a>> {
+    /// Spawns a tokio task sending a Tester Present with the suppressPosRspMsgIndicationBit set every `period`, to prevent the ECU from falling back to the default session after the S3 timeout. The task owns a handle to the CAN adapter instead of borrowing the client, and shares the request lock of the client so the Tester Present is never sent while waiting for a response. Unlike [`UDSClient::tester_present_keepalive`], negative responses are not checked. The keepalive stops when the returned handle is dropped.
+    pub fn start_tester_present(&self, period: std::time::Duration) -> TesterPresentHandle {
+        let frame = self.adapter.single_frame(&[
+            ServiceIdentifier::TesterPresent as u8,
+            SUPPRESS_POSITIVE_RESPONSE,
+        ]);
+        let sender = self.adapter.can_sender();
+        let transfer_lock = self.transfer_lock.clone();
+
+        let handle = tokio::spawn(async move {
+            let frame = frame?;
+            let mut interval = tokio::time::interval(period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                interval.tick().await;
+                let _lock = transfer_lock.lock().await;
+                sender.send(&frame).await?;
+            }
+        });
+        TesterPresentHandle { handle }
+    }
+}
@@ -12,27 +12,83 @@
 //!     println!("Application Software Identification: {}", hex::encode(response));
 //! }
 
+mod codec;
 mod constants;
 mod error;
+mod keepalive;
+mod session;
 mod types;
 
 use crate::isotp::IsoTPAdapter;
+use crate::transport::DiagnosticTransport;
 use crate::Result;
-use crate::StreamExt;
+use crate::{Stream, StreamExt};
+pub use codec::DidCodec;
 pub use constants::*;
 pub use error::{Error, NegativeResponseCode};
+pub use keepalive::TesterPresentHandle;
+pub use session::DiagnosticSession;
 pub use types::*;
 
-use tracing::info;
-
-/// UDS Client. Wraps an IsoTPAdapter to provide a simple interface for making UDS calls.
-pub struct UDSClient<'a> {
-    adapter: &'a IsoTPAdapter<'a>,
+use async_stream::stream;
+use tracing::{debug, info};
+
+const RESPONSIVE_INITIAL_BACKOFF_MS: u64 = 10;
+const DEFAULT_P2_STAR_TIMEOUT_MS: u64 = 5000;
+const DEFAULT_MAX_RESPONSE_PENDING: usize = 10;
+/// Time to wait for a negative response when the positive response is suppressed (default P2 server max)
+const SUPPRESSED_RESPONSE_TIMEOUT_MS: u64 = 50;
+const RESPONSIVE_MAX_BACKOFF_MS: u64 = 1000;
+/// Group of DTC selecting all DTCs
+const DTC_GROUP_ALL: u32 = 0xFFFFFF;
+/// testFailed, testFailedThisOperationCycle, pendingDTC and confirmedDTC. DTCs that are cleared typically report testNotCompletedSinceLastClear, which is not an indication of a fault.
+const DTC_STATUS_MASK_FAULT: u8 = 0x0f;
+
+/// UDS Client. Wraps an IsoTPAdapter, or any other [`DiagnosticTransport`], to provide a simple interface for making UDS calls.
+pub struct UDSClient<'a, T: DiagnosticTransport = IsoTPAdapter<'a>> {
+    adapter: &'a T,
+    inter_request_delay: std::time::Duration,
+    p2_star_timeout: std::sync::Mutex<std::time::Duration>,
+    max_response_pending: usize,
+    apply_session_timing: bool,
+    last_request: std::sync::Mutex<Option<tokio::time::Instant>>,
+    /// Serializes requests, so background requests don't interleave with a request waiting for a response
+    transfer_lock: std::sync::Arc<tokio::sync::Mutex<()>>,
 }
 
-impl<'a> UDSClient<'a> {
-    pub fn new(adapter: &'a IsoTPAdapter) -> Self {
-        Self { adapter }
+impl<'a, T: DiagnosticTransport> UDSClient<'a, T> {
+    pub fn new(adapter: &'a T) -> Self {
+        Self {
+            adapter,
+            inter_request_delay: std::time::Duration::ZERO,
+            p2_star_timeout: std::sync::Mutex::new(std::time::Duration::from_millis(
+                DEFAULT_P2_STAR_TIMEOUT_MS,
+            )),
+            max_response_pending: DEFAULT_MAX_RESPONSE_PENDING,
+            apply_session_timing: false,
+            last_request: std::sync::Mutex::new(None),
+            transfer_lock: std::sync::Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    /// Minimum delay between the completion of a request and the start of the next request. Some ECUs respond with BusyRepeatRequest if requests are sent back to back. This trades throughput for reliability. Defaults to zero.
+    pub fn set_inter_request_delay(&mut self, delay: std::time::Duration) {
+        self.inter_request_delay = delay;
+    }
+
+    /// Maximum time to wait for the next response after the ECU sent a Response Pending (P2* server max). Defaults to 5 s.
+    pub fn set_p2_star_timeout(&mut self, timeout: std::time::Duration) {
+        *self.p2_star_timeout.get_mut().unwrap() = timeout;
+    }
+
+    /// Apply the P2* server max returned by Diagnostic Session Control as the P2* timeout, so the client adapts to the timing advertised by the ECU for each session. The P2 server max is not applied, the timeout for the first response is set by the transport. Defaults to false.
+    pub fn set_apply_session_timing(&mut self, apply: bool) {
+        self.apply_session_timing = apply;
+    }
+
+    /// Maximum number of consecutive Response Pending messages accepted for a single request, after which [`crate::Error::Timeout`] is returned. Prevents hanging on an ECU that is stuck sending Response Pending. Defaults to 10.
+    pub fn set_max_response_pending(&mut self, count: usize) {
+        self.max_response_pending = count;
     }
 
     /// Helper function to make custom UDS requests. This function will verify the ECU responds with the correct service identifier and sub function, handle negative responses, and will return the response data.
@@ -42,6 +98,113 @@ impl<'a> UDSClient<'a> {
         sub_function: Option<u8>,
         data: Option<&[u8]>,
     ) -> Result<Vec<u8>> {
+        self.wait_inter_request_delay().await;
+
+        let result = self.request_response(sid, sub_function, data).await;
+        *self.last_request.lock().unwrap() = Some(tokio::time::Instant::now());
+        result
+    }
+
+    /// Helper function to make custom UDS requests for services that result in multiple responses, such as Read DTC Information with some report types. Collects up to `count` responses, or until the transport times out after at least one response. Each response is checked the same way as in [`UDSClient::request`], and a negative response aborts the collection with an error.
+    pub async fn request_multi(
+        &self,
+        sid: u8,
+        sub_function: Option<u8>,
+        data: Option<&[u8]>,
+        count: usize,
+    ) -> Result<Vec<Vec<u8>>> {
+        self.wait_inter_request_delay().await;
+
+        let result = self.request_responses(sid, sub_function, data, count).await;
+        *self.last_request.lock().unwrap() = Some(tokio::time::Instant::now());
+        result
+    }
+
+    /// Helper function to make custom UDS requests without expecting a positive response. The suppressPosRspMsgIndicationBit is set in the `sub_function`, and the function returns after the request is sent. The ECU may still send a negative response, so the function listens for a short time and returns the negative response as error.
+    pub async fn request_no_response(
+        &self,
+        sid: u8,
+        sub_function: u8,
+        data: Option<&[u8]>,
+    ) -> Result<()> {
+        self.wait_inter_request_delay().await;
+
+        let result = self
+            .request_suppressed_response(sid, sub_function, data)
+            .await;
+        *self.last_request.lock().unwrap() = Some(tokio::time::Instant::now());
+        result
+    }
+
+    async fn request_suppressed_response(
+        &self,
+        sid: u8,
+        sub_function: u8,
+        data: Option<&[u8]>,
+    ) -> Result<()> {
+        let mut request: Vec<u8> = vec![sid, sub_function | SUPPRESS_POSITIVE_RESPONSE];
+        if let Some(data) = data {
+            request.extend(data);
+        }
+
+        // No positive response is expected, so the lock is only held while sending. A negative response is still picked up by this stream, and ignored by concurrent requests for other services.
+        let mut stream = self.adapter.recv();
+        {
+            let _lock = self.transfer_lock.lock().await;
+            self.adapter.send(&request).await?;
+        }
+
+        let timeout = std::time::Duration::from_millis(SUPPRESSED_RESPONSE_TIMEOUT_MS);
+        while let Ok(Some(Ok(response))) = tokio::time::timeout(timeout, stream.next()).await {
+            if response.len() < 3 || response[0] != NEGATIVE_RESPONSE || response[1] != sid {
+                continue;
+            }
+
+            let code: NegativeResponseCode = response[2].into();
+            if code == NegativeResponseCode::RequestCorrectlyReceivedResponsePending {
+                continue;
+            }
+
+            return Err(Error::NegativeResponse {
+                service: response[1],
+                code,
+                extra: response[3..].to_vec(),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    async fn wait_inter_request_delay(&self) {
+        if !self.inter_request_delay.is_zero() {
+            let last_request = *self.last_request.lock().unwrap();
+            if let Some(last_request) = last_request {
+                tokio::time::sleep_until(last_request + self.inter_request_delay).await;
+            }
+        }
+    }
+
+    async fn request_response(
+        &self,
+        sid: u8,
+        sub_function: Option<u8>,
+        data: Option<&[u8]>,
+    ) -> Result<Vec<u8>> {
+        let mut responses = self.request_responses(sid, sub_function, data, 1).await?;
+        Ok(responses.remove(0))
+    }
+
+    /// Sends the request and collects up to `count` positive responses. A timeout ends the collection once at least one response was received.
+    async fn request_responses(
+        &self,
+        sid: u8,
+        sub_function: Option<u8>,
+        data: Option<&[u8]>,
+        count: usize,
+    ) -> Result<Vec<Vec<u8>>> {
+        let _lock = self.transfer_lock.lock().await;
+
         let mut request: Vec<u8> = vec![sid];
 
         if let Some(sub_function) = sub_function {
@@ -56,20 +219,61 @@ impl<'a> UDSClient<'a> {
 
         self.adapter.send(&request).await?;
 
-        loop {
-            let response = stream.next().await.unwrap()?;
+        let mut responses = vec![];
+        let mut response_pending: usize = 0;
+        let mut p2_star_deadline: Option<tokio::time::Instant> = None;
+
+        while responses.len() < count {
+            let response = match stream.next().await.ok_or(crate::Error::Disconnected)? {
+                Ok(response) => response,
+                // After a Response Pending the ECU has up to P2* to send the next response
+                Err(e)
+                    if e.is_timeout()
+                        && p2_star_deadline.is_some_and(|d| tokio::time::Instant::now() < d) =>
+                {
+                    continue;
+                }
+                Err(e) if e.is_timeout() && !responses.is_empty() => break,
+                Err(e) => return Err(e),
+            };
 
             // Check for errors
             let response_sid = response[0];
             if response_sid == NEGATIVE_RESPONSE {
+                if response.len() < 3 {
+                    return Err(Error::InvalidResponseLength.into());
+                }
+
+                // Negative response to a concurrent request without positive response, e.g. a Tester Present keepalive
+                if response[1] != sid {
+                    debug!(
+                        "Ignoring negative response to service 0x{:02x}",
+                        response[1]
+                    );
+                    continue;
+                }
+
                 let code: NegativeResponseCode = response[2].into();
 
                 if code == NegativeResponseCode::RequestCorrectlyReceivedResponsePending {
                     info!("Received Response Pending");
+
+                    response_pending += 1;
+                    if response_pending > self.max_response_pending {
+                        return Err(crate::Error::Timeout);
+                    }
+
+                    let p2_star_timeout = *self.p2_star_timeout.lock().unwrap();
+                    p2_star_deadline = Some(tokio::time::Instant::now() + p2_star_timeout);
                     continue;
                 }
 
-                return Err(Error::NegativeResponse(code).into());
+                return Err(Error::NegativeResponse {
+                    service: response[1],
+                    code,
+                    extra: response[3..].to_vec(),
+                }
+                .into());
             }
 
             // Check service id
@@ -85,11 +289,16 @@ impl<'a> UDSClient<'a> {
             }
 
             let start: usize = if sub_function.is_some() { 2 } else { 1 };
-            return Ok(response[start..].to_vec());
+            responses.push(response[start..].to_vec());
+
+            response_pending = 0;
+            p2_star_deadline = None;
         }
+
+        Ok(responses)
     }
 
-    /// 0x10 - Diagnostic Session Control. ECU may optionally return 4 bytes of sessionParameterRecord with some timing information.
+    /// 0x10 - Diagnostic Session Control. ECU may optionally return 4 bytes of sessionParameterRecord with some timing information. See [`UDSClient::set_apply_session_timing`] to use the returned timing for subsequent requests.
     pub async fn diagnostic_session_control(
         &self,
         session_type: u8,
@@ -105,7 +314,7 @@ impl<'a> UDSClient<'a> {
         let result = if result.len() == 4 {
             let p2_server_max = u16::from_be_bytes([result[0], result[1]]);
             let p2_server_max = std::time::Duration::from_millis(p2_server_max as u64);
-            let p2_star_server_max = u16::from_be_bytes([result[0], result[1]]);
+            let p2_star_server_max = u16::from_be_bytes([result[2], result[3]]);
             let p2_star_server_max =
                 std::time::Duration::from_millis(p2_star_server_max as u64 * 10);
 
@@ -117,27 +326,74 @@ impl<'a> UDSClient<'a> {
             None
         };
 
+        if let Some(record) = result.filter(|_| self.apply_session_timing) {
+            debug!("Applying P2* timeout of {:?}", record.p2_star_server_max);
+            *self.p2_star_timeout.lock().unwrap() = record.p2_star_server_max;
+        }
+
         Ok(result)
     }
 
-    /// 0x11 - ECU Reset. The `reset_type` parameter can be used to specify the type of reset to perform. Use the [`constants::ResetType`] enum for  the reset types defined in the standard. This function returns the power down time when the reset type is [`constants::ResetType::EnableRapidPowerShutDown`].
-    pub async fn ecu_reset(&self, reset_type: u8) -> Result<Option<u8>> {
+    /// 0x10 - Diagnostic Session Control, using the [`constants::SessionType`] enum for the session type.
+    pub async fn diagnostic_session_control_typed(
+        &self,
+        session: SessionType,
+    ) -> Result<Option<types::SessionParameterRecord>> {
+        self.diagnostic_session_control(session as u8).await
+    }
+
+    /// Enters the diagnostic session `session_type` using Diagnostic Session Control, and returns a guard that returns the ECU to the default session when dropped. This prevents the ECU from being left in e.g. the programming session after an error or panic.
+    pub async fn session(&self, session_type: u8) -> Result<DiagnosticSession<'_, 'a, T>>
+    where
+        T: Sync,
+    {
+        DiagnosticSession::new(self, session_type).await
+    }
+
+    /// 0x11 - ECU Reset. The `reset_type` parameter can be used to specify the type of reset to perform. Use the [`constants::ResetType`] enum for  the reset types defined in the standard. This function returns the power down time when the reset type is [`constants::ResetType::EnableRapidPowerShutDown`], and `None` for all other reset types. The power down time is also `None` if the ECU reports it as not available (0xFF).
+    pub async fn ecu_reset(&self, reset_type: u8) -> Result<Option<std::time::Duration>> {
         let result = self
             .request(ServiceIdentifier::EcuReset as u8, Some(reset_type), None)
             .await?;
 
-        let result = if result.len() == 1 {
-            Some(result[0])
-        } else {
-            None
+        let rapid_shutdown = reset_type == ResetType::EnableRapidPowerShutDown as u8;
+        let result = match result.as_slice() {
+            [power_down_time] if rapid_shutdown && *power_down_time != 0xff => {
+                Some(std::time::Duration::from_secs(*power_down_time as u64))
+            }
+            _ => None,
         };
 
         Ok(result)
     }
 
+    /// 0x11 - ECU Reset, using the [`constants::ResetType`] enum for the reset type. Unlike [`UDSClient::ecu_reset`], the response is validated: the power down time must be present for [`constants::ResetType::EnableRapidPowerShutDown`], and absent for all other reset types. Returns the power down time, or `None` if the ECU reports it as not available (0xFF).
+    pub async fn ecu_reset_typed(
+        &self,
+        reset_type: ResetType,
+    ) -> Result<Option<std::time::Duration>> {
+        let result = self
+            .request(
+                ServiceIdentifier::EcuReset as u8,
+                Some(reset_type as u8),
+                None,
+            )
+            .await?;
+
+        match (reset_type, result.as_slice()) {
+            (ResetType::EnableRapidPowerShutDown, [0xff]) => Ok(None),
+            (ResetType::EnableRapidPowerShutDown, [power_down_time]) => Ok(Some(
+                std::time::Duration::from_secs(*power_down_time as u64),
+            )),
+            (ResetType::EnableRapidPowerShutDown, _) => Err(Error::InvalidResponseLength.into()),
+            (_, []) => Ok(None),
+            (_, _) => Err(Error::InvalidResponseLength.into()),
+        }
+    }
+
     /// 0x27 - Security Access. Odd `access_type` values are used to request a seed, even values to send a key. The `data` parameter is optional when requesting a seed. You can use the [`constants::SecurityAccessType`] enum for the default security level.
     pub async fn security_access(&self, access_type: u8, data: Option<&[u8]>) -> Result<Vec<u8>> {
-        let send_key = access_type % 2 == 0;
+        let send_key = access_type & 1 == 0;
         if send_key && data.is_none() {
             panic!("Missing data parameter when sending key");
         }
@@ -153,6 +409,23 @@ impl<'a> UDSClient<'a> {
         Ok(resp)
     }
 
+    /// Unlocks the ECU using Security Access. Requests a seed at the odd `level`, computes the key using `key_fn`, and sends the key at `level + 1`. If the ECU returns an all zero seed it is already unlocked, and no key is sent. An incorrect key results in an InvalidKey negative response. Returns [`Error::MalformedRequest`] if `level` is even or above 0x7D, as the key level needs to fit in the sub function.
+    pub async fn unlock(&self, level: u8, key_fn: impl Fn(&[u8]) -> Vec<u8>) -> Result<()> {
+        if level & 1 == 0 || level >= 0x7F {
+            return Err(Error::MalformedRequest.into());
+        }
+
+        let seed = self.security_access(level, None).await?;
+        if seed.iter().all(|&b| b == 0) {
+            debug!("Zero seed, ECU already unlocked");
+            return Ok(());
+        }
+
+        let key = key_fn(&seed);
+        self.security_access(level + 1, Some(&key)).await?;
+        Ok(())
+    }
+
     /// 0x3E - Tester Present
     pub async fn tester_present(&self) -> Result<()> {
         self.request(ServiceIdentifier::TesterPresent as u8, Some(0), None)
@@ -160,6 +433,97 @@ impl<'a> UDSClient<'a> {
         Ok(())
     }
 
+    /// Periodically sends a Tester Present with the suppressPosRspMsgIndicationBit set, to prevent the ECU from falling back to the default session after the S3 timeout. The ECU does not respond to these requests. The future only resolves when sending fails or the ECU sends a negative response, and stops sending when dropped. Run it concurrently with other requests on the same client, e.g. using `tokio::select!`; requests are serialized so the Tester Present is never sent while waiting for a response. Use [`UDSClient::start_tester_present`] to run a keepalive as a background task instead.
+    pub async fn tester_present_keepalive(&self, period: std::time::Duration) -> Result<()> {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            self.request_no_response(ServiceIdentifier::TesterPresent as u8, 0, None)
+                .await?;
+        }
+    }
+
+    /// Measures the round trip time to the ECU by sending a Tester Present and waiting for the positive response. The timestamps of the request leaving and the response arriving are used if the transport provides them (see [`DiagnosticTransport::round_trip_time`]), otherwise the time is measured on the host, including its scheduling latency. Returns [`crate::Error::Timeout`] if the ECU doesn't respond.
+    pub async fn ping(&self) -> Result<std::time::Duration> {
+        self.wait_inter_request_delay().await;
+
+        let start = tokio::time::Instant::now();
+        let result = self
+            .request_response(ServiceIdentifier::TesterPresent as u8, Some(0), None)
+            .await;
+        let now = tokio::time::Instant::now();
+        *self.last_request.lock().unwrap() = Some(now);
+
+        // Report a silent ECU the same way for every transport
+        result.map_err(|e| match e.is_timeout() {
+            true => crate::Error::Timeout,
+            false => e,
+        })?;
+        Ok(self.adapter.round_trip_time().unwrap_or(now - start))
+    }
+
+    /// Repeatedly sends a Tester Present until the ECU responds with a positive response, or `timeout` is exceeded. Useful to wait for the ECU to come back online after an ECU Reset. The delay between attempts starts at 10 ms, and doubles after every failed attempt up to 1 s.
+    pub async fn wait_until_responsive(&self, timeout: std::time::Duration) -> Result<()> {
+        let start = tokio::time::Instant::now();
+        let mut backoff = std::time::Duration::from_millis(RESPONSIVE_INITIAL_BACKOFF_MS);
+
+        loop {
+            let remaining = timeout.saturating_sub(start.elapsed());
+            if remaining.is_zero() {
+                return Err(crate::Error::Timeout);
+            }
+
+            match tokio::time::timeout(remaining, self.tester_present()).await {
+                Ok(Ok(())) => return Ok(()),
+                Ok(Err(e)) => debug!("ECU not responsive yet: {}", e),
+                Err(_) => return Err(crate::Error::Timeout),
+            }
+
+            let remaining = timeout.saturating_sub(start.elapsed());
+            tokio::time::sleep(std::cmp::min(backoff, remaining)).await;
+            backoff = std::cmp::min(
+                backoff * 2,
+                std::time::Duration::from_millis(RESPONSIVE_MAX_BACKOFF_MS),
+            );
+        }
+    }
+
+    /// 0x83 - Access Timing Parameter. Use the [`constants::AccessTimingParameterType`] enum for the `access_type`. The `request_record` is only used when setting the timing parameters to given values. Returns the timing parameter record when reading, and an empty vec when setting the timing parameters. The format of the record is the same as the [`SessionParameterRecord`] returned by Diagnostic Session Control, but can be extended by the manufacturer.
+    pub async fn access_timing_parameter(
+        &self,
+        access_type: u8,
+        request_record: Option<&[u8]>,
+    ) -> Result<Vec<u8>> {
+        self.request(
+            ServiceIdentifier::AccessTimingParameter as u8,
+            Some(access_type),
+            request_record,
+        )
+        .await
+    }
+
+    /// 0x86 - Response On Event. Use the [`constants::ResponseOnEventType`] enum for the `event_type`. The request consists of the `event_window_time`, the `event_type_record` and the `service_to_respond_to` record. Returns the response data following the sub function. After starting, the ECU sends unsolicited responses to the service to respond to when the event occurs. Keep a stream from the transport (e.g. `isotp.recv()`) open to capture them, as they are not returned by this function.
+    pub async fn response_on_event(
+        &self,
+        event_type: u8,
+        event_window_time: u8,
+        event_type_record: &[u8],
+        service_to_respond_to: &[u8],
+    ) -> Result<Vec<u8>> {
+        let mut data: Vec<u8> = vec![event_window_time];
+        data.extend(event_type_record);
+        data.extend(service_to_respond_to);
+
+        self.request(
+            ServiceIdentifier::ResponseOnEvent as u8,
+            Some(event_type),
+            Some(&data),
+        )
+        .await
+    }
+
     async fn read_write_memory_by_adddress(
         &self,
         sid: ServiceIdentifier,
@@ -187,7 +551,7 @@ impl<'a> UDSClient<'a> {
         self.request(sid as u8, None, Some(&buf)).await
     }
 
-    /// 0x22 - Read Data By Identifier. Specify a 16 bit data identifier, or use a constant from [`constants::DataIdentifier`] for standardized identifiers. Reading multiple identifiers simultaneously is possible on some ECUs, but not supported by this function.
+    /// 0x22 - Read Data By Identifier. Specify a 16 bit data identifier, or use a constant from [`constants::DataIdentifier`] for standardized identifiers. Use [`UDSClient::read_data_by_identifiers`] to read multiple identifiers in a single request.
     pub async fn read_data_by_identifier(&self, data_identifier: u16) -> Result<Vec<u8>> {
         let did = data_identifier.to_be_bytes();
         let resp = self
@@ -210,35 +574,63 @@ impl<'a> UDSClient<'a> {
         Ok(resp[2..].to_vec())
     }
 
-    /// 0x23 - Read Memory By Address. The `memory_address` parameter should be the address to read from, and the `memory_size` parameter should be the number of bytes to read.
-    pub async fn read_memory_by_address(
-        &self,
-        memory_address: &[u8],
-        memory_size: &[u8],
-    ) -> Result<Vec<u8>> {
-        self.read_write_memory_by_adddress(
-            ServiceIdentifier::ReadMemoryByAddress,
-            memory_address,
-            memory_size,
-            None,
-        )
-        .await
+    /// 0x22 - Read Data By Identifier, reading multiple identifiers in a single request. The response does not contain the length of each value, so it is split on the occurrence of the next requested identifier. This requires the ECU to return the identifiers in the same order as `dids`, and can split incorrectly if a value contains the bytes of the next identifier. Returns a list of `(did, value)` tuples.
+    pub async fn read_data_by_identifiers(&self, dids: &[u16]) -> Result<Vec<(u16, Vec<u8>)>> {
+        let data: Vec<u8> = dids.iter().flat_map(|did| did.to_be_bytes()).collect();
+        let resp = self
+            .request(
+                ServiceIdentifier::ReadDataByIdentifier as u8,
+                None,
+                Some(&data),
+            )
+            .await?;
+
+        let mut result = vec![];
+        let mut rest = resp.as_slice();
+        for (i, &data_identifier) in dids.iter().enumerate() {
+            if rest.len() < 2 {
+                return Err(Error::InvalidResponseLength.into());
+            }
+
+            let did = u16::from_be_bytes([rest[0], rest[1]]);
+            if did != data_identifier {
+                return Err(Error::InvalidDataIdentifier(did).into());
+            }
+            rest = &rest[2..];
+
+            // Value runs until the next requested identifier, or the end of the response
+            let len = match dids.get(i + 1) {
+                Some(next) => rest
+                    .windows(2)
+                    .position(|w| w == next.to_be_bytes())
+                    .ok_or(Error::InvalidResponseLength)?,
+                None => rest.len(),
+            };
+
+            result.push((did, rest[..len].to_vec()));
+            rest = &rest[len..];
+        }
+
+        Ok(result)
     }
 
-    /// 0x2E - Write Data By Identifier. Specify a 16 bit data identifier, or use a constant from [`constants::DataIdentifier`] for standardized identifiers.
-    pub async fn write_data_by_identifier(
+    /// 0x22 - Read Data By Identifier. Decodes the data record using the [`DidCodec`] implementation of `V`.
+    pub async fn read_data_by_identifier_typed<V: DidCodec>(
         &self,
         data_identifier: u16,
-        data_record: &[u8],
-    ) -> Result<()> {
-        let mut data: Vec<u8> = data_identifier.to_be_bytes().to_vec();
-        data.extend(data_record);
+    ) -> Result<V> {
+        let data = self.read_data_by_identifier(data_identifier).await?;
+        V::decode(&data)
+    }
 
+    /// 0x24 - Read Scaling Data By Identifier. Returns the scaling byte record of the data identifier, which describes the format and units of the data returned by Read Data By Identifier.
+    pub async fn read_scaling_data_by_identifier(&self, data_identifier: u16) -> Result<Vec<u8>> {
+        let did = data_identifier.to_be_bytes();
         let resp = self
             .request(
-                ServiceIdentifier::WriteDataByIdentifier as u8,
+                ServiceIdentifier::ReadScalingDataByIdentifier as u8,
                 None,
-                Some(&data),
+                Some(&did),
             )
             .await?;
 
@@ -251,99 +643,351 @@ impl<'a> UDSClient<'a> {
             return Err(Error::InvalidDataIdentifier(did).into());
         }
 
-        Ok(())
+        Ok(resp[2..].to_vec())
     }
 
-    /// 0x3D - Write Memory By Address. The `memory_address` parameter should be the address to write to, and the `memory_size` parameter should be the number of bytes to write. The `data` parameter should be the data to write.
-    pub async fn write_memory_by_address(
+    /// 0x2A - Read Data By Periodic Identifier. Use the [`constants::TransmissionMode`] enum for the `transmission_mode`. Periodic DIDs are a single byte, and refer to the DID `0xF2XX`. When using [`TransmissionMode::StopSending`], an empty list of `periodic_dids` stops all periodic transmissions. The periodic responses are not returned by this function, use [`UDSClient::periodic_stream`] to receive them.
+    pub async fn read_data_by_periodic_identifier(
         &self,
-        memory_address: &[u8],
-        memory_size: &[u8],
-        data: &[u8],
+        transmission_mode: u8,
+        periodic_dids: &[u8],
     ) -> Result<()> {
-        self.read_write_memory_by_adddress(
-            ServiceIdentifier::WriteMemoryByAddress,
-            memory_address,
-            memory_size,
-            Some(data),
+        let mut data = vec![transmission_mode];
+        data.extend(periodic_dids);
+
+        self.request(
+            ServiceIdentifier::ReadDataByPeriodicIdentifier as u8,
+            None,
+            Some(&data),
         )
         .await?;
         Ok(())
     }
 
-    pub async fn read_dtc_information_number_of_dtc_by_status_mask(
+    /// Stream of periodic responses sent by the ECU after a [`UDSClient::read_data_by_periodic_identifier`] request, yielding `(periodic_did, data)` tuples. Timeouts of the transport are skipped, as the ECU may send the responses at a slow rate. Create the stream before sending the request to make sure the first responses are not missed.
+    pub fn periodic_stream(&self) -> impl Stream<Item = Result<(u8, Vec<u8>)>> + Unpin + '_ {
+        let mut stream = self.adapter.recv();
+        let sid = ServiceIdentifier::ReadDataByPeriodicIdentifier as u8 | POSITIVE_RESPONSE;
+
+        Box::pin(stream! {
+            while let Some(response) = stream.next().await {
+                match response {
+                    Ok(response) if response.len() >= 2 && response[0] == sid => {
+                        yield Ok((response[1], response[2..].to_vec()));
+                    }
+                    Ok(_) => {}
+                    Err(e) if e.is_timeout() => {}
+                    Err(e) => yield Err(e),
+                }
+            }
+        })
+    }
+
+    /// 0x23 - Read Memory By Address. The `memory_address` parameter should be the address to read from, and the `memory_size` parameter should be the number of bytes to read, both big endian. Returns [`Error::InvalidResponseLength`] if the ECU doesn't return exactly `memory_size` bytes.
+    pub async fn read_memory_by_address(
         &self,
-        mask: u8,
-    ) -> Result<DTCReportNumberByStatusMask> {
+        memory_address: &[u8],
+        memory_size: &[u8],
+    ) -> Result<Vec<u8>> {
         let resp = self
-            .request(
-                ServiceIdentifier::ReadDTCInformation as u8,
-                Some(ReportType::ReportNumberOfDTCByStatusMask as u8),
-                Some(&[mask]),
+            .read_write_memory_by_adddress(
+                ServiceIdentifier::ReadMemoryByAddress,
+                memory_address,
+                memory_size,
+                None,
             )
             .await?;
 
-        if resp.len() != 4 {
+        // A size that doesn't fit in a usize can't match the response length
+        let size = memory_size.iter().try_fold(0usize, |size, &b| {
+            size.checked_mul(0x100)?.checked_add(b as usize)
+        });
+        if size != Some(resp.len()) {
             return Err(Error::InvalidResponseLength.into());
         }
 
-        let mask = resp[0];
-        let format =
-            DTCFormatIdentifier::from_repr(resp[1]).expect("Unknown DTC Format Identifier");
-        let count = u16::from_be_bytes([resp[2], resp[3]]);
-
-        Ok(DTCReportNumberByStatusMask {
-            dtc_status_availability_mask: mask,
-            dtc_format_identifier: format,
-            dtc_count: count,
-        })
+        Ok(resp)
     }
 
-    /// 0x31 - Routine Control. The `routine_control_type` selects the operation such as Start and Stop, see [`constants::RoutineControlType`]. The `routine_identifier` is a 16-bit identifier for the routine. The `data` parameter is optional and can be used when starting or stopping a routine. The ECU can optionally return data for all routine operations.
-    pub async fn routine_control(
+    /// 0x2C - Dynamically Define Data Identifier, define by identifier. Defines `dynamic_did` as the concatenation of parts of other DIDs. Each source definition is a tuple of `(source_did, position, size)`, where position is the 1-based index of the first byte in the source DID.
+    pub async fn dynamically_define_data_identifier_by_identifier(
         &self,
-        routine_control_type: constants::RoutineControlType,
-        routine_identifier: u16,
-        data: Option<&[u8]>,
-    ) -> Result<Option<Vec<u8>>> {
-        let mut buf: Vec<u8> = vec![];
-        buf.extend(routine_identifier.to_be_bytes());
-        if let Some(data) = data {
-            buf.extend(data);
+        dynamic_did: u16,
+        source_definitions: &[(u16, u8, u8)],
+    ) -> Result<()> {
+        let mut data: Vec<u8> = dynamic_did.to_be_bytes().to_vec();
+        for (source_did, position, size) in source_definitions {
+            data.extend(source_did.to_be_bytes());
+            data.push(*position);
+            data.push(*size);
         }
 
         let resp = self
             .request(
-                ServiceIdentifier::RoutineControl as u8,
-                Some(routine_control_type as u8),
-                Some(&buf),
+                ServiceIdentifier::DynamicallyDefineDataIdentifier as u8,
+                Some(DynamicallyDefineDataIdentifierType::DefineByIdentifier as u8),
+                Some(&data),
+            )
+            .await?;
+
+        Self::check_dynamic_did(&resp, dynamic_did)
+    }
+
+    /// 0x2C - Dynamically Define Data Identifier, clear dynamically defined data identifier.
+    pub async fn clear_dynamically_defined_data_identifier(&self, dynamic_did: u16) -> Result<()> {
+        let resp = self
+            .request(
+                ServiceIdentifier::DynamicallyDefineDataIdentifier as u8,
+                Some(
+                    DynamicallyDefineDataIdentifierType::ClearDynamicallyDefinedDataIdentifier
+                        as u8,
+                ),
+                Some(&dynamic_did.to_be_bytes()),
             )
             .await?;
 
+        Self::check_dynamic_did(&resp, dynamic_did)
+    }
+
+    fn check_dynamic_did(resp: &[u8], dynamic_did: u16) -> Result<()> {
         if resp.len() < 2 {
             return Err(Error::InvalidResponseLength.into());
         }
 
-        let id = u16::from_be_bytes([resp[0], resp[1]]);
-        if id != routine_identifier {
-            return Err(Error::InvalidDataIdentifier(id).into());
+        let did = u16::from_be_bytes([resp[0], resp[1]]);
+        if did != dynamic_did {
+            return Err(Error::InvalidDataIdentifier(did).into());
         }
 
-        Ok(if resp.len() > 2 {
-            Some(resp[2..].to_vec())
-        } else {
-            None
-        })
+        Ok(())
     }
 
-    async fn request_download_upload(
+    /// 0x2E - Write Data By Identifier. Specify a 16 bit data identifier, or use a constant from [`constants::DataIdentifier`] for standardized identifiers.
+    pub async fn write_data_by_identifier(
         &self,
-        sid: ServiceIdentifier,
-        compression_method: u8,
+        data_identifier: u16,
+        data_record: &[u8],
+    ) -> Result<()> {
+        let mut data: Vec<u8> = data_identifier.to_be_bytes().to_vec();
+        data.extend(data_record);
+
+        let resp = self
+            .request(
+                ServiceIdentifier::WriteDataByIdentifier as u8,
+                None,
+                Some(&data),
+            )
+            .await?;
+
+        if resp.len() < 2 {
+            return Err(Error::InvalidResponseLength.into());
+        }
+
+        let did = u16::from_be_bytes([resp[0], resp[1]]);
+        if did != data_identifier {
+            return Err(Error::InvalidDataIdentifier(did).into());
+        }
+
+        Ok(())
+    }
+
+    /// 0x2E - Write Data By Identifier. Encodes `value` using the [`DidCodec`] implementation of `V`.
+    pub async fn write_data_by_identifier_typed<V: DidCodec>(
+        &self,
+        data_identifier: u16,
+        value: &V,
+    ) -> Result<()> {
+        let data_record = value.encode()?;
+        self.write_data_by_identifier(data_identifier, &data_record)
+            .await
+    }
+
+    /// 0x2F - Input Output Control By Identifier. The request consists of the `data_identifier`, the `control_option` (see [`constants::InputOutputControlParameter`]), the `control_state` and the `control_enable_mask`. The state and mask can be empty when not used by the ECU. Returns the control status record reported by the ECU.
+    pub async fn input_output_control_by_identifier(
+        &self,
+        data_identifier: u16,
+        control_option: u8,
+        control_state: &[u8],
+        control_enable_mask: &[u8],
+    ) -> Result<Vec<u8>> {
+        let mut data: Vec<u8> = data_identifier.to_be_bytes().to_vec();
+        data.push(control_option);
+        data.extend(control_state);
+        data.extend(control_enable_mask);
+
+        let resp = self
+            .request(
+                ServiceIdentifier::InputOutputControlByIdentifier as u8,
+                None,
+                Some(&data),
+            )
+            .await?;
+
+        if resp.len() < 2 {
+            return Err(Error::InvalidResponseLength.into());
+        }
+
+        let did = u16::from_be_bytes([resp[0], resp[1]]);
+        if did != data_identifier {
+            return Err(Error::InvalidDataIdentifier(did).into());
+        }
+
+        Ok(resp[2..].to_vec())
+    }
+
+    /// 0x3D - Write Memory By Address. The `memory_address` parameter should be the address to write to, and the `memory_size` parameter should be the number of bytes to write. The `data` parameter should be the data to write.
+    pub async fn write_memory_by_address(
+        &self,
+        memory_address: &[u8],
+        memory_size: &[u8],
+        data: &[u8],
+    ) -> Result<()> {
+        self.read_write_memory_by_adddress(
+            ServiceIdentifier::WriteMemoryByAddress,
+            memory_address,
+            memory_size,
+            Some(data),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// 0x14 - Clear Diagnostic Information. The `group_of_dtc` is a 3 byte value, use 0xFFFFFF to clear all DTCs. Returns [`Error::MalformedRequest`] if the group doesn't fit in 3 bytes.
+    pub async fn clear_diagnostic_information(&self, group_of_dtc: u32) -> Result<()> {
+        if group_of_dtc > 0xFFFFFF {
+            return Err(Error::MalformedRequest.into());
+        }
+
+        let group = group_of_dtc.to_be_bytes();
+        self.request(
+            ServiceIdentifier::ClearDiagnosticInformation as u8,
+            None,
+            Some(&group[1..]),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Clears the DTCs in `group_of_dtc` and reads back the DTCs in that group that are reported as failing or pending afterwards. A non empty result indicates a persistent fault that is immediately detected again by the ECU. The ECU is given `settle_time` between clearing and reading the DTCs to run its tests, the required time is ECU and fault specific. Other than 0xFFFFFF (all DTCs), `group_of_dtc` is matched as a single DTC, manufacturer specific groups are not resolved.
+    pub async fn clear_and_verify_dtcs(
+        &self,
+        group_of_dtc: u32,
+        settle_time: std::time::Duration,
+    ) -> Result<Vec<DTCAndStatusRecord>> {
+        self.clear_diagnostic_information(group_of_dtc).await?;
+
+        tokio::time::sleep(settle_time).await;
+
+        let dtcs = self
+            .read_dtc_information_by_status_mask(DTC_STATUS_MASK_FAULT)
+            .await?;
+        Ok(dtcs
+            .into_iter()
+            .filter(|record| group_of_dtc == DTC_GROUP_ALL || record.dtc.0 == group_of_dtc)
+            .collect())
+    }
+
+    /// 0x19 0x01 - Read DTC Information, report Number Of DTC By Status Mask. Returns the number of DTCs that match the status `mask`, use [`UDSClient::read_dtc_information_by_status_mask`] to get the actual DTCs.
+    pub async fn read_dtc_information_number_of_dtc_by_status_mask(
+        &self,
+        mask: u8,
+    ) -> Result<DTCReportNumberByStatusMask> {
+        let resp = self
+            .request(
+                ServiceIdentifier::ReadDTCInformation as u8,
+                Some(ReportType::ReportNumberOfDTCByStatusMask as u8),
+                Some(&[mask]),
+            )
+            .await?;
+
+        if resp.len() != 4 {
+            return Err(Error::InvalidResponseLength.into());
+        }
+
+        let mask = resp[0];
+        let format =
+            DTCFormatIdentifier::from_repr(resp[1]).expect("Unknown DTC Format Identifier");
+        let count = u16::from_be_bytes([resp[2], resp[3]]);
+
+        Ok(DTCReportNumberByStatusMask {
+            dtc_status_availability_mask: mask,
+            dtc_format_identifier: format,
+            dtc_count: count,
+        })
+    }
+
+    /// 0x19 0x02 - Read DTC Information, report DTC By Status Mask. Returns all DTCs that match the status `mask`.
+    pub async fn read_dtc_information_by_status_mask(
+        &self,
+        mask: u8,
+    ) -> Result<Vec<DTCAndStatusRecord>> {
+        let resp = self
+            .request(
+                ServiceIdentifier::ReadDTCInformation as u8,
+                Some(ReportType::ReportDTCByStatusMask as u8),
+                Some(&[mask]),
+            )
+            .await?;
+
+        // First byte is the DTC status availability mask, followed by 4 bytes per DTC
+        if resp.is_empty() || (resp.len() - 1) % 4 != 0 {
+            return Err(Error::InvalidResponseLength.into());
+        }
+
+        Ok(resp[1..]
+            .chunks(4)
+            .map(|record| DTCAndStatusRecord {
+                dtc: Dtc(u32::from_be_bytes([0, record[0], record[1], record[2]])),
+                status: record[3],
+            })
+            .collect())
+    }
+
+    /// 0x31 - Routine Control. The `routine_control_type` selects the operation such as Start and Stop, see [`constants::RoutineControlType`]. The `routine_identifier` is a 16-bit identifier for the routine. The `data` parameter is optional and can be used when starting or stopping a routine. The ECU can optionally return data for all routine operations.
+    pub async fn routine_control(
+        &self,
+        routine_control_type: constants::RoutineControlType,
+        routine_identifier: u16,
+        data: Option<&[u8]>,
+    ) -> Result<Option<Vec<u8>>> {
+        let mut buf: Vec<u8> = vec![];
+        buf.extend(routine_identifier.to_be_bytes());
+        if let Some(data) = data {
+            buf.extend(data);
+        }
+
+        let resp = self
+            .request(
+                ServiceIdentifier::RoutineControl as u8,
+                Some(routine_control_type as u8),
+                Some(&buf),
+            )
+            .await?;
+
+        if resp.len() < 2 {
+            return Err(Error::InvalidResponseLength.into());
+        }
+
+        let id = u16::from_be_bytes([resp[0], resp[1]]);
+        if id != routine_identifier {
+            return Err(Error::InvalidDataIdentifier(id).into());
+        }
+
+        Ok(if resp.len() > 2 {
+            Some(resp[2..].to_vec())
+        } else {
+            None
+        })
+    }
+
+    async fn request_download_upload(
+        &self,
+        sid: ServiceIdentifier,
+        compression_method: u8,
         encryption_method: u8,
         memory_address: &[u8],
         memory_size: &[u8],
-    ) -> Result<usize> {
+    ) -> Result<TransferBlockLength> {
         assert!(
             sid == ServiceIdentifier::RequestDownload || sid == ServiceIdentifier::RequestUpload
         );
@@ -377,17 +1021,17 @@ impl<'a> UDSClient<'a> {
             .iter()
             .fold(0, |acc, &x| (acc << 8) | x as usize);
 
-        Ok(length)
+        Ok(length.into())
     }
 
-    /// 0x34 - Request Download. Used to initiate a transfer from the client to the ECU. Returns the maximum number of bytes to include in each TransferData request.
+    /// 0x34 - Request Download. Used to initiate a transfer from the client to the ECU. Returns the maximum length of each TransferData request. Note that the length reported by the ECU includes the service identifier and block sequence counter, use [`TransferBlockLength::usable_payload`] for the amount of data that can be sent in each request.
     pub async fn request_download(
         &self,
         compression_method: u8,
         encryption_method: u8,
         memory_address: &[u8],
         memory_size: &[u8],
-    ) -> Result<usize> {
+    ) -> Result<TransferBlockLength> {
         self.request_download_upload(
             ServiceIdentifier::RequestDownload,
             compression_method,
@@ -398,14 +1042,14 @@ impl<'a> UDSClient<'a> {
         .await
     }
 
-    /// 0x35 - Request Upload. Used to initiate a transfer from the client to the ECU. Returns the maximum number of bytes to include in each TransferData request.
+    /// 0x35 - Request Upload. Used to initiate a transfer from the ECU to the client. Returns the maximum length of each TransferData request. Note that the length reported by the ECU includes the service identifier and block sequence counter, use [`TransferBlockLength::usable_payload`] for the amount of data that can be received in each request.
     pub async fn request_upload(
         &self,
         compression_method: u8,
         encryption_method: u8,
         memory_address: &[u8],
         memory_size: &[u8],
-    ) -> Result<usize> {
+    ) -> Result<TransferBlockLength> {
         self.request_download_upload(
             ServiceIdentifier::RequestUpload,
             compression_method,
@@ -456,4 +1100,1092 @@ impl<'a> UDSClient<'a> {
 
         Ok(if !resp.is_empty() { Some(resp) } else { None })
     }
+
+    /// Downloads `data` to the ECU at `memory_address`. Performs a Request Download, followed by as many Transfer Data requests as needed to send all data, and finally a Request Transfer Exit. The data is split in chunks that fit in the maximum block length reported by the ECU.
+    pub async fn download(
+        &self,
+        memory_address: &[u8],
+        data: &[u8],
+        compression_method: u8,
+        encryption_method: u8,
+    ) -> Result<()> {
+        let memory_size = Self::memory_size_bytes(data.len());
+        let block_length = self
+            .request_download(
+                compression_method,
+                encryption_method,
+                memory_address,
+                &memory_size,
+            )
+            .await?;
+
+        if block_length.usable_payload == 0 {
+            return Err(Error::InvalidResponseLength.into());
+        }
+
+        let mut block_sequence_counter: u8 = 1;
+        for chunk in data.chunks(block_length.usable_payload) {
+            self.transfer_data(block_sequence_counter, Some(chunk))
+                .await?;
+            block_sequence_counter = block_sequence_counter.wrapping_add(1);
+        }
+
+        self.request_transfer_exit(None).await?;
+        Ok(())
+    }
+
+    /// Uploads `memory_size` bytes from the ECU starting at `memory_address`. Performs a Request Upload, followed by Transfer Data requests until all data is received, and finally a Request Transfer Exit. Returns the received data, or [`Error::InvalidResponseLength`] if the ECU sends more than `memory_size` bytes.
+    pub async fn upload(
+        &self,
+        memory_address: &[u8],
+        memory_size: usize,
+        compression_method: u8,
+        encryption_method: u8,
+    ) -> Result<Vec<u8>> {
+        self.request_upload(
+            compression_method,
+            encryption_method,
+            memory_address,
+            &Self::memory_size_bytes(memory_size),
+        )
+        .await?;
+
+        let mut data: Vec<u8> = Vec::with_capacity(memory_size);
+        let mut block_sequence_counter: u8 = 1;
+        while data.len() < memory_size {
+            // An empty block would cause an infinite loop
+            let block = self
+                .transfer_data(block_sequence_counter, None)
+                .await?
+                .ok_or(Error::InvalidResponseLength)?;
+
+            data.extend(block);
+            block_sequence_counter = block_sequence_counter.wrapping_add(1);
+        }
+
+        if data.len() != memory_size {
+            return Err(Error::InvalidResponseLength.into());
+        }
+
+        self.request_transfer_exit(None).await?;
+        Ok(data)
+    }
+
+    /// Encodes a memory size using the smallest number of bytes
+    fn memory_size_bytes(size: usize) -> Vec<u8> {
+        let bytes = size.to_be_bytes();
+        let start = bytes
+            .iter()
+            .position(|&b| b != 0)
+            .unwrap_or(bytes.len() - 1);
+        bytes[start..].to_vec()
+    }
+
+    /// 0x38 - Request File Transfer. Use the [`constants::ModeOfOperation`] enum for the `mode_of_operation`. The `data_format` contains the compression method in the high nibble and the encryption method in the low nibble, and is not sent for DeleteFile and ReadDir. The uncompressed file size is required for AddFile and ReplaceFile, the compressed size defaults to the uncompressed size if not given. Returns [`Error::MalformedRequest`] if the file size is missing, or the file path is longer than 0xFFFF bytes.
+    pub async fn request_file_transfer(
+        &self,
+        mode_of_operation: u8,
+        file_path: &str,
+        data_format: u8,
+        file_size_uncompressed: Option<u64>,
+        file_size_compressed: Option<u64>,
+    ) -> Result<FileTransferResponse> {
+        if file_path.len() > 0xFFFF {
+            return Err(Error::MalformedRequest.into());
+        }
+
+        let has_data_format = mode_of_operation != ModeOfOperation::DeleteFile as u8
+            && mode_of_operation != ModeOfOperation::ReadDir as u8;
+        let has_file_size = mode_of_operation == ModeOfOperation::AddFile as u8
+            || mode_of_operation == ModeOfOperation::ReplaceFile as u8;
+
+        let mut data: Vec<u8> = (file_path.len() as u16).to_be_bytes().to_vec();
+        data.extend(file_path.as_bytes());
+
+        if has_data_format {
+            data.push(data_format);
+        }
+
+        if has_file_size {
+            let uncompressed = file_size_uncompressed.ok_or(Error::MalformedRequest)?;
+            let compressed = file_size_compressed.unwrap_or(uncompressed);
+
+            // Use the smallest number of bytes that fits both sizes
+            let num_bytes = std::cmp::max(
+                1,
+                (u64::BITS - (uncompressed | compressed).leading_zeros()).div_ceil(8) as usize,
+            );
+
+            data.push(num_bytes as u8);
+            data.extend(&uncompressed.to_be_bytes()[8 - num_bytes..]);
+            data.extend(&compressed.to_be_bytes()[8 - num_bytes..]);
+        }
+
+        let resp = self
+            .request(
+                ServiceIdentifier::RequestFileTransfer as u8,
+                Some(mode_of_operation),
+                Some(&data),
+            )
+            .await?;
+
+        let mut result = FileTransferResponse {
+            max_number_of_block_length: None,
+            data_format_identifier: None,
+            file_size_uncompressed_or_dir_info_length: None,
+            file_size_compressed: None,
+        };
+
+        // Reads `len` bytes from the response as big endian integer
+        let mut idx = 0;
+        let mut take = |len: usize| -> Result<u64> {
+            let bytes = resp
+                .get(idx..idx + len)
+                .ok_or(Error::InvalidResponseLength)?;
+            idx += len;
+            Ok(bytes.iter().fold(0, |acc, &x| (acc << 8) | x as u64))
+        };
+
+        if mode_of_operation != ModeOfOperation::DeleteFile as u8 {
+            let num_length_bytes = (take(1)? >> 4) as usize;
+            if num_length_bytes == 0 || num_length_bytes > 8 {
+                return Err(Error::InvalidResponseLength.into());
+            }
+
+            result.max_number_of_block_length = Some(take(num_length_bytes)? as usize);
+            result.data_format_identifier = Some(take(1)? as u8);
+        }
+
+        let is_read_file = mode_of_operation == ModeOfOperation::ReadFile as u8;
+        if is_read_file || mode_of_operation == ModeOfOperation::ReadDir as u8 {
+            let num_bytes = take(2)? as usize;
+            if num_bytes == 0 || num_bytes > 8 {
+                return Err(Error::InvalidResponseLength.into());
+            }
+
+            result.file_size_uncompressed_or_dir_info_length = Some(take(num_bytes)?);
+            if is_read_file {
+                result.file_size_compressed = Some(take(num_bytes)?);
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::can::{Frame, Identifier, VirtualCanAdapter};
+    use crate::transport::mock::MockTransport;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const RESPONSE_PENDING: [u8; 3] = [0x7f, 0x3e, 0x78];
+
+    #[tokio::test]
+    async fn response_pending() {
+        let transport = MockTransport::new(|_| {
+            let mut responses = vec![RESPONSE_PENDING.to_vec(); 3];
+            responses.push(vec![0x7e, 0x00]);
+            responses
+        });
+        let uds = UDSClient::new(&transport);
+        uds.tester_present().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn response_pending_limit() {
+        let transport = MockTransport::new(|_| vec![RESPONSE_PENDING.to_vec(); 11]);
+        let uds = UDSClient::new(&transport);
+        assert_eq!(uds.tester_present().await, Err(crate::Error::Timeout));
+    }
+
+    #[tokio::test]
+    async fn response_pending_p2_star_timeout() {
+        let transport = MockTransport::new(|_| vec![RESPONSE_PENDING.to_vec()]);
+        let mut uds = UDSClient::new(&transport);
+        uds.set_p2_star_timeout(std::time::Duration::from_millis(50));
+
+        let start = tokio::time::Instant::now();
+        assert_eq!(uds.tester_present().await, Err(crate::Error::Timeout));
+        assert!(start.elapsed() >= std::time::Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn clear_diagnostic_information() {
+        let transport = MockTransport::new(|req| match req {
+            [0x14, 0xff, 0xff, 0xff] => vec![vec![0x54]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        uds.clear_diagnostic_information(0xFFFFFF).await.unwrap();
+        assert_eq!(
+            uds.clear_diagnostic_information(0x1000000).await,
+            Err(Error::MalformedRequest.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn ping() {
+        // No timestamps, measured on the host
+        let transport = MockTransport::new(|req| match req {
+            [0x3e, 0x00] => vec![vec![0x7e, 0x00]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+        assert!(uds.ping().await.unwrap() < std::time::Duration::from_secs(1));
+
+        // Response timestamped 10 s after creating the adapter, the request is timestamped by the virtual adapter
+        let adapter = VirtualCanAdapter::simulated_ecu(|_| {
+            let mut frame =
+                Frame::new(0, Identifier::Standard(0x7a9), &[0x02, 0x7e, 0x00]).unwrap();
+            frame.timestamp = Some(std::time::Duration::from_secs(10));
+            vec![frame]
+        });
+        let isotp = IsoTPAdapter::from_id(&adapter, 0x7a1);
+        let uds = UDSClient::new(&isotp);
+
+        let rtt = uds.ping().await.unwrap();
+        assert!(rtt > std::time::Duration::from_secs(9));
+        assert!(rtt < std::time::Duration::from_secs(10));
+
+        // Silent ECU
+        let adapter = VirtualCanAdapter::simulated_ecu(|_| vec![]);
+        let mut config = crate::isotp::IsoTPConfig::new(0, Identifier::Standard(0x7a1));
+        config.timeout = std::time::Duration::from_millis(20);
+        let isotp = IsoTPAdapter::new(&adapter, config);
+        let uds = UDSClient::new(&isotp);
+        assert_eq!(uds.ping().await, Err(crate::Error::Timeout));
+    }
+
+    #[tokio::test]
+    async fn read_dtc_information() {
+        let transport = MockTransport::new(|req| match req {
+            [0x19, 0x01, 0x08] => vec![vec![0x59, 0x01, 0xff, 0x01, 0x00, 0x02]],
+            [0x19, 0x02, 0x08] => vec![vec![
+                0x59, 0x02, 0xff, 0x04, 0x20, 0x00, 0x08, 0xc1, 0x00, 0x87, 0x09,
+            ]],
+            [0x19, 0x02, 0x01] => vec![vec![0x59, 0x02, 0xff]],
+            // Truncated record
+            [0x19, 0x02, 0x04] => vec![vec![0x59, 0x02, 0xff, 0x04, 0x20, 0x00]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        assert_eq!(
+            uds.read_dtc_information_number_of_dtc_by_status_mask(0x08)
+                .await
+                .unwrap(),
+            DTCReportNumberByStatusMask {
+                dtc_status_availability_mask: 0xff,
+                dtc_format_identifier: DTCFormatIdentifier::ISO_14229_1_DTCFormat,
+                dtc_count: 2,
+            }
+        );
+
+        let dtcs = uds.read_dtc_information_by_status_mask(0x08).await.unwrap();
+        assert_eq!(
+            dtcs,
+            vec![
+                DTCAndStatusRecord {
+                    dtc: Dtc(0x042000),
+                    status: 0x08
+                },
+                DTCAndStatusRecord {
+                    dtc: Dtc(0xc10087),
+                    status: 0x09
+                },
+            ]
+        );
+        assert_eq!(dtcs[0].dtc.to_string(), "P0420");
+        assert_eq!(dtcs[1].dtc.failure_type(), 0x87);
+
+        assert_eq!(
+            uds.read_dtc_information_by_status_mask(0x01).await,
+            Ok(vec![])
+        );
+        assert_eq!(
+            uds.read_dtc_information_by_status_mask(0x04).await,
+            Err(Error::InvalidResponseLength.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn clear_and_verify_dtcs() {
+        // Both DTCs are still failing after clearing
+        let transport = MockTransport::new(|req| match req {
+            [0x14, 0x12, 0x34, 0x56] => vec![vec![0x54]],
+            [0x19, 0x02, 0x0f] => vec![vec![
+                0x59, 0x02, 0xff, 0x12, 0x34, 0x56, 0x09, 0xab, 0xcd, 0xef, 0x08,
+            ]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        assert_eq!(
+            uds.clear_and_verify_dtcs(0x123456, std::time::Duration::ZERO)
+                .await
+                .unwrap(),
+            vec![DTCAndStatusRecord {
+                dtc: Dtc(0x123456),
+                status: 0x09
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn request_file_transfer() {
+        let transport = MockTransport::new(|req| match req {
+            // AddFile "a/b", uncompressed size 0x1234, compressed size 0x80
+            [0x38, 0x01, 0x00, 0x03, b'a', b'/', b'b', 0x11, 0x02, 0x12, 0x34, 0x00, 0x80] => {
+                vec![vec![0x78, 0x01, 0x20, 0x02, 0x00, 0x11]]
+            }
+            [0x38, 0x02, 0x00, 0x03, b'a', b'/', b'b'] => vec![vec![0x78, 0x02]],
+            // ReadFile, uncompressed size 0x1000, compressed size 0x0800
+            [0x38, 0x04, 0x00, 0x03, b'a', b'/', b'b', 0x00] => vec![vec![
+                0x78, 0x04, 0x20, 0x01, 0x02, 0x00, 0x00, 0x02, 0x10, 0x00, 0x08, 0x00,
+            ]],
+            // ReadDir, directory information of 0x40 bytes
+            [0x38, 0x05, 0x00, 0x03, b'a', b'/', b'b'] => {
+                vec![vec![0x78, 0x05, 0x10, 0x82, 0x00, 0x00, 0x01, 0x40]]
+            }
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        assert_eq!(
+            uds.request_file_transfer(
+                ModeOfOperation::AddFile as u8,
+                "a/b",
+                0x11,
+                Some(0x1234),
+                Some(0x80)
+            )
+            .await
+            .unwrap(),
+            FileTransferResponse {
+                max_number_of_block_length: Some(0x0200),
+                data_format_identifier: Some(0x11),
+                file_size_uncompressed_or_dir_info_length: None,
+                file_size_compressed: None,
+            }
+        );
+        assert_eq!(
+            uds.request_file_transfer(ModeOfOperation::DeleteFile as u8, "a/b", 0x11, None, None)
+                .await
+                .unwrap(),
+            FileTransferResponse {
+                max_number_of_block_length: None,
+                data_format_identifier: None,
+                file_size_uncompressed_or_dir_info_length: None,
+                file_size_compressed: None,
+            }
+        );
+        assert_eq!(
+            uds.request_file_transfer(ModeOfOperation::ReadFile as u8, "a/b", 0x00, None, None)
+                .await
+                .unwrap(),
+            FileTransferResponse {
+                max_number_of_block_length: Some(0x0102),
+                data_format_identifier: Some(0x00),
+                file_size_uncompressed_or_dir_info_length: Some(0x1000),
+                file_size_compressed: Some(0x0800),
+            }
+        );
+        assert_eq!(
+            uds.request_file_transfer(ModeOfOperation::ReadDir as u8, "a/b", 0x11, None, None)
+                .await
+                .unwrap(),
+            FileTransferResponse {
+                max_number_of_block_length: Some(0x82),
+                data_format_identifier: Some(0x00),
+                file_size_uncompressed_or_dir_info_length: Some(0x40),
+                file_size_compressed: None,
+            }
+        );
+
+        // File size is required for ReplaceFile
+        assert_eq!(
+            uds.request_file_transfer(ModeOfOperation::ReplaceFile as u8, "a/b", 0x00, None, None)
+                .await,
+            Err(Error::MalformedRequest.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn start_tester_present() {
+        let (tester, ecu) = VirtualCanAdapter::pair_async();
+        let isotp = IsoTPAdapter::from_id(&tester, 0x7a1);
+        let uds = UDSClient::new(&isotp);
+
+        let stream = ecu.recv_filter(|frame| !frame.loopback);
+        tokio::pin!(stream);
+
+        let handle = uds.start_tester_present(std::time::Duration::from_millis(20));
+        for _ in 0..3 {
+            let frame = stream.next().await.unwrap();
+            assert_eq!(frame.id, Identifier::Standard(0x7a1));
+            assert_eq!(frame.data[..3], [0x02, 0x3e, 0x80]);
+        }
+        assert!(!handle.is_finished());
+
+        // Dropping the handle stops the keepalive
+        drop(handle);
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        let quiet = ecu.recv_timeout(std::time::Duration::from_millis(50));
+        assert_eq!(quiet.collect::<Vec<_>>().await, vec![]);
+    }
+
+    #[tokio::test]
+    async fn ignore_negative_response_other_service() {
+        // Negative response to a concurrent keepalive, followed by the actual response
+        let transport = MockTransport::new(|req| match req {
+            [0x22, 0x12, 0x34] => vec![vec![0x7f, 0x3e, 0x12], vec![0x62, 0x12, 0x34, 0xaa]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        assert_eq!(
+            uds.read_data_by_identifier(0x1234).await.unwrap(),
+            vec![0xaa]
+        );
+    }
+
+    #[tokio::test]
+    async fn unlock() {
+        let transport = MockTransport::new(|request| match request {
+            [0x27, 0x01] => vec![vec![0x67, 0x01, 0x12, 0x34]],
+            [0x27, 0x02, 0xed, 0xcb] => vec![vec![0x67, 0x02]],
+            [0x27, 0x02, ..] => vec![vec![0x7f, 0x27, 0x35]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        let key_fn = |seed: &[u8]| seed.iter().map(|b| !b).collect();
+        uds.unlock(0x01, key_fn).await.unwrap();
+
+        let invalid_key = Error::NegativeResponse {
+            service: 0x27,
+            code: NegativeResponseCode::InvalidKey,
+            extra: vec![],
+        };
+        assert_eq!(
+            invalid_key.to_string(),
+            "Negative Response to service 0x27: InvalidKey"
+        );
+        assert_eq!(
+            uds.unlock(0x01, |seed| seed.to_vec()).await,
+            Err(invalid_key.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn unlock_zero_seed() {
+        let transport = MockTransport::new(|request| match request {
+            [0x27, 0x01] => vec![vec![0x67, 0x01, 0x00, 0x00]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        uds.unlock(0x01, |_| panic!("No key expected for zero seed"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn unlock_invalid_level() {
+        let transport = MockTransport::new(|_| vec![]);
+        let uds = UDSClient::new(&transport);
+
+        for level in [0x00, 0x02, 0x7f, 0xff] {
+            assert_eq!(
+                uds.unlock(level, |seed| seed.to_vec()).await,
+                Err(Error::MalformedRequest.into())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn read_data_by_identifiers() {
+        let transport = MockTransport::new(|request| match request {
+            [0x22, 0xf1, 0x90, 0xf1, 0x8c] => vec![vec![
+                0x62, 0xf1, 0x90, 0x56, 0x49, 0x4e, 0xf1, 0x8c, 0x53, 0x4e,
+            ]],
+            [0x22, 0xf1, 0x8c, 0xf1, 0x90] => vec![vec![0x62, 0xf1, 0x90, 0x56, 0xf1, 0x8c, 0x53]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        let resp = uds.read_data_by_identifiers(&[0xf190, 0xf18c]).await;
+        assert_eq!(
+            resp,
+            Ok(vec![(0xf190, b"VIN".to_vec()), (0xf18c, b"SN".to_vec())])
+        );
+
+        // Identifiers returned in a different order than requested
+        let resp = uds.read_data_by_identifiers(&[0xf18c, 0xf190]).await;
+        assert_eq!(resp, Err(Error::InvalidDataIdentifier(0xf190).into()));
+    }
+
+    #[tokio::test]
+    async fn read_data_by_periodic_identifier() {
+        let transport = MockTransport::new(|request| match request {
+            [0x2a, 0x03, 0x01, 0x02] => vec![
+                vec![0x6a],
+                vec![0x6a, 0x01, 0xaa, 0xbb],
+                vec![0x62, 0xf1, 0x90],
+                vec![0x6a, 0x02, 0xcc],
+            ],
+            [0x2a, 0x04] => vec![vec![0x6a]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        let mut stream = uds.periodic_stream();
+        uds.read_data_by_periodic_identifier(TransmissionMode::SendAtFastRate as u8, &[0x01, 0x02])
+            .await
+            .unwrap();
+
+        assert_eq!(stream.next().await, Some(Ok((0x01, vec![0xaa, 0xbb]))));
+        assert_eq!(stream.next().await, Some(Ok((0x02, vec![0xcc]))));
+
+        uds.read_data_by_periodic_identifier(TransmissionMode::StopSending as u8, &[])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn diagnostic_session_control_timing() {
+        // P2 server max in ms, followed by P2* server max in units of 10 ms
+        let transport = MockTransport::new(|request| match request {
+            [0x10, 0x02] => vec![vec![0x50, 0x02, 0x01, 0x02, 0x03, 0x04]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        let record = uds.diagnostic_session_control(0x02).await.unwrap().unwrap();
+        assert_eq!(
+            record.p2_server_max,
+            std::time::Duration::from_millis(0x0102)
+        );
+        assert_eq!(
+            record.p2_star_server_max,
+            std::time::Duration::from_millis(0x0304 * 10)
+        );
+    }
+
+    #[tokio::test]
+    async fn diagnostic_session_control_typed() {
+        let transport = MockTransport::new(|request| match request {
+            [0x10, 0x01] => vec![vec![0x50, 0x01]],
+            [0x10, 0x03] => vec![vec![0x50, 0x03, 0x00, 0x32, 0x00, 0xc8]],
+            _ => vec![],
+        });
+        let mut uds = UDSClient::new(&transport);
+
+        let record = uds
+            .diagnostic_session_control_typed(SessionType::ExtendedDiagnostic)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.p2_server_max, std::time::Duration::from_millis(50));
+        assert_eq!(
+            record.p2_star_server_max,
+            std::time::Duration::from_millis(2000)
+        );
+        assert_eq!(
+            *uds.p2_star_timeout.lock().unwrap(),
+            std::time::Duration::from_millis(DEFAULT_P2_STAR_TIMEOUT_MS)
+        );
+
+        uds.set_apply_session_timing(true);
+        let record = uds
+            .diagnostic_session_control_typed(SessionType::ExtendedDiagnostic)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            *uds.p2_star_timeout.lock().unwrap(),
+            record.p2_star_server_max
+        );
+
+        // No session parameter record, the timeout is kept
+        let resp = uds
+            .diagnostic_session_control_typed(SessionType::Default)
+            .await;
+        assert_eq!(resp, Ok(None));
+        assert_eq!(
+            *uds.p2_star_timeout.lock().unwrap(),
+            record.p2_star_server_max
+        );
+    }
+
+    #[tokio::test]
+    async fn ecu_reset_typed() {
+        let transport = MockTransport::new(|request| match request {
+            [0x11, 0x01] => vec![vec![0x51, 0x01]],
+            [0x11, 0x02] => vec![vec![0x51, 0x02, 0x0a]],
+            [0x11, 0x03] => vec![vec![0x51, 0x01]],
+            [0x11, 0x04] => vec![vec![0x51, 0x04, 0x0a]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        assert_eq!(uds.ecu_reset_typed(ResetType::HardReset).await, Ok(None));
+        assert_eq!(
+            uds.ecu_reset_typed(ResetType::EnableRapidPowerShutDown)
+                .await,
+            Ok(Some(std::time::Duration::from_secs(10)))
+        );
+
+        // Power down time is only valid for rapid power shutdown
+        assert_eq!(
+            uds.ecu_reset_typed(ResetType::KeyOffOnReset).await,
+            Err(Error::InvalidResponseLength.into())
+        );
+
+        // Reset type is not echoed
+        assert_eq!(
+            uds.ecu_reset_typed(ResetType::SoftRest).await,
+            Err(Error::InvalidSubFunction(0x01).into())
+        );
+    }
+
+    static SESSION_RETURNED: std::sync::atomic::AtomicUsize =
+        std::sync::atomic::AtomicUsize::new(0);
+
+    #[tokio::test]
+    async fn session() {
+        use std::sync::atomic::Ordering;
+
+        let transport = MockTransport::new(|request| match request {
+            [0x10, 0x01] => {
+                SESSION_RETURNED.fetch_add(1, Ordering::Relaxed);
+                vec![vec![0x50, 0x01]]
+            }
+            [0x10, 0x02] => vec![vec![0x50, 0x02]],
+            [0x3e, 0x00] => vec![vec![0x7e, 0x00]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        {
+            let session = uds.session(SessionType::Programming as u8).await.unwrap();
+            session.tester_present().await.unwrap();
+            assert_eq!(SESSION_RETURNED.load(Ordering::Relaxed), 0);
+        }
+        assert_eq!(SESSION_RETURNED.load(Ordering::Relaxed), 1);
+
+        let session = uds.session(SessionType::Programming as u8).await.unwrap();
+        session.close().await.unwrap();
+        assert_eq!(SESSION_RETURNED.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn read_memory_by_address() {
+        let transport = MockTransport::new(|request| match request {
+            [0x23, 0x22, 0x12, 0x34, 0x00, 0x04] => vec![vec![0x63, 0x01, 0x02, 0x03, 0x04]],
+            [0x23, 0x22, 0x12, 0x34, 0x00, 0x08] => vec![vec![0x63, 0x01, 0x02, 0x03, 0x04]],
+            [0x23, 0x22, 0x12, 0x34, 0x00, 0x02] => vec![vec![0x63, 0x01, 0x02, 0x03, 0x04]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        let resp = uds
+            .read_memory_by_address(&[0x12, 0x34], &[0x00, 0x04])
+            .await;
+        assert_eq!(resp, Ok(vec![0x01, 0x02, 0x03, 0x04]));
+
+        // Truncated response
+        let resp = uds
+            .read_memory_by_address(&[0x12, 0x34], &[0x00, 0x08])
+            .await;
+        assert_eq!(resp, Err(Error::InvalidResponseLength.into()));
+
+        // Response longer than requested
+        let resp = uds
+            .read_memory_by_address(&[0x12, 0x34], &[0x00, 0x02])
+            .await;
+        assert_eq!(resp, Err(Error::InvalidResponseLength.into()));
+    }
+
+    #[tokio::test]
+    async fn request_multi() {
+        let transport = MockTransport::new(|request| match request {
+            [0x19, 0x02, 0xff] => vec![
+                vec![0x59, 0x02, 0xff, 0x01],
+                RESPONSE_PENDING.to_vec(),
+                vec![0x59, 0x02, 0xff, 0x02],
+                vec![0x59, 0x02, 0xff, 0x03],
+            ],
+            [0x19, 0x0a] => vec![vec![0x59, 0x0a, 0xff], vec![0x7f, 0x19, 0x31]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        let resp = uds.request_multi(0x19, Some(0x02), Some(&[0xff]), 2).await;
+        assert_eq!(resp, Ok(vec![vec![0xff, 0x01], vec![0xff, 0x02]]));
+
+        // Less responses than requested
+        let resp = uds.request_multi(0x19, Some(0x02), Some(&[0xff]), 5).await;
+        assert_eq!(
+            resp,
+            Ok(vec![vec![0xff, 0x01], vec![0xff, 0x02], vec![0xff, 0x03]])
+        );
+
+        let resp = uds.request_multi(0x19, Some(0x0a), None, 2).await;
+        assert_eq!(
+            resp,
+            Err(Error::NegativeResponse {
+                service: 0x19,
+                code: NegativeResponseCode::RequestOutOfRange,
+                extra: vec![],
+            }
+            .into())
+        );
+
+        let resp = uds.request_multi(0x19, Some(0x01), None, 2).await;
+        assert_eq!(resp, Err(crate::Error::Timeout));
+    }
+
+    #[tokio::test]
+    async fn download() {
+        static BLOCKS: AtomicUsize = AtomicUsize::new(0);
+
+        // Maximum block length of 4, leaving 2 bytes of data per Transfer Data request
+        let transport = MockTransport::new(|req| match req {
+            [0x34, 0x00, 0x22, 0x10, 0x00, 0x02, 0x59] => vec![vec![0x74, 0x10, 0x04]],
+            // Each block contains the index of the block, which is one less than the counter
+            [0x36, counter, block @ ..]
+                if (1..=2).contains(&block.len())
+                    && block.iter().all(|&b| b == counter.wrapping_sub(1)) =>
+            {
+                BLOCKS.fetch_add(1, Ordering::Relaxed);
+                vec![vec![0x76, *counter]]
+            }
+            [0x37] => vec![vec![0x77]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        // 300 full blocks and a partial one, wrapping the block sequence counter
+        let data: Vec<u8> = (0..601).map(|i| (i / 2) as u8).collect();
+        uds.download(&[0x10, 0x00], &data, 0, 0).await.unwrap();
+        assert_eq!(BLOCKS.load(Ordering::Relaxed), 301);
+    }
+
+    #[tokio::test]
+    async fn download_negative_response() {
+        let transport = MockTransport::new(|req| match req {
+            [0x34, 0x00, 0x12, 0x10, 0x00, 0x0a] => vec![vec![0x74, 0x10, 0x04]],
+            [0x36, 0x03, ..] => vec![vec![0x7f, 0x36, 0x72]],
+            [0x36, counter, ..] => vec![vec![0x76, *counter]],
+            [0x37] => vec![vec![0x77]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        assert_eq!(
+            uds.download(&[0x10, 0x00], &[0; 10], 0, 0).await,
+            Err(Error::NegativeResponse {
+                service: 0x36,
+                code: NegativeResponseCode::GeneralProgrammingFailure,
+                extra: vec![],
+            }
+            .into())
+        );
+    }
+
+    #[tokio::test]
+    async fn upload() {
+        static BLOCKS: AtomicUsize = AtomicUsize::new(0);
+
+        // Every block contains 3 bytes with the value of the block sequence counter
+        let transport = MockTransport::new(|req| match req {
+            [0x35, 0x00, 0x22, 0x10, 0x00, 0x03, 0x84 | 0x83] => vec![vec![0x75, 0x10, 0x05]],
+            [0x36, counter] => {
+                BLOCKS.fetch_add(1, Ordering::Relaxed);
+                vec![vec![0x76, *counter, *counter, *counter, *counter]]
+            }
+            [0x37] => vec![vec![0x77]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        // 300 blocks, starting at 1 and wrapping the block sequence counter
+        let data = uds.upload(&[0x10, 0x00], 900, 0, 0).await.unwrap();
+        let expected: Vec<u8> = (0..900).map(|i| (i / 3 + 1) as u8).collect();
+        assert_eq!(data, expected);
+        assert_eq!(BLOCKS.load(Ordering::Relaxed), 300);
+
+        // Last block overshoots the requested size
+        assert_eq!(
+            uds.upload(&[0x10, 0x00], 899, 0, 0).await,
+            Err(Error::InvalidResponseLength.into())
+        );
+        assert_eq!(BLOCKS.load(Ordering::Relaxed), 600);
+    }
+
+    #[tokio::test]
+    async fn access_timing_parameter() {
+        let transport = MockTransport::new(|req| match req {
+            [0x83, 0x03] => vec![vec![0xc3, 0x03, 0x00, 0x32, 0x01, 0xf4]],
+            [0x83, 0x04, 0x00, 0x19, 0x00, 0xfa] => vec![vec![0xc3, 0x04]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        let record = uds
+            .access_timing_parameter(
+                AccessTimingParameterType::ReadCurrentlyActiveTimingParameters as u8,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(record, vec![0x00, 0x32, 0x01, 0xf4]);
+
+        let record = uds
+            .access_timing_parameter(
+                AccessTimingParameterType::SetTimingParametersToGivenValues as u8,
+                Some(&[0x00, 0x19, 0x00, 0xfa]),
+            )
+            .await
+            .unwrap();
+        assert!(record.is_empty());
+    }
+
+    #[tokio::test]
+    async fn dynamically_define_data_identifier() {
+        let transport = MockTransport::new(|req| match req {
+            [0x2c, 0x01, 0xf3, 0x00, 0xf1, 0x90, 0x01, 0x04, 0xf1, 0x8c, 0x02, 0x02] => {
+                vec![vec![0x6c, 0x01, 0xf3, 0x00]]
+            }
+            [0x2c, 0x03, 0xf3, 0x00] => vec![vec![0x6c, 0x03, 0xf3, 0x00]],
+            [0x2c, 0x03, 0xf3, 0x01] => vec![vec![0x6c, 0x03, 0xf3, 0x02]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        uds.dynamically_define_data_identifier_by_identifier(
+            0xf300,
+            &[(0xf190, 0x01, 0x04), (0xf18c, 0x02, 0x02)],
+        )
+        .await
+        .unwrap();
+        uds.clear_dynamically_defined_data_identifier(0xf300)
+            .await
+            .unwrap();
+
+        // Response for a different DID
+        assert_eq!(
+            uds.clear_dynamically_defined_data_identifier(0xf301).await,
+            Err(Error::InvalidDataIdentifier(0xf302).into())
+        );
+    }
+
+    #[tokio::test]
+    async fn input_output_control_by_identifier() {
+        let transport = MockTransport::new(|req| match req {
+            [0x2f, 0x49, 0x01, 0x03, 0x3a, 0xff] => vec![vec![0x6f, 0x49, 0x01, 0x03, 0x3a]],
+            [0x2f, 0x49, 0x01, 0x00] => vec![vec![0x6f, 0x49, 0x01, 0x00]],
+            [0x2f, 0x49, 0x02, 0x00] => vec![vec![0x6f, 0x49, 0x01, 0x00]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        let status = uds
+            .input_output_control_by_identifier(
+                0x4901,
+                InputOutputControlParameter::ShortTermAdjustment as u8,
+                &[0x3a],
+                &[0xff],
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, vec![0x03, 0x3a]);
+
+        let status = uds
+            .input_output_control_by_identifier(
+                0x4901,
+                InputOutputControlParameter::ReturnControlToECU as u8,
+                &[],
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, vec![0x00]);
+
+        // Response for a different DID
+        assert_eq!(
+            uds.input_output_control_by_identifier(0x4902, 0x00, &[], &[])
+                .await,
+            Err(Error::InvalidDataIdentifier(0x4901).into())
+        );
+    }
+
+    #[tokio::test]
+    async fn read_scaling_data_by_identifier() {
+        let transport = MockTransport::new(|req| match req {
+            [0x24, 0xf1, 0x90] => vec![vec![0x64, 0xf1, 0x90, 0x6f, 0x11]],
+            [0x24, 0xf1, 0x91] => vec![vec![0x64, 0xf1]],
+            [0x24, 0xf1, 0x92] => vec![vec![0x64, 0xf1, 0x90, 0x6f, 0x11]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        let scaling = uds.read_scaling_data_by_identifier(0xf190).await.unwrap();
+        assert_eq!(scaling, vec![0x6f, 0x11]);
+
+        assert_eq!(
+            uds.read_scaling_data_by_identifier(0xf191).await,
+            Err(Error::InvalidResponseLength.into())
+        );
+        assert_eq!(
+            uds.read_scaling_data_by_identifier(0xf192).await,
+            Err(Error::InvalidDataIdentifier(0xf190).into())
+        );
+    }
+
+    #[tokio::test]
+    async fn request_no_response() {
+        let transport = MockTransport::new(|req| match req {
+            [0x3e, 0x80] => vec![],
+            // Response pending followed by a negative response
+            [0x31, 0x81, 0xff, 0x00] => vec![vec![0x7f, 0x31, 0x78], vec![0x7f, 0x31, 0x31]],
+            // Negative response to another service
+            [0x10, 0x83] => vec![vec![0x7f, 0x3e, 0x12]],
+            // Anything else, e.g. without the suppressPosRspMsgIndicationBit, is rejected
+            _ => vec![vec![0x7f, req[0], 0x12]],
+        });
+        let uds = UDSClient::new(&transport);
+
+        uds.request_no_response(0x3e, 0x00, None).await.unwrap();
+        uds.request_no_response(0x10, 0x03, None).await.unwrap();
+        assert_eq!(
+            uds.request_no_response(0x31, 0x01, Some(&[0xff, 0x00]))
+                .await,
+            Err(Error::NegativeResponse {
+                service: 0x31,
+                code: NegativeResponseCode::RequestOutOfRange,
+                extra: vec![],
+            }
+            .into())
+        );
+    }
+
+    #[tokio::test]
+    async fn response_on_event() {
+        let transport = MockTransport::new(|req| match req {
+            // On change of DID 0xf190 within an infinite window, responding with Read Data By Identifier
+            [0x86, 0x03, 0x02, 0xf1, 0x90, 0x22, 0xf1, 0x90] => {
+                vec![vec![0xc6, 0x03, 0x00, 0x02, 0xf1, 0x90, 0x22, 0xf1, 0x90]]
+            }
+            [0x86, 0x05, 0x02] => vec![vec![0xc6, 0x05, 0x00, 0x02]],
+            [0x86, 0x00, 0x02] => vec![vec![0x7f, 0x86, 0x22]],
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        let resp = uds
+            .response_on_event(
+                ResponseOnEventType::OnChangeOfDataIdentifier as u8,
+                0x02,
+                &[0xf1, 0x90],
+                &[0x22, 0xf1, 0x90],
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp, vec![0x00, 0x02, 0xf1, 0x90, 0x22, 0xf1, 0x90]);
+
+        let resp = uds
+            .response_on_event(
+                ResponseOnEventType::StartResponseOnEvent as u8,
+                0x02,
+                &[],
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp, vec![0x00, 0x02]);
+
+        assert_eq!(
+            uds.response_on_event(
+                ResponseOnEventType::StopResponseOnEvent as u8,
+                0x02,
+                &[],
+                &[]
+            )
+            .await,
+            Err(Error::NegativeResponse {
+                service: 0x86,
+                code: NegativeResponseCode::ConditionsNotCorrect,
+                extra: vec![],
+            }
+            .into())
+        );
+    }
+
+    #[tokio::test]
+    async fn wait_until_responsive() {
+        static ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+
+        // The ECU only responds from the third Tester Present on
+        let transport = MockTransport::new(|req| match req {
+            [0x3e, 0x00] if ATTEMPTS.fetch_add(1, Ordering::Relaxed) >= 2 => {
+                vec![vec![0x7e, 0x00]]
+            }
+            _ => vec![],
+        });
+        let uds = UDSClient::new(&transport);
+
+        uds.wait_until_responsive(std::time::Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(ATTEMPTS.load(Ordering::Relaxed), 3);
+
+        // ECU never responds
+        let transport = MockTransport::new(|_| vec![]);
+        let uds = UDSClient::new(&transport);
+
+        let start = tokio::time::Instant::now();
+        assert_eq!(
+            uds.wait_until_responsive(std::time::Duration::from_millis(100))
+                .await,
+            Err(crate::Error::Timeout)
+        );
+        assert!(start.elapsed() >= std::time::Duration::from_millis(100));
+        assert!(start.elapsed() < std::time::Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn inter_request_delay() {
+        let transport = MockTransport::new(|req| match req {
+            [0x3e, 0x00] => vec![vec![0x7e, 0x00]],
+            _ => vec![],
+        });
+        let mut uds = UDSClient::new(&transport);
+        let delay = std::time::Duration::from_millis(50);
+        uds.set_inter_request_delay(delay);
+
+        // The first request is not delayed
+        let start = tokio::time::Instant::now();
+        uds.tester_present().await.unwrap();
+        assert!(start.elapsed() < delay);
+
+        // Both the response and the suppressed response requests wait for the delay
+        let start = tokio::time::Instant::now();
+        uds.tester_present().await.unwrap();
+        assert!(start.elapsed() >= delay);
+
+        let start = tokio::time::Instant::now();
+        uds.request_no_response(0x3e, 0x00, None).await.unwrap();
+        assert!(start.elapsed() >= delay);
+
+        // No delay once the delay has passed since the last request
+        tokio::time::sleep(delay).await;
+        let start = tokio::time::Instant::now();
+        uds.tester_present().await.unwrap();
+        assert!(start.elapsed() < delay);
+    }
 }
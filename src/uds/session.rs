@@ -0,0 +1,87 @@
+//! RAII guard for a diagnostic session.
+use super::{SessionType, UDSClient};
+use crate::transport::DiagnosticTransport;
+use crate::Result;
+
+use tokio::runtime::RuntimeFlavor;
+use tracing::{debug, warn};
+
+/// Maximum time to wait for the ECU to return to the default session when the guard is dropped
+const SESSION_RETURN_TIMEOUT_MS: u64 = 1000;
+
+/// Diagnostic session guard, created by [`UDSClient::session`]. Dereferences to the [`UDSClient`], so requests like unlocking security access can be made through the guard. Returns the ECU to the default session when dropped, even when unwinding from a panic. Use [`DiagnosticSession::close`] to return to the default session and handle the result.
+pub struct DiagnosticSession<'s, 'a, T: DiagnosticTransport + Sync> {
+    client: &'s UDSClient<'a, T>,
+    active: bool,
+}
+
+impl<'s, 'a, T: DiagnosticTransport + Sync> DiagnosticSession<'s, 'a, T> {
+    pub(super) async fn new(client: &'s UDSClient<'a, T>, session_type: u8) -> Result<Self> {
+        client.diagnostic_session_control(session_type).await?;
+        Ok(Self {
+            client,
+            active: true,
+        })
+    }
+
+    /// Return to the default session, and end the guard
+    pub async fn close(mut self) -> Result<()> {
+        self.active = false;
+        self.client
+            .diagnostic_session_control(SessionType::Default as u8)
+            .await?;
+        Ok(())
+    }
+}
+
+impl<'a, T: DiagnosticTransport + Sync> std::ops::Deref for DiagnosticSession<'_, 'a, T> {
+    type Target = UDSClient<'a, T>;
+
+    fn deref(&self) -> &Self::Target {
+        self.client
+    }
+}
+
+impl<T: DiagnosticTransport + Sync> Drop for DiagnosticSession<'_, '_, T> {
+    /// Drop can't be async, so this blocks the dropping task until the ECU responds or the timeout expires. On a multi thread runtime the request runs on the current runtime using [`tokio::task::block_in_place`], so transports driven by tasks on the runtime (e.g. DoIP) and a concurrent Tester Present keepalive keep running. A current thread runtime can't make progress while blocked, so the request is made from a separate thread with its own runtime instead. This only works for transports that don't depend on the blocked runtime (e.g. ISO-TP), use [`DiagnosticSession::close`] otherwise.
+    fn drop(&mut self) {
+        if !self.active {
+            return;
+        }
+
+        let client = self.client;
+        let return_to_default = async {
+            let timeout = std::time::Duration::from_millis(SESSION_RETURN_TIMEOUT_MS);
+            let result = tokio::time::timeout(
+                timeout,
+                client.diagnostic_session_control(SessionType::Default as u8),
+            )
+            .await;
+
+            match result {
+                Ok(Ok(_)) => debug!("Returned to default session"),
+                Ok(Err(e)) => warn!("Failed to return to default session: {}", e),
+                Err(_) => warn!("Failed to return to default session: Timeout"),
+            }
+        };
+
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(|| handle.block_on(return_to_default));
+            }
+            _ => {
+                std::thread::scope(|s| {
+                    s.spawn(|| {
+                        match tokio::runtime::Builder::new_current_thread()
+                            .enable_all()
+                            .build()
+                        {
+                            Ok(runtime) => runtime.block_on(return_to_default),
+                            Err(e) => warn!("Failed to return to default session: {}", e),
+                        }
+                    });
+                });
+            }
+        }
+    }
+}
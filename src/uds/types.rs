@@ -33,3 +33,89 @@ pub struct DTCReportNumberByStatusMask {
     pub dtc_format_identifier: DTCFormatIdentifier,
     pub dtc_count: u16,
 }
+
+/// DTC and its status, returned by ReadDTCInformation (0x19)
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DTCAndStatusRecord {
+    /// 3 byte Diagnostic Trouble Code
+    pub dtc: Dtc,
+    /// DTC status byte
+    pub status: u8,
+}
+
+/// Formats a 3 byte DTC in the SAE J2012 format, e.g. `P0420`. The first two bytes form the DTC and are formatted the same as an OBD-II DTC, see [`crate::obd::dtc_to_string`]. The last byte is the failure type byte, which is not part of the J2012 string and can be read using [`Dtc::failure_type`].
+pub fn to_j2012_string(dtc: u32) -> String {
+    crate::obd::dtc_to_string((dtc >> 8) as u16)
+}
+
+/// Diagnostic Trouble Code. Displayed in the SAE J2012 format, see [`to_j2012_string`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dtc(pub u32);
+
+impl Dtc {
+    /// Failure type byte (the last byte of the DTC), describing the kind of fault
+    pub fn failure_type(&self) -> u8 {
+        (self.0 & 0xff) as u8
+    }
+}
+
+impl std::fmt::Display for Dtc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", to_j2012_string(self.0))
+    }
+}
+
+impl From<u32> for Dtc {
+    fn from(dtc: u32) -> Self {
+        Self(dtc)
+    }
+}
+
+/// Struct returned by RequestDownload (0x34) and RequestUpload (0x35)
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransferBlockLength {
+    /// The maxNumberOfBlockLength as returned by the ECU. As defined in ISO 14229 this is the length of the complete TransferData request, so it includes the service identifier and the block sequence counter.
+    pub max_block_length: usize,
+    /// Number of data bytes that fit in a single TransferData request (`max_block_length - 2`).
+    pub usable_payload: usize,
+}
+
+impl From<usize> for TransferBlockLength {
+    fn from(max_block_length: usize) -> Self {
+        Self {
+            max_block_length,
+            usable_payload: max_block_length.saturating_sub(2),
+        }
+    }
+}
+
+/// Struct returned by RequestFileTransfer (0x38). Fields are `None` if they are not present for the requested mode of operation.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileTransferResponse {
+    /// Maximum length of each TransferData request, including the service identifier and block sequence counter. Not present for DeleteFile.
+    pub max_number_of_block_length: Option<usize>,
+    /// Compression and encryption method used by the ECU. Not present for DeleteFile.
+    pub data_format_identifier: Option<u8>,
+    /// Uncompressed size of the file for ReadFile, or length of the directory information for ReadDir.
+    pub file_size_uncompressed_or_dir_info_length: Option<u64>,
+    /// Compressed size of the file. Only present for ReadFile.
+    pub file_size_compressed: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn j2012_string() {
+        assert_eq!(to_j2012_string(0x042000), "P0420");
+        assert_eq!(to_j2012_string(0x4a1b1f), "C0A1B");
+        assert_eq!(to_j2012_string(0x9234ff), "B1234");
+        assert_eq!(Dtc(0xc10000).to_string(), "U0100");
+        assert_eq!(Dtc(0x4a1b1f).failure_type(), 0x1f);
+    }
+}
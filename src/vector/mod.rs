@@ -8,13 +8,14 @@ pub use error::Error;
 
 use std::collections::VecDeque;
 
-use crate::can::{AsyncCanAdapter, CanAdapter, Frame};
-use crate::vector::types::{PortHandle, XLaccess, XLcanFdConf, XLcanTxEvent};
+use crate::can::{AsyncCanAdapter, CanAdapter, Frame, DLC_TO_LEN};
+use crate::vector::types::{PortHandle, VectorChannelInfo, XLaccess, XLcanFdConf, XLcanTxEvent};
 use crate::vector::vxlapi::*;
 use crate::Result;
-use tracing::info;
+use tracing::{info, warn};
 
-const CONFIG_500K_2M_80: XLcanFdConf = XLcanFdConf {
+/// CAN-FD configuration with 500 kbit/s arbitration and 2 Mbit/s data bitrate, at 80% sample point
+pub const CONFIG_500K_2M_80: XLcanFdConf = XLcanFdConf {
     arbitrationBitRate: 500_000,
     sjwAbr: 1,
     tseg1Abr: 15,
@@ -29,10 +30,13 @@ const CONFIG_500K_2M_80: XLcanFdConf = XLcanFdConf {
     reserved2: 0,
 };
 
-#[derive(Clone)]
+/// Vector CAN Adapter. Not `Clone`, as dropping the adapter closes the port and the driver.
 pub struct VectorCan {
     port_handle: PortHandle,
+    /// Combined mask of all opened channels
     channel_mask: XLaccess,
+    /// Global channel index and channel mask of each bus, used to map [`Frame::bus`] to the channel
+    channels: Vec<(u32, XLaccess)>,
 }
 
 impl VectorCan {
@@ -42,30 +46,61 @@ impl VectorCan {
         Ok(AsyncCanAdapter::new(vector))
     }
 
-    /// Create a new Vector Adapter based on the global channel ID
+    /// List all CAN channels available in the XL driver. The returned `channel_idx` can be used to open the channel with [`VectorCan::new`].
+    pub fn list_channels() -> Result<Vec<VectorChannelInfo>> {
+        xl_open_driver()?;
+        let channels = xl_get_channels();
+        xl_close_driver()?;
+        channels
+    }
+
+    /// Create a new Vector Adapter based on the global channel ID, using 500 kbit/s arbitration and 2 Mbit/s data bitrate
     pub fn new(channel_idx: usize) -> Result<VectorCan> {
+        Self::new_with_config(channel_idx, &CONFIG_500K_2M_80)
+    }
+
+    /// Create a new Vector Adapter based on the global channel ID, and configure the bitrate and bit timing of the channel
+    pub fn new_with_config(channel_idx: usize, fd_config: &XLcanFdConf) -> Result<VectorCan> {
+        Self::new_multi(&[channel_idx], fd_config)
+    }
+
+    /// Open multiple channels in a single port, using the same bitrate configuration for all channels. Frames on the first channel use bus 0, the second channel bus 1, etc.
+    pub fn new_multi(channel_idxs: &[usize], fd_config: &XLcanFdConf) -> Result<VectorCan> {
+        if channel_idxs.is_empty() {
+            return Err(crate::Error::NotFound);
+        }
+
         xl_open_driver()?;
 
-        // Get config based on global channel number
-        let config = xl_get_driver_config(channel_idx)?;
-        info!("Got Application Config: {:?}", config);
+        let mut channels = vec![];
+        for &channel_idx in channel_idxs {
+            // Get config based on global channel number
+            let config = xl_get_driver_config(channel_idx)?;
+            info!("Got Application Config: {:?}", config);
 
-        // TODO: This produces weird errors
-        // Get config based on predfined config.
-        // let config = xl_get_application_config("CANalyzer", 0)?;
+            // TODO: This produces weird errors
+            // Get config based on predfined config.
+            // let config = xl_get_application_config("CANalyzer", 0)?;
 
-        let channel_mask = xl_get_channel_mask(&config)?;
+            channels.push((
+                xl_get_channel_index(&config)?,
+                xl_get_channel_mask(&config)?,
+            ));
+        }
+
+        let channel_mask = channels.iter().fold(0, |mask, (_, m)| mask | m);
         let port_handle = xl_open_port("automotive", channel_mask)?;
 
         // Configure bitrate
-        xl_can_fd_set_configuration(&port_handle, channel_mask, &CONFIG_500K_2M_80)?;
+        xl_can_fd_set_configuration(&port_handle, channel_mask, fd_config)?;
 
         xl_activate_channel(&port_handle, channel_mask)?;
-        info!("Connected to Vector Device. HW: {:?}", config.hw_type);
+        info!("Connected to Vector Device. Channels: {:?}", channel_idxs);
 
         Ok(VectorCan {
             port_handle,
             channel_mask,
+            channels,
         })
     }
 }
@@ -83,10 +118,12 @@ impl CanAdapter for VectorCan {
     fn send(&mut self, frames: &mut VecDeque<Frame>) -> Result<()> {
         // TODO: can we send frames in bulk? If we fill up the TX queue can we figure out which messages were actually sent out?
         while let Some(frame) = frames.pop_front() {
+            let (_, channel_mask) = self.channels[frame.bus as usize];
+
             let xl_frame: XLcanTxEvent = frame.clone().into();
             let xl_frames = vec![xl_frame];
 
-            if let Ok(tx) = xl_can_transmit_ex(&self.port_handle, self.channel_mask, &xl_frames) {
+            if let Ok(tx) = xl_can_transmit_ex(&self.port_handle, channel_mask, &xl_frames) {
                 assert_eq!(tx, 1);
             } else {
                 // TODO: figure out what error happened, and decide if we can retry later or need to shut down
@@ -98,12 +135,35 @@ impl CanAdapter for VectorCan {
         Ok(())
     }
 
+    fn check_frame(&self, frame: &Frame) -> Result<()> {
+        if frame.bus as usize >= self.channels.len() {
+            return Err(crate::Error::NotSupported);
+        }
+
+        // Data length needs to map to a valid DLC
+        let valid_len =
+            frame.data.len() <= 8 || (frame.fd && DLC_TO_LEN.contains(&frame.data.len()));
+        if !valid_len || (frame.fd && frame.rtr) {
+            return Err(crate::Error::MalformedFrame);
+        }
+
+        Ok(())
+    }
+
     fn recv(&mut self) -> Result<Vec<Frame>> {
         let mut frames = vec![];
 
         while let Some(frame) = xl_can_receive(&self.port_handle)? {
-            if let Ok(frame) = frame.try_into() {
-                frames.push(frame);
+            if let Ok(mut frame) = Frame::try_from(frame) {
+                // Map global channel index to bus
+                let channel_index = frame.bus as u32;
+                match self.channels.iter().position(|&(c, _)| c == channel_index) {
+                    Some(bus) => {
+                        frame.bus = bus as u8;
+                        frames.push(frame);
+                    }
+                    None => warn!("Received frame on unknown channel {}", channel_index),
+                }
             }
         }
 
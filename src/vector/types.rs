@@ -96,6 +96,25 @@ pub struct ChannelConfig {
     pub hw_channel: u32,
 }
 
+/// Information about a channel reported by the XL driver, returned by [`crate::vector::VectorCan::list_channels`]
+#[derive(Debug, Clone)]
+pub struct VectorChannelInfo {
+    /// Global channel index, to be passed to [`crate::vector::VectorCan::new`]
+    pub channel_idx: usize,
+    /// Channel name as reported by the driver (e.g. "VN1630A Channel 1")
+    pub name: String,
+    /// Hardware type, `None` if the hardware type is not known to this library
+    pub hw_type: Option<HwType>,
+    /// Index of the hardware (of the same type)
+    pub hw_index: u32,
+    /// Index of the channel on the hardware
+    pub hw_channel: u32,
+    /// Serial number of the device
+    pub serial_number: u32,
+    /// Channel supports ISO CAN-FD
+    pub fd: bool,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct PortHandle {
     pub port_handle: XLportHandle,
@@ -108,13 +127,19 @@ impl From<crate::can::Frame> for XLcanTxEvent {
             crate::can::Identifier::Standard(id) => id,
             crate::can::Identifier::Extended(id) => id | xl::XL_CAN_EXT_MSG_ID,
         };
-        let flags = match frame.fd {
+        let mut flags = match frame.fd {
             true => xl::XL_CAN_TXMSG_FLAG_EDL,
             false => 0,
         };
+        if frame.brs {
+            flags |= xl::XL_CAN_TXMSG_FLAG_BRS;
+        }
+        if frame.rtr {
+            flags |= xl::XL_CAN_TXMSG_FLAG_RTR;
+        }
 
         // TODO: move calculation to can::Frame?
-        let dlc = LEN_TO_DLC[frame.data.len()];
+        let dlc = frame.dlc.unwrap_or(LEN_TO_DLC[frame.data.len()]);
 
         // Copy data into array
         let mut data = [0; xl::XL_CAN_MAX_DATA_LEN as usize];
@@ -153,15 +178,28 @@ impl TryFrom<XLcanRxEvent> for crate::can::Frame {
                     false => crate::can::Identifier::Standard(frame.canId & 0x7ff),
                     true => crate::can::Identifier::Extended(frame.canId & 0x1fffffff),
                 };
-                let len = DLC_TO_LEN[frame.dlc as usize];
                 let fd = frame.msgFlags & xl::XL_CAN_RXMSG_FLAG_EDL != 0;
+                let brs = frame.msgFlags & xl::XL_CAN_RXMSG_FLAG_BRS != 0;
+                let rtr = frame.msgFlags & xl::XL_CAN_RXMSG_FLAG_RTR != 0;
+
+                // Remote frames don't carry data, the DLC only indicates the requested length. Classic frames with a DLC of 9-15 carry 8 bytes.
+                let (len, dlc) = match (rtr, fd) {
+                    (true, _) => (0, (frame.dlc != 0).then_some(frame.dlc)),
+                    (false, false) if frame.dlc > 8 => (8, Some(frame.dlc)),
+                    (false, _) => (DLC_TO_LEN[frame.dlc as usize], None),
+                };
 
                 Ok(Self {
-                    bus: event.channelIndex as u8, // TODO: perform proper mapping based on xlGetChannelIndex,
+                    bus: event.channelIndex as u8, // Global channel index, mapped to the bus by VectorCan
                     id,
                     data: frame.data[..len].into(),
                     loopback,
                     fd,
+                    brs,
+                    // Timestamp in ns, the clock is reset when the channel is activated
+                    timestamp: Some(std::time::Duration::from_nanos(event.timeStampSync)),
+                    rtr,
+                    dlc,
                 })
             }
             RxTags::XL_CAN_EV_TAG_CHIP_STATE | RxTags::XL_CAN_EV_TAG_TX_ERROR => {
@@ -1,7 +1,8 @@
 use crate::vector::bindings as xl;
 use crate::vector::error::Error;
 use crate::vector::types::{
-    ChannelConfig, HwType, PortHandle, XLaccess, XLcanFdConf, XLcanRxEvent, XLcanTxEvent,
+    ChannelConfig, HwType, PortHandle, VectorChannelInfo, XLaccess, XLcanFdConf, XLcanRxEvent,
+    XLcanTxEvent,
 };
 use crate::Result;
 
@@ -53,6 +54,43 @@ pub fn xl_get_driver_config(channel_idx: usize) -> Result<ChannelConfig> {
     }
 }
 
+/// Returns all CAN capable channels known to the driver
+pub fn xl_get_channels() -> Result<Vec<VectorChannelInfo>> {
+    unsafe {
+        let mut config: xl::XLdriverConfig = std::mem::zeroed();
+        let status = xl::xlGetDriverConfig(&mut config);
+
+        match status as u32 {
+            xl::XL_SUCCESS => {
+                let channel_count: usize = config.channelCount as usize;
+
+                Ok(config.channel[..channel_count]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, channel)| {
+                        channel.channelBusCapabilities & (xl::XL_BUS_TYPE_CAN << 16) != 0
+                    })
+                    .map(|(channel_idx, channel)| VectorChannelInfo {
+                        channel_idx,
+                        name: std::ffi::CStr::from_ptr(channel.name.as_ptr())
+                            .to_string_lossy()
+                            .into_owned(),
+                        hw_type: HwType::from_repr(channel.hwType as u32),
+                        hw_index: channel.hwIndex as u32,
+                        hw_channel: channel.hwChannel as u32,
+                        serial_number: channel.serialNumber,
+                        fd: channel.channelCapabilities & xl::XL_CHANNEL_FLAG_CANFD_ISO_SUPPORT
+                            != 0,
+                    })
+                    .collect())
+            }
+            _ => {
+                Err(Error::DriverError(format!("xlGetDriverConfig failed, err {}", status)).into())
+            }
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub fn xl_get_application_config(app_name: &str, app_channel: u32) -> Result<ChannelConfig> {
     unsafe {
@@ -79,7 +117,6 @@ pub fn xl_get_application_config(app_name: &str, app_channel: u32) -> Result<Cha
     }
 }
 
-#[allow(dead_code)]
 pub fn xl_get_channel_index(app_config: &ChannelConfig) -> Result<u32> {
     unsafe {
         Ok(xl::xlGetChannelIndex(
@@ -137,8 +174,12 @@ pub fn xl_close_port(port_handle: &PortHandle) -> Result<()> {
 
 pub fn xl_activate_channel(port_handle: &PortHandle, access_mask: XLaccess) -> Result<()> {
     unsafe {
-        let status =
-            xl::xlActivateChannel(port_handle.port_handle, access_mask, xl::XL_BUS_TYPE_CAN, 0);
+        let status = xl::xlActivateChannel(
+            port_handle.port_handle,
+            access_mask,
+            xl::XL_BUS_TYPE_CAN,
+            xl::XL_ACTIVATE_RESET_CLOCK,
+        );
         match status as u32 {
             xl::XL_SUCCESS => Ok(()),
             _ => {
@@ -0,0 +1,50 @@
+//! Constants for the XCP Client.
+use strum_macros::EnumIter;
+
+/// Packet identifier of a positive command response
+pub static RESPONSE: u8 = 0xff;
+/// Packet identifier of an error response
+pub static ERROR: u8 = 0xfe;
+/// Packet identifier of an event packet
+pub static EVENT: u8 = 0xfd;
+/// Packet identifier of a service request packet
+pub static SERVICE_REQUEST: u8 = 0xfc;
+
+/// Bit in the COMM_MODE_BASIC byte of the CONNECT response, set if the slave uses Motorola (big endian) byte order
+pub static COMM_MODE_BYTE_ORDER_MOTOROLA: u8 = 0x01;
+
+/// Standard command codes as defined in ASAM MCD-1 XCP
+#[derive(Debug, PartialEq, Copy, Clone, EnumIter)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum Command {
+    Connect = 0xff,
+    Disconnect = 0xfe,
+    GetStatus = 0xfd,
+    Synch = 0xfc,
+    GetCommModeInfo = 0xfb,
+    GetId = 0xfa,
+    SetRequest = 0xf9,
+    GetSeed = 0xf8,
+    Unlock = 0xf7,
+    SetMta = 0xf6,
+    Upload = 0xf5,
+    ShortUpload = 0xf4,
+    BuildChecksum = 0xf3,
+    TransportLayerCmd = 0xf2,
+    UserCmd = 0xf1,
+    Download = 0xf0,
+    DownloadNext = 0xef,
+    DownloadMax = 0xee,
+    ShortDownload = 0xed,
+    ModifyBits = 0xec,
+}
+
+/// Connect mode used by the CONNECT command
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum ConnectMode {
+    Normal = 0x00,
+    UserDefined = 0x01,
+}
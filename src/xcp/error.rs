@@ -0,0 +1,76 @@
+//! Error types for the XCP Client.
+use thiserror::Error;
+
+/// Error codes returned by the slave in an error packet as defined in ASAM MCD-1 XCP
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ErrorCode {
+    CommandSynch,
+    CommandBusy,
+    DaqActive,
+    PgmActive,
+    CommandUnknown,
+    CommandSyntax,
+    OutOfRange,
+    WriteProtected,
+    AccessDenied,
+    AccessLocked,
+    PageNotValid,
+    ModeNotValid,
+    SegmentNotValid,
+    Sequence,
+    DaqConfig,
+    MemoryOverflow,
+    Generic,
+    Verify,
+    ResourceTemporaryNotAccessible,
+    SubCommandUnknown,
+    NonStandard(u8),
+}
+
+impl From<u8> for ErrorCode {
+    fn from(val: u8) -> ErrorCode {
+        match val {
+            0x00 => ErrorCode::CommandSynch,
+            0x10 => ErrorCode::CommandBusy,
+            0x11 => ErrorCode::DaqActive,
+            0x12 => ErrorCode::PgmActive,
+            0x20 => ErrorCode::CommandUnknown,
+            0x21 => ErrorCode::CommandSyntax,
+            0x22 => ErrorCode::OutOfRange,
+            0x23 => ErrorCode::WriteProtected,
+            0x24 => ErrorCode::AccessDenied,
+            0x25 => ErrorCode::AccessLocked,
+            0x26 => ErrorCode::PageNotValid,
+            0x27 => ErrorCode::ModeNotValid,
+            0x28 => ErrorCode::SegmentNotValid,
+            0x29 => ErrorCode::Sequence,
+            0x2a => ErrorCode::DaqConfig,
+            0x30 => ErrorCode::MemoryOverflow,
+            0x31 => ErrorCode::Generic,
+            0x32 => ErrorCode::Verify,
+            0x33 => ErrorCode::ResourceTemporaryNotAccessible,
+            0x34 => ErrorCode::SubCommandUnknown,
+            _ => ErrorCode::NonStandard(val),
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum Error {
+    #[error("Invalid Packet ID: {0}")]
+    InvalidPacketId(u8),
+    #[error("Invalid Response Length")]
+    InvalidResponseLength,
+    #[error("Data Too Large")]
+    DataTooLarge,
+    #[error("Invalid MAX_CTO: {0}")]
+    InvalidMaxCto(u8),
+    #[error("Error Response to command 0x{command:02x}: {code:?}")]
+    ErrorResponse {
+        /// Command code of the rejected command
+        command: u8,
+        /// Error code
+        code: ErrorCode,
+    },
+}
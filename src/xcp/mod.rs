@@ -0,0 +1,385 @@
+//! Universal Measurement and Calibration Protocol (XCP) Client, implements the master side of ASAM MCD-1 XCP on CAN
+//! ## Example
+//! ```rust
+//! async fn xcp_example() {
+//!     use automotive::can::Identifier;
+//!
+//!     let adapter = automotive::can::get_adapter().unwrap();
+//!     let config = automotive::xcp::XcpConfig::new(0, Identifier::Standard(0x554), Identifier::Standard(0x555));
+//!     let xcp = automotive::xcp::XcpClient::new(&adapter, config);
+//!
+//!     xcp.connect(automotive::xcp::ConnectMode::Normal).await.unwrap();
+//!     let data = xcp.short_upload(0, 0x4000_0000, 4).await.unwrap();
+//!
+//!     println!("Data: {}", hex::encode(data));
+//! }
+//! ```
+
+mod constants;
+mod error;
+
+use crate::can::{AsyncCanAdapter, Frame, Identifier};
+use crate::Result;
+use crate::StreamExt;
+pub use constants::*;
+pub use error::{Error, ErrorCode};
+
+use std::sync::Mutex;
+use tracing::debug;
+
+const DEFAULT_TIMEOUT_MS: u64 = 100;
+const DEFAULT_PADDING_BYTE: u8 = 0x00;
+
+/// Max length of a command or response packet (MAX_CTO) on classic CAN
+const CAN_MAX_CTO: usize = 8;
+
+/// Configuration passed to the XCP Client.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct XcpConfig {
+    pub bus: u8,
+    /// Command Receive Object ID, used for commands sent to the slave
+    pub cro_id: Identifier,
+    /// Data Transmission Object ID, used for responses from the slave
+    pub dto_id: Identifier,
+    /// Pad command packets to 8 bytes using this byte. Set to None to send packets with a variable length.
+    pub padding: Option<u8>,
+    /// Max time to wait for a response (T1)
+    pub timeout: std::time::Duration,
+}
+
+impl XcpConfig {
+    pub fn new(bus: u8, cro_id: Identifier, dto_id: Identifier) -> Self {
+        Self {
+            bus,
+            cro_id,
+            dto_id,
+            padding: Some(DEFAULT_PADDING_BYTE),
+            timeout: std::time::Duration::from_millis(DEFAULT_TIMEOUT_MS),
+        }
+    }
+}
+
+/// Response to the CONNECT command, describing the capabilities of the slave
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectResponse {
+    /// Resources (CAL/PAG, DAQ, STIM, PGM) available in the slave
+    pub resource: u8,
+    /// Byte order, address granularity and block mode support
+    pub comm_mode_basic: u8,
+    /// Max length of a command or response packet
+    pub max_cto: u8,
+    /// Max length of a data acquisition packet
+    pub max_dto: u16,
+    pub protocol_layer_version: u8,
+    pub transport_layer_version: u8,
+}
+
+impl ConnectResponse {
+    /// Whether the slave uses Motorola (big endian) byte order for multi byte values
+    pub fn is_motorola(&self) -> bool {
+        self.comm_mode_basic & COMM_MODE_BYTE_ORDER_MOTOROLA != 0
+    }
+}
+
+/// Response to the GET_STATUS command
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Status {
+    /// Current session state, e.g. whether DAQ is running
+    pub session_status: u8,
+    /// Resources that are protected using GET_SEED and UNLOCK
+    pub resource_protection_status: u8,
+    pub session_configuration_id: u16,
+}
+
+/// XCP Client. Sends commands on the CRO ID, and receives the responses on the DTO ID using a [`AsyncCanAdapter`]. Call [`XcpClient::connect`] first, the packet size and byte order reported by the slave are used for the other commands.
+pub struct XcpClient<'a> {
+    adapter: &'a AsyncCanAdapter,
+    config: XcpConfig,
+    connection: Mutex<Option<ConnectResponse>>,
+}
+
+impl<'a> XcpClient<'a> {
+    pub fn new(adapter: &'a AsyncCanAdapter, config: XcpConfig) -> Self {
+        Self {
+            adapter,
+            config,
+            connection: Mutex::new(None),
+        }
+    }
+
+    /// Max length of a command packet, as reported by the slave on connect
+    fn max_cto(&self) -> usize {
+        match *self.connection.lock().unwrap() {
+            Some(connection) => (connection.max_cto as usize).min(CAN_MAX_CTO),
+            None => CAN_MAX_CTO,
+        }
+    }
+
+    fn encode_u32(&self, val: u32) -> [u8; 4] {
+        match *self.connection.lock().unwrap() {
+            Some(connection) if connection.is_motorola() => val.to_be_bytes(),
+            _ => val.to_le_bytes(),
+        }
+    }
+
+    fn decode_u16(&self, val: [u8; 2]) -> u16 {
+        match *self.connection.lock().unwrap() {
+            Some(connection) if connection.is_motorola() => u16::from_be_bytes(val),
+            _ => u16::from_le_bytes(val),
+        }
+    }
+
+    /// Helper function to send custom commands. This function will wait for the response packet, skipping event and service request packets. Error packets are returned as error. Returns the response data following the packet identifier, including any padding.
+    pub async fn command(&self, command: u8, data: &[u8]) -> Result<Vec<u8>> {
+        let mut packet = vec![command];
+        packet.extend(data);
+        if packet.len() > self.max_cto() {
+            return Err(Error::DataTooLarge.into());
+        }
+        if let Some(padding) = self.config.padding {
+            packet.resize(CAN_MAX_CTO, padding);
+        }
+
+        let frame = Frame::new(self.config.bus, self.config.cro_id, &packet)?;
+
+        let (bus, dto_id) = (self.config.bus, self.config.dto_id);
+        let stream = self
+            .adapter
+            .recv_filter(move |frame| frame.bus == bus && frame.id == dto_id && !frame.loopback)
+            .timeout(self.config.timeout);
+        tokio::pin!(stream);
+
+        self.adapter.send(&frame).await?;
+
+        loop {
+            let response = stream.next().await.ok_or(crate::Error::Disconnected)??;
+            match response.data.first() {
+                Some(&pid) if pid == RESPONSE => return Ok(response.data[1..].to_vec()),
+                Some(&pid) if pid == ERROR => {
+                    let code = *response.data.get(1).ok_or(Error::InvalidResponseLength)?;
+                    return Err(Error::ErrorResponse {
+                        command,
+                        code: code.into(),
+                    }
+                    .into());
+                }
+                Some(&pid) if pid == EVENT || pid == SERVICE_REQUEST => {
+                    debug!("Ignoring XCP packet {}", hex::encode(&response.data));
+                }
+                Some(&pid) => return Err(Error::InvalidPacketId(pid).into()),
+                None => return Err(Error::InvalidResponseLength.into()),
+            }
+        }
+    }
+
+    /// Helper function for commands that return data, verifies the response contains at least `len` bytes.
+    async fn command_with_response(
+        &self,
+        command: Command,
+        data: &[u8],
+        len: usize,
+    ) -> Result<Vec<u8>> {
+        let mut response = self.command(command as u8, data).await?;
+        if response.len() < len {
+            return Err(Error::InvalidResponseLength.into());
+        }
+        response.truncate(len);
+        Ok(response)
+    }
+
+    /// 0xFF - Connect. Sets up the session with the slave, and returns the capabilities of the slave. Returns [`Error::InvalidMaxCto`] if the slave reports a MAX_CTO below 8, the minimum for XCP on CAN.
+    pub async fn connect(&self, mode: ConnectMode) -> Result<ConnectResponse> {
+        let resp = self
+            .command_with_response(Command::Connect, &[mode as u8], 7)
+            .await?;
+
+        if (resp[2] as usize) < CAN_MAX_CTO {
+            return Err(Error::InvalidMaxCto(resp[2]).into());
+        }
+
+        let max_dto = [resp[3], resp[4]];
+        let mut connection = ConnectResponse {
+            resource: resp[0],
+            comm_mode_basic: resp[1],
+            max_cto: resp[2],
+            max_dto: 0,
+            protocol_layer_version: resp[5],
+            transport_layer_version: resp[6],
+        };
+        connection.max_dto = match connection.is_motorola() {
+            true => u16::from_be_bytes(max_dto),
+            false => u16::from_le_bytes(max_dto),
+        };
+
+        *self.connection.lock().unwrap() = Some(connection);
+        Ok(connection)
+    }
+
+    /// 0xFE - Disconnect. Ends the session with the slave.
+    pub async fn disconnect(&self) -> Result<()> {
+        self.command(Command::Disconnect as u8, &[]).await?;
+        *self.connection.lock().unwrap() = None;
+        Ok(())
+    }
+
+    /// 0xFD - Get Status. Returns the current session status.
+    pub async fn get_status(&self) -> Result<Status> {
+        let resp = self
+            .command_with_response(Command::GetStatus, &[], 5)
+            .await?;
+
+        Ok(Status {
+            session_status: resp[0],
+            resource_protection_status: resp[1],
+            session_configuration_id: self.decode_u16([resp[3], resp[4]]),
+        })
+    }
+
+    /// 0xF6 - Set Memory Transfer Address. Sets the address used by [`XcpClient::upload`] and [`XcpClient::download`].
+    pub async fn set_mta(&self, address_extension: u8, address: u32) -> Result<()> {
+        let mut data = vec![0x00, 0x00, address_extension];
+        data.extend(self.encode_u32(address));
+
+        self.command(Command::SetMta as u8, &data).await?;
+        Ok(())
+    }
+
+    /// 0xF5 - Upload. Reads `len` bytes starting at the memory transfer address. Larger reads are split into multiple commands, the slave increments the memory transfer address after each command.
+    pub async fn upload(&self, len: usize) -> Result<Vec<u8>> {
+        let mut data = vec![];
+        while data.len() < len {
+            let n = (len - data.len()).min(self.max_cto() - 1);
+            let resp = self
+                .command_with_response(Command::Upload, &[n as u8], n)
+                .await?;
+            data.extend(resp);
+        }
+        Ok(data)
+    }
+
+    /// 0xF4 - Short Upload. Reads up to MAX_CTO - 1 bytes from the given address.
+    pub async fn short_upload(
+        &self,
+        address_extension: u8,
+        address: u32,
+        len: u8,
+    ) -> Result<Vec<u8>> {
+        if len as usize > self.max_cto() - 1 {
+            return Err(Error::DataTooLarge.into());
+        }
+
+        let mut data = vec![len, 0x00, address_extension];
+        data.extend(self.encode_u32(address));
+
+        self.command_with_response(Command::ShortUpload, &data, len as usize)
+            .await
+    }
+
+    /// 0xF0 - Download. Writes the data starting at the memory transfer address. Larger writes are split into multiple commands, the slave increments the memory transfer address after each command.
+    pub async fn download(&self, data: &[u8]) -> Result<()> {
+        for chunk in data.chunks(self.max_cto() - 2) {
+            let mut packet = vec![chunk.len() as u8];
+            packet.extend(chunk);
+            self.command(Command::Download as u8, &packet).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::can::virtual_can::VirtualCanAdapter;
+
+    const CRO_ID: Identifier = Identifier::Standard(0x554);
+    const DTO_ID: Identifier = Identifier::Standard(0x555);
+
+    /// Simulates a slave, answering every command on the CRO ID with the packet returned by `respond`
+    fn slave(adapter: AsyncCanAdapter, respond: fn(&[u8]) -> Vec<u8>) {
+        // Subscribe before returning, so the first command is not missed
+        let mut stream = adapter.recv_filter(|frame| frame.id == CRO_ID && !frame.loopback);
+        tokio::spawn(async move {
+            while let Some(frame) = stream.next().await {
+                let response = Frame::new(0, DTO_ID, &respond(&frame.data)).unwrap();
+                adapter.send(&response).await.unwrap();
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn connect() {
+        let (master, ecu) = VirtualCanAdapter::pair_async();
+        slave(ecu, |command| match command[0] {
+            0xff => vec![0xff, 0x15, 0x01, 0x08, 0x00, 0x08, 0x01, 0x01],
+            0xfd => vec![0xff, 0x00, 0x15, 0x00, 0x12, 0x34],
+            _ => vec![0xfe, 0x20],
+        });
+
+        let xcp = XcpClient::new(&master, XcpConfig::new(0, CRO_ID, DTO_ID));
+        let connection = xcp.connect(ConnectMode::Normal).await.unwrap();
+        assert!(connection.is_motorola());
+        assert_eq!(connection.max_cto, 8);
+        assert_eq!(connection.max_dto, 8);
+
+        let status = xcp.get_status().await.unwrap();
+        assert_eq!(status.resource_protection_status, 0x15);
+        assert_eq!(status.session_configuration_id, 0x1234);
+
+        let e = xcp.command(0xf1, &[]).await;
+        assert_eq!(
+            e,
+            Err(Error::ErrorResponse {
+                command: 0xf1,
+                code: ErrorCode::CommandUnknown
+            }
+            .into())
+        );
+    }
+
+    #[tokio::test]
+    async fn connect_invalid_max_cto() {
+        let (master, ecu) = VirtualCanAdapter::pair_async();
+        slave(ecu, |_| {
+            vec![0xff, 0x15, 0x01, 0x01, 0x00, 0x08, 0x01, 0x01]
+        });
+
+        let xcp = XcpClient::new(&master, XcpConfig::new(0, CRO_ID, DTO_ID));
+        let e = xcp.connect(ConnectMode::Normal).await;
+        assert_eq!(e, Err(Error::InvalidMaxCto(1).into()));
+    }
+
+    #[tokio::test]
+    async fn upload_download() {
+        let (master, ecu) = VirtualCanAdapter::pair_async();
+        slave(ecu, |command| match command {
+            // Intel byte order
+            [0xff, ..] => vec![0xff, 0x15, 0x00, 0x08, 0x08, 0x00, 0x01, 0x01],
+            [0xf6, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x20] => vec![0xff],
+            [0xf5, n, ..] => (0..=*n).map(|i| if i == 0 { 0xff } else { i }).collect(),
+            [0xf4, 0x02, 0x00, 0x01, 0x00, 0x10, 0x00, 0x20] => vec![0xff, 0xaa, 0xbb],
+            [0xf0, n, ..] if *n <= 6 => vec![0xff],
+            _ => vec![0xfe, 0x21],
+        });
+
+        let xcp = XcpClient::new(&master, XcpConfig::new(0, CRO_ID, DTO_ID));
+        xcp.connect(ConnectMode::Normal).await.unwrap();
+
+        xcp.set_mta(0, 0x2000_1000).await.unwrap();
+
+        // Split into uploads of 7 and 3 bytes
+        let data = xcp.upload(10).await.unwrap();
+        assert_eq!(data, vec![1, 2, 3, 4, 5, 6, 7, 1, 2, 3]);
+
+        let data = xcp.short_upload(1, 0x2000_1000, 2).await.unwrap();
+        assert_eq!(data, vec![0xaa, 0xbb]);
+        assert_eq!(
+            xcp.short_upload(1, 0x2000_1000, 8).await,
+            Err(Error::DataTooLarge.into())
+        );
+
+        xcp.download(&[0x55; 20]).await.unwrap();
+    }
+}
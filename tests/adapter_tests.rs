@@ -67,6 +67,7 @@ fn bulk_send_sync<T: CanAdapter>(adapter: &mut T) {
         for frame in rx {
             let mut copy = frame.clone();
             copy.loopback = false;
+            copy.timestamp = None;
             received.push(copy);
         }
         std::thread::sleep(Duration::from_millis(1));
@@ -82,12 +83,29 @@ async fn bulk_send(adapter: &AsyncCanAdapter) {
     let frames = get_test_frames(BULK_NUM_FRAMES_ASYNC);
 
     let r = frames.iter().map(|frame| adapter.send(frame));
-    tokio::time::timeout(
+    let results = tokio::time::timeout(
         Duration::from_millis(BULK_ASYNC_TIMEOUT_MS),
         futures::future::join_all(r),
     )
     .await
     .unwrap();
+
+    for result in results {
+        result.unwrap();
+    }
+}
+
+/// Sends a large number of frames to the adapter as a single batch.
+async fn bulk_send_batch(adapter: &AsyncCanAdapter) {
+    let frames = get_test_frames(BULK_NUM_FRAMES_ASYNC);
+
+    tokio::time::timeout(
+        Duration::from_millis(BULK_ASYNC_TIMEOUT_MS),
+        adapter.send_batch(&frames),
+    )
+    .await
+    .unwrap()
+    .unwrap();
 }
 
 #[cfg(feature = "test-panda")]
@@ -122,6 +140,22 @@ async fn vector_bulk_send_async() {
     bulk_send(&vector).await;
 }
 
+#[cfg(feature = "test-pcan")]
+#[test]
+#[serial_test::serial]
+fn pcan_bulk_send_sync() {
+    let mut pcan = automotive::pcan::Pcan::new(automotive::pcan::PCAN_USBBUS1, 500_000).unwrap();
+    bulk_send_sync(&mut pcan);
+}
+
+#[cfg(feature = "test-pcan")]
+#[tokio::test]
+#[serial_test::serial]
+async fn pcan_bulk_send_async() {
+    let pcan = automotive::pcan::Pcan::new_async(automotive::pcan::PCAN_USBBUS1, 500_000).unwrap();
+    bulk_send(&pcan).await;
+}
+
 #[cfg(feature = "test-socketcan")]
 #[test]
 #[serial_test::serial]
@@ -155,6 +189,14 @@ async fn vcan_bulk_send_async() {
     bulk_send(&adapter).await;
 }
 
+#[cfg(feature = "test-vcan")]
+#[tokio::test]
+#[serial_test::serial]
+async fn vcan_bulk_send_batch() {
+    let adapter = automotive::socketcan::SocketCan::new_async("vcan0").unwrap();
+    bulk_send_batch(&adapter).await;
+}
+
 #[cfg(feature = "test-vcan")]
 #[tokio::test]
 #[serial_test::serial]
@@ -162,7 +204,109 @@ async fn vcan_send_fd() {
     let adapter = automotive::socketcan::SocketCan::new_async("vcan0").unwrap();
     adapter
         .send(&Frame::new(0, 0x123.into(), &[0u8; 64]).unwrap())
-        .await;
+        .await
+        .unwrap();
+}
+
+#[cfg(feature = "test-vcan")]
+#[tokio::test]
+#[serial_test::serial]
+async fn vcan_send_fd_short() {
+    use automotive::StreamExt;
+
+    let adapter = automotive::socketcan::SocketCan::new_async("vcan0").unwrap();
+    let mut stream = adapter.recv_filter(|frame| frame.loopback);
+
+    let mut frame = Frame::new(0, 0x123.into(), &[0xaa; 4]).unwrap();
+    frame.fd = true;
+    adapter.send(&frame).await.unwrap();
+
+    let received = stream.next().await.unwrap();
+    assert!(received.fd);
+    assert_eq!(received.data, vec![0xaa; 4]);
+}
+
+#[cfg(feature = "test-vcan")]
+#[tokio::test]
+#[serial_test::serial]
+async fn vcan_timestamp() {
+    use automotive::StreamExt;
+
+    let adapter = automotive::socketcan::SocketCan::new_async("vcan0").unwrap();
+    let mut stream = adapter.recv_filter(|frame| frame.loopback);
+
+    for _ in 0..2 {
+        let frame = Frame::new(0, 0x123.into(), &[0xaa; 8]).unwrap();
+        adapter.send(&frame).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let first = stream.next().await.unwrap().timestamp.unwrap();
+    let second = stream.next().await.unwrap().timestamp.unwrap();
+    assert!(second - first >= Duration::from_millis(10));
+}
+
+#[cfg(feature = "test-vcan")]
+async fn vcan_filter_received_ids(
+    filters: &[automotive::socketcan::SocketCanFilter],
+    sent: &[u32],
+) -> Vec<Identifier> {
+    use automotive::StreamExt;
+
+    let socket = automotive::socketcan::SocketCan::new("vcan0").unwrap();
+    socket.set_filters(filters).unwrap();
+    let rx = AsyncCanAdapter::new(socket);
+    let stream = rx.recv_timeout(Duration::from_millis(100));
+
+    let tx = automotive::socketcan::SocketCan::new_async("vcan0").unwrap();
+    for id in sent {
+        tx.send(&Frame::new(0, (*id).into(), &[0xaa; 8]).unwrap())
+            .await
+            .unwrap();
+    }
+
+    stream.map(|frame| frame.id).collect().await
+}
+
+#[cfg(feature = "test-vcan")]
+#[tokio::test]
+#[serial_test::serial]
+async fn vcan_filter_ids() {
+    use automotive::socketcan::SocketCanFilter;
+
+    let sent = [0x7e0, 0x7e8, 0x123, 0x7e9, 0x7ea, 0x7e8, 0x12345678];
+    let filters = [
+        SocketCanFilter::exact(Identifier::Standard(0x7e8)),
+        SocketCanFilter::exact(Identifier::Standard(0x7e9)),
+        SocketCanFilter::exact(Identifier::Extended(0x12345678)),
+    ];
+
+    let received = vcan_filter_received_ids(&filters, &sent).await;
+    assert_eq!(
+        received,
+        vec![
+            Identifier::Standard(0x7e8),
+            Identifier::Standard(0x7e9),
+            Identifier::Standard(0x7e8),
+            Identifier::Extended(0x12345678),
+        ]
+    );
+}
+
+#[cfg(feature = "test-vcan")]
+#[tokio::test]
+#[serial_test::serial]
+async fn vcan_filter_exclude() {
+    use automotive::socketcan::SocketCanFilter;
+
+    let sent = [0x7e0, 0x7e8, 0x123];
+    let filters = [SocketCanFilter::exclude(Identifier::Standard(0x7e8))];
+
+    let received = vcan_filter_received_ids(&filters, &sent).await;
+    assert_eq!(
+        received,
+        vec![Identifier::Standard(0x7e0), Identifier::Standard(0x123)]
+    );
 }
 
 #[cfg(all(target_os = "linux", feature = "socketcan"))]
@@ -176,3 +320,13 @@ async fn socketcan_open_nonexistent() {
         _ => panic!("Expected NotFound error"),
     }
 }
+
+#[cfg(feature = "test-vcan")]
+#[test]
+#[serial_test::serial]
+fn vcan_available() {
+    let available = automotive::socketcan::SocketCan::available();
+    assert!(available.contains(&"vcan0".to_string()));
+    assert!(!available.contains(&"lo".to_string()));
+    assert!(available.is_sorted());
+}
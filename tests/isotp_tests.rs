@@ -137,6 +137,43 @@ async fn isotp_test_stmin() {
     assert!(start.elapsed() > stmin * 8);
 }
 
+#[cfg(feature = "test-vcan")]
+#[tokio::test]
+#[serial_test::serial]
+async fn isotp_test_send_with_stmin() {
+    let stmin = std::time::Duration::from_millis(50);
+    let config = VECUConfig {
+        stmin: stmin.as_millis() as u32,
+        ..Default::default()
+    };
+
+    let adapter = automotive::socketcan::SocketCan::new_async("vcan0").unwrap();
+    let _vecu = vecu_spawn(&adapter, config).await;
+
+    let mut isotp_config = IsoTPConfig::new(0, Identifier::Standard(0x7a1));
+    isotp_config.timeout = std::time::Duration::from_millis(1000);
+    let isotp = IsoTPAdapter::new(&adapter, isotp_config);
+
+    let request = vec![0xcc; 64];
+
+    // Separation time requested by the ECU is ignored
+    let mut stream = isotp.recv();
+    let start = std::time::Instant::now();
+    isotp
+        .send_with_stmin(&request, std::time::Duration::ZERO)
+        .await
+        .unwrap();
+    assert!(start.elapsed() < stmin * 8);
+    assert_eq!(stream.next().await.unwrap().unwrap(), request);
+
+    // Longer separation time for a single transfer
+    let mut stream = isotp.recv();
+    let start = std::time::Instant::now();
+    isotp.send_with_stmin(&request, stmin * 2).await.unwrap();
+    assert!(start.elapsed() > stmin * 16);
+    assert_eq!(stream.next().await.unwrap().unwrap(), request);
+}
+
 #[cfg(feature = "test-vcan")]
 #[tokio::test]
 #[serial_test::serial]
@@ -4,6 +4,7 @@ use automotive::can::Identifier;
 use automotive::isotp::{IsoTPAdapter, IsoTPConfig};
 use automotive::uds::Error as UDSError;
 use automotive::uds::NegativeResponseCode;
+use automotive::uds::ResetType;
 use automotive::uds::UDSClient;
 use automotive::StreamExt;
 use std::process::{Child, Command};
@@ -44,11 +45,43 @@ async fn uds_test_sids() {
 
     uds.tester_present().await.unwrap();
 
+    let round_trip_time = uds.ping().await.unwrap();
+    assert!(round_trip_time < isotp_config.timeout);
+
     let data = uds.read_data_by_identifier(0x1234).await.unwrap();
     assert_eq!(data, b"deadbeef".to_vec());
 
+    let data: String = uds.read_data_by_identifier_typed(0x1234).await.unwrap();
+    assert_eq!(data, "deadbeef");
+
     let resp = uds.diagnostic_session_control(0x2).await;
-    let security_access_denied =
-        UDSError::NegativeResponse(NegativeResponseCode::SecurityAccessDenied);
+    let security_access_denied = UDSError::NegativeResponse {
+        service: 0x10,
+        code: NegativeResponseCode::SecurityAccessDenied,
+        extra: vec![],
+    };
     assert_eq!(resp, Err(security_access_denied.into()));
 }
+
+#[cfg(feature = "test-vcan")]
+#[tokio::test]
+#[serial_test::serial]
+async fn uds_test_ecu_reset() {
+    let adapter = automotive::socketcan::SocketCan::new_async("vcan0").unwrap();
+    let _vecu = vecu_spawn(&adapter).await;
+
+    let mut isotp_config = IsoTPConfig::new(0, Identifier::Standard(0x7a1));
+    isotp_config.timeout = std::time::Duration::from_millis(1000);
+
+    let isotp = IsoTPAdapter::new(&adapter, isotp_config);
+    let uds = UDSClient::new(&isotp);
+
+    let power_down_time = uds.ecu_reset(ResetType::HardReset as u8).await.unwrap();
+    assert_eq!(power_down_time, None);
+
+    let power_down_time = uds
+        .ecu_reset(ResetType::EnableRapidPowerShutDown as u8)
+        .await
+        .unwrap();
+    assert_eq!(power_down_time, Some(std::time::Duration::from_secs(5)));
+}